@@ -0,0 +1,501 @@
+// rustdb-derive/src/lib.rs
+//! `#[derive(FromRow, ToRow)]` for mapping query result rows onto Rust structs,
+//! and `#[derive(FromValue, ToValue)]` for storing custom struct and enum
+//! types in a single column as `Value::Struct` / `Value::Enum`.
+//!
+//! Fields are matched to result columns by name. Supported field attributes:
+//!
+//! - `#[rustdb(rename = "col")]` reads/writes the field from column `col`
+//!   (or, for `ToValue`/`FromValue`, the struct field or enum variant `col`).
+//! - `#[rustdb(flatten)]` maps a nested struct (which must itself derive the
+//!   trait) onto the same row; `#[rustdb(flatten, prefix = "p_")]` looks its
+//!   columns up as `p_<field>`. A nested struct that derives `FromValue`
+//!   can instead be read from a single struct-valued column.
+//! - `#[rustdb(skip)]` ignores the field; `FromRow` fills it with `Default`.
+//!
+//! Type parameters of a generic struct or enum must implement `ToValue` or
+//! `FromValue`, whichever the derived trait converts its fields with.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Fields, Generics, LitStr};
+
+#[proc_macro_derive(FromRow, attributes(rustdb))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_row(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(ToRow, attributes(rustdb))]
+pub fn derive_to_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_row(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(ToValue, attributes(rustdb))]
+pub fn derive_to_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_value(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromValue, attributes(rustdb))]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_value(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct FieldSpec {
+    ident: syn::Ident,
+    ty: syn::Type,
+    column: String,
+    flatten: bool,
+    prefix: String,
+    skip: bool,
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<FieldSpec>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(_) => field_specs(&data.fields),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "rows can only be mapped onto structs with named fields",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "rows can only be mapped onto structs",
+        )),
+    }
+}
+
+fn field_specs(fields: &Fields) -> syn::Result<Vec<FieldSpec>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let ident = field
+                .ident
+                .clone()
+                .unwrap_or_else(|| quote::format_ident!("f{}", i));
+            let mut spec = FieldSpec {
+                column: ident.to_string().trim_start_matches("r#").to_string(),
+                ident,
+                ty: field.ty.clone(),
+                flatten: false,
+                prefix: String::new(),
+                skip: false,
+            };
+
+            for attr in field.attrs.iter().filter(|a| a.path().is_ident("rustdb")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        spec.column = meta.value()?.parse::<LitStr>()?.value();
+                    } else if meta.path.is_ident("flatten") {
+                        spec.flatten = true;
+                    } else if meta.path.is_ident("prefix") {
+                        spec.prefix = meta.value()?.parse::<LitStr>()?.value();
+                    } else if meta.path.is_ident("skip") {
+                        spec.skip = true;
+                    } else {
+                        return Err(meta.error("unknown rustdb attribute"));
+                    }
+                    Ok(())
+                })?;
+            }
+
+            if !spec.prefix.is_empty() && !spec.flatten {
+                return Err(syn::Error::new_spanned(
+                    &spec.ident,
+                    "`prefix` can only be used together with `flatten`",
+                ));
+            }
+
+            Ok(spec)
+        })
+        .collect()
+}
+
+/// Fields of a struct or enum variant stored as a single value. Flattening
+/// only makes sense for whole rows.
+fn value_field_specs(fields: &Fields) -> syn::Result<Vec<FieldSpec>> {
+    let specs = field_specs(fields)?;
+    if let Some(f) = specs.iter().find(|f| f.flatten) {
+        return Err(syn::Error::new_spanned(
+            &f.ident,
+            "`flatten` is only supported by FromRow/ToRow",
+        ));
+    }
+    Ok(specs)
+}
+
+/// The name a variant is stored under, honouring `#[rustdb(rename = "...")]`.
+fn variant_name(ident: &syn::Ident, attrs: &[Attribute]) -> syn::Result<String> {
+    let mut name = ident.to_string();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("rustdb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown rustdb attribute on enum variant"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+/// `generics` with `bound` added to every type parameter.
+fn with_bound(generics: &Generics, bound: syn::Path) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn expand_from_row(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = with_bound(&input.generics, parse_quote!(::rustdb::types::FromValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let fields = named_fields(input)?;
+
+    let inits = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        if f.skip {
+            quote! { #ident: ::std::default::Default::default() }
+        } else if f.flatten {
+            let prefix = &f.prefix;
+            quote! {
+                #ident: <#ty as ::rustdb::row::FromRow>::from_row(&row.with_prefix(#prefix))?
+            }
+        } else {
+            let column = &f.column;
+            let field = format!("{}::{}", name, ident);
+            quote! { #ident: row.get_field::<#ty>(#column, #field)? }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::rustdb::row::FromRow for #name #ty_generics #where_clause {
+            fn from_row(
+                row: &::rustdb::row::RowRef<'_>,
+            ) -> ::std::result::Result<Self, ::rustdb::Error> {
+                ::std::result::Result::Ok(Self {
+                    #(#inits,)*
+                })
+            }
+        }
+    })
+}
+
+fn expand_to_row(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = with_bound(&input.generics, parse_quote!(::rustdb::types::ToValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let fields = named_fields(input)?;
+
+    let pushes = fields.iter().filter(|f| !f.skip).map(|f| {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        if f.flatten {
+            let prefix = &f.prefix;
+            quote! {
+                columns.extend(
+                    <#ty as ::rustdb::row::ToRow>::to_row(&self.#ident)
+                        .into_iter()
+                        .map(|(column, value)| (::std::format!("{}{}", #prefix, column), value)),
+                );
+            }
+        } else {
+            let column = &f.column;
+            quote! {
+                columns.push((
+                    ::std::string::String::from(#column),
+                    ::rustdb::types::ToValue::to_value(&self.#ident),
+                ));
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::rustdb::row::ToRow for #name #ty_generics #where_clause {
+            fn to_row(&self) -> ::std::vec::Vec<(::std::string::String, ::rustdb::types::Value)> {
+                let mut columns = ::std::vec::Vec::new();
+                #(#pushes)*
+                columns
+            }
+        }
+    })
+}
+
+/// Builds the `Value` for a set of fields that are bound to local variables
+/// of the same name.
+fn fields_to_value(fields: &Fields, specs: &[FieldSpec], bindings: &[syn::Ident]) -> TokenStream2 {
+    match fields {
+        Fields::Named(_) => {
+            let entries = specs.iter().filter(|f| !f.skip).map(|f| {
+                let column = &f.column;
+                let ident = &f.ident;
+                quote! {
+                    (
+                        ::std::string::String::from(#column),
+                        ::rustdb::types::ToValue::to_value(#ident),
+                    )
+                }
+            });
+            quote! { ::rustdb::types::Value::Struct(::std::vec![#(#entries),*]) }
+        }
+        Fields::Unnamed(_) if bindings.len() == 1 => {
+            let b = &bindings[0];
+            quote! { ::rustdb::types::ToValue::to_value(#b) }
+        }
+        Fields::Unnamed(_) => {
+            quote! {
+                ::rustdb::types::Value::Array(::std::vec![
+                    #(::rustdb::types::ToValue::to_value(#bindings)),*
+                ])
+            }
+        }
+        Fields::Unit => quote! { ::rustdb::types::Value::Null },
+    }
+}
+
+/// Builds the constructor expression (`Path { .. }` / `Path(..)`) that decodes
+/// `fields` from the `Value` held in `source`.
+fn fields_from_value(
+    path: TokenStream2,
+    type_name: &str,
+    fields: &Fields,
+    specs: &[FieldSpec],
+    source: TokenStream2,
+) -> TokenStream2 {
+    match fields {
+        Fields::Named(_) => {
+            let inits = specs.iter().map(|f| {
+                let ident = &f.ident;
+                let ty = &f.ty;
+                let column = &f.column;
+                let context = format!("field {}::{}", type_name, ident);
+                if f.skip {
+                    quote! { #ident: ::std::default::Default::default() }
+                } else {
+                    // A missing field decodes as NULL so that values written
+                    // before an optional field was added remain readable.
+                    quote! {
+                        #ident: <#ty as ::rustdb::types::FromValue>::from_value(
+                            fields
+                                .iter()
+                                .find(|(name, _)| name == #column)
+                                .map(|(_, value)| value)
+                                .unwrap_or(&::rustdb::types::Value::Null),
+                        )
+                        .map_err(|e| ::rustdb::types::conversion_context(#context, e))?
+                    }
+                }
+            });
+            let expected = format!("struct {}", type_name);
+            quote! {
+                match #source {
+                    ::rustdb::types::Value::Struct(fields) => #path { #(#inits,)* },
+                    other => return ::std::result::Result::Err(
+                        ::rustdb::types::conversion_mismatch(#expected, other),
+                    ),
+                }
+            }
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            let ty = &unnamed.unnamed[0].ty;
+            quote! { #path(<#ty as ::rustdb::types::FromValue>::from_value(#source)?) }
+        }
+        Fields::Unnamed(unnamed) => {
+            let len = unnamed.unnamed.len();
+            let items = unnamed.unnamed.iter().enumerate().map(|(i, f)| {
+                let ty = &f.ty;
+                quote! { <#ty as ::rustdb::types::FromValue>::from_value(&items[#i])? }
+            });
+            let expected = format!("array of {} items for {}", len, type_name);
+            quote! {
+                match #source {
+                    ::rustdb::types::Value::Array(items) if items.len() == #len => {
+                        #path(#(#items),*)
+                    }
+                    other => return ::std::result::Result::Err(
+                        ::rustdb::types::conversion_mismatch(#expected, other),
+                    ),
+                }
+            }
+        }
+        Fields::Unit => quote! { #path },
+    }
+}
+
+fn bindings(fields: &Fields) -> Vec<syn::Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => ident.clone(),
+            None => quote::format_ident!("f{}", i),
+        })
+        .collect()
+}
+
+fn destructure(path: TokenStream2, fields: &Fields, bindings: &[syn::Ident]) -> TokenStream2 {
+    match fields {
+        Fields::Named(_) => quote! { #path { #(#bindings,)* } },
+        Fields::Unnamed(_) => quote! { #path(#(#bindings),*) },
+        Fields::Unit => quote! { #path },
+    }
+}
+
+fn expand_to_value(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = with_bound(&input.generics, parse_quote!(::rustdb::types::ToValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let specs = value_field_specs(&data.fields)?;
+            let binds = bindings(&data.fields);
+            let pattern = destructure(quote! { Self }, &data.fields, &binds);
+            let value = fields_to_value(&data.fields, &specs, &binds);
+            quote! {
+                let #pattern = self;
+                #value
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|v| {
+                    let ident = &v.ident;
+                    let stored = variant_name(ident, &v.attrs)?;
+                    let specs = value_field_specs(&v.fields)?;
+                    let binds = bindings(&v.fields);
+                    let pattern = destructure(quote! { Self::#ident }, &v.fields, &binds);
+                    let payload = if matches!(v.fields, Fields::Unit) {
+                        quote! { ::std::option::Option::None }
+                    } else {
+                        let value = fields_to_value(&v.fields, &specs, &binds);
+                        quote! { ::std::option::Option::Some(::std::boxed::Box::new(#value)) }
+                    };
+                    Ok(quote! {
+                        #pattern => ::rustdb::types::Value::Enum {
+                            variant: ::std::string::String::from(#stored),
+                            payload: #payload,
+                        },
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(name, "unions cannot be stored as values"))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rustdb::types::ToValue for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn to_value(&self) -> ::rustdb::types::Value {
+                #body
+            }
+        }
+    })
+}
+
+fn expand_from_value(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let type_name = name.to_string();
+    let generics = with_bound(&input.generics, parse_quote!(::rustdb::types::FromValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let specs = value_field_specs(&data.fields)?;
+            let build =
+                fields_from_value(quote! { Self }, &type_name, &data.fields, &specs, quote! { value });
+            quote! { ::std::result::Result::Ok(#build) }
+        }
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|v| {
+                    let ident = &v.ident;
+                    let stored = variant_name(ident, &v.attrs)?;
+                    let specs = value_field_specs(&v.fields)?;
+                    if matches!(v.fields, Fields::Unit) {
+                        return Ok(quote! { #stored => ::std::result::Result::Ok(Self::#ident), });
+                    }
+                    let build = fields_from_value(
+                        quote! { Self::#ident },
+                        &format!("{}::{}", type_name, ident),
+                        &v.fields,
+                        &specs,
+                        quote! { payload },
+                    );
+                    let missing = format!("variant {}::{} requires a payload", type_name, ident);
+                    Ok(quote! {
+                        #stored => {
+                            let payload: &::rustdb::types::Value = match payload {
+                                ::std::option::Option::Some(p) => p,
+                                ::std::option::Option::None => {
+                                    return ::std::result::Result::Err(::rustdb::Error::Type(
+                                        ::std::string::String::from(#missing),
+                                    ))
+                                }
+                            };
+                            ::std::result::Result::Ok(#build)
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            let expected = format!("enum {}", type_name);
+            let unknown = format!("unknown variant {{}} for enum {}", type_name);
+            quote! {
+                match value {
+                    ::rustdb::types::Value::Enum { variant, payload } => match variant.as_str() {
+                        #(#arms)*
+                        other => ::std::result::Result::Err(::rustdb::Error::Type(
+                            ::std::format!(#unknown, other),
+                        )),
+                    },
+                    other => ::std::result::Result::Err(
+                        ::rustdb::types::conversion_mismatch(#expected, other),
+                    ),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(name, "unions cannot be stored as values"))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rustdb::types::FromValue for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn from_value(
+                value: &::rustdb::types::Value,
+            ) -> ::std::result::Result<Self, ::rustdb::Error> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,1614 @@
+// src/analyzer.rs
+//
+// Semantic analysis: resolves column references against the catalog, infers
+// a type for every expression and makes implicit conversions explicit as
+// `Expr::Cast` nodes, so the executor never has to guess.
+
+use crate::catalog::{custom_type_name, Catalog, ColumnSchema, TableSchema};
+use crate::error::Error;
+use crate::parser::ast::*;
+use crate::types::cast;
+
+/// Inferred type of an expression; `None` is the type of a bare `NULL`,
+/// which is compatible with every column type.
+pub type Type = Option<DataType>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputColumn {
+    pub name: String,
+    pub data_type: Type,
+}
+
+/// A statement that passed analysis, rewritten with resolved column
+/// references and implicit casts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzedStatement {
+    pub statement: Statement,
+    /// Result columns of a query; empty for other statements.
+    pub columns: Vec<OutputColumn>,
+}
+
+/// A table visible to column references, under its alias or name.
+struct Binding {
+    name: String,
+    schema: TableSchema,
+}
+
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    /// Columns joined with `USING`, which may be referenced unqualified.
+    using: Vec<String>,
+}
+
+pub struct Analyzer<'a> {
+    catalog: &'a dyn Catalog,
+    scopes: Vec<Scope>,
+    clause: &'static str,
+    aggregates_allowed: bool,
+    in_aggregate: bool,
+    saw_aggregate: bool,
+}
+
+const AGGREGATES: &[&str] = &["COUNT", "SUM", "AVG", "MIN", "MAX"];
+
+fn is_aggregate(name: &str) -> bool {
+    AGGREGATES.iter().any(|a| a.eq_ignore_ascii_case(name))
+}
+
+fn type_error(span: Span, message: impl Into<String>) -> Error {
+    let message = message.into();
+    if span.is_known() {
+        Error::Type(format!("{} at {}", message, span))
+    } else {
+        Error::Type(message)
+    }
+}
+
+fn type_label(ty: &Type) -> String {
+    match ty {
+        Some(ty) => ty.to_string(),
+        None => "NULL".to_string(),
+    }
+}
+
+/// Best position to report for an expression.
+fn expr_span(expr: &Expr) -> Span {
+    match expr {
+        Expr::Column(col) => col.span,
+        Expr::Binary { left, span, .. } => {
+            let left = expr_span(left);
+            if left.is_known() { left } else { *span }
+        }
+        Expr::Unary { span, .. } | Expr::Function { span, .. } => *span,
+        Expr::Cast { expr, .. } => expr_span(expr),
+        _ => Span::default(),
+    }
+}
+
+fn literal_type(value: &Value) -> Result<Type, String> {
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Bool(_) => DataType::Boolean,
+        Value::Int8(_) => DataType::TinyInt,
+        Value::Int16(_) => DataType::SmallInt,
+        Value::Int32(_) => DataType::Integer(None),
+        Value::Int(_) => DataType::BigInt,
+        Value::UInt8(_) => DataType::UnsignedTinyInt,
+        Value::UInt16(_) => DataType::UnsignedSmallInt,
+        Value::UInt32(_) => DataType::UnsignedInteger,
+        Value::UInt64(_) => DataType::UnsignedBigInt,
+        Value::Float32(_) => DataType::Real,
+        Value::Float(_) => DataType::Float(None),
+        Value::Decimal(_) => DataType::Decimal(None),
+        Value::String(_) => DataType::Text,
+        Value::Bytes(_) => DataType::Binary(None),
+        Value::Uuid(_) => DataType::Uuid,
+        Value::DateTime(_) => DataType::Timestamp,
+        Value::Date(_) => DataType::Date,
+        Value::Time(_) => DataType::Time,
+        Value::Interval(_) => DataType::Interval,
+        Value::Json(_) => DataType::Json,
+        other => return Err(format!("{} literals are not supported in queries", other.type_name())),
+    }))
+}
+
+/// The constant value of a literal operand, looking through unary minus.
+fn literal_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Literal(value) => Some(value.clone()),
+        Expr::Unary { op: UnaryOp::Negative, expr, .. } => match literal_value(expr)? {
+            Value::Int(i) => i.checked_neg().map(Value::Int),
+            Value::Decimal(d) => Some(Value::Decimal(-d)),
+            Value::Float(f) => Some(Value::Float(-f)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Signedness and width in bytes of an integer type.
+fn int_info(ty: &DataType) -> Option<(bool, u8)> {
+    Some(match ty {
+        DataType::TinyInt => (true, 1),
+        DataType::SmallInt => (true, 2),
+        DataType::Integer(_) => (true, 4),
+        DataType::BigInt => (true, 8),
+        DataType::UnsignedTinyInt => (false, 1),
+        DataType::UnsignedSmallInt => (false, 2),
+        DataType::UnsignedInteger => (false, 4),
+        DataType::UnsignedBigInt => (false, 8),
+        _ => return None,
+    })
+}
+
+fn int_type(signed: bool, width: u8) -> DataType {
+    match (signed, width) {
+        (true, 1) => DataType::TinyInt,
+        (true, 2) => DataType::SmallInt,
+        (true, 4) => DataType::Integer(None),
+        (true, _) => DataType::BigInt,
+        (false, 1) => DataType::UnsignedTinyInt,
+        (false, 2) => DataType::UnsignedSmallInt,
+        (false, 4) => DataType::UnsignedInteger,
+        (false, _) => DataType::UnsignedBigInt,
+    }
+}
+
+fn fits_integer(value: &Value, ty: &DataType) -> bool {
+    let (Some(v), Some((signed, width))) = (value.as_i128(), int_info(ty)) else {
+        return false;
+    };
+    let bits = width as u32 * 8;
+    if signed {
+        v >= -(1i128 << (bits - 1)) && v < (1i128 << (bits - 1))
+    } else {
+        v >= 0 && v < (1i128 << bits)
+    }
+}
+
+/// Smallest numeric type both operands convert to without losing range.
+fn promote_numeric(a: &DataType, b: &DataType) -> DataType {
+    use DataType::*;
+    match (a, b) {
+        (Real, Real) => Real,
+        (Float(_) | Real, _) | (_, Float(_) | Real) => Float(None),
+        (Decimal(_), _) | (_, Decimal(_)) => Decimal(None),
+        _ => {
+            let (sa, wa) = int_info(a).unwrap_or((true, 8));
+            let (sb, wb) = int_info(b).unwrap_or((true, 8));
+            if sa == sb {
+                int_type(sa, wa.max(wb))
+            } else {
+                let (signed, unsigned) = if sa { (wa, wb) } else { (wb, wa) };
+                if signed > unsigned {
+                    int_type(true, signed)
+                } else if unsigned < 8 {
+                    int_type(true, unsigned * 2)
+                } else {
+                    Decimal(None)
+                }
+            }
+        }
+    }
+}
+
+/// Type a literal of type `lit` adapts to when combined with `other`, so
+/// that `tiny_col = 5` converts the constant rather than every row.
+fn adapt_literal(value: &Value, lit: &DataType, other: &DataType) -> Option<DataType> {
+    if lit.is_integer() && other.is_integer() {
+        return fits_integer(value, other).then(|| other.clone());
+    }
+    if lit.is_numeric() && matches!(other, DataType::Real | DataType::Float(_) | DataType::Decimal(_)) {
+        return Some(other.clone());
+    }
+    if lit.is_string()
+        && (other.is_temporal()
+            || matches!(other, DataType::Interval | DataType::Uuid | DataType::Json))
+    {
+        return Some(other.clone());
+    }
+    None
+}
+
+struct Operand {
+    ty: DataType,
+    literal: Option<Value>,
+}
+
+/// Common type of two operands, or `None` if they cannot be compared.
+fn common_type(a: &Operand, b: &Operand) -> Option<DataType> {
+    if a.ty == b.ty {
+        return Some(a.ty.clone());
+    }
+    if a.ty.same_family(&b.ty) {
+        // Drop length and precision limits, which only apply to storage.
+        return Some(match &a.ty {
+            t if t.is_string() => DataType::Text,
+            t if t.is_temporal() => DataType::Timestamp,
+            DataType::Integer(_) => DataType::Integer(None),
+            DataType::Float(_) => DataType::Float(None),
+            DataType::Decimal(_) => DataType::Decimal(None),
+            DataType::Binary(_) => DataType::Binary(None),
+            t => t.clone(),
+        });
+    }
+    if let (DataType::Date, DataType::DateTime | DataType::Timestamp)
+    | (DataType::DateTime | DataType::Timestamp, DataType::Date) = (&a.ty, &b.ty)
+    {
+        return Some(DataType::Timestamp);
+    }
+    match (&a.literal, &b.literal) {
+        (Some(value), None) => {
+            if let Some(ty) = adapt_literal(value, &a.ty, &b.ty) {
+                return Some(ty);
+            }
+        }
+        (None, Some(value)) => {
+            if let Some(ty) = adapt_literal(value, &b.ty, &a.ty) {
+                return Some(ty);
+            }
+        }
+        _ => {}
+    }
+    if a.ty.is_numeric() && b.ty.is_numeric() {
+        return Some(promote_numeric(&a.ty, &b.ty));
+    }
+    None
+}
+
+/// Whether a value of type `from` needs converting to be stored as `to`:
+/// either the representation differs or `to` limits length or precision.
+fn needs_cast(from: &DataType, to: &DataType) -> bool {
+    let limited = matches!(
+        to,
+        DataType::Char(Some(_)) | DataType::Varchar(Some(_)) | DataType::Decimal(Some(_)) | DataType::Binary(Some(_))
+    );
+    !from.same_family(to) || (limited && from != to)
+}
+
+/// Wraps `expr` in a conversion to `target` if needed. Constants are
+/// converted right away so that e.g. `'2024-13-01'` against a DATE column is
+/// reported before execution.
+fn cast_to(expr: &mut Expr, ty: &Type, target: &DataType, span: Span) -> Result<(), Error> {
+    match ty {
+        Some(ty) if needs_cast(ty, target) => {
+            if let Some(value) = literal_value(expr) {
+                if let Err(Error::Type(message)) = cast::cast(&value, target) {
+                    return Err(type_error(span, message));
+                }
+            }
+            let inner = std::mem::replace(expr, Expr::Literal(Value::Null));
+            *expr = Expr::Cast {
+                expr: Box::new(inner),
+                data_type: target.clone(),
+                try_cast: false,
+            };
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl<'a> Analyzer<'a> {
+    pub fn new(catalog: &'a dyn Catalog) -> Self {
+        Analyzer {
+            catalog,
+            scopes: Vec::new(),
+            clause: "",
+            aggregates_allowed: false,
+            in_aggregate: false,
+            saw_aggregate: false,
+        }
+    }
+
+    pub fn analyze(&mut self, statement: &Statement) -> Result<AnalyzedStatement, Error> {
+        self.scopes.clear();
+        self.in_aggregate = false;
+        self.saw_aggregate = false;
+
+        let mut statement = statement.clone();
+        let columns = match &mut statement {
+            Statement::Select(select) => self.select(select)?,
+            Statement::Insert(insert) => {
+                self.insert(insert)?;
+                Vec::new()
+            }
+            Statement::Update(update) => {
+                self.update(update)?;
+                Vec::new()
+            }
+            Statement::Delete(delete) => {
+                self.delete(delete)?;
+                Vec::new()
+            }
+            Statement::Create(create) => {
+                self.create(create)?;
+                Vec::new()
+            }
+            Statement::Drop(drop) => {
+                if !drop.if_exists {
+                    self.table(&drop.table)?;
+                }
+                Vec::new()
+            }
+            Statement::Alter(alter) => {
+                self.alter(alter)?;
+                Vec::new()
+            }
+            Statement::CreateIndex(create) => {
+                self.create_index(create)?;
+                Vec::new()
+            }
+            Statement::CreateType(create) => {
+                self.create_type(create)?;
+                Vec::new()
+            }
+            Statement::AlterType(alter) => {
+                self.alter_type(alter)?;
+                Vec::new()
+            }
+            Statement::Checkpoint => Vec::new(),
+        };
+        Ok(AnalyzedStatement { statement, columns })
+    }
+
+    fn table(&self, table: &TableReference) -> Result<TableSchema, Error> {
+        let name = match &table.schema {
+            Some(schema) => format!("{}.{}", schema, table.name),
+            None => table.name.clone(),
+        };
+        self.catalog
+            .table(&name)
+            .ok_or_else(|| Error::Type(format!("table {} does not exist", name)))
+    }
+
+    fn bind(&mut self, table: &TableReference) -> Result<(), Error> {
+        let schema = self.table(table)?;
+        let name = table.alias.clone().unwrap_or_else(|| table.name.clone());
+        let scope = self.scopes.last_mut().expect("bind outside of a scope");
+        if scope.bindings.iter().any(|b| b.name.eq_ignore_ascii_case(&name)) {
+            return Err(Error::Type(format!("table name {} specified more than once", name)));
+        }
+        scope.bindings.push(Binding { name, schema });
+        Ok(())
+    }
+
+    fn resolve(&self, col: &mut ColumnRef) -> Result<Type, Error> {
+        for scope in self.scopes.iter().rev() {
+            let mut found: Option<(&Binding, &ColumnSchema)> = None;
+            let mut table_found = false;
+            for binding in &scope.bindings {
+                if let Some(table) = &col.table {
+                    if !binding.name.eq_ignore_ascii_case(table) {
+                        continue;
+                    }
+                    table_found = true;
+                }
+                if let Some(column) = binding.schema.column(&col.name) {
+                    if found.is_none() {
+                        found = Some((binding, column));
+                    } else if !scope.using.iter().any(|u| u.eq_ignore_ascii_case(&col.name)) {
+                        return Err(type_error(
+                            col.span,
+                            format!("column reference {} is ambiguous", col.name),
+                        ));
+                    }
+                }
+            }
+            if let Some((binding, column)) = found {
+                col.table = Some(binding.name.clone());
+                col.name = column.name.clone();
+                return Ok(Some(column.data_type.clone()));
+            }
+            if table_found {
+                break;
+            }
+        }
+        Err(match &col.table {
+            Some(table) if !self.scopes.iter().any(|s| {
+                s.bindings.iter().any(|b| b.name.eq_ignore_ascii_case(table))
+            }) => type_error(col.span, format!("unknown table {} in column reference", table)),
+            Some(table) => type_error(col.span, format!("column {}.{} does not exist", table, col.name)),
+            None => type_error(col.span, format!("column {} does not exist", col.name)),
+        })
+    }
+
+    /// Rewrites a qualified column reference whose qualifier names no table
+    /// in scope into a column followed by fields, when that column exists:
+    /// `users.phone.country_code` is the `country_code` field of
+    /// `users.phone`, and `phone.country_code` that of `phone`.
+    fn split_fields(&self, expr: &mut Expr) {
+        let mut bottom = expr;
+        while let Expr::Field { expr: inner, .. } = bottom {
+            bottom = inner;
+        }
+        let Expr::Column(col) = bottom else { return };
+        let bindings = || self.scopes.iter().rev().flat_map(|s| s.bindings.iter());
+        let bound = |name: &str| bindings().any(|b| b.name.eq_ignore_ascii_case(name));
+        if col.table.as_deref().is_none_or(bound) {
+            return;
+        }
+
+        let mut names: Vec<&String> = col.schema.iter().chain(&col.table).chain([&col.name]).collect();
+        let table = if bound(names[0]) { Some(names.remove(0).clone()) } else { None };
+        let exists = bindings().any(|b| {
+            table.as_ref().is_none_or(|t| b.name.eq_ignore_ascii_case(t)) && b.schema.column(names[0]).is_some()
+        });
+        if names.len() < 2 || !exists {
+            return;
+        }
+        let column = ColumnRef { name: names[0].clone(), table, schema: None, span: col.span };
+        let fields: Vec<String> = names[1..].iter().map(|n| n.to_string()).collect();
+        let span = col.span;
+        *bottom = fields.into_iter().fold(Expr::Column(column), |expr, field| Expr::Field {
+            expr: Box::new(expr),
+            step: PathStep::Field(field),
+            span,
+        });
+    }
+
+    fn select(&mut self, select: &mut SelectStatement) -> Result<Vec<OutputColumn>, Error> {
+        self.scopes.push(Scope::default());
+        let saw_aggregate = std::mem::replace(&mut self.saw_aggregate, false);
+        let result = self.select_in_scope(select);
+        self.saw_aggregate = saw_aggregate;
+        self.scopes.pop();
+        result
+    }
+
+    fn select_in_scope(&mut self, select: &mut SelectStatement) -> Result<Vec<OutputColumn>, Error> {
+        self.bind(&select.from)?;
+        for join in &mut select.joins {
+            self.bind(&join.table)?;
+            if let Some(using) = &join.using {
+                let scope = self.scopes.last().unwrap();
+                let (joined, earlier) = scope.bindings.split_last().unwrap();
+                for name in using {
+                    if joined.schema.column(name).is_none()
+                        || !earlier.iter().any(|b| b.schema.column(name).is_some())
+                    {
+                        return Err(Error::Type(format!(
+                            "column {} in USING must exist on both sides of the join",
+                            name
+                        )));
+                    }
+                }
+                let using = using.clone();
+                self.scopes.last_mut().unwrap().using.extend(using);
+            }
+            if let Some(on) = &mut join.on {
+                self.condition(on, "ON")?;
+            }
+        }
+
+        if let Some(expr) = &mut select.where_clause {
+            self.condition(expr, "WHERE")?;
+        }
+
+        self.enter_clause("GROUP BY", false);
+        for expr in &mut select.group_by {
+            self.expr(expr, Span::default())?;
+        }
+
+        self.enter_clause("SELECT", true);
+        let mut columns = Vec::new();
+        for column in &mut select.columns {
+            if let Expr::Wildcard { table } = &column.expr {
+                let scope = self.scopes.last().unwrap();
+                let mut matched = false;
+                for binding in &scope.bindings {
+                    if table.as_ref().is_some_and(|t| !binding.name.eq_ignore_ascii_case(t)) {
+                        continue;
+                    }
+                    matched = true;
+                    columns.extend(binding.schema.columns.iter().map(|c| OutputColumn {
+                        name: c.name.clone(),
+                        data_type: Some(c.data_type.clone()),
+                    }));
+                }
+                if !matched {
+                    return Err(Error::Type(format!(
+                        "unknown table {} in select list",
+                        table.as_deref().unwrap_or_default()
+                    )));
+                }
+                continue;
+            }
+            let data_type = self.expr(&mut column.expr, Span::default())?;
+            let name = match (&column.alias, &column.expr) {
+                (Some(alias), _) => alias.clone(),
+                (None, Expr::Column(col)) => col.name.clone(),
+                (None, Expr::Field { step: PathStep::Field(name), .. }) => name.clone(),
+                (None, expr) => expr.to_string(),
+            };
+            columns.push(OutputColumn { name, data_type });
+        }
+
+        if let Some(expr) = &mut select.having {
+            self.condition(expr, "HAVING")?;
+        }
+
+        self.enter_clause("ORDER BY", true);
+        let aliases: Vec<&String> = select.columns.iter().filter_map(|c| c.alias.as_ref()).collect();
+        let is_alias = |expr: &Expr| {
+            matches!(expr, Expr::Column(col) if col.table.is_none()
+                && aliases.iter().any(|a| a.eq_ignore_ascii_case(&col.name)))
+        };
+        let mut by_alias = Vec::new();
+        for order in &mut select.order_by {
+            by_alias.push(is_alias(&order.expr));
+        }
+        for (order, by_alias) in select.order_by.iter_mut().zip(&by_alias) {
+            if !by_alias {
+                self.expr(&mut order.expr, Span::default())?;
+            }
+        }
+
+        let grouped = !select.group_by.is_empty() || select.having.is_some() || self.saw_aggregate;
+        if grouped {
+            for column in &select.columns {
+                if matches!(column.expr, Expr::Wildcard { .. }) {
+                    return Err(Error::Type(
+                        "* cannot be used in an aggregated query".to_string(),
+                    ));
+                }
+                self.check_grouped(&column.expr, &select.group_by)?;
+            }
+            if let Some(having) = &select.having {
+                self.check_grouped(having, &select.group_by)?;
+            }
+            for (order, by_alias) in select.order_by.iter().zip(by_alias) {
+                if !by_alias {
+                    self.check_grouped(&order.expr, &select.group_by)?;
+                }
+            }
+        }
+
+        Ok(columns)
+    }
+
+    /// Checks that every column used outside an aggregate is grouped on.
+    fn check_grouped(&self, expr: &Expr, group_by: &[Expr]) -> Result<(), Error> {
+        if group_by.contains(expr) {
+            return Ok(());
+        }
+        match expr {
+            Expr::Column(col) => {
+                let local = self.scopes.last().is_some_and(|scope| {
+                    scope.bindings.iter().any(|b| Some(&b.name) == col.table.as_ref())
+                });
+                if local {
+                    Err(type_error(
+                        col.span,
+                        format!(
+                            "column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                            col.name
+                        ),
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            Expr::Function { name, .. } if is_aggregate(name) => Ok(()),
+            Expr::Function { args, .. } => args.iter().try_for_each(|a| self.check_grouped(a, group_by)),
+            Expr::Binary { left, right, .. } => {
+                self.check_grouped(left, group_by)?;
+                self.check_grouped(right, group_by)
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } | Expr::Field { expr, .. } => {
+                self.check_grouped(expr, group_by)
+            }
+            Expr::Case { operand, when_clauses, else_result } => {
+                for expr in operand.iter().chain(else_result.iter()) {
+                    self.check_grouped(expr, group_by)?;
+                }
+                for (condition, result) in when_clauses {
+                    self.check_grouped(condition, group_by)?;
+                    self.check_grouped(result, group_by)?;
+                }
+                Ok(())
+            }
+            Expr::List(items) => items.iter().try_for_each(|i| self.check_grouped(i, group_by)),
+            Expr::Literal(_) | Expr::Wildcard { .. } | Expr::Exists(_) | Expr::Subquery(_) => Ok(()),
+        }
+    }
+
+    fn enter_clause(&mut self, clause: &'static str, aggregates_allowed: bool) {
+        self.clause = clause;
+        self.aggregates_allowed = aggregates_allowed;
+    }
+
+    /// Analyzes a filter such as WHERE, HAVING or ON, which must be boolean.
+    fn condition(&mut self, expr: &mut Expr, clause: &'static str) -> Result<(), Error> {
+        self.enter_clause(clause, clause == "HAVING");
+        let ty = self.expr(expr, Span::default())?;
+        self.expect_boolean(&ty, expr_span(expr), &format!("{} clause", clause))
+    }
+
+    fn expect_boolean(&self, ty: &Type, span: Span, what: &str) -> Result<(), Error> {
+        match ty {
+            None | Some(DataType::Boolean) => Ok(()),
+            Some(ty) => Err(type_error(
+                span,
+                format!("{} must be a boolean expression, got {}", what, ty),
+            )),
+        }
+    }
+
+    fn subquery(&mut self, query: &mut SelectStatement) -> Result<Vec<OutputColumn>, Error> {
+        let saved = (self.clause, self.aggregates_allowed, self.in_aggregate);
+        self.in_aggregate = false;
+        let result = self.select(query);
+        (self.clause, self.aggregates_allowed, self.in_aggregate) = saved;
+        result
+    }
+
+    fn scalar_subquery(&mut self, query: &mut SelectStatement, span: Span) -> Result<Type, Error> {
+        let mut columns = self.subquery(query)?;
+        if columns.len() != 1 {
+            return Err(type_error(
+                span,
+                format!("subquery must return one column, got {}", columns.len()),
+            ));
+        }
+        Ok(columns.pop().unwrap().data_type)
+    }
+
+    fn expr(&mut self, expr: &mut Expr, span: Span) -> Result<Type, Error> {
+        if matches!(expr, Expr::Column(_) | Expr::Field { .. }) {
+            self.split_fields(expr);
+        }
+        match expr {
+            Expr::Literal(value) => literal_type(value).map_err(|m| type_error(span, m)),
+            Expr::Column(col) => self.resolve(col),
+            Expr::Field { expr: inner, step, span } => {
+                let span = *span;
+                match self.expr(inner, span)? {
+                    // Struct and array values are stored as JSON; what a
+                    // field holds is only known at run time, so like NULL it
+                    // is compared as it is.
+                    None | Some(DataType::Json) => Ok(None),
+                    Some(ty) => Err(type_error(span, format!("cannot take {} of {}", step, ty))),
+                }
+            }
+            Expr::Wildcard { .. } => Err(type_error(
+                span,
+                "* is only allowed in a select list or COUNT(*)",
+            )),
+            Expr::Binary { left, op, right, span } => {
+                let span = *span;
+                self.binary(left, op, right, span)
+            }
+            Expr::Unary { op, expr: inner, span } => {
+                let span = *span;
+                let ty = self.expr(inner, span)?;
+                match op {
+                    UnaryOp::Not => {
+                        self.expect_boolean(&ty, span, "operand of NOT")?;
+                        Ok(Some(DataType::Boolean))
+                    }
+                    UnaryOp::IsNull | UnaryOp::IsNotNull => Ok(Some(DataType::Boolean)),
+                    UnaryOp::Negative => match &ty {
+                        None | Some(DataType::Interval) => Ok(ty),
+                        Some(t) if matches!(int_info(t), Some((false, _))) => {
+                            let target = promote_numeric(&DataType::TinyInt, t);
+                            cast_to(inner, &ty, &target, span)?;
+                            Ok(Some(target))
+                        }
+                        Some(t) if t.is_numeric() => Ok(ty),
+                        Some(t) => Err(type_error(span, format!("cannot negate {}", t))),
+                    },
+                }
+            }
+            Expr::Function { name, args, distinct, span } => {
+                let span = *span;
+                self.function(name, args, *distinct, span)
+            }
+            Expr::Cast { expr: inner, data_type, try_cast } => {
+                let span = match expr_span(inner) {
+                    inner_span if inner_span.is_known() => inner_span,
+                    _ => span,
+                };
+                let ty = self.expr(inner, span)?;
+                if let Some(from) = &ty {
+                    if !cast::can_cast(from, data_type) {
+                        return Err(type_error(
+                            span,
+                            format!("cannot cast {} to {}", from, data_type),
+                        ));
+                    }
+                }
+                if !*try_cast {
+                    if let Some(value) = literal_value(inner) {
+                        if let Err(Error::Type(message)) = cast::cast(&value, data_type) {
+                            return Err(type_error(span, message));
+                        }
+                    }
+                }
+                Ok(Some(data_type.clone()))
+            }
+            Expr::Case { operand, when_clauses, else_result } => {
+                self.case(operand, when_clauses, else_result, span)
+            }
+            Expr::Exists(query) => {
+                self.subquery(query)?;
+                Ok(Some(DataType::Boolean))
+            }
+            Expr::Subquery(query) => self.scalar_subquery(query, span),
+            Expr::List(_) => Err(type_error(span, "unexpected expression list")),
+        }
+    }
+
+    /// Finds the common type of `exprs` and casts each one to it.
+    fn unify(&self, exprs: Vec<(&mut Expr, Type)>, span: Span, what: &str) -> Result<Type, Error> {
+        let mut common: Option<Operand> = None;
+        for (expr, ty) in &exprs {
+            let Some(ty) = ty else { continue };
+            let operand = Operand { ty: ty.clone(), literal: literal_value(expr) };
+            common = Some(match common {
+                None => operand,
+                Some(current) => match common_type(&current, &operand) {
+                    Some(ty) => Operand {
+                        ty,
+                        literal: current.literal.and(operand.literal),
+                    },
+                    None => {
+                        return Err(type_error(
+                            span,
+                            format!("cannot {} {} with {}", what, current.ty, operand.ty),
+                        ))
+                    }
+                },
+            });
+        }
+        let Some(common) = common else { return Ok(None) };
+        for (expr, ty) in exprs {
+            cast_to(expr, &ty, &common.ty, span)?;
+        }
+        Ok(Some(common.ty))
+    }
+
+    fn binary(&mut self, left: &mut Expr, op: &BinaryOp, right: &mut Expr, span: Span) -> Result<Type, Error> {
+        use BinaryOp::*;
+        match op {
+            And | Or => {
+                for side in [left, right] {
+                    let ty = self.expr(side, span)?;
+                    self.expect_boolean(&ty, expr_span(side), &format!("operand of {}", op))?;
+                }
+                Ok(Some(DataType::Boolean))
+            }
+            Eq | NotEq | Lt | Gt | LtEq | GtEq => {
+                let lt = self.expr(left, span)?;
+                let rt = self.expr(right, span)?;
+                for ty in [&lt, &rt].into_iter().flatten() {
+                    if matches!(ty, DataType::Json) && !matches!(op, Eq | NotEq) {
+                        return Err(type_error(span, format!("cannot order JSON values with {}", op)));
+                    }
+                }
+                self.unify(vec![(left, lt), (right, rt)], span, "compare")?;
+                Ok(Some(DataType::Boolean))
+            }
+            Add | Subtract | Multiply | Divide | Modulo => {
+                let lt = self.expr(left, span)?;
+                let rt = self.expr(right, span)?;
+                self.arithmetic(left, lt, op, right, rt, span)
+            }
+            Like | NotLike => {
+                let lt = self.expr(left, span)?;
+                self.expect_string(&lt, span, "LIKE")?;
+                let patterns: Vec<&mut Expr> = match right {
+                    Expr::List(items) => items.iter_mut().collect(),
+                    pattern => vec![pattern],
+                };
+                for pattern in patterns {
+                    let ty = self.expr(pattern, span)?;
+                    self.expect_string(&ty, span, "LIKE")?;
+                }
+                Ok(Some(DataType::Boolean))
+            }
+            In | NotIn => {
+                let lt = self.expr(left, span)?;
+                match right {
+                    Expr::List(items) => {
+                        let mut operands = vec![(left, lt)];
+                        for item in items.iter_mut() {
+                            let ty = self.expr(item, span)?;
+                            operands.push((item, ty));
+                        }
+                        self.unify(operands, span, "compare")?;
+                    }
+                    Expr::Subquery(query) => {
+                        let rt = self.scalar_subquery(query, span)?;
+                        let target = match (&lt, &rt) {
+                            (Some(l), Some(r)) => {
+                                let l_op = Operand { ty: l.clone(), literal: literal_value(left) };
+                                let r_op = Operand { ty: r.clone(), literal: None };
+                                common_type(&l_op, &r_op).ok_or_else(|| {
+                                    type_error(span, format!("cannot compare {} with {}", l, r))
+                                })?
+                            }
+                            _ => return Ok(Some(DataType::Boolean)),
+                        };
+                        cast_to(left, &lt, &target, span)?;
+                    }
+                    other => {
+                        return Err(type_error(
+                            span,
+                            format!("IN expects a list or subquery, got {}", other),
+                        ))
+                    }
+                }
+                Ok(Some(DataType::Boolean))
+            }
+        }
+    }
+
+    fn arithmetic(
+        &self,
+        left: &mut Expr,
+        lt: Type,
+        op: &BinaryOp,
+        right: &mut Expr,
+        rt: Type,
+        span: Span,
+    ) -> Result<Type, Error> {
+        use BinaryOp::*;
+        use DataType::*;
+        let invalid = |l: &Type, r: &Type| {
+            type_error(
+                span,
+                format!("cannot apply {} to {} and {}", op, type_label(l), type_label(r)),
+            )
+        };
+        let (l, r) = match (&lt, &rt) {
+            (Some(l), Some(r)) => (l, r),
+            (Some(t), None) | (None, Some(t)) => {
+                return if t.is_numeric() || t.is_temporal() || *t == Interval {
+                    Ok(None)
+                } else {
+                    Err(invalid(&lt, &rt))
+                };
+            }
+            (None, None) => return Ok(None),
+        };
+
+        if l.is_numeric() && r.is_numeric() {
+            return self.unify(vec![(left, lt), (right, rt)], span, "combine");
+        }
+
+        // An interval written as a string, as in `created + '1 day'`.
+        let string_interval = |t: &DataType, e: &Expr| {
+            t.is_string() && matches!(e, Expr::Literal(Value::String(_)))
+        };
+        let r = if (l.is_temporal() || *l == Interval) && string_interval(r, right) {
+            cast_to(right, &rt, &Interval, span)?;
+            &Interval
+        } else {
+            r
+        };
+
+        let result = match (op, l, r) {
+            (Add | Subtract, t, Interval) if t.is_temporal() => t.clone(),
+            (Add, Interval, t) if t.is_temporal() => t.clone(),
+            (Add | Subtract, Interval, Interval) => Interval,
+            (Subtract, DateTime | Timestamp, DateTime | Timestamp) => Interval,
+            (Subtract, Date, Date) => BigInt,
+            (Add | Subtract, Date, i) if i.is_integer() => Date,
+            (Add, i, Date) if i.is_integer() => Date,
+            _ => return Err(invalid(&lt, &rt)),
+        };
+        Ok(Some(result))
+    }
+
+    fn expect_string(&self, ty: &Type, span: Span, what: &str) -> Result<(), Error> {
+        match ty {
+            Some(t) if !t.is_string() => Err(type_error(
+                span,
+                format!("{} requires string operands, got {}", what, t),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn case(
+        &mut self,
+        operand: &mut Option<Box<Expr>>,
+        when_clauses: &mut [(Expr, Expr)],
+        else_result: &mut Option<Box<Expr>>,
+        span: Span,
+    ) -> Result<Type, Error> {
+        let mut conditions = Vec::new();
+        let mut results = Vec::new();
+        let operand_type = match operand {
+            Some(operand) => Some(self.expr(operand, span)?),
+            None => None,
+        };
+        for (condition, result) in when_clauses.iter_mut() {
+            let ty = self.expr(condition, span)?;
+            if operand_type.is_none() {
+                self.expect_boolean(&ty, expr_span(condition), "WHEN condition")?;
+            }
+            conditions.push((condition, ty));
+            let ty = self.expr(result, span)?;
+            results.push((result, ty));
+        }
+        if let Some(else_result) = else_result {
+            let ty = self.expr(else_result, span)?;
+            results.push((else_result, ty));
+        }
+        if let (Some(operand), Some(ty)) = (operand, operand_type) {
+            conditions.insert(0, (operand, ty));
+            self.unify(conditions, span, "compare")?;
+        }
+        self.unify(results, span, "combine CASE results of type")
+    }
+
+    fn function(&mut self, name: &str, args: &mut [Expr], distinct: bool, span: Span) -> Result<Type, Error> {
+        let upper = name.to_uppercase();
+        if is_aggregate(&upper) {
+            if !self.aggregates_allowed {
+                return Err(type_error(
+                    span,
+                    format!("aggregate function {} is not allowed in {}", upper, self.clause),
+                ));
+            }
+            if self.in_aggregate {
+                return Err(type_error(span, "aggregate function calls cannot be nested"));
+            }
+            self.saw_aggregate = true;
+            self.in_aggregate = true;
+            let result = self.aggregate(&upper, args, distinct, span);
+            self.in_aggregate = false;
+            return result;
+        }
+        if distinct {
+            return Err(type_error(
+                span,
+                format!("DISTINCT is not allowed in non-aggregate function {}", upper),
+            ));
+        }
+
+        let arity = |min: usize, max: usize| {
+            if args.len() < min || args.len() > max {
+                Err(type_error(
+                    span,
+                    format!("wrong number of arguments to {}: {}", upper, args.len()),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        match upper.as_str() {
+            "NOW" => {
+                arity(0, 0)?;
+                Ok(Some(DataType::Timestamp))
+            }
+            "UPPER" | "LOWER" => {
+                arity(1, 1)?;
+                let ty = self.expr(&mut args[0], span)?;
+                self.expect_string(&ty, span, &upper)?;
+                Ok(ty.map(|_| DataType::Text))
+            }
+            "LENGTH" => {
+                arity(1, 1)?;
+                match self.expr(&mut args[0], span)? {
+                    Some(t) if !t.is_string() && !matches!(t, DataType::Binary(_)) => Err(type_error(
+                        span,
+                        format!("LENGTH requires a string or binary argument, got {}", t),
+                    )),
+                    _ => Ok(Some(DataType::BigInt)),
+                }
+            }
+            "ABS" | "ROUND" => {
+                if upper == "ABS" { arity(1, 1)? } else { arity(1, 2)? }
+                let ty = self.expr(&mut args[0], span)?;
+                self.expect_numeric(&upper, &ty, span)?;
+                if let Some(digits) = args.get_mut(1) {
+                    match self.expr(digits, span)? {
+                        Some(t) if !t.is_integer() => {
+                            return Err(type_error(
+                                span,
+                                format!("ROUND requires an integer number of digits, got {}", t),
+                            ))
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(ty)
+            }
+            "COALESCE" => {
+                arity(1, usize::MAX)?;
+                let mut operands = Vec::new();
+                for arg in args.iter_mut() {
+                    let ty = self.expr(arg, span)?;
+                    operands.push((arg, ty));
+                }
+                self.unify(operands, span, "combine COALESCE arguments of type")
+            }
+            _ => Err(type_error(span, format!("unknown function {}", name))),
+        }
+    }
+
+    fn expect_numeric(&self, function: &str, ty: &Type, span: Span) -> Result<(), Error> {
+        match ty {
+            Some(t) if !t.is_numeric() => Err(type_error(
+                span,
+                format!("{} requires a numeric argument, got {}", function, t),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn aggregate(&mut self, name: &str, args: &mut [Expr], distinct: bool, span: Span) -> Result<Type, Error> {
+        if args.len() != 1 {
+            return Err(type_error(
+                span,
+                format!("{} takes exactly one argument, got {}", name, args.len()),
+            ));
+        }
+        if let Expr::Wildcard { table: None } = &args[0] {
+            if name == "COUNT" && !distinct {
+                return Ok(Some(DataType::BigInt));
+            }
+        }
+        let ty = self.expr(&mut args[0], span)?;
+        match name {
+            "COUNT" => Ok(Some(DataType::BigInt)),
+            "SUM" | "AVG" => {
+                self.expect_numeric(name, &ty, span)?;
+                Ok(ty.map(|t| match (name, t) {
+                    ("SUM", t) if t.is_integer() => DataType::BigInt,
+                    (_, DataType::Real | DataType::Float(_)) => DataType::Float(None),
+                    _ => DataType::Decimal(None),
+                }))
+            }
+            _ => Ok(ty),
+        }
+    }
+
+    /// Analyzes a value stored into `column`, converting it to the column
+    /// type.
+    fn assign(&mut self, expr: &mut Expr, column: &ColumnSchema) -> Result<(), Error> {
+        self.enter_clause("a column value", false);
+        let ty = self.expr(expr, Span::default())?;
+        let span = expr_span(expr);
+        let target = &column.data_type;
+        match &ty {
+            None if !column.nullable => Err(type_error(
+                span,
+                format!("column {} cannot be NULL", column.name),
+            )),
+            None => Ok(()),
+            Some(t) => {
+                let assignable = t.same_family(target)
+                    || (t.is_numeric() && target.is_numeric())
+                    || literal_value(expr).is_some_and(|v| adapt_literal(&v, t, target).is_some())
+                    || (target.is_string() && matches!(t, DataType::Uuid | DataType::Json));
+                if !assignable {
+                    return Err(type_error(
+                        span,
+                        format!(
+                            "cannot assign {} to column {} of type {}",
+                            t, column.name, target
+                        ),
+                    ));
+                }
+                cast_to(expr, &ty, target, span)
+            }
+        }
+    }
+
+    fn insert(&mut self, insert: &mut InsertStatement) -> Result<(), Error> {
+        let schema = self.table(&insert.table)?;
+        let mut targets = Vec::new();
+        if insert.columns.is_empty() {
+            targets.extend(0..schema.columns.len());
+        } else {
+            for name in &insert.columns {
+                let index = schema.column_index(name).ok_or_else(|| {
+                    Error::Type(format!("column {} does not exist in table {}", name, schema.name))
+                })?;
+                if targets.contains(&index) {
+                    return Err(Error::Type(format!("column {} specified more than once", name)));
+                }
+                targets.push(index);
+            }
+        }
+
+        for column in schema.columns.iter().enumerate().filter(|(i, _)| !targets.contains(i)).map(|(_, c)| c) {
+            if !column.nullable && column.default.is_none() {
+                return Err(Error::Type(format!(
+                    "column {} is NOT NULL and has no default value",
+                    column.name
+                )));
+            }
+        }
+
+        for row in &mut insert.values {
+            if row.len() != targets.len() {
+                return Err(Error::Type(format!(
+                    "INSERT has {} values but {} target columns",
+                    row.len(),
+                    targets.len()
+                )));
+            }
+            for (value, &index) in row.iter_mut().zip(&targets) {
+                self.assign(value, &schema.columns[index])?;
+            }
+        }
+
+        if let Some(sets) = &mut insert.on_duplicate {
+            self.scopes.push(Scope::default());
+            let result = self.bind(&insert.table).and_then(|_| self.assignments(sets, &schema));
+            self.scopes.pop();
+            result?;
+        }
+        Ok(())
+    }
+
+    fn assignments(&mut self, sets: &mut [(String, Expr)], schema: &TableSchema) -> Result<(), Error> {
+        for (name, value) in sets {
+            let column = schema.column(name).ok_or_else(|| {
+                Error::Type(format!("column {} does not exist in table {}", name, schema.name))
+            })?;
+            self.assign(value, column)?;
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, update: &mut UpdateStatement) -> Result<(), Error> {
+        let schema = self.table(&update.table)?;
+        self.scopes.push(Scope::default());
+        let result = self.bind(&update.table).and_then(|_| {
+            self.assignments(&mut update.sets, &schema)?;
+            self.filter_and_order(&mut update.where_clause, &mut update.order_by)
+        });
+        self.scopes.pop();
+        result
+    }
+
+    fn delete(&mut self, delete: &mut DeleteStatement) -> Result<(), Error> {
+        self.scopes.push(Scope::default());
+        let result = self
+            .bind(&delete.table)
+            .and_then(|_| self.filter_and_order(&mut delete.where_clause, &mut delete.order_by));
+        self.scopes.pop();
+        result
+    }
+
+    fn filter_and_order(&mut self, filter: &mut Option<Expr>, order_by: &mut [OrderByExpr]) -> Result<(), Error> {
+        if let Some(expr) = filter {
+            self.condition(expr, "WHERE")?;
+        }
+        self.enter_clause("ORDER BY", false);
+        for order in order_by {
+            self.expr(&mut order.expr, Span::default())?;
+        }
+        Ok(())
+    }
+
+    fn create(&mut self, create: &mut CreateStatement) -> Result<(), Error> {
+        if !create.if_not_exists && self.table(&create.table).is_ok() {
+            return Err(Error::Type(format!("table {} already exists", create.table.name)));
+        }
+        for (i, column) in create.columns.iter().enumerate() {
+            if create.columns[..i].iter().any(|c| c.name.eq_ignore_ascii_case(&column.name)) {
+                return Err(Error::Type(format!("column {} specified more than once", column.name)));
+            }
+        }
+
+        let schema = TableSchema::from_create(create);
+        for column in &mut create.columns {
+            self.column_def(column, &schema)?;
+        }
+        for constraint in &mut create.constraints {
+            self.table_constraint(constraint, &schema)?;
+        }
+        Ok(())
+    }
+
+    fn create_index(&mut self, create: &mut CreateIndexStatement) -> Result<(), Error> {
+        let schema = self.table(&create.table)?;
+        let exists = self.catalog.indexes(&schema.name).iter().any(|i| i.name.eq_ignore_ascii_case(&create.name));
+        if exists && !create.if_not_exists {
+            return Err(Error::Type(format!("index {} already exists", create.name)));
+        }
+        for path in &mut create.columns {
+            let column = schema.column(&path.column).ok_or_else(|| {
+                Error::Type(format!("column {} does not exist in table {}", path.column, schema.name))
+            })?;
+            if !path.steps.is_empty() && column.data_type != DataType::Json {
+                return Err(Error::Type(format!(
+                    "cannot index {}: column {} is {}, not a struct or array",
+                    path, column.name, column.data_type
+                )));
+            }
+            path.column = column.name.clone();
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with the columns of `schema` in scope, for CHECK constraints.
+    fn in_table_scope<T>(
+        &mut self,
+        schema: &TableSchema,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.scopes.push(Scope {
+            bindings: vec![Binding { name: schema.name.clone(), schema: schema.clone() }],
+            using: Vec::new(),
+        });
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn column_def(&mut self, def: &mut ColumnDef, schema: &TableSchema) -> Result<(), Error> {
+        self.check_data_type(&def.data_type)?;
+        let column = ColumnSchema::from_def(def);
+        for constraint in &mut def.constraints {
+            match constraint {
+                ColumnConstraint::Default(expr) => {
+                    let scopes = std::mem::take(&mut self.scopes);
+                    let result = self.assign(expr, &ColumnSchema { nullable: true, ..column.clone() });
+                    self.scopes = scopes;
+                    result?;
+                }
+                ColumnConstraint::Check(expr) => {
+                    self.in_table_scope(schema, |a| a.condition(expr, "CHECK"))?;
+                }
+                ColumnConstraint::ForeignKey { table, column: ref_column, .. } => {
+                    self.check_reference(table, std::slice::from_ref(ref_column), schema)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn check_data_type(&self, data_type: &DataType) -> Result<(), Error> {
+        match custom_type_name(data_type) {
+            Some(name) if self.catalog.custom_type(name).is_none() => {
+                Err(Error::Type(format!("type {} does not exist", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn create_type(&self, create: &CreateTypeStatement) -> Result<(), Error> {
+        if !create.if_not_exists && self.catalog.custom_type(&create.name).is_some() {
+            return Err(Error::Type(format!("type {} already exists", create.name)));
+        }
+        let types: Vec<&DataType> = match &create.definition {
+            TypeDefinition::Struct(fields) => fields.iter().map(|(_, ty)| ty).collect(),
+            TypeDefinition::Enum(variants) => variants.iter().filter_map(|(_, ty)| ty.as_ref()).collect(),
+        };
+        // A type using itself is for the catalog to reject.
+        let own = |ty: &&DataType| custom_type_name(ty).is_some_and(|name| name.eq_ignore_ascii_case(&create.name));
+        types.into_iter().filter(|ty| !own(ty)).try_for_each(|ty| self.check_data_type(ty))
+    }
+
+    fn alter_type(&mut self, alter: &mut AlterTypeStatement) -> Result<(), Error> {
+        let ty = self
+            .catalog
+            .custom_type(&alter.name)
+            .ok_or_else(|| Error::Type(format!("type {} does not exist", alter.name)))?;
+        let has_member = |name: &str| ty.member_names().iter().any(|member| member.eq_ignore_ascii_case(name));
+        let (kind, member) = match &mut alter.action {
+            AlterTypeAction::AddField { name, data_type, default } => {
+                self.check_data_type(data_type)?;
+                if let Some(default) = default {
+                    let field = ColumnSchema::new(name.clone(), data_type.clone());
+                    let scopes = std::mem::take(&mut self.scopes);
+                    let result = self.assign(default, &field);
+                    self.scopes = scopes;
+                    result?;
+                }
+                if has_member(name) {
+                    return Err(Error::Type(format!("type {} already has a member {}", ty.name, name)));
+                }
+                ("STRUCT", None)
+            }
+            AlterTypeAction::AddVariant { name, payload } => {
+                if let Some(payload) = payload {
+                    self.check_data_type(payload)?;
+                }
+                if has_member(name) {
+                    return Err(Error::Type(format!("type {} already has a member {}", ty.name, name)));
+                }
+                ("ENUM", None)
+            }
+            AlterTypeAction::RenameField(from, to) => {
+                if has_member(to) && !from.eq_ignore_ascii_case(to) {
+                    return Err(Error::Type(format!("type {} already has a member {}", ty.name, to)));
+                }
+                ("STRUCT", Some(from.clone()))
+            }
+        };
+        if ty.kind_name() != kind {
+            return Err(Error::Type(format!("type {} is not a {}", ty.name, kind.to_lowercase())));
+        }
+        match member {
+            Some(name) if !has_member(&name) => {
+                Err(Error::Type(format!("type {} has no field {}", ty.name, name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn table_constraint(&mut self, constraint: &mut TableConstraint, schema: &TableSchema) -> Result<(), Error> {
+        let check_columns = |columns: &[String]| {
+            for name in columns {
+                if schema.column(name).is_none() {
+                    return Err(Error::Type(format!(
+                        "column {} in constraint does not exist in table {}",
+                        name, schema.name
+                    )));
+                }
+            }
+            Ok(())
+        };
+        match constraint {
+            TableConstraint::PrimaryKey { columns, .. } | TableConstraint::Unique { columns, .. } => {
+                check_columns(columns)
+            }
+            TableConstraint::ForeignKey { columns, ref_table, ref_columns, .. } => {
+                check_columns(columns)?;
+                if columns.len() != ref_columns.len() {
+                    return Err(Error::Type(format!(
+                        "foreign key has {} columns but references {}",
+                        columns.len(),
+                        ref_columns.len()
+                    )));
+                }
+                self.check_reference(ref_table, ref_columns, schema)
+            }
+            TableConstraint::Check { expr, .. } => {
+                self.in_table_scope(schema, |a| a.condition(expr, "CHECK"))
+            }
+        }
+    }
+
+    fn check_reference(&self, table: &str, columns: &[String], current: &TableSchema) -> Result<(), Error> {
+        let referenced = if table.eq_ignore_ascii_case(&current.name) {
+            current.clone()
+        } else {
+            self.catalog
+                .table(table)
+                .ok_or_else(|| Error::Type(format!("referenced table {} does not exist", table)))?
+        };
+        for name in columns {
+            if referenced.column(name).is_none() {
+                return Err(Error::Type(format!(
+                    "referenced column {}.{} does not exist",
+                    table, name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn alter(&mut self, alter: &mut AlterStatement) -> Result<(), Error> {
+        let mut schema = self.table(&alter.table)?;
+        let missing = |schema: &TableSchema, name: &str| {
+            Error::Type(format!("column {} does not exist in table {}", name, schema.name))
+        };
+        let exists = |name: &str| Error::Type(format!("column {} already exists", name));
+        for action in &mut alter.actions {
+            match action {
+                AlterAction::AddColumn(def) => {
+                    if schema.column(&def.name).is_some() {
+                        return Err(exists(&def.name));
+                    }
+                    schema.columns.push(ColumnSchema::from_def(def));
+                    self.column_def(def, &schema)?;
+                }
+                AlterAction::DropColumn(name) => {
+                    let index = schema.column_index(name).ok_or_else(|| missing(&schema, name))?;
+                    schema.columns.remove(index);
+                }
+                AlterAction::ModifyColumn(def) => {
+                    let index = schema.column_index(&def.name).ok_or_else(|| missing(&schema, &def.name))?;
+                    schema.columns[index] = ColumnSchema::from_def(def);
+                    self.column_def(def, &schema)?;
+                }
+                AlterAction::RenameColumn(from, to) => {
+                    let index = schema.column_index(from).ok_or_else(|| missing(&schema, from))?;
+                    if schema.column(to).is_some() {
+                        return Err(exists(to));
+                    }
+                    schema.columns[index].name = to.clone();
+                }
+                AlterAction::AddConstraint(constraint) => {
+                    self.table_constraint(constraint, &schema)?;
+                }
+                AlterAction::DropConstraint(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::MemoryCatalog;
+    use crate::parser::parse_sql;
+
+    fn catalog() -> MemoryCatalog {
+        let mut catalog = MemoryCatalog::new();
+        catalog
+            .create_table(TableSchema::new(
+                "users",
+                vec![
+                    ColumnSchema::new("id", DataType::BigInt).primary_key(),
+                    ColumnSchema::new("name", DataType::Varchar(Some(64))).not_null(),
+                    ColumnSchema::new("age", DataType::TinyInt),
+                    ColumnSchema::new("born", DataType::Date),
+                ],
+            ))
+            .unwrap();
+        catalog
+            .create_table(TableSchema::new(
+                "orders",
+                vec![
+                    ColumnSchema::new("id", DataType::BigInt).primary_key(),
+                    ColumnSchema::new("user_id", DataType::BigInt),
+                    ColumnSchema::new("total", DataType::Decimal(Some((10, 2)))),
+                ],
+            ))
+            .unwrap();
+        catalog
+            .create_table(TableSchema::new(
+                "contacts",
+                vec![
+                    ColumnSchema::new("id", DataType::BigInt).primary_key(),
+                    ColumnSchema::new("phone", DataType::Json),
+                    ColumnSchema::new("tags", DataType::Json),
+                ],
+            ))
+            .unwrap();
+        catalog
+    }
+
+    fn analyze(sql: &str) -> Result<AnalyzedStatement, Error> {
+        let catalog = catalog();
+        Analyzer::new(&catalog).analyze(&parse_sql(sql).unwrap())
+    }
+
+    fn type_error_message(sql: &str) -> String {
+        match analyze(sql) {
+            Err(Error::Type(message)) => message,
+            other => panic!("Expected type error for {}, got {:?}", sql, other),
+        }
+    }
+
+    #[test]
+    fn test_resolution_and_implicit_casts() {
+        let analyzed = analyze(
+            "SELECT name, age + 1.5 AS older FROM users WHERE age > 18 AND born < '2000-01-01'",
+        )
+        .unwrap();
+        assert_eq!(
+            analyzed.columns,
+            vec![
+                OutputColumn { name: "name".into(), data_type: Some(DataType::Varchar(Some(64))) },
+                OutputColumn { name: "older".into(), data_type: Some(DataType::Decimal(None)) },
+            ]
+        );
+        let Statement::Select(select) = analyzed.statement else { panic!("Expected SELECT") };
+        assert_eq!(
+            select.where_clause.unwrap().to_string(),
+            "((age > CAST(18 AS TINYINT)) AND (born < CAST('2000-01-01' AS DATE)))"
+        );
+        assert_eq!(select.columns[1].expr.to_string(), "(CAST(age AS DECIMAL) + 1.5)");
+        let Expr::Column(col) = &select.columns[0].expr else { panic!("Expected column") };
+        assert_eq!(col.table.as_deref(), Some("users"));
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(
+            type_error_message("SELECT id FROM users\nWHERE name > 5"),
+            "cannot compare VARCHAR(64) with BIGINT at line 2, column 12"
+        );
+        assert!(type_error_message("SELECT SUM('abc') FROM users")
+            .starts_with("SUM requires a numeric argument, got TEXT"));
+        assert!(type_error_message("SELECT id FROM users WHERE age").contains("must be a boolean"));
+        assert!(type_error_message("SELECT nope FROM users").starts_with("column nope does not exist"));
+        assert!(type_error_message("SELECT id FROM missing").contains("does not exist"));
+    }
+
+    #[test]
+    fn test_ambiguous_columns() {
+        assert!(type_error_message("SELECT id FROM users u JOIN orders o ON u.id = o.user_id")
+            .starts_with("column reference id is ambiguous"));
+        analyze("SELECT u.id, total FROM users u JOIN orders o ON u.id = o.user_id").unwrap();
+        analyze("SELECT id FROM users JOIN orders USING (id)").unwrap();
+    }
+
+    #[test]
+    fn test_aggregates() {
+        assert!(type_error_message("SELECT id FROM users WHERE COUNT(*) > 1")
+            .starts_with("aggregate function COUNT is not allowed in WHERE"));
+        assert!(type_error_message("SELECT name, COUNT(*) FROM users")
+            .starts_with("column name must appear in the GROUP BY clause"));
+        assert!(type_error_message("SELECT MAX(SUM(age)) FROM users").contains("cannot be nested"));
+        let analyzed = analyze(
+            "SELECT u.name, COUNT(*) AS n, SUM(o.total) FROM users u JOIN orders o ON u.id = o.user_id \
+             GROUP BY u.name HAVING COUNT(*) > 5 ORDER BY n DESC",
+        )
+        .unwrap();
+        assert_eq!(analyzed.columns[1].data_type, Some(DataType::BigInt));
+        assert_eq!(analyzed.columns[2].data_type, Some(DataType::Decimal(None)));
+    }
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(
+            type_error_message("SELECT CAST(born AS BOOLEAN) FROM users"),
+            "cannot cast DATE to BOOLEAN at line 1, column 13"
+        );
+        assert!(type_error_message("SELECT CAST('abc' AS INT) FROM users")
+            .starts_with("cannot cast 'abc' to INTEGER: invalid integer"));
+        analyze("SELECT TRY_CAST('abc' AS INT), age::TEXT FROM users").unwrap();
+        assert!(type_error_message("SELECT id FROM users WHERE born < '2024-13-01'")
+            .contains("invalid date"));
+        let long = "x".repeat(65);
+        assert!(type_error_message(&format!("INSERT INTO users (id, name) VALUES (1, '{}')", long))
+            .contains("value too long for VARCHAR(64)"));
+        let analyzed = analyze("UPDATE users SET name = 'bob'").unwrap();
+        let Statement::Update(update) = analyzed.statement else { panic!("Expected UPDATE") };
+        assert_eq!(update.sets[0].1.to_string(), "CAST('bob' AS VARCHAR(64))");
+    }
+
+    #[test]
+    fn test_dml_and_ddl() {
+        let analyzed = analyze("INSERT INTO users (id, name, age) VALUES (1, 'a', 30)").unwrap();
+        let Statement::Insert(insert) = analyzed.statement else { panic!("Expected INSERT") };
+        assert_eq!(insert.values[0][2].to_string(), "CAST(30 AS TINYINT)");
+
+        assert!(type_error_message("INSERT INTO users (id) VALUES (1)").contains("NOT NULL"));
+        assert!(type_error_message("INSERT INTO users (id, name) VALUES (1)").contains("1 values but 2"));
+        assert!(type_error_message("INSERT INTO users (id, name) VALUES (1, NULL)").contains("cannot be NULL"));
+        assert!(type_error_message("UPDATE users SET age = 'old'").starts_with("cannot assign TEXT"));
+        analyze("DELETE FROM users WHERE born < NOW()").unwrap();
+        analyze("DELETE FROM users WHERE age IS NULL").unwrap();
+
+        assert!(type_error_message("CREATE TABLE t (a INT, A TEXT)").contains("more than once"));
+        assert!(type_error_message("CREATE TABLE t (a INT DEFAULT 'x')").starts_with("cannot assign TEXT"));
+        assert!(type_error_message("CREATE TABLE t (a INT CHECK (a))").contains("must be a boolean"));
+        analyze("CREATE TABLE t (a INT CHECK (a > 0), b BIGINT REFERENCES users (id))").unwrap();
+        assert!(type_error_message("ALTER TABLE users DROP COLUMN nope").contains("does not exist"));
+        analyze("ALTER TABLE users RENAME COLUMN name TO full_name").unwrap();
+    }
+
+    #[test]
+    fn test_fields_of_composite_columns() {
+        let analyzed = analyze(
+            "SELECT contacts.phone.country_code, tags[0] FROM contacts \
+             WHERE phone.country_code = 44 AND c.phone.area_code IS NULL",
+        );
+        assert!(analyzed.is_err(), "c is not a table in scope");
+        let analyzed = analyze(
+            "SELECT contacts.phone.country_code, tags[0] FROM contacts WHERE phone.country_code = 44",
+        )
+        .unwrap();
+        assert_eq!(analyzed.columns[0].name, "country_code");
+        assert_eq!(analyzed.columns[1].data_type, None);
+        let Statement::Select(select) = analyzed.statement else { panic!("Expected SELECT") };
+        let Some(Expr::Binary { left, right, .. }) = select.where_clause else { panic!("Expected comparison") };
+        let Expr::Field { expr, .. } = *left else { panic!("Expected field") };
+        assert!(matches!(*expr, Expr::Column(c) if c.table.as_deref() == Some("contacts") && c.name == "phone"));
+        assert_eq!(*right, Expr::Literal(Value::Int(44)));
+
+        assert!(type_error_message("SELECT name.first FROM users").contains("cannot take .first of VARCHAR"));
+        assert!(type_error_message("SELECT x.name FROM users").contains("unknown table x"));
+
+        analyze("CREATE INDEX by_country ON contacts (phone.country_code, tags[0])").unwrap();
+        assert!(type_error_message("CREATE INDEX i ON users (name.first)").contains("not a struct or array"));
+        assert!(type_error_message("CREATE INDEX i ON users (nope)").contains("does not exist"));
+    }
+}
@@ -0,0 +1,807 @@
+// src/catalog.rs
+pub mod alter;
+pub mod information_schema;
+pub mod system;
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+pub use system::{CatalogTransaction, SystemCatalog};
+
+use crate::error::Error;
+use crate::parser::ast::{
+    ColumnConstraint, ColumnDef, CreateIndexStatement, CreateStatement, CreateTypeStatement, DataType, Expr, FieldPath,
+    IndexMethod, TableConstraint, TypeDefinition,
+};
+use crate::parser::parse_data_type;
+use crate::row::codec::RowCodec;
+use crate::types::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub data_type: DataType,
+    pub nullable: bool,
+    pub primary_key: bool,
+    pub default: Option<Expr>,
+    /// The value of the column in rows stored before it was added to the
+    /// table: its default at the time, `None` for NULL.
+    pub missing: Option<Value>,
+}
+
+impl ColumnSchema {
+    pub fn new(name: impl Into<String>, data_type: DataType) -> Self {
+        ColumnSchema {
+            name: name.into(),
+            data_type,
+            nullable: true,
+            primary_key: false,
+            default: None,
+            missing: None,
+        }
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.nullable = false;
+        self
+    }
+
+    pub fn primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self.nullable = false;
+        self
+    }
+
+    pub fn from_def(def: &ColumnDef) -> Self {
+        let mut column = ColumnSchema::new(def.name.clone(), def.data_type.clone());
+        for constraint in &def.constraints {
+            match constraint {
+                ColumnConstraint::NotNull => column.nullable = false,
+                ColumnConstraint::PrimaryKey => column = column.primary_key(),
+                ColumnConstraint::Default(expr) => column.default = Some(expr.clone()),
+                _ => {}
+            }
+        }
+        column
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    /// UNIQUE, CHECK and FOREIGN KEY constraints, every one named; the
+    /// primary key is kept on the columns.
+    pub constraints: Vec<TableConstraint>,
+    /// The custom types its columns use, directly or within other custom
+    /// types, as the catalog defines them, in name order. The catalog fills
+    /// them in; rows are encoded with them.
+    pub types: Vec<TypeSchema>,
+}
+
+impl TableSchema {
+    pub fn new(name: impl Into<String>, columns: Vec<ColumnSchema>) -> Self {
+        TableSchema {
+            name: name.into(),
+            columns,
+            constraints: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+
+    /// Builds the schema described by a `CREATE TABLE` statement. Column
+    /// constraints other than NOT NULL, PRIMARY KEY and DEFAULT become table
+    /// constraints, and unnamed constraints are named the way PostgreSQL
+    /// names them: `users_email_key`, `users_age_check`.
+    pub fn from_create(create: &CreateStatement) -> Self {
+        let mut schema = TableSchema::new(
+            create.table.name.clone(),
+            create.columns.iter().map(ColumnSchema::from_def).collect(),
+        );
+        for def in &create.columns {
+            schema.add_column_constraints(def);
+        }
+        for constraint in &create.constraints {
+            if let TableConstraint::PrimaryKey { columns, .. } = constraint {
+                for name in columns {
+                    if let Some(i) = schema.column_index(name) {
+                        schema.columns[i] = schema.columns[i].clone().primary_key();
+                    }
+                }
+            } else {
+                schema.add_constraint(constraint.clone(), None);
+            }
+        }
+        schema
+    }
+
+    /// Adds the UNIQUE, CHECK and REFERENCES constraints written on a
+    /// column as table constraints, returning how many there were.
+    pub fn add_column_constraints(&mut self, def: &ColumnDef) -> usize {
+        let mut added = 0;
+        for constraint in &def.constraints {
+            let constraint = match constraint {
+                ColumnConstraint::Unique => TableConstraint::Unique { name: None, columns: vec![def.name.clone()] },
+                ColumnConstraint::Check(expr) => TableConstraint::Check { name: None, expr: expr.clone() },
+                ColumnConstraint::ForeignKey { table, column, on_delete, on_update } => TableConstraint::ForeignKey {
+                    name: None,
+                    columns: vec![def.name.clone()],
+                    ref_table: table.clone(),
+                    ref_columns: vec![column.clone()],
+                    on_delete: on_delete.clone(),
+                    on_update: on_update.clone(),
+                },
+                _ => continue,
+            };
+            self.add_constraint(constraint, Some(&def.name));
+            added += 1;
+        }
+        added
+    }
+
+    /// Adds a constraint, naming it if it has no name. A CHECK is named
+    /// after `column` when it was written on one.
+    pub fn add_constraint(&mut self, mut constraint: TableConstraint, column: Option<&str>) {
+        if constraint.name().is_none() {
+            let (columns, suffix) = match &constraint {
+                TableConstraint::PrimaryKey { .. } => (Vec::new(), "pkey"),
+                TableConstraint::Unique { columns, .. } => (columns.clone(), "key"),
+                TableConstraint::ForeignKey { columns, .. } => (columns.clone(), "fkey"),
+                TableConstraint::Check { .. } => (column.map(str::to_string).into_iter().collect(), "check"),
+            };
+            let base = [vec![self.name.clone()], columns, vec![suffix.to_string()]].concat().join("_");
+            let mut name = base.clone();
+            for n in 1.. {
+                if self.constraint(&name).is_none() {
+                    break;
+                }
+                name = format!("{}{}", base, n);
+            }
+            match &mut constraint {
+                TableConstraint::PrimaryKey { name: slot, .. }
+                | TableConstraint::Unique { name: slot, .. }
+                | TableConstraint::ForeignKey { name: slot, .. }
+                | TableConstraint::Check { name: slot, .. } => *slot = Some(name),
+            }
+        }
+        self.constraints.push(constraint);
+    }
+
+    pub fn constraint(&self, name: &str) -> Option<&TableConstraint> {
+        self.constraints.iter().find(|c| c.name().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// A `CREATE TABLE` statement that [`TableSchema::from_create`] turns
+    /// back into this schema.
+    pub fn to_sql(&self) -> String {
+        let mut parts: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut def = format!("{} {}", column.name, column.data_type);
+                if !column.nullable && !column.primary_key {
+                    def.push_str(" NOT NULL");
+                }
+                if let Some(default) = &column.default {
+                    def.push_str(&format!(" DEFAULT {}", default));
+                }
+                def
+            })
+            .collect();
+        let key: Vec<&str> = self.columns.iter().filter(|c| c.primary_key).map(|c| c.name.as_str()).collect();
+        if !key.is_empty() {
+            parts.push(format!("PRIMARY KEY ({})", key.join(", ")));
+        }
+        parts.extend(self.constraints.iter().map(TableConstraint::to_string));
+        format!("CREATE TABLE {} ({})", self.name, parts.join(", "))
+    }
+
+    /// Column names are matched case-insensitively, like SQL identifiers.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.column_index(name).map(|i| &self.columns[i])
+    }
+}
+
+/// An index over one or more columns of a table, or over values nested
+/// inside them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<FieldPath>,
+    pub unique: bool,
+    pub method: IndexMethod,
+}
+
+impl IndexSchema {
+    pub fn from_create(create: &CreateIndexStatement) -> Self {
+        IndexSchema {
+            name: create.name.clone(),
+            table: create.table.name.clone(),
+            columns: create.columns.clone(),
+            unique: create.unique,
+            method: create.method,
+        }
+    }
+
+    /// A `CREATE INDEX` statement that [`IndexSchema::from_create`] turns
+    /// back into this schema.
+    pub fn to_sql(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(FieldPath::to_string).collect();
+        format!(
+            "CREATE {}INDEX {} ON {} USING {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            self.name,
+            self.table,
+            self.method,
+            columns.join(", ")
+        )
+    }
+}
+
+/// A custom type, usable wherever a struct or enum value is.
+///
+/// `ALTER TYPE` only appends members and renames fields, so the versions of
+/// a type differ in how many members they have: a type starts at version 1,
+/// and each member added makes a new version. Values are stored with the
+/// version they were written under and read under the latest, a struct
+/// stored before a field was added reading it as the field's default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeSchema {
+    pub name: String,
+    pub kind: TypeKind,
+    /// For each member added after the type was created, which are its
+    /// last members, the value older values read for it if it is a field;
+    /// `None` for a variant or a field read as NULL.
+    pub added: Vec<Option<Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    /// Named fields, in declaration order.
+    Struct(Vec<(String, DataType)>),
+    /// Variants, each with the type of its payload if it has one.
+    Enum(Vec<(String, Option<DataType>)>),
+}
+
+impl TypeSchema {
+    pub fn new(name: impl Into<String>, kind: TypeKind) -> Self {
+        TypeSchema { name: name.into(), kind, added: Vec::new() }
+    }
+
+    pub fn from_create(create: &CreateTypeStatement) -> Self {
+        let kind = match &create.definition {
+            TypeDefinition::Struct(fields) => TypeKind::Struct(fields.clone()),
+            TypeDefinition::Enum(variants) => TypeKind::Enum(variants.clone()),
+        };
+        TypeSchema::new(create.name.clone(), kind)
+    }
+
+    pub fn version(&self) -> u32 {
+        self.added.len() as u32 + 1
+    }
+
+    /// The names of the members, in order.
+    pub fn member_names(&self) -> Vec<&String> {
+        match &self.kind {
+            TypeKind::Struct(fields) => fields.iter().map(|(name, _)| name).collect(),
+            TypeKind::Enum(variants) => variants.iter().map(|(name, _)| name).collect(),
+        }
+    }
+
+    /// The types of the members, in order; `None` for a unit variant.
+    pub fn member_types(&self) -> Vec<Option<&DataType>> {
+        match &self.kind {
+            TypeKind::Struct(fields) => fields.iter().map(|(_, ty)| Some(ty)).collect(),
+            TypeKind::Enum(variants) => variants.iter().map(|(_, ty)| ty.as_ref()).collect(),
+        }
+    }
+
+    /// How many members values stored under `version` have, if the type
+    /// has that version.
+    pub fn members_at(&self, version: u32) -> Option<usize> {
+        let since = (version as usize).checked_sub(1).filter(|&n| n <= self.added.len())?;
+        Some(self.member_names().len() - self.added.len() + since)
+    }
+
+    /// The names of the custom types its members use.
+    pub fn uses(&self) -> Vec<&str> {
+        self.member_types().into_iter().flatten().filter_map(custom_type_name).collect()
+    }
+
+    /// Whether values stored under `older`, an earlier definition of the
+    /// type, read correctly under this one.
+    pub fn extends(&self, older: &TypeSchema) -> bool {
+        let (types, old_types) = (self.member_types(), older.member_types());
+        self.kind_name() == older.kind_name()
+            && types.len() - self.added.len() == old_types.len() - older.added.len()
+            && self.added.starts_with(&older.added)
+            && types.starts_with(&old_types)
+    }
+
+    /// The columns of the rows [`TypeSchema::to_rows`] makes.
+    pub fn row_columns() -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::new("type_name", DataType::Text).not_null(),
+            ColumnSchema::new("kind", DataType::Text).not_null(),
+            ColumnSchema::new("position", DataType::BigInt).not_null(),
+            ColumnSchema::new("member", DataType::Text).not_null(),
+            ColumnSchema::new("data_type", DataType::Text),
+            ColumnSchema::new("version", DataType::BigInt),
+            ColumnSchema::new("missing_value", DataType::Binary(None)),
+        ]
+    }
+
+    /// The type as a row per member, as it is stored: the type's name and
+    /// kind, the member's position, name and type, the version that added
+    /// it, and what older values read for it, encoded.
+    pub fn to_rows(&self) -> Result<Vec<Vec<Value>>, Error> {
+        let first_added = self.member_names().len() - self.added.len();
+        let text = |s: &str| Value::String(s.to_string());
+        let mut rows = Vec::new();
+        for (i, (name, ty)) in self.member_names().into_iter().zip(self.member_types()).enumerate() {
+            let added = i.checked_sub(first_added);
+            let missing = match (added.and_then(|k| self.added[k].as_ref()), ty) {
+                (Some(value), Some(ty)) => Value::Bytes(RowCodec::encode_value(ty, &[], value)?),
+                _ => Value::Null,
+            };
+            rows.push(vec![
+                text(&self.name),
+                text(self.kind_name()),
+                Value::Int(i as i64),
+                text(name),
+                ty.map_or(Value::Null, |ty| text(&ty.to_string())),
+                Value::Int(added.map_or(1, |k| k as i64 + 2)),
+                missing,
+            ]);
+        }
+        Ok(rows)
+    }
+
+    /// Reads back types from the rows of [`TypeSchema::to_rows`], in any
+    /// order. Rows stored before types had versions may lack the last two
+    /// columns.
+    pub fn from_rows(mut rows: Vec<Vec<Value>>) -> Result<Vec<TypeSchema>, Error> {
+        let corrupt = || Error::Storage("corrupt type definition".to_string());
+        let text = |value: &Value| match value {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(corrupt()),
+        };
+        rows.sort_by_key(|row| match row.get(2) {
+            Some(Value::Int(position)) => *position,
+            _ => 0,
+        });
+        let mut types: Vec<TypeSchema> = Vec::new();
+        for row in rows {
+            let name = text(&row[0])?;
+            let at = match types.iter().position(|t| t.name.eq_ignore_ascii_case(&name)) {
+                Some(at) => at,
+                None => {
+                    let kind = match text(&row[1])?.as_str() {
+                        "STRUCT" => TypeKind::Struct(Vec::new()),
+                        "ENUM" => TypeKind::Enum(Vec::new()),
+                        _ => return Err(corrupt()),
+                    };
+                    types.push(TypeSchema::new(name, kind));
+                    types.len() - 1
+                }
+            };
+            let schema = &mut types[at];
+            let member = text(&row[3])?;
+            let ty = match &row[4] {
+                Value::String(ty) => Some(parse_data_type(ty)?),
+                _ => None,
+            };
+            // Added members follow the others, in the order they were added.
+            let version = match row.get(5) {
+                Some(Value::Int(version)) => *version,
+                _ => 1,
+            };
+            if version as usize == schema.added.len() + 2 {
+                schema.added.push(match (row.get(6), &ty) {
+                    (Some(Value::Bytes(bytes)), Some(ty)) => Some(RowCodec::decode_value(ty, &[], bytes)?),
+                    _ => None,
+                });
+            } else if version != 1 || !schema.added.is_empty() {
+                return Err(corrupt());
+            }
+            match (&mut schema.kind, ty) {
+                (TypeKind::Struct(fields), Some(ty)) => fields.push((member, ty)),
+                (TypeKind::Enum(variants), ty) => variants.push((member, ty)),
+                _ => return Err(corrupt()),
+            }
+        }
+        Ok(types)
+    }
+
+    /// `STRUCT` or `ENUM`.
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            TypeKind::Struct(_) => "STRUCT",
+            TypeKind::Enum(_) => "ENUM",
+        }
+    }
+
+    /// The type's members, as `STRUCT(x INTEGER, y TEXT)` or
+    /// `ENUM(none, some(INTEGER))`.
+    pub fn definition(&self) -> String {
+        let members: Vec<String> = match &self.kind {
+            TypeKind::Struct(fields) => fields.iter().map(|(name, ty)| format!("{} {}", name, ty)).collect(),
+            TypeKind::Enum(variants) => variants
+                .iter()
+                .map(|(name, payload)| match payload {
+                    Some(ty) => format!("{}({})", name, ty),
+                    None => name.clone(),
+                })
+                .collect(),
+        };
+        format!("{}({})", self.kind_name(), members.join(", "))
+    }
+}
+
+/// The name of the custom type `data_type` is, if it is one.
+pub fn custom_type_name(data_type: &DataType) -> Option<&str> {
+    match data_type {
+        DataType::Custom(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// A named query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewSchema {
+    pub name: String,
+    /// Names for the query's columns; empty to keep the query's own.
+    pub columns: Vec<String>,
+    /// The `SELECT` statement, as written.
+    pub query: String,
+}
+
+/// A generator of increasing (or decreasing) integers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceSchema {
+    pub name: String,
+    pub start: i64,
+    pub increment: i64,
+    pub min: i64,
+    pub max: i64,
+    /// Whether to start over from the other end once past `min` or `max`,
+    /// rather than fail.
+    pub cycle: bool,
+}
+
+impl SequenceSchema {
+    /// A sequence counting 1, 2, 3, ...
+    pub fn new(name: impl Into<String>) -> Self {
+        SequenceSchema { name: name.into(), start: 1, increment: 1, min: 1, max: i64::MAX, cycle: false }
+    }
+
+    /// The value after `last`, or the first value if there is no `last`.
+    pub fn next(&self, last: Option<i64>) -> Result<i64, Error> {
+        let Some(last) = last else { return Ok(self.start) };
+        match last.checked_add(self.increment).filter(|next| (self.min..=self.max).contains(next)) {
+            Some(next) => Ok(next),
+            None if self.cycle => Ok(if self.increment > 0 { self.min } else { self.max }),
+            None => Err(Error::Execution(format!(
+                "sequence {} reached its {} value",
+                self.name,
+                if self.increment > 0 { "maximum" } else { "minimum" }
+            ))),
+        }
+    }
+}
+
+/// A schema migration that has been applied; see [`crate::migrate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    /// The name of its file, without the extension.
+    pub name: String,
+    pub version: i64,
+    /// The CRC-32C of the file as it was applied.
+    pub checksum: u32,
+    pub applied_at: DateTime<Utc>,
+}
+
+/// Source of table definitions for semantic analysis.
+pub trait Catalog {
+    fn table(&self, name: &str) -> Option<TableSchema>;
+
+    /// The indexes of a table.
+    fn indexes(&self, _table: &str) -> Vec<IndexSchema> {
+        Vec::new()
+    }
+
+    fn view(&self, _name: &str) -> Option<ViewSchema> {
+        None
+    }
+
+    fn custom_type(&self, _name: &str) -> Option<TypeSchema> {
+        None
+    }
+
+    fn sequence(&self, _name: &str) -> Option<SequenceSchema> {
+        None
+    }
+}
+
+/// A catalog held entirely in memory. Every kind of object has its own
+/// namespace, except that tables and views share one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryCatalog {
+    tables: HashMap<String, TableSchema>,
+    indexes: HashMap<String, IndexSchema>,
+    types: HashMap<String, TypeSchema>,
+    views: HashMap<String, ViewSchema>,
+    sequences: HashMap<String, SequenceSchema>,
+    migrations: HashMap<String, AppliedMigration>,
+}
+
+impl MemoryCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fills in the custom types the table uses, which must exist.
+    pub fn create_table(&mut self, mut schema: TableSchema) -> Result<(), Error> {
+        let key = schema.name.to_lowercase();
+        if self.tables.contains_key(&key) || self.views.contains_key(&key) {
+            return Err(Error::Execution(format!("table {} already exists", schema.name)));
+        }
+        self.resolve(&mut schema)?;
+        self.tables.insert(key, schema);
+        Ok(())
+    }
+
+    /// Drops a table along with its indexes.
+    pub fn drop_table(&mut self, name: &str) -> Result<TableSchema, Error> {
+        let schema = self
+            .tables
+            .remove(&name.to_lowercase())
+            .ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))?;
+        self.indexes.retain(|_, index| !index.table.eq_ignore_ascii_case(name));
+        Ok(schema)
+    }
+
+    /// Replaces the schema of a table, keeping its indexes.
+    pub fn replace_table(&mut self, mut schema: TableSchema) -> Result<TableSchema, Error> {
+        self.resolve(&mut schema)?;
+        let slot = self
+            .tables
+            .get_mut(&schema.name.to_lowercase())
+            .ok_or_else(|| Error::Execution(format!("table {} does not exist", schema.name)))?;
+        Ok(std::mem::replace(slot, schema))
+    }
+
+    /// Index names are unique across tables.
+    pub fn create_index(&mut self, index: IndexSchema) -> Result<(), Error> {
+        let key = index.name.to_lowercase();
+        if self.indexes.contains_key(&key) {
+            return Err(Error::Execution(format!("index {} already exists", index.name)));
+        }
+        if !self.tables.contains_key(&index.table.to_lowercase()) {
+            return Err(Error::Execution(format!("table {} does not exist", index.table)));
+        }
+        self.indexes.insert(key, index);
+        Ok(())
+    }
+
+    pub fn drop_index(&mut self, name: &str) -> Result<IndexSchema, Error> {
+        self.indexes
+            .remove(&name.to_lowercase())
+            .ok_or_else(|| Error::Execution(format!("index {} does not exist", name)))
+    }
+
+    pub fn create_type(&mut self, schema: TypeSchema) -> Result<(), Error> {
+        insert_new(&mut self.types, "type", &schema.name.clone(), schema)
+    }
+
+    /// Fails if a table or another type uses the type.
+    pub fn drop_type(&mut self, name: &str) -> Result<TypeSchema, Error> {
+        let named = |ty: &TypeSchema| ty.name.eq_ignore_ascii_case(name);
+        if let Some(table) = self.tables().into_iter().find(|table| table.types.iter().any(named)) {
+            return Err(Error::Execution(format!("type {} is used by table {}", name, table.name)));
+        }
+        let uses = |ty: &&TypeSchema| ty.uses().iter().any(|used| used.eq_ignore_ascii_case(name));
+        if let Some(other) = self.types().into_iter().find(uses) {
+            return Err(Error::Execution(format!("type {} is used by type {}", name, other.name)));
+        }
+        remove_existing(&mut self.types, "type", name)
+    }
+
+    /// Replaces the definition of a type, in the tables that use it too.
+    pub fn replace_type(&mut self, schema: TypeSchema) -> Result<TypeSchema, Error> {
+        let slot = self
+            .types
+            .get_mut(&schema.name.to_lowercase())
+            .ok_or_else(|| Error::Execution(format!("type {} does not exist", schema.name)))?;
+        let old = std::mem::replace(slot, schema);
+        let using: Vec<TableSchema> = self
+            .tables()
+            .into_iter()
+            .filter(|table| table.types.iter().any(|t| t.name.eq_ignore_ascii_case(&old.name)))
+            .cloned()
+            .collect();
+        for table in using {
+            self.replace_table(table)?;
+        }
+        Ok(old)
+    }
+
+    /// Sets the custom types of a table to those it uses.
+    pub(crate) fn resolve(&self, schema: &mut TableSchema) -> Result<(), Error> {
+        let mut names: Vec<String> =
+            schema.columns.iter().filter_map(|c| custom_type_name(&c.data_type)).map(str::to_lowercase).collect();
+        let mut types: Vec<TypeSchema> = Vec::new();
+        while let Some(name) = names.pop() {
+            if types.iter().any(|t| t.name.to_lowercase() == name) {
+                continue;
+            }
+            let ty = self.types.get(&name).ok_or_else(|| Error::Execution(format!("type {} does not exist", name)))?;
+            names.extend(ty.uses().into_iter().map(str::to_lowercase));
+            types.push(ty.clone());
+        }
+        types.sort_by_key(|t| t.name.to_lowercase());
+        schema.types = types;
+        Ok(())
+    }
+
+    pub fn create_view(&mut self, schema: ViewSchema) -> Result<(), Error> {
+        if self.tables.contains_key(&schema.name.to_lowercase()) {
+            return Err(Error::Execution(format!("table {} already exists", schema.name)));
+        }
+        insert_new(&mut self.views, "view", &schema.name.clone(), schema)
+    }
+
+    pub fn drop_view(&mut self, name: &str) -> Result<ViewSchema, Error> {
+        remove_existing(&mut self.views, "view", name)
+    }
+
+    pub fn create_sequence(&mut self, schema: SequenceSchema) -> Result<(), Error> {
+        if schema.increment == 0 || schema.min > schema.max || !(schema.min..=schema.max).contains(&schema.start) {
+            return Err(Error::Execution(format!(
+                "sequence {} must have a nonzero increment and start between its minimum and maximum",
+                schema.name
+            )));
+        }
+        insert_new(&mut self.sequences, "sequence", &schema.name.clone(), schema)
+    }
+
+    pub fn drop_sequence(&mut self, name: &str) -> Result<SequenceSchema, Error> {
+        remove_existing(&mut self.sequences, "sequence", name)
+    }
+
+    /// Versions are unique across migrations.
+    pub fn record_migration(&mut self, migration: AppliedMigration) -> Result<(), Error> {
+        if let Some(other) = self.migrations.values().find(|m| m.version == migration.version) {
+            return Err(Error::Execution(format!(
+                "migration {} has the same version as {}",
+                migration.name, other.name
+            )));
+        }
+        insert_new(&mut self.migrations, "migration", &migration.name.clone(), migration)
+    }
+
+    pub fn forget_migration(&mut self, name: &str) -> Result<AppliedMigration, Error> {
+        remove_existing(&mut self.migrations, "migration", name)
+    }
+
+    /// Every table, in name order; likewise the methods below.
+    pub fn tables(&self) -> Vec<&TableSchema> {
+        sorted_values(&self.tables)
+    }
+
+    pub fn all_indexes(&self) -> Vec<&IndexSchema> {
+        sorted_values(&self.indexes)
+    }
+
+    pub fn types(&self) -> Vec<&TypeSchema> {
+        sorted_values(&self.types)
+    }
+
+    pub fn views(&self) -> Vec<&ViewSchema> {
+        sorted_values(&self.views)
+    }
+
+    pub fn sequences(&self) -> Vec<&SequenceSchema> {
+        sorted_values(&self.sequences)
+    }
+
+    /// Applied migrations, in version order.
+    pub fn migrations(&self) -> Vec<&AppliedMigration> {
+        let mut migrations: Vec<&AppliedMigration> = self.migrations.values().collect();
+        migrations.sort_by_key(|m| m.version);
+        migrations
+    }
+}
+
+fn insert_new<T>(objects: &mut HashMap<String, T>, kind: &str, name: &str, object: T) -> Result<(), Error> {
+    let key = name.to_lowercase();
+    if objects.contains_key(&key) {
+        return Err(Error::Execution(format!("{} {} already exists", kind, name)));
+    }
+    objects.insert(key, object);
+    Ok(())
+}
+
+fn remove_existing<T>(objects: &mut HashMap<String, T>, kind: &str, name: &str) -> Result<T, Error> {
+    objects.remove(&name.to_lowercase()).ok_or_else(|| Error::Execution(format!("{} {} does not exist", kind, name)))
+}
+
+fn sorted_values<T>(objects: &HashMap<String, T>) -> Vec<&T> {
+    let mut keys: Vec<&String> = objects.keys().collect();
+    keys.sort();
+    keys.into_iter().map(|key| &objects[key]).collect()
+}
+
+impl Catalog for MemoryCatalog {
+    fn table(&self, name: &str) -> Option<TableSchema> {
+        self.tables.get(&name.to_lowercase()).cloned()
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexSchema> {
+        let mut indexes: Vec<IndexSchema> =
+            self.indexes.values().filter(|i| i.table.eq_ignore_ascii_case(table)).cloned().collect();
+        indexes.sort_by(|a, b| a.name.cmp(&b.name));
+        indexes
+    }
+
+    fn view(&self, name: &str) -> Option<ViewSchema> {
+        self.views.get(&name.to_lowercase()).cloned()
+    }
+
+    fn custom_type(&self, name: &str) -> Option<TypeSchema> {
+        self.types.get(&name.to_lowercase()).cloned()
+    }
+
+    fn sequence(&self, name: &str) -> Option<SequenceSchema> {
+        self.sequences.get(&name.to_lowercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ast::Statement, parse_sql};
+
+    #[test]
+    fn test_schema_from_create() {
+        let Statement::Create(create) = parse_sql(
+            "CREATE TABLE users (id INT, name TEXT NOT NULL DEFAULT 'x', PRIMARY KEY (id))",
+        )
+        .unwrap() else {
+            panic!("Expected CREATE statement")
+        };
+        let schema = TableSchema::from_create(&create);
+        assert!(schema.column("ID").unwrap().primary_key);
+        assert!(!schema.column("id").unwrap().nullable);
+        assert!(schema.column("name").unwrap().default.is_some());
+        assert_eq!(
+            schema.to_sql(),
+            "CREATE TABLE users (id INTEGER, name TEXT NOT NULL DEFAULT 'x', PRIMARY KEY (id))"
+        );
+
+        let mut catalog = MemoryCatalog::new();
+        catalog.create_table(schema.clone()).unwrap();
+        assert!(catalog.create_table(schema).is_err());
+        assert!(catalog.table("Users").is_some());
+
+        let Statement::CreateIndex(create) = parse_sql("CREATE INDEX by_name ON users (name)").unwrap() else {
+            panic!("Expected CREATE INDEX statement")
+        };
+        catalog.create_index(IndexSchema::from_create(&create)).unwrap();
+        assert!(catalog.create_index(IndexSchema::from_create(&create)).is_err());
+        let sql = IndexSchema::from_create(&create).to_sql();
+        assert_eq!(sql, "CREATE INDEX by_name ON users USING BTREE (name)");
+        let Statement::CreateIndex(again) = parse_sql(&sql).unwrap() else { panic!("Expected CREATE INDEX") };
+        assert_eq!(IndexSchema::from_create(&again), IndexSchema::from_create(&create));
+        assert_eq!(catalog.indexes("USERS")[0].columns, vec![FieldPath::column("name")]);
+        catalog.drop_table("users").unwrap();
+        assert!(catalog.table("users").is_none());
+        assert!(catalog.indexes("users").is_empty());
+    }
+}
@@ -0,0 +1,885 @@
+// src/catalog/alter.rs
+//! Carrying out `ALTER TABLE`.
+//!
+//! [`plan`] works out a table's new schema and indexes, and how each row
+//! of the table as stored becomes a row of the new schema: every new column
+//! takes the value of a stored column, cast if its type changed, or one
+//! value for every stored row, such as an added column's default. Most
+//! changes need nothing more, since rows read an added column as its
+//! `missing` value without being touched. Dropping a column or changing a
+//! column's type rewrites the table: an [`Alteration`] copies the converted
+//! rows into a new table, which replaces the old one on commit. New
+//! constraints, and columns becoming NOT NULL, are checked against every
+//! converted row.
+//!
+//! Neither holds writers back. An alteration reads the table while it is
+//! written, then catches up with the rows written meanwhile, which the
+//! table records for it. Only the last catch-up, on commit, holds writers
+//! back, and it only has the rows written since the one before to go
+//! through.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, ColumnSchema, IndexSchema, MemoryCatalog, TableSchema};
+use crate::engine::{RowId, StorageEngine, Table, TableChange};
+use crate::error::Error;
+use crate::eval::{compile, constant, CompiledExpr};
+use crate::parser::ast::{AlterAction, AlterStatement, DataType, Expr, TableConstraint};
+use crate::types::cast::{can_cast, cast};
+use crate::types::Value;
+
+/// Prefix of the tables rows are rewritten into.
+const REWRITE_PREFIX: &str = "rustdb_rewrite_";
+
+/// Where a column of an altered table gets its values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// The stored column at the position, cast to each type in turn.
+    Column(usize, Vec<DataType>),
+    /// The same value for every stored row.
+    Value(Value),
+}
+
+/// What an `ALTER TABLE` changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    /// The table's schema after the change.
+    pub schema: TableSchema,
+    /// The table's indexes after the change, without those on dropped
+    /// columns.
+    pub indexes: Vec<IndexSchema>,
+    /// Other tables with foreign keys on renamed columns, as renamed.
+    pub referencing: Vec<TableSchema>,
+    /// Where each column of `schema` gets its values.
+    pub sources: Vec<Source>,
+    /// Whether the stored rows must be rewritten to fit `schema`.
+    pub rewrite: bool,
+    /// Whether the rows must be checked against the constraints of
+    /// `schema`.
+    pub validate: bool,
+}
+
+/// Works out what `alter` changes in the table as `catalog` has it.
+pub fn plan(catalog: &MemoryCatalog, alter: &AlterStatement) -> Result<Plan, Error> {
+    let name = &alter.table.name;
+    let schema = catalog.table(name).ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))?;
+    let mut plan = Plan::keep(schema, catalog.indexes(name));
+    for action in &alter.actions {
+        plan.apply(catalog, action)?;
+    }
+    catalog.resolve(&mut plan.schema)?;
+    if plan.rewrite {
+        // Rewritten rows hold every column.
+        for column in &mut plan.schema.columns {
+            column.missing = None;
+        }
+    }
+    Ok(plan)
+}
+
+impl Plan {
+    /// A plan giving a table `schema` and `indexes` without touching its
+    /// rows, which must fit `schema` as they are.
+    pub fn keep(schema: TableSchema, indexes: Vec<IndexSchema>) -> Self {
+        Plan {
+            sources: (0..schema.columns.len()).map(|i| Source::Column(i, Vec::new())).collect(),
+            schema,
+            indexes,
+            referencing: Vec::new(),
+            rewrite: false,
+            validate: false,
+        }
+    }
+
+    fn apply(&mut self, catalog: &MemoryCatalog, action: &AlterAction) -> Result<(), Error> {
+        let table = self.schema.name.clone();
+        let position = |schema: &TableSchema, name: &str| {
+            schema
+                .column_index(name)
+                .ok_or_else(|| Error::Execution(format!("column {} does not exist in table {}", name, table)))
+        };
+        let taken = |schema: &TableSchema, name: &str| match schema.column(name) {
+            Some(_) => Err(Error::Execution(format!("column {} already exists in table {}", name, table))),
+            None => Ok(()),
+        };
+        match action {
+            AlterAction::AddColumn(def) => {
+                taken(&self.schema, &def.name)?;
+                let mut column = ColumnSchema::from_def(def);
+                let value = match &column.default {
+                    Some(default) => cast(&constant(default)?, &column.data_type)?,
+                    None => Value::Null,
+                };
+                self.validate |= !column.nullable;
+                column.missing = (!value.is_null()).then(|| value.clone());
+                self.schema.columns.push(column);
+                self.sources.push(Source::Value(value));
+                self.validate |= self.schema.add_column_constraints(def) > 0;
+            }
+            AlterAction::DropColumn(name) => {
+                let i = position(&self.schema, name)?;
+                if self.schema.columns.len() == 1 {
+                    return Err(Error::Execution(format!("cannot drop the only column of table {}", table)));
+                }
+                let column = self.schema.columns[i].name.clone();
+                for other in catalog.tables().into_iter().filter(|t| !t.name.eq_ignore_ascii_case(&table)) {
+                    if let Some(constraint) = other.constraints.iter().find(|c| references(c, &table, &column)) {
+                        return Err(Error::Execution(format!(
+                            "column {} of table {} is referenced by constraint {} of table {}",
+                            column,
+                            table,
+                            constraint.name().unwrap_or_default(),
+                            other.name
+                        )));
+                    }
+                }
+                self.schema.columns.remove(i);
+                self.sources.remove(i);
+                self.schema.constraints.retain(|c| !mentions(c, &column) && !references(c, &table, &column));
+                self.indexes.retain(|index| !index.columns.iter().any(|p| p.column.eq_ignore_ascii_case(&column)));
+                self.rewrite = true;
+            }
+            AlterAction::ModifyColumn(def) => {
+                let i = position(&self.schema, &def.name)?;
+                let old = self.schema.columns[i].clone();
+                let mut column = ColumnSchema::from_def(def);
+                if old.primary_key {
+                    column = column.primary_key();
+                }
+                if column.data_type == old.data_type {
+                    column.missing = old.missing.clone();
+                } else {
+                    if !can_cast(&old.data_type, &column.data_type) {
+                        return Err(Error::Type(format!(
+                            "column {} cannot be changed from {} to {}",
+                            old.name, old.data_type, column.data_type
+                        )));
+                    }
+                    match &mut self.sources[i] {
+                        Source::Column(_, casts) => casts.push(column.data_type.clone()),
+                        Source::Value(value) => *value = cast(value, &column.data_type)?,
+                    }
+                    self.rewrite = true;
+                    self.validate = true;
+                }
+                self.validate |= old.nullable && !column.nullable;
+                self.schema.columns[i] = column;
+                self.validate |= self.schema.add_column_constraints(def) > 0;
+            }
+            AlterAction::RenameColumn(from, to) => {
+                let i = position(&self.schema, from)?;
+                if !from.eq_ignore_ascii_case(to) {
+                    taken(&self.schema, to)?;
+                }
+                let from = std::mem::replace(&mut self.schema.columns[i].name, to.clone());
+                for constraint in &mut self.schema.constraints {
+                    rename_in(constraint, &table, &from, to, true);
+                }
+                for path in self.indexes.iter_mut().flat_map(|index| index.columns.iter_mut()) {
+                    if path.column.eq_ignore_ascii_case(&from) {
+                        path.column = to.clone();
+                    }
+                }
+                for other in catalog.tables().into_iter().filter(|t| !t.name.eq_ignore_ascii_case(&table)) {
+                    if !other.constraints.iter().any(|c| references(c, &table, &from)) {
+                        continue;
+                    }
+                    let at = match self.referencing.iter().position(|t| t.name == other.name) {
+                        Some(at) => at,
+                        None => {
+                            self.referencing.push(other.clone());
+                            self.referencing.len() - 1
+                        }
+                    };
+                    for constraint in &mut self.referencing[at].constraints {
+                        rename_in(constraint, &table, &from, to, false);
+                    }
+                }
+            }
+            AlterAction::AddConstraint(constraint) => {
+                if let Some(name) = constraint.name() {
+                    if self.schema.constraint(name).is_some() {
+                        return Err(Error::Execution(format!("constraint {} already exists on table {}", name, table)));
+                    }
+                }
+                let columns = match constraint {
+                    TableConstraint::PrimaryKey { columns, .. }
+                    | TableConstraint::Unique { columns, .. }
+                    | TableConstraint::ForeignKey { columns, .. } => columns.clone(),
+                    TableConstraint::Check { .. } => Vec::new(),
+                };
+                let positions = columns.iter().map(|c| position(&self.schema, c)).collect::<Result<Vec<_>, _>>()?;
+                if let TableConstraint::PrimaryKey { .. } = constraint {
+                    if self.schema.columns.iter().any(|c| c.primary_key) {
+                        return Err(Error::Execution(format!("table {} already has a primary key", table)));
+                    }
+                    for i in positions {
+                        self.schema.columns[i] = self.schema.columns[i].clone().primary_key();
+                    }
+                } else {
+                    self.schema.add_constraint(constraint.clone(), None);
+                }
+                self.validate = true;
+            }
+            AlterAction::DropConstraint(name) => {
+                let named = |c: &TableConstraint| c.name().is_some_and(|n| n.eq_ignore_ascii_case(name));
+                match self.schema.constraints.iter().position(named) {
+                    Some(i) => {
+                        self.schema.constraints.remove(i);
+                    }
+                    None if name.eq_ignore_ascii_case(&format!("{}_pkey", table))
+                        && self.schema.columns.iter().any(|c| c.primary_key) =>
+                    {
+                        for column in &mut self.schema.columns {
+                            column.primary_key = false;
+                        }
+                    }
+                    None => {
+                        return Err(Error::Execution(format!("constraint {} does not exist on table {}", name, table)));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sources of a second change to a table, made after the first: where
+/// the columns after both get their values from the table as stored.
+pub fn compose(first: &[Source], then: &[Source]) -> Result<Vec<Source>, Error> {
+    then.iter()
+        .map(|source| match source {
+            Source::Value(value) => Ok(Source::Value(value.clone())),
+            Source::Column(i, casts) => match &first[*i] {
+                Source::Column(j, earlier) => Ok(Source::Column(*j, [earlier.clone(), casts.clone()].concat())),
+                Source::Value(value) => Ok(Source::Value(cast_all(value.clone(), casts)?)),
+            },
+        })
+        .collect()
+}
+
+fn cast_all(value: Value, casts: &[DataType]) -> Result<Value, Error> {
+    casts.iter().try_fold(value, |value, data_type| cast(&value, data_type))
+}
+
+/// A stored row as a row of the new schema.
+fn convert(sources: &[Source], row: &[Value]) -> Result<Vec<Value>, Error> {
+    sources
+        .iter()
+        .map(|source| match source {
+            Source::Column(i, casts) => cast_all(row[*i].clone(), casts),
+            Source::Value(value) => Ok(value.clone()),
+        })
+        .collect()
+}
+
+/// Calls `f` on the name of every column `expr` refers to.
+fn for_each_column(expr: &mut Expr, f: &mut dyn FnMut(&mut String)) {
+    match expr {
+        Expr::Column(column) => f(&mut column.name),
+        Expr::Binary { left, right, .. } => {
+            for_each_column(left, f);
+            for_each_column(right, f);
+        }
+        Expr::Unary { expr, .. } | Expr::Cast { expr, .. } | Expr::Field { expr, .. } => for_each_column(expr, f),
+        Expr::Function { args: exprs, .. } | Expr::List(exprs) => {
+            for expr in exprs {
+                for_each_column(expr, f);
+            }
+        }
+        Expr::Case { operand, when_clauses, else_result } => {
+            for expr in operand.iter_mut().chain(else_result.iter_mut()) {
+                for_each_column(expr, f);
+            }
+            for (when, then) in when_clauses {
+                for_each_column(when, f);
+                for_each_column(then, f);
+            }
+        }
+        Expr::Literal(_) | Expr::Wildcard { .. } | Expr::Exists(_) | Expr::Subquery(_) => {}
+    }
+}
+
+/// Whether a constraint of a table is on `column` of that table.
+fn mentions(constraint: &TableConstraint, column: &str) -> bool {
+    match constraint {
+        TableConstraint::PrimaryKey { columns, .. }
+        | TableConstraint::Unique { columns, .. }
+        | TableConstraint::ForeignKey { columns, .. } => columns.iter().any(|c| c.eq_ignore_ascii_case(column)),
+        TableConstraint::Check { expr, .. } => {
+            let mut found = false;
+            for_each_column(&mut expr.clone(), &mut |name| found |= name.eq_ignore_ascii_case(column));
+            found
+        }
+    }
+}
+
+/// Whether a constraint is a foreign key to `column` of `table`.
+fn references(constraint: &TableConstraint, table: &str, column: &str) -> bool {
+    matches!(constraint, TableConstraint::ForeignKey { ref_table, ref_columns, .. }
+        if ref_table.eq_ignore_ascii_case(table) && ref_columns.iter().any(|c| c.eq_ignore_ascii_case(column)))
+}
+
+/// Renames column `from` of `table` to `to` where a constraint names it:
+/// its own columns too if the constraint is on `table`.
+fn rename_in(constraint: &mut TableConstraint, table: &str, from: &str, to: &str, own: bool) {
+    let rename = |name: &mut String| {
+        if name.eq_ignore_ascii_case(from) {
+            *name = to.to_string();
+        }
+    };
+    match constraint {
+        TableConstraint::PrimaryKey { columns, .. } | TableConstraint::Unique { columns, .. } if own => {
+            columns.iter_mut().for_each(rename);
+        }
+        TableConstraint::ForeignKey { columns, ref_table, ref_columns, .. } => {
+            if own {
+                columns.iter_mut().for_each(rename);
+            }
+            if ref_table.eq_ignore_ascii_case(table) {
+                ref_columns.iter_mut().for_each(rename);
+            }
+        }
+        TableConstraint::Check { expr, .. } if own => for_each_column(expr, &mut { rename }),
+        _ => {}
+    }
+}
+
+/// Key columns, and the rows holding each key.
+#[derive(Debug, Default)]
+struct Keys {
+    columns: Vec<usize>,
+    rows: BTreeMap<Vec<Value>, BTreeSet<RowId>>,
+    of: HashMap<RowId, Vec<Value>>,
+}
+
+impl Keys {
+    fn new(columns: Vec<usize>) -> Self {
+        Keys { columns, ..Keys::default() }
+    }
+
+    /// Notes the key of a row, if it has one with no NULL in it.
+    fn set(&mut self, id: RowId, row: Option<&[Value]>) {
+        if let Some(key) = self.of.remove(&id) {
+            if let Some(ids) = self.rows.get_mut(&key) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.rows.remove(&key);
+                }
+            }
+        }
+        let Some(row) = row else { return };
+        let key: Vec<Value> = self.columns.iter().map(|&i| row[i].clone()).collect();
+        if key.iter().any(Value::is_null) {
+            return;
+        }
+        self.rows.entry(key.clone()).or_default().insert(id);
+        self.of.insert(id, key);
+    }
+}
+
+fn show(key: &[Value]) -> String {
+    key.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// A foreign key to check.
+#[derive(Debug)]
+struct Reference {
+    name: String,
+    keys: Keys,
+    table: String,
+    columns: Vec<String>,
+    /// The table's own keys, for a foreign key to the table itself.
+    own: Option<Keys>,
+}
+
+/// The constraints of a schema, checked row by row as rows are converted.
+#[derive(Debug)]
+struct Checks {
+    schema: TableSchema,
+    /// Rows breaking a NOT NULL or CHECK constraint, with how.
+    broken: BTreeMap<RowId, String>,
+    /// UNIQUE constraints, the primary key among them.
+    unique: Vec<(String, Keys)>,
+    references: Vec<Reference>,
+    /// CHECK constraints by name, compiled against the schema.
+    checks: Vec<(String, CompiledExpr)>,
+}
+
+impl Checks {
+    fn new(schema: &TableSchema) -> Result<Self, Error> {
+        let positions = |columns: &[String]| -> Result<Vec<usize>, Error> {
+            columns
+                .iter()
+                .map(|c| {
+                    schema.column_index(c).ok_or_else(|| {
+                        Error::Execution(format!("column {} does not exist in table {}", c, schema.name))
+                    })
+                })
+                .collect()
+        };
+        let key: Vec<usize> = (0..schema.columns.len()).filter(|&i| schema.columns[i].primary_key).collect();
+        let mut unique = Vec::new();
+        if !key.is_empty() {
+            unique.push((format!("{}_pkey", schema.name), Keys::new(key)));
+        }
+        let mut references = Vec::new();
+        let mut checks = Vec::new();
+        for constraint in &schema.constraints {
+            let name = constraint.name().unwrap_or_default().to_string();
+            match constraint {
+                TableConstraint::Check { expr, .. } => {
+                    let compiled = compile(expr, schema).map_err(|err| {
+                        Error::Execution(format!("check constraint {} of table {} failed: {}", name, schema.name, err))
+                    })?;
+                    checks.push((name, compiled));
+                }
+                TableConstraint::Unique { columns, .. } => unique.push((name, Keys::new(positions(columns)?))),
+                TableConstraint::ForeignKey { columns, ref_table, ref_columns, .. } => {
+                    let own = ref_table.eq_ignore_ascii_case(&schema.name);
+                    references.push(Reference {
+                        name,
+                        keys: Keys::new(positions(columns)?),
+                        table: ref_table.clone(),
+                        columns: ref_columns.clone(),
+                        own: if own { Some(Keys::new(positions(ref_columns)?)) } else { None },
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(Checks { schema: schema.clone(), broken: BTreeMap::new(), unique, references, checks })
+    }
+
+    /// Notes the current version of a row, `None` once it is deleted.
+    fn set(&mut self, id: RowId, row: Option<&[Value]>) {
+        self.broken.remove(&id);
+        for (_, keys) in &mut self.unique {
+            keys.set(id, row);
+        }
+        for reference in &mut self.references {
+            reference.keys.set(id, row);
+            if let Some(own) = &mut reference.own {
+                own.set(id, row);
+            }
+        }
+        let Some(row) = row else { return };
+        let table = &self.schema.name;
+        for (column, value) in self.schema.columns.iter().zip(row) {
+            if !column.nullable && value.is_null() {
+                self.broken.insert(id, format!("column {} of table {} contains NULL values", column.name, table));
+                return;
+            }
+        }
+        for (name, check) in &self.checks {
+            match check.eval(row) {
+                Ok(Value::Bool(false)) => {
+                    let broken = format!("check constraint {} of table {} is violated by some row", name, table);
+                    self.broken.insert(id, broken);
+                }
+                Ok(_) => continue,
+                Err(err) => {
+                    self.broken.insert(id, format!("check constraint {} of table {} failed: {}", name, table, err));
+                }
+            }
+            return;
+        }
+    }
+
+    /// Fails unless every row noted meets every constraint.
+    fn verify(&self, engine: &dyn StorageEngine) -> Result<(), Error> {
+        if let Some(broken) = self.broken.values().next() {
+            return Err(Error::Execution(broken.clone()));
+        }
+        for (name, keys) in &self.unique {
+            if let Some((key, _)) = keys.rows.iter().find(|(_, ids)| ids.len() > 1) {
+                return Err(Error::Execution(format!(
+                    "could not create unique constraint {}: key ({}) is duplicated",
+                    name,
+                    show(key)
+                )));
+            }
+        }
+        for reference in self.references.iter().filter(|r| !r.keys.rows.is_empty()) {
+            let referenced: BTreeSet<&Vec<Value>> = match &reference.own {
+                Some(own) => own.rows.keys().collect(),
+                None => BTreeSet::new(),
+            };
+            let scanned;
+            let referenced = match &reference.own {
+                Some(_) => referenced,
+                None => {
+                    scanned = referenced_keys(engine, reference)?;
+                    scanned.iter().collect()
+                }
+            };
+            if let Some(key) = reference.keys.rows.keys().find(|key| !referenced.contains(key)) {
+                return Err(Error::Execution(format!(
+                    "foreign key constraint {} is violated: key ({}) is not in table {}",
+                    reference.name,
+                    show(key),
+                    reference.table
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The keys a foreign key can refer to in another table.
+fn referenced_keys(engine: &dyn StorageEngine, reference: &Reference) -> Result<BTreeSet<Vec<Value>>, Error> {
+    let table = engine
+        .table(&reference.table)
+        .ok_or_else(|| Error::Execution(format!("table {} does not exist", reference.table)))?;
+    let schema = table.schema();
+    let columns = reference
+        .columns
+        .iter()
+        .map(|c| {
+            schema.column_index(c).ok_or_else(|| {
+                Error::Execution(format!("column {} does not exist in table {}", c, reference.table))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut keys = BTreeSet::new();
+    for row in table.scan() {
+        let (_, row) = row?;
+        keys.insert(columns.iter().map(|&i| row[i].clone()).collect());
+    }
+    Ok(keys)
+}
+
+/// A table the rows are being rewritten into.
+#[derive(Debug)]
+struct Rewrite {
+    table: Arc<dyn Table>,
+    /// The id each row copied so far has in the new table.
+    ids: HashMap<RowId, RowId>,
+    /// Rows the new table could not take yet, with why.
+    pending: BTreeMap<RowId, Error>,
+}
+
+/// An `ALTER TABLE` under way on one table, until the transaction making it
+/// commits.
+#[derive(Debug)]
+pub struct Alteration {
+    table: Arc<dyn Table>,
+    plan: Plan,
+    rewrite: Option<Rewrite>,
+    checks: Option<Checks>,
+}
+
+impl Alteration {
+    /// Starts converting the rows of `table` as `plan` says. Reads every
+    /// row while writes go on, and fails if one cannot be converted or
+    /// breaks a constraint.
+    pub fn start(engine: &dyn StorageEngine, table: Arc<dyn Table>, plan: Plan) -> Result<Self, Error> {
+        let mut alteration = Alteration { table, plan, rewrite: None, checks: None };
+        if !alteration.plan.rewrite && !alteration.plan.validate {
+            return Ok(alteration);
+        }
+        alteration.table.track_changes(true);
+        if let Err(err) = alteration.first_pass(engine) {
+            alteration.abandon(engine)?;
+            return Err(err);
+        }
+        Ok(alteration)
+    }
+
+    fn first_pass(&mut self, engine: &dyn StorageEngine) -> Result<(), Error> {
+        if self.plan.validate {
+            self.checks = Some(Checks::new(&self.plan.schema)?);
+        }
+        if self.plan.rewrite {
+            let base = format!("{}{}", REWRITE_PREFIX, self.plan.schema.name.to_lowercase());
+            let name = (1..).map(|n| format!("{}_{}", base, n)).find(|name| engine.table(name).is_none());
+            let name = name.unwrap_or(base);
+            let table = engine.create_table(TableSchema { name: name.clone(), ..self.plan.schema.clone() })?;
+            self.rewrite = Some(Rewrite { table: table.clone(), ids: HashMap::new(), pending: BTreeMap::new() });
+            for index in &self.plan.indexes {
+                table.create_index(IndexSchema { table: name.clone(), ..index.clone() })?;
+            }
+        }
+        let table = self.table.clone();
+        for row in table.scan() {
+            let (id, row) = row?;
+            self.set(id, Some(row))?;
+        }
+        self.catch_up(engine, false)
+    }
+
+    /// What the alteration makes of the table.
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    /// Converts the current version of a row and passes it on.
+    fn set(&mut self, id: RowId, row: Option<Vec<Value>>) -> Result<(), Error> {
+        let row = row.map(|row| convert(&self.plan.sources, &row)).transpose()?;
+        if let Some(checks) = &mut self.checks {
+            checks.set(id, row.as_deref());
+        }
+        let Some(rewrite) = &mut self.rewrite else { return Ok(()) };
+        let copied = match (row, rewrite.ids.get(&id).copied()) {
+            (Some(row), Some(copy)) => rewrite.table.update(copy, row),
+            (Some(row), None) => rewrite.table.insert(row).map(|copy| {
+                rewrite.ids.insert(id, copy);
+            }),
+            (None, Some(copy)) => rewrite.table.delete(copy).map(|_| {
+                rewrite.ids.remove(&id);
+            }),
+            (None, None) => Ok(()),
+        };
+        // A row can clash with another's old version in a unique index of
+        // the new table; it is tried again once that one has caught up.
+        match copied {
+            Ok(()) => rewrite.pending.remove(&id),
+            Err(err) => rewrite.pending.insert(id, err),
+        };
+        Ok(())
+    }
+
+    /// Brings the conversion up to date with the rows written since the
+    /// last catch-up. The last one, made while writes are held back, fails
+    /// unless every row has been copied and meets every constraint.
+    pub fn catch_up(&mut self, engine: &dyn StorageEngine, last: bool) -> Result<(), Error> {
+        if !self.plan.rewrite && !self.plan.validate {
+            return Ok(());
+        }
+        for id in self.table.take_changes() {
+            let row = self.table.get(id)?;
+            self.set(id, row)?;
+        }
+        loop {
+            let pending: Vec<RowId> = match &self.rewrite {
+                Some(rewrite) => rewrite.pending.keys().copied().collect(),
+                None => Vec::new(),
+            };
+            for &id in &pending {
+                let row = self.table.get(id)?;
+                self.set(id, row)?;
+            }
+            let left = self.rewrite.as_ref().map_or(0, |rewrite| rewrite.pending.len());
+            if left == 0 || left == pending.len() {
+                break;
+            }
+        }
+        if let Some(checks) = &self.checks {
+            checks.verify(engine)?;
+        }
+        if last {
+            if let Some((_, err)) = self.rewrite.as_mut().and_then(|rewrite| rewrite.pending.pop_first()) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// The change to make to the table on commit, for it to end up with
+    /// `schema` and `indexes`. These can differ from the plan's in
+    /// constraints and names, but not in how rows are stored.
+    pub fn change(
+        &self,
+        engine: &dyn StorageEngine,
+        schema: TableSchema,
+        indexes: Vec<IndexSchema>,
+    ) -> Result<TableChange, Error> {
+        let Some(rewrite) = &self.rewrite else { return Ok(TableChange::Schema { schema, indexes }) };
+        let name = rewrite.table.schema().name;
+        let schema = TableSchema { name: name.clone(), ..schema };
+        let indexes = indexes.into_iter().map(|index| IndexSchema { table: name.clone(), ..index }).collect();
+        engine.alter_table(&name, TableChange::Schema { schema, indexes }, &mut || Ok(()))?;
+        Ok(TableChange::Replace(name))
+    }
+
+    /// Stops following the table once the change is made.
+    pub fn finish(self) {
+        self.table.track_changes(false);
+    }
+
+    /// Gives the alteration up, dropping the table the rows were being
+    /// rewritten into. Returns the plan, to start it again with.
+    pub fn abandon(self, engine: &dyn StorageEngine) -> Result<Plan, Error> {
+        self.table.track_changes(false);
+        if let Some(rewrite) = self.rewrite {
+            engine.drop_table(&rewrite.table.schema().name)?;
+        }
+        Ok(self.plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::catalog::SystemCatalog;
+    use crate::engine::{DiskEngine, MemoryEngine};
+    use crate::parser::ast::Statement;
+    use crate::parser::parse_sql;
+    use crate::storage::{Pager, PagerOptions};
+
+    fn setup(engine: Arc<dyn StorageEngine>, sqls: &[&str]) -> SystemCatalog {
+        let catalog = SystemCatalog::open(engine).unwrap();
+        let mut txn = catalog.begin();
+        for sql in sqls {
+            match parse_sql(sql).unwrap() {
+                Statement::Create(create) => txn.create_table(TableSchema::from_create(&create)).unwrap(),
+                Statement::CreateIndex(create) => txn.create_index(IndexSchema::from_create(&create)).unwrap(),
+                other => panic!("Expected CREATE, got {:?}", other),
+            }
+        }
+        txn.commit().unwrap();
+        catalog
+    }
+
+    fn alter(sql: &str) -> AlterStatement {
+        let Statement::Alter(alter) = parse_sql(sql).unwrap() else { panic!("Expected ALTER TABLE") };
+        alter
+    }
+
+    fn rows(table: &dyn Table) -> Vec<Vec<Value>> {
+        table.scan().map(|row| row.unwrap().1).collect()
+    }
+
+    fn text(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn test_adding_a_column_leaves_rows_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || {
+            let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+            Arc::new(DiskEngine::open(Arc::new(BufferPool::new(pager, 64))).unwrap()) as Arc<dyn StorageEngine>
+        };
+        {
+            let engine = open();
+            let catalog = setup(engine.clone(), &["CREATE TABLE users (id INT PRIMARY KEY, name TEXT)"]);
+            let users = engine.table("users").unwrap();
+            users.insert(vec![Value::Int(1), text("ann")]).unwrap();
+            let mut txn = catalog.begin();
+            txn.alter_table(&alter("ALTER TABLE users ADD COLUMN active BOOLEAN DEFAULT true, ADD level INT"))
+                .unwrap();
+            users.insert(vec![Value::Int(2), text("bob")]).unwrap();
+            txn.commit().unwrap();
+
+            // The table was not rebuilt, so the handle still works.
+            users.insert(vec![Value::Int(3), text("cy"), Value::Bool(false), Value::Int(7)]).unwrap();
+            assert_eq!(catalog.table("users").unwrap().columns[2].missing, Some(Value::Bool(true)));
+            engine.flush().unwrap();
+        }
+        let engine = open();
+        let catalog = SystemCatalog::open(engine.clone()).unwrap();
+        assert_eq!(catalog.table("users").unwrap().columns.len(), 4);
+        assert_eq!(
+            rows(engine.table("users").unwrap().as_ref()),
+            vec![
+                vec![Value::Int(1), text("ann"), Value::Bool(true), Value::Null],
+                vec![Value::Int(2), text("bob"), Value::Bool(true), Value::Null],
+                vec![Value::Int(3), text("cy"), Value::Bool(false), Value::Int(7)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrites_keep_up_with_writes() {
+        let engine: Arc<dyn StorageEngine> = Arc::new(MemoryEngine::new());
+        let catalog = setup(
+            engine.clone(),
+            &["CREATE TABLE t (id INT PRIMARY KEY, score INT, note TEXT)", "CREATE UNIQUE INDEX by_score ON t (score)"],
+        );
+        let t = engine.table("t").unwrap();
+        let ids: Vec<RowId> =
+            (1..=3).map(|i| t.insert(vec![Value::Int(i), Value::Int(i * 10), text("x")]).unwrap()).collect();
+
+        let mut txn = catalog.begin();
+        txn.alter_table(&alter("ALTER TABLE t MODIFY COLUMN score TEXT, DROP COLUMN note, RENAME COLUMN id TO key"))
+            .unwrap();
+        assert!(txn.create_index(IndexSchema { name: "other".into(), ..catalog.indexes("t")[0].clone() }).is_err());
+        // Scores swap places, which the unique index of the new table only
+        // takes once both rows are copied.
+        t.update(ids[0], vec![Value::Int(1), Value::Int(20), text("y")]).unwrap_err();
+        t.update(ids[1], vec![Value::Int(2), Value::Int(99), text("y")]).unwrap();
+        t.update(ids[0], vec![Value::Int(1), Value::Int(20), text("y")]).unwrap();
+        t.delete(ids[2]).unwrap();
+        t.insert(vec![Value::Int(4), Value::Int(40), Value::Null]).unwrap();
+        txn.alter_table(&alter("ALTER TABLE t ADD COLUMN extra INT DEFAULT 5")).unwrap();
+        txn.commit().unwrap();
+
+        assert!(t.insert(vec![Value::Int(5), Value::Int(50), Value::Null]).is_err());
+        let t = engine.table("t").unwrap();
+        let mut stored = rows(t.as_ref());
+        stored.sort();
+        assert_eq!(
+            stored,
+            vec![
+                vec![Value::Int(1), text("20"), Value::Int(5)],
+                vec![Value::Int(2), text("99"), Value::Int(5)],
+                vec![Value::Int(4), text("40"), Value::Int(5)],
+            ]
+        );
+        assert_eq!(t.indexes()[0].columns[0].column, "score");
+        assert!(t.insert(vec![Value::Int(6), text("99"), Value::Null]).is_err());
+        assert_eq!(catalog.table("t").unwrap().columns[0].name, "key");
+        assert_eq!(engine.table_names().len(), 9);
+    }
+
+    #[test]
+    fn test_constraints_are_checked_against_every_row() {
+        let engine: Arc<dyn StorageEngine> = Arc::new(MemoryEngine::new());
+        let catalog = setup(
+            engine.clone(),
+            &["CREATE TABLE teams (id INT PRIMARY KEY)", "CREATE TABLE people (id INT, team INT, score INT)"],
+        );
+        engine.table("teams").unwrap().insert(vec![Value::Int(1)]).unwrap();
+        let people = engine.table("people").unwrap();
+        people.insert(vec![Value::Int(1), Value::Int(1), Value::Int(5)]).unwrap();
+        people.insert(vec![Value::Int(1), Value::Int(2), Value::Null]).unwrap();
+
+        let failing = [
+            ("ALTER TABLE people ADD CONSTRAINT people_pk PRIMARY KEY (id)", "duplicated"),
+            ("ALTER TABLE people ADD FOREIGN KEY (team) REFERENCES teams (id)", "not in table teams"),
+            ("ALTER TABLE people MODIFY score INT NOT NULL", "NULL values"),
+            ("ALTER TABLE people ADD COLUMN level INT NOT NULL", "NULL values"),
+            ("ALTER TABLE people ADD CONSTRAINT small CHECK (team < 2)", "violated"),
+            ("ALTER TABLE people MODIFY team DATE", "cannot be changed"),
+            ("ALTER TABLE people DROP COLUMN nope", "does not exist"),
+        ];
+        let mut txn = catalog.begin();
+        for (sql, error) in failing {
+            let err = txn.alter_table(&alter(sql)).expect_err(sql);
+            assert!(err.to_string().contains(error), "{}: {}", sql, err);
+            assert_eq!(txn.table("people"), catalog.table("people"));
+        }
+        // Rows written after the check are checked on commit.
+        txn.alter_table(&alter("ALTER TABLE people ADD CONSTRAINT small CHECK (team < 3)")).unwrap();
+        people.insert(vec![Value::Int(3), Value::Int(3), Value::Null]).unwrap();
+        assert!(txn.commit().is_err());
+        assert!(catalog.table("people").unwrap().constraints.is_empty());
+        assert_eq!(engine.table_names().len(), 10);
+        assert_eq!(rows(engine.table("people").unwrap().as_ref()).len(), 3);
+    }
+
+    #[test]
+    fn test_writers_go_on_during_a_rewrite() {
+        let engine: Arc<dyn StorageEngine> = Arc::new(MemoryEngine::new());
+        let catalog = setup(engine.clone(), &["CREATE TABLE t (id INT PRIMARY KEY, n SMALLINT)"]);
+        let writer = {
+            let engine = engine.clone();
+            std::thread::spawn(move || {
+                for i in 0..500 {
+                    // The handle goes stale when the table is replaced.
+                    while engine.table("t").unwrap().insert(vec![Value::Int(i), Value::Int(i % 100)]).is_err() {}
+                }
+            })
+        };
+        let mut txn = catalog.begin();
+        txn.alter_table(&alter("ALTER TABLE t MODIFY n BIGINT NOT NULL, ADD CHECK (n < 100)")).unwrap();
+        txn.commit().unwrap();
+        writer.join().unwrap();
+        let t = engine.table("t").unwrap();
+        assert_eq!(t.schema().columns[1].data_type, DataType::BigInt);
+        assert_eq!(rows(t.as_ref()).len(), 500);
+    }
+}
@@ -0,0 +1,207 @@
+// src/catalog/information_schema.rs
+//! The `information_schema` views: the catalog as read-only tables.
+//!
+//! Each view is computed from a catalog when it is read and reflects that
+//! catalog at that moment; it has no storage of its own, and every attempt
+//! to change one fails.
+
+use crate::catalog::{ColumnSchema, IndexSchema, MemoryCatalog, TableSchema, TypeSchema};
+use crate::engine::{RowId, ScanItem, Table};
+use crate::error::Error;
+use crate::index::KeyRange;
+use crate::parser::ast::DataType;
+use crate::types::Value;
+
+/// The schema the views are in.
+pub const SCHEMA: &str = "information_schema";
+
+/// The names of the views.
+pub const VIEWS: [&str; 7] =
+    ["columns", "indexes", "sequences", "table_constraints", "tables", "user_defined_types", "views"];
+
+/// The view's name if `name` is a qualified `information_schema` name.
+pub fn view_name(name: &str) -> Option<&str> {
+    let (schema, view) = name.split_once('.')?;
+    let view = VIEWS.iter().find(|v| v.eq_ignore_ascii_case(view))?;
+    schema.eq_ignore_ascii_case(SCHEMA).then_some(view)
+}
+
+/// The columns of a view, named by [`view_name`].
+pub fn schema(view: &str) -> Option<TableSchema> {
+    let text = |name: &str| ColumnSchema::new(name, DataType::Text).not_null();
+    let number = |name: &str| ColumnSchema::new(name, DataType::BigInt).not_null();
+    let columns = match view {
+        "tables" => vec![text("table_name"), text("table_type")],
+        "columns" => vec![
+            text("table_name"),
+            text("column_name"),
+            number("ordinal_position"),
+            text("data_type"),
+            text("is_nullable"),
+            ColumnSchema::new("column_default", DataType::Text),
+        ],
+        "table_constraints" => vec![text("constraint_name"), text("table_name"), text("constraint_type")],
+        "indexes" => vec![
+            text("index_name"),
+            text("table_name"),
+            text("index_method"),
+            text("is_unique"),
+            text("columns"),
+        ],
+        "views" => vec![text("table_name"), text("view_definition")],
+        "sequences" => vec![
+            text("sequence_name"),
+            number("start_value"),
+            number("increment"),
+            number("minimum_value"),
+            number("maximum_value"),
+            text("cycle_option"),
+        ],
+        "user_defined_types" => vec![text("type_name"), text("type_kind"), text("definition")],
+        _ => return None,
+    };
+    Some(TableSchema::new(format!("{}.{}", SCHEMA, view), columns))
+}
+
+/// A view's rows as of `catalog`.
+pub fn view(catalog: &MemoryCatalog, view: &str) -> Option<ViewTable> {
+    let schema = schema(view)?;
+    let text = |s: &str| Value::String(s.to_string());
+    let yes_no = |b: bool| text(if b { "YES" } else { "NO" });
+    let rows: Vec<Vec<Value>> = match view {
+        "tables" => {
+            let tables = catalog.tables().into_iter().map(|t| (t.name.as_str(), "BASE TABLE"));
+            let views = catalog.views().into_iter().map(|v| (v.name.as_str(), "VIEW"));
+            let mut rows: Vec<(&str, &str)> = tables.chain(views).collect();
+            rows.sort();
+            rows.into_iter().map(|(name, kind)| vec![text(name), text(kind)]).collect()
+        }
+        "columns" => catalog
+            .tables()
+            .into_iter()
+            .flat_map(|table| {
+                table.columns.iter().enumerate().map(move |(i, column)| {
+                    vec![
+                        text(&table.name),
+                        text(&column.name),
+                        Value::Int(i as i64 + 1),
+                        text(&column.data_type.to_string()),
+                        yes_no(column.nullable),
+                        column.default.as_ref().map_or(Value::Null, |default| text(&default.to_string())),
+                    ]
+                })
+            })
+            .collect(),
+        "table_constraints" => catalog
+            .tables()
+            .into_iter()
+            .flat_map(|table| {
+                let key = table.columns.iter().any(|c| c.primary_key);
+                let key = key.then(|| (format!("{}_pkey", table.name), "PRIMARY KEY"));
+                let others = table.constraints.iter().map(|c| (c.name().unwrap_or_default().to_string(), c.kind()));
+                key.into_iter()
+                    .chain(others)
+                    .map(|(name, kind)| vec![text(&name), text(&table.name), text(kind)])
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+        "indexes" => catalog
+            .all_indexes()
+            .into_iter()
+            .map(|index: &IndexSchema| {
+                let columns: Vec<String> = index.columns.iter().map(ToString::to_string).collect();
+                vec![
+                    text(&index.name),
+                    text(&index.table),
+                    text(&index.method.to_string()),
+                    yes_no(index.unique),
+                    text(&columns.join(", ")),
+                ]
+            })
+            .collect(),
+        "views" => catalog.views().into_iter().map(|v| vec![text(&v.name), text(&v.query)]).collect(),
+        "sequences" => catalog
+            .sequences()
+            .into_iter()
+            .map(|s| {
+                vec![
+                    text(&s.name),
+                    Value::Int(s.start),
+                    Value::Int(s.increment),
+                    Value::Int(s.min),
+                    Value::Int(s.max),
+                    yes_no(s.cycle),
+                ]
+            })
+            .collect(),
+        "user_defined_types" => catalog
+            .types()
+            .into_iter()
+            .map(|t: &TypeSchema| vec![text(&t.name), text(t.kind_name()), text(&t.definition())])
+            .collect(),
+        _ => return None,
+    };
+    Some(ViewTable { schema, rows })
+}
+
+/// A view, read at one moment.
+#[derive(Debug)]
+pub struct ViewTable {
+    schema: TableSchema,
+    rows: Vec<Vec<Value>>,
+}
+
+impl ViewTable {
+    fn read_only(&self) -> Error {
+        Error::Execution(format!("{} is read-only", self.schema.name))
+    }
+}
+
+impl Table for ViewTable {
+    fn schema(&self) -> TableSchema {
+        self.schema.clone()
+    }
+
+    fn scan(&self) -> Box<dyn Iterator<Item = ScanItem> + '_> {
+        Box::new(self.rows.iter().enumerate().map(|(i, row)| Ok((RowId(i as u64 + 1), row.clone()))))
+    }
+
+    fn get(&self, id: RowId) -> Result<Option<Vec<Value>>, Error> {
+        Ok(id.0.checked_sub(1).and_then(|i| self.rows.get(i as usize)).cloned())
+    }
+
+    fn insert(&self, _row: Vec<Value>) -> Result<RowId, Error> {
+        Err(self.read_only())
+    }
+
+    fn update(&self, _id: RowId, _row: Vec<Value>) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn delete(&self, _id: RowId) -> Result<bool, Error> {
+        Err(self.read_only())
+    }
+
+    fn create_index(&self, _schema: IndexSchema) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn drop_index(&self, _name: &str) -> Result<bool, Error> {
+        Err(self.read_only())
+    }
+
+    fn indexes(&self) -> Vec<IndexSchema> {
+        Vec::new()
+    }
+
+    fn index_scan(&self, index: &str, _range: &KeyRange) -> Result<Vec<RowId>, Error> {
+        Err(Error::Execution(format!("table {} has no index {}", self.schema.name, index)))
+    }
+
+    /// A view's rows never change.
+    fn track_changes(&self, _on: bool) {}
+
+    fn take_changes(&self) -> Vec<RowId> {
+        Vec::new()
+    }
+}
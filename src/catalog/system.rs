@@ -0,0 +1,1369 @@
+// src/catalog/system.rs
+//! The persistent catalog, kept in system tables of the database itself.
+//!
+//! Every object is stored as rows of one or more reserved `rustdb_` tables,
+//! which an engine stores like any other, and the whole catalog is cached in
+//! memory as a [`MemoryCatalog`]. Changes go through a [`CatalogTransaction`]:
+//! it works on its own copy of the cache, creates tables and indexes in the
+//! engine straight away but only drops them on commit, and writes the system
+//! tables on commit by comparing its copy with the cache it started from.
+//! Rolling back, or dropping the transaction, drops what it created, so a
+//! rolled-back `CREATE TABLE` leaves nothing behind. Catalog transactions
+//! run one at a time.
+//!
+//! `ALTER TABLE` converts and checks the rows of the table as soon as it is
+//! made, through an [`Alteration`], but changes the table in the engine only
+//! on commit, together with the system tables. Over a logged engine the
+//! whole commit is one transaction of the log, so a crash part way through
+//! it leaves the catalog as it was.
+//!
+//! `ALTER TYPE` makes a new version of a type without touching the rows
+//! holding it, which read as the latest version from then on; the tables
+//! using the type take the new definition on commit.
+//! [`SystemCatalog::rewrite_type`] later brings the rows up to date.
+//!
+//! Applied schema migrations are recorded in the catalog too, so that a
+//! migration's changes and its record are committed together.
+//!
+//! Sequence values are not transactional: [`SystemCatalog::next_value`]
+//! stores each value as it hands it out, so no value is handed out twice.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use parking_lot::{Mutex, MutexGuard, RwLock};
+
+use crate::analyzer::Analyzer;
+use crate::catalog::alter::{self, Alteration, Plan};
+use crate::catalog::information_schema::{self, ViewTable};
+use crate::catalog::{
+    custom_type_name, AppliedMigration, Catalog, ColumnSchema, IndexSchema, MemoryCatalog, SequenceSchema,
+    TableSchema, TypeKind, TypeSchema, ViewSchema,
+};
+use crate::engine::{RowId, StorageEngine, Table, TableChange};
+use crate::error::Error;
+use crate::eval::constant;
+use crate::executor::change_rows;
+use crate::executor::keys::{self, Made};
+use crate::parser::ast::{
+    AlterStatement, AlterTypeAction, AlterTypeStatement, DataType, DeleteStatement, InsertStatement, Statement,
+    UpdateStatement,
+};
+use crate::parser::{parse_data_type, parse_expr, parse_sql, parse_table_constraint};
+use crate::row::codec::RowCodec;
+use crate::types::cast::cast;
+use crate::types::Value;
+use crate::wal::TxnManager;
+
+/// Names starting with this are reserved for system tables.
+pub const RESERVED_PREFIX: &str = "rustdb_";
+
+const TABLES: &str = "rustdb_tables";
+const COLUMNS: &str = "rustdb_columns";
+const CONSTRAINTS: &str = "rustdb_constraints";
+const INDEXES: &str = "rustdb_indexes";
+const TYPES: &str = "rustdb_types";
+const VIEWS: &str = "rustdb_views";
+const SEQUENCES: &str = "rustdb_sequences";
+const MIGRATIONS: &str = "rustdb_migrations";
+
+/// The system tables. The first column of each holds the name of the
+/// object a row belongs to.
+fn system_tables() -> Vec<TableSchema> {
+    let text = |name: &str| ColumnSchema::new(name, DataType::Text).not_null();
+    let number = |name: &str| ColumnSchema::new(name, DataType::BigInt).not_null();
+    let flag = |name: &str| ColumnSchema::new(name, DataType::Boolean).not_null();
+    vec![
+        TableSchema::new(TABLES, vec![text("name")]),
+        TableSchema::new(
+            COLUMNS,
+            vec![
+                text("table_name"),
+                number("position"),
+                text("name"),
+                text("data_type"),
+                flag("nullable"),
+                flag("primary_key"),
+                ColumnSchema::new("default_value", DataType::Text),
+                ColumnSchema::new("missing_value", DataType::Binary(None)),
+            ],
+        ),
+        TableSchema::new(
+            CONSTRAINTS,
+            vec![text("table_name"), number("position"), text("name"), text("kind"), text("definition")],
+        ),
+        TableSchema::new(INDEXES, vec![text("name"), text("table_name"), text("definition")]),
+        TableSchema::new(TYPES, TypeSchema::row_columns()),
+        TableSchema::new(VIEWS, vec![text("name"), ColumnSchema::new("columns", DataType::Text), text("query")]),
+        TableSchema::new(
+            SEQUENCES,
+            vec![
+                text("name"),
+                number("start"),
+                number("increment"),
+                number("min_value"),
+                number("max_value"),
+                flag("cycle"),
+                ColumnSchema::new("last_value", DataType::BigInt),
+            ],
+        ),
+        TableSchema::new(
+            MIGRATIONS,
+            vec![
+                text("name"),
+                number("version"),
+                number("checksum"),
+                ColumnSchema::new("applied_at", DataType::Timestamp).not_null(),
+            ],
+        ),
+    ]
+}
+
+#[derive(Debug)]
+pub struct SystemCatalog {
+    engine: Arc<dyn StorageEngine>,
+    cache: RwLock<Arc<MemoryCatalog>>,
+    /// Held by the open transaction.
+    ddl: Mutex<()>,
+    /// Held while handing out a sequence value.
+    sequences: Mutex<()>,
+    /// Held by statements that change rows, from checking their keys until
+    /// the rows are changed.
+    writes: Mutex<()>,
+    /// The log the engine's changes go to, if they are logged.
+    txns: Option<Arc<TxnManager>>,
+}
+
+impl SystemCatalog {
+    /// Loads the catalog of the engine's tables, creating the system tables
+    /// if they are missing. Tables and indexes that have storage but no
+    /// catalog entry, left by a crash during a transaction, are dropped.
+    pub fn open(engine: Arc<dyn StorageEngine>) -> Result<Self, Error> {
+        Self::open_with(engine, None)
+    }
+
+    /// Like [`SystemCatalog::open`], over an engine whose changes are
+    /// transactions of `txns`, and commits as one transaction of it.
+    pub fn open_logged(engine: Arc<dyn StorageEngine>, txns: Arc<TxnManager>) -> Result<Self, Error> {
+        Self::open_with(engine, Some(txns))
+    }
+
+    fn open_with(engine: Arc<dyn StorageEngine>, txns: Option<Arc<TxnManager>>) -> Result<Self, Error> {
+        for schema in system_tables() {
+            if engine.table(&schema.name).is_none() {
+                engine.create_table(schema)?;
+            }
+        }
+        let catalog = SystemCatalog {
+            cache: RwLock::new(Arc::new(load(engine.as_ref())?)),
+            engine,
+            ddl: Mutex::new(()),
+            sequences: Mutex::new(()),
+            writes: Mutex::new(()),
+            txns,
+        };
+        catalog.reconcile()?;
+        Ok(catalog)
+    }
+
+    pub fn engine(&self) -> &Arc<dyn StorageEngine> {
+        &self.engine
+    }
+
+    /// The catalog as of the last commit.
+    pub fn snapshot(&self) -> Arc<MemoryCatalog> {
+        self.cache.read().clone()
+    }
+
+    /// Keeps other statements from changing rows until the guard drops.
+    /// Taken inside [`SystemCatalog::atomic`], never around it.
+    pub(crate) fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.writes.lock()
+    }
+
+    /// Runs `f` as one transaction of the log, if changes are logged.
+    pub(crate) fn atomic<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        match &self.txns {
+            Some(txns) => txns.atomic(f),
+            None => f(),
+        }
+    }
+
+    /// Starts a transaction, waiting for the open one to finish first.
+    pub fn begin(&self) -> CatalogTransaction<'_> {
+        let ddl = self.ddl.lock();
+        let base = self.snapshot();
+        CatalogTransaction {
+            catalog: self,
+            _ddl: ddl,
+            state: (*base).clone(),
+            base,
+            created: Vec::new(),
+            dropped: Vec::new(),
+            alterations: Vec::new(),
+            rows: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Stores the values of a type in every table under the type's latest
+    /// version, in a transaction of its own; see
+    /// [`CatalogTransaction::rewrite_type`].
+    pub fn rewrite_type(&self, name: &str) -> Result<(), Error> {
+        let mut txn = self.begin();
+        txn.rewrite_type(name)?;
+        txn.commit()
+    }
+
+    /// [`SystemCatalog::rewrite_type`] on a thread of its own.
+    pub fn rewrite_type_in_background(self: &Arc<Self>, name: &str) -> JoinHandle<Result<(), Error>> {
+        let (catalog, name) = (self.clone(), name.to_string());
+        thread::spawn(move || catalog.rewrite_type(&name))
+    }
+
+    /// An `information_schema` view, such as `tables`, as of the last
+    /// commit.
+    pub fn information_schema(&self, view: &str) -> Option<Arc<dyn Table>> {
+        let view = information_schema::VIEWS.iter().find(|v| v.eq_ignore_ascii_case(view))?;
+        Some(Arc::new(information_schema::view(&self.snapshot(), view)?) as Arc<dyn Table>)
+    }
+
+    /// Hands out the next value of a sequence.
+    pub fn next_value(&self, sequence: &str) -> Result<i64, Error> {
+        let schema = self
+            .sequence(sequence)
+            .ok_or_else(|| Error::Execution(format!("sequence {} does not exist", sequence)))?;
+        let _guard = self.sequences.lock();
+        let table = system_table(self.engine.as_ref(), SEQUENCES)?;
+        let (id, mut row) = owned_rows(table.as_ref(), &schema.name)?
+            .pop()
+            .ok_or_else(|| Error::Storage(format!("sequence {} has no row", schema.name)))?;
+        let last = match row[6] {
+            Value::Int(last) => Some(last),
+            _ => None,
+        };
+        let next = schema.next(last)?;
+        row[6] = Value::Int(next);
+        table.update(id, row)?;
+        self.engine.flush()?;
+        Ok(next)
+    }
+
+    /// Drops storage that the catalog does not know of, and checks that
+    /// everything it does know of has storage.
+    fn reconcile(&self) -> Result<(), Error> {
+        let catalog = self.snapshot();
+        let system: Vec<String> = system_tables().into_iter().map(|t| t.name).collect();
+        for name in self.engine.table_names() {
+            if catalog.table(&name).is_none() && !system.contains(&name) {
+                self.engine.drop_table(&name)?;
+            }
+        }
+        for schema in catalog.tables() {
+            let table = self.engine.table(&schema.name).ok_or_else(|| {
+                Error::Storage(format!("table {} is in the catalog but has no storage", schema.name))
+            })?;
+            let indexes = catalog.indexes(&schema.name);
+            for index in table.indexes() {
+                if !indexes.iter().any(|i| i.name.eq_ignore_ascii_case(&index.name)) {
+                    table.drop_index(&index.name)?;
+                }
+            }
+            let stored = table.indexes();
+            if let Some(index) = indexes.iter().find(|i| !stored.iter().any(|s| s.name.eq_ignore_ascii_case(&i.name))) {
+                return Err(Error::Storage(format!("index {} is in the catalog but has no storage", index.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Catalog for SystemCatalog {
+    fn table(&self, name: &str) -> Option<TableSchema> {
+        match information_schema::view_name(name) {
+            Some(view) => information_schema::schema(view),
+            None => self.cache.read().table(name),
+        }
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexSchema> {
+        self.cache.read().indexes(table)
+    }
+
+    fn view(&self, name: &str) -> Option<ViewSchema> {
+        self.cache.read().view(name)
+    }
+
+    fn custom_type(&self, name: &str) -> Option<TypeSchema> {
+        self.cache.read().custom_type(name)
+    }
+
+    fn sequence(&self, name: &str) -> Option<SequenceSchema> {
+        self.cache.read().sequence(name)
+    }
+}
+
+/// Storage a transaction has created or dropped.
+#[derive(Debug, Clone, PartialEq)]
+enum Storage {
+    Table(String),
+    Index { table: String, name: String },
+}
+
+/// A set of catalog changes, made together or not at all. Dropping an
+/// unfinished transaction rolls it back.
+#[derive(Debug)]
+pub struct CatalogTransaction<'a> {
+    catalog: &'a SystemCatalog,
+    _ddl: MutexGuard<'a, ()>,
+    base: Arc<MemoryCatalog>,
+    state: MemoryCatalog,
+    /// Storage to drop on rollback.
+    created: Vec<Storage>,
+    /// Storage to drop on commit.
+    dropped: Vec<Storage>,
+    /// Tables to change on commit.
+    alterations: Vec<Alteration>,
+    /// Changes to rows, in the order made, to undo on rollback.
+    rows: Vec<Made>,
+    done: bool,
+}
+
+impl CatalogTransaction<'_> {
+    /// Creates a table, with storage for it in the engine.
+    pub fn create_table(&mut self, schema: TableSchema) -> Result<(), Error> {
+        reserved(&schema.name)?;
+        let name = schema.name.clone();
+        if self.dropped.contains(&Storage::Table(name.to_lowercase())) {
+            return Err(Error::Execution(format!("table {} was dropped in this transaction", name)));
+        }
+        self.state.create_table(schema)?;
+        let schema = self.state.table(&name).expect("the table was just created");
+        if let Err(err) = self.catalog.engine.create_table(schema) {
+            self.state.drop_table(&name)?;
+            return Err(err);
+        }
+        self.created.push(Storage::Table(name.to_lowercase()));
+        Ok(())
+    }
+
+    /// Drops a table with its indexes. Its storage goes on commit.
+    pub fn drop_table(&mut self, name: &str) -> Result<TableSchema, Error> {
+        let schema = self.state.drop_table(name)?;
+        let key = schema.name.to_lowercase();
+        if let Some(i) = self.alteration(&key) {
+            self.alterations.remove(i).abandon(self.catalog.engine.as_ref())?;
+        }
+        let owned = |storage: &Storage| match storage {
+            Storage::Table(table) | Storage::Index { table, .. } => *table == key,
+        };
+        if self.created.contains(&Storage::Table(key.clone())) {
+            self.created.retain(|s| !owned(s));
+            self.rows.retain(|made| !made.table.eq_ignore_ascii_case(&key));
+            self.catalog.engine.drop_table(&key)?;
+        } else {
+            // Indexes created on it still need dropping on rollback.
+            self.dropped.retain(|s| !owned(s));
+            self.dropped.push(Storage::Table(key));
+        }
+        Ok(schema)
+    }
+
+    /// Creates an index and fills it from its table.
+    pub fn create_index(&mut self, schema: IndexSchema) -> Result<(), Error> {
+        self.unaltered(&schema.table)?;
+        let storage = Storage::Index { table: schema.table.to_lowercase(), name: schema.name.to_lowercase() };
+        if self.dropped.contains(&storage) {
+            return Err(Error::Execution(format!("index {} was dropped in this transaction", schema.name)));
+        }
+        self.state.create_index(schema.clone())?;
+        let created = self
+            .catalog
+            .engine
+            .table(&schema.table)
+            .ok_or_else(|| Error::Storage(format!("table {} has no storage", schema.table)))
+            .and_then(|table| table.create_index(schema.clone()));
+        if let Err(err) = created {
+            self.state.drop_index(&schema.name)?;
+            return Err(err);
+        }
+        self.created.push(storage);
+        Ok(())
+    }
+
+    /// Drops an index. Its storage goes on commit.
+    pub fn drop_index(&mut self, name: &str) -> Result<IndexSchema, Error> {
+        if let Some(index) = self.state.all_indexes().into_iter().find(|i| i.name.eq_ignore_ascii_case(name)) {
+            self.unaltered(&index.table)?;
+        }
+        let schema = self.state.drop_index(name)?;
+        let storage = Storage::Index { table: schema.table.to_lowercase(), name: schema.name.to_lowercase() };
+        match self.created.iter().position(|s| *s == storage) {
+            Some(i) => {
+                self.created.remove(i);
+                if let Some(table) = self.catalog.engine.table(&schema.table) {
+                    table.drop_index(&schema.name)?;
+                }
+            }
+            None => self.dropped.push(storage),
+        }
+        Ok(schema)
+    }
+
+    /// Alters a table. Its rows are converted, and checked against new
+    /// constraints, straight away while it is written; the table itself
+    /// changes on commit. Altering a table again in the same transaction
+    /// starts over from its rows as they are stored.
+    pub fn alter_table(&mut self, alter: &AlterStatement) -> Result<(), Error> {
+        reserved(&alter.table.name)?;
+        let engine = self.catalog.engine.clone();
+        let mut plan = alter::plan(&self.state, alter)?;
+        let table = storage(engine.as_ref(), &plan.schema.name)?;
+        let previous = match self.alteration(&plan.schema.name) {
+            Some(i) => Some(self.alterations.remove(i).abandon(engine.as_ref())?),
+            None => None,
+        };
+        if let Some(first) = &previous {
+            plan.sources = alter::compose(&first.sources, &plan.sources)?;
+            plan.rewrite |= first.rewrite;
+            plan.validate |= first.validate;
+            if plan.rewrite {
+                for column in &mut plan.schema.columns {
+                    column.missing = None;
+                }
+            }
+        }
+        let referencing = std::mem::take(&mut plan.referencing);
+        let (schema, indexes) = (plan.schema.clone(), plan.indexes.clone());
+        match Alteration::start(engine.as_ref(), table.clone(), plan) {
+            Ok(alteration) => self.alterations.push(alteration),
+            Err(err) => {
+                if let Some(first) = previous {
+                    self.alterations.push(Alteration::start(engine.as_ref(), table, first)?);
+                }
+                return Err(err);
+            }
+        }
+        for index in self.state.indexes(&schema.name) {
+            self.state.drop_index(&index.name)?;
+        }
+        self.state.replace_table(schema)?;
+        for index in indexes {
+            self.state.create_index(index)?;
+        }
+        // Foreign keys naming renamed columns change with them.
+        for other in referencing {
+            if self.alteration(&other.name).is_none() {
+                let plan = Plan::keep(other.clone(), self.state.indexes(&other.name));
+                let table = storage(engine.as_ref(), &other.name)?;
+                self.alterations.push(Alteration::start(engine.as_ref(), table, plan)?);
+            }
+            self.state.replace_table(other)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a type. The types its members use must exist.
+    pub fn create_type(&mut self, schema: TypeSchema) -> Result<(), Error> {
+        let members = schema.member_names();
+        if members.is_empty() {
+            return Err(Error::Execution(format!("type {} must have at least one member", schema.name)));
+        }
+        for (i, member) in members.iter().enumerate() {
+            if members[..i].iter().any(|m| m.eq_ignore_ascii_case(member)) {
+                return Err(Error::Execution(format!("type {} has {} more than once", schema.name, member)));
+            }
+        }
+        for used in schema.uses() {
+            if used.eq_ignore_ascii_case(&schema.name) {
+                return Err(Error::Execution(format!("type {} cannot contain itself", schema.name)));
+            }
+            if self.state.custom_type(used).is_none() {
+                return Err(Error::Execution(format!("type {} does not exist", used)));
+            }
+        }
+        self.state.create_type(schema)
+    }
+
+    /// Adds a field or variant to a type, or renames a field, making a new
+    /// version of the type. The rows of the tables using it are not
+    /// touched: values stored under older versions read as the latest, and
+    /// [`CatalogTransaction::rewrite_type`] stores them that way. A field
+    /// cannot be added or renamed while an index covers a column holding
+    /// the type, as the index keys hold the field names.
+    pub fn alter_type(&mut self, alter: &AlterTypeStatement) -> Result<(), Error> {
+        let mut schema = self
+            .state
+            .custom_type(&alter.name)
+            .ok_or_else(|| Error::Execution(format!("type {} does not exist", alter.name)))?;
+        let name = schema.name.clone();
+        let has_member = |member: &str| schema.member_names().iter().any(|m| m.eq_ignore_ascii_case(member));
+        let exists = |member: &str| Error::Execution(format!("type {} already has a member {}", name, member));
+        // Indexes on the type's values name its fields in their paths.
+        let renames_field = matches!(alter.action, AlterTypeAction::RenameField(..));
+        match (&alter.action, &schema.kind) {
+            (AlterTypeAction::AddField { name: field, data_type, default }, TypeKind::Struct(_)) => {
+                if has_member(field) {
+                    return Err(exists(field));
+                }
+                self.check_member_type(data_type, &name)?;
+                let default = match default {
+                    Some(default) => Some(cast(&constant(default)?, data_type)?).filter(|v| !v.is_null()),
+                    None => None,
+                };
+                if default.is_some() && custom_type_name(data_type).is_some() {
+                    return Err(Error::Execution(format!("field {} of a custom type cannot have a default", field)));
+                }
+                if let TypeKind::Struct(fields) = &mut schema.kind {
+                    fields.push((field.clone(), data_type.clone()));
+                }
+                schema.added.push(default);
+            }
+            (AlterTypeAction::AddVariant { name: variant, payload }, TypeKind::Enum(_)) => {
+                if has_member(variant) {
+                    return Err(exists(variant));
+                }
+                if let Some(payload) = payload {
+                    self.check_member_type(payload, &name)?;
+                }
+                if let TypeKind::Enum(variants) = &mut schema.kind {
+                    variants.push((variant.clone(), payload.clone()));
+                }
+                schema.added.push(None);
+            }
+            (AlterTypeAction::RenameField(from, to), TypeKind::Struct(fields)) => {
+                let at = fields
+                    .iter()
+                    .position(|(field, _)| field.eq_ignore_ascii_case(from))
+                    .ok_or_else(|| Error::Execution(format!("type {} has no field {}", name, from)))?;
+                if has_member(to) && !from.eq_ignore_ascii_case(to) {
+                    return Err(exists(to));
+                }
+                if let TypeKind::Struct(fields) = &mut schema.kind {
+                    fields[at].0 = to.clone();
+                }
+            }
+            (AlterTypeAction::AddVariant { .. }, _) => {
+                return Err(Error::Execution(format!("type {} is not an enum", name)));
+            }
+            _ => return Err(Error::Execution(format!("type {} is not a struct", name))),
+        }
+
+        let using = self.tables_using(&name);
+        for table in &using {
+            self.unaltered(&table.name)?;
+            if !renames_field {
+                continue;
+            }
+            let holds_type = |column: &str| {
+                table.column(column).is_some_and(|c| self.contains_type(&c.data_type, &name))
+            };
+            if let Some(index) = self
+                .state
+                .indexes(&table.name)
+                .into_iter()
+                .find(|index| index.columns.iter().any(|path| holds_type(&path.column)))
+            {
+                return Err(Error::Execution(format!(
+                    "index {} covers values of type {}; drop it to change the type's fields",
+                    index.name, name
+                )));
+            }
+        }
+        self.state.replace_type(schema)?;
+        let engine = self.catalog.engine.clone();
+        for table in using {
+            let schema = self.state.table(&table.name).expect("the table uses the type");
+            let plan = Plan::keep(schema, self.state.indexes(&table.name));
+            let table = storage(engine.as_ref(), &table.name)?;
+            self.alterations.push(Alteration::start(engine.as_ref(), table, plan)?);
+        }
+        Ok(())
+    }
+
+    /// Rewrites the rows of the tables using a type, so that its values are
+    /// all stored under its latest version. Writes to the tables go on while
+    /// their rows are copied.
+    pub fn rewrite_type(&mut self, name: &str) -> Result<(), Error> {
+        if self.state.custom_type(name).is_none() {
+            return Err(Error::Execution(format!("type {} does not exist", name)));
+        }
+        let engine = self.catalog.engine.clone();
+        for mut schema in self.tables_using(name) {
+            self.unaltered(&schema.name)?;
+            // Rewritten rows hold every column.
+            for column in &mut schema.columns {
+                column.missing = None;
+            }
+            let mut plan = Plan::keep(schema.clone(), self.state.indexes(&schema.name));
+            plan.rewrite = true;
+            let table = storage(engine.as_ref(), &schema.name)?;
+            self.alterations.push(Alteration::start(engine.as_ref(), table, plan)?);
+            self.state.replace_table(schema)?;
+        }
+        Ok(())
+    }
+
+    /// The tables holding values of a type, directly or within other types.
+    fn tables_using(&self, name: &str) -> Vec<TableSchema> {
+        let uses = |table: &&TableSchema| table.types.iter().any(|ty| ty.name.eq_ignore_ascii_case(name));
+        self.state.tables().into_iter().filter(uses).cloned().collect()
+    }
+
+    /// Whether values of `data_type` hold values of the type `name`.
+    fn contains_type(&self, data_type: &DataType, name: &str) -> bool {
+        let Some(used) = custom_type_name(data_type) else { return false };
+        used.eq_ignore_ascii_case(name)
+            || self
+                .state
+                .custom_type(used)
+                .is_some_and(|ty| ty.member_types().into_iter().flatten().any(|ty| self.contains_type(ty, name)))
+    }
+
+    /// Checks a member's type for the type `name`.
+    fn check_member_type(&self, data_type: &DataType, name: &str) -> Result<(), Error> {
+        match custom_type_name(data_type) {
+            Some(used) if self.state.custom_type(used).is_none() => {
+                Err(Error::Execution(format!("type {} does not exist", used)))
+            }
+            _ if self.contains_type(data_type, name) => {
+                Err(Error::Execution(format!("type {} cannot contain itself", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn drop_type(&mut self, name: &str) -> Result<TypeSchema, Error> {
+        self.state.drop_type(name)
+    }
+
+    pub fn create_view(&mut self, schema: ViewSchema) -> Result<(), Error> {
+        reserved(&schema.name)?;
+        let Statement::Select(_) = parse_sql(&schema.query)? else {
+            return Err(Error::Execution(format!("view {} must be defined by a SELECT", schema.name)));
+        };
+        self.state.create_view(schema)
+    }
+
+    pub fn drop_view(&mut self, name: &str) -> Result<ViewSchema, Error> {
+        self.state.drop_view(name)
+    }
+
+    pub fn create_sequence(&mut self, schema: SequenceSchema) -> Result<(), Error> {
+        self.state.create_sequence(schema)
+    }
+
+    pub fn drop_sequence(&mut self, name: &str) -> Result<SequenceSchema, Error> {
+        self.state.drop_sequence(name)
+    }
+
+    pub fn record_migration(&mut self, migration: AppliedMigration) -> Result<(), Error> {
+        self.state.record_migration(migration)
+    }
+
+    pub fn forget_migration(&mut self, name: &str) -> Result<AppliedMigration, Error> {
+        self.state.forget_migration(name)
+    }
+
+    /// Analyzes and makes a schema change written in SQL: `CREATE TABLE`,
+    /// `DROP TABLE`, `CREATE INDEX`, `ALTER TABLE`, `CREATE TYPE` or
+    /// `ALTER TYPE`; or a
+    /// change to rows, `INSERT`, `UPDATE` or `DELETE`, which other
+    /// statements see straight away and which is undone on rollback. Rows
+    /// of a table altered in the transaction cannot be changed.
+    pub fn execute(&mut self, statement: &Statement) -> Result<(), Error> {
+        let analyzed = Analyzer::new(&*self).analyze(statement)?;
+        match analyzed.statement {
+            Statement::Create(create) => {
+                if create.temporary {
+                    return Err(Error::Execution("temporary tables are not supported".to_string()));
+                }
+                if !(create.if_not_exists && self.table(&create.table.name).is_some()) {
+                    self.create_table(TableSchema::from_create(&create))?;
+                }
+            }
+            Statement::Drop(drop) => {
+                if !(drop.if_exists && self.table(&drop.table.name).is_none()) {
+                    self.drop_table(&drop.table.name)?;
+                }
+            }
+            Statement::CreateIndex(create) => {
+                let exists = self.indexes(&create.table.name).iter().any(|i| i.name.eq_ignore_ascii_case(&create.name));
+                if !(create.if_not_exists && exists) {
+                    self.create_index(IndexSchema::from_create(&create))?;
+                }
+            }
+            Statement::Alter(alter) => self.alter_table(&alter)?,
+            Statement::CreateType(create) => {
+                if !(create.if_not_exists && self.custom_type(&create.name).is_some()) {
+                    self.create_type(TypeSchema::from_create(&create))?;
+                }
+            }
+            Statement::AlterType(alter) => self.alter_type(&alter)?,
+            Statement::Insert(InsertStatement { ref table, .. })
+            | Statement::Update(UpdateStatement { ref table, .. })
+            | Statement::Delete(DeleteStatement { ref table, .. }) => {
+                self.unaltered(&table.name)?;
+                let (catalog, state, rows) = (self.catalog, &self.state, &mut self.rows);
+                catalog.atomic(|| {
+                    let _writes = catalog.lock_writes();
+                    change_rows(state, catalog.engine.as_ref(), &analyzed.statement, rows)
+                })?;
+            }
+            _ => return Err(Error::Execution("only schema and row changes run in a catalog transaction".to_string())),
+        }
+        Ok(())
+    }
+
+    /// Applied migrations as this transaction sees them, in version order.
+    pub fn migrations(&self) -> Vec<&AppliedMigration> {
+        self.state.migrations()
+    }
+
+    /// Makes the changes durable and visible. If the system tables cannot
+    /// be written, the transaction is rolled back instead.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.done = true;
+        let catalog = self.catalog;
+        catalog.atomic(|| self.commit_storage())
+    }
+
+    fn commit_storage(&mut self) -> Result<(), Error> {
+        let engine = self.catalog.engine.clone();
+        let mut alterations = std::mem::take(&mut self.alterations);
+        let changes = alterations
+            .iter()
+            .map(|alteration| {
+                let name = &alteration.plan().schema.name;
+                let schema = self
+                    .state
+                    .table(name)
+                    .ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))?;
+                alteration.change(engine.as_ref(), schema, self.state.indexes(name))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let written = changes.and_then(|changes| {
+            let mut steps: Vec<_> = alterations.iter_mut().zip(changes).collect();
+            self.alter_and_write(&mut steps)
+        });
+        if let Err(err) = written {
+            for alteration in alterations {
+                alteration.abandon(engine.as_ref())?;
+            }
+            self.undo_storage()?;
+            return Err(err);
+        }
+        for alteration in alterations {
+            alteration.finish();
+        }
+        *self.catalog.cache.write() = Arc::new(std::mem::take(&mut self.state));
+        for storage in std::mem::take(&mut self.dropped) {
+            match storage {
+                Storage::Table(name) => {
+                    engine.drop_table(&name)?;
+                }
+                Storage::Index { table, name } => {
+                    if let Some(table) = engine.table(&table) {
+                        table.drop_index(&name)?;
+                    }
+                }
+            }
+        }
+        engine.flush()
+    }
+
+    /// Undoes the changes.
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.done = true;
+        self.undo_storage()
+    }
+
+    fn undo_storage(&mut self) -> Result<(), Error> {
+        let engine = &self.catalog.engine;
+        keys::undo(engine.as_ref(), std::mem::take(&mut self.rows))?;
+        for alteration in std::mem::take(&mut self.alterations) {
+            alteration.abandon(engine.as_ref())?;
+        }
+        for storage in std::mem::take(&mut self.created).into_iter().rev() {
+            match storage {
+                Storage::Table(name) => {
+                    engine.drop_table(&name)?;
+                }
+                Storage::Index { table, name } => {
+                    if let Some(table) = engine.table(&table) {
+                        table.drop_index(&name)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The position of the table's alteration, if it is altered.
+    fn alteration(&self, table: &str) -> Option<usize> {
+        self.alterations.iter().position(|a| a.plan().schema.name.eq_ignore_ascii_case(table))
+    }
+
+    fn unaltered(&self, table: &str) -> Result<(), Error> {
+        match self.alteration(table) {
+            Some(_) => Err(Error::Execution(format!("table {} was altered in this transaction", table))),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes each change, after the last catch-up of its alteration, then
+    /// writes the system tables, all while writes to the tables wait; or
+    /// does none of it.
+    fn alter_and_write(&self, steps: &mut [(&mut Alteration, TableChange)]) -> Result<(), Error> {
+        let Some(((alteration, change), rest)) = steps.split_first_mut() else {
+            return self.write();
+        };
+        let engine = self.catalog.engine.as_ref();
+        let name = alteration.plan().schema.name.clone();
+        engine.alter_table(&name, change.clone(), &mut || {
+            alteration.catch_up(engine, true)?;
+            self.alter_and_write(rest)
+        })
+    }
+
+    /// Writes every changed object to the system tables, or nothing.
+    fn write(&self) -> Result<(), Error> {
+        let mut writer = Writer { engine: self.catalog.engine.as_ref(), undo: Vec::new() };
+        let written = writer.write_changes(&self.base, &self.state);
+        if written.is_err() {
+            writer.undo()?;
+        }
+        written
+    }
+}
+
+impl Drop for CatalogTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.undo_storage();
+        }
+    }
+}
+
+/// The transaction sees its own changes.
+impl Catalog for CatalogTransaction<'_> {
+    fn table(&self, name: &str) -> Option<TableSchema> {
+        match information_schema::view_name(name) {
+            Some(view) => information_schema::schema(view),
+            None => self.state.table(name),
+        }
+    }
+
+    fn indexes(&self, table: &str) -> Vec<IndexSchema> {
+        self.state.indexes(table)
+    }
+
+    fn view(&self, name: &str) -> Option<ViewSchema> {
+        self.state.view(name)
+    }
+
+    fn custom_type(&self, name: &str) -> Option<TypeSchema> {
+        self.state.custom_type(name)
+    }
+
+    fn sequence(&self, name: &str) -> Option<SequenceSchema> {
+        self.state.sequence(name)
+    }
+}
+
+impl CatalogTransaction<'_> {
+    /// An `information_schema` view as this transaction sees the catalog.
+    pub fn information_schema(&self, view: &str) -> Option<ViewTable> {
+        let view = information_schema::VIEWS.iter().find(|v| v.eq_ignore_ascii_case(view))?;
+        information_schema::view(&self.state, view)
+    }
+}
+
+fn reserved(name: &str) -> Result<(), Error> {
+    let lower = name.to_lowercase();
+    if lower.starts_with(RESERVED_PREFIX) || lower.starts_with(&format!("{}.", information_schema::SCHEMA)) {
+        return Err(Error::Execution(format!("name {} is reserved", name)));
+    }
+    Ok(())
+}
+
+fn storage(engine: &dyn StorageEngine, table: &str) -> Result<Arc<dyn Table>, Error> {
+    engine.table(table).ok_or_else(|| Error::Storage(format!("table {} has no storage", table)))
+}
+
+fn system_table(engine: &dyn StorageEngine, name: &str) -> Result<Arc<dyn Table>, Error> {
+    engine.table(name).ok_or_else(|| Error::Storage(format!("system table {} is missing", name)))
+}
+
+/// The rows of a system table that belong to the named object.
+fn owned_rows(table: &dyn Table, owner: &str) -> Result<Vec<(RowId, Vec<Value>)>, Error> {
+    let mut rows = Vec::new();
+    for row in table.scan() {
+        let (id, row) = row?;
+        if matches!(&row[0], Value::String(name) if name.eq_ignore_ascii_case(owner)) {
+            rows.push((id, row));
+        }
+    }
+    Ok(rows)
+}
+
+/// A system table change, as it is undone.
+enum Undo {
+    Inserted(Arc<dyn Table>, RowId),
+    Deleted(Arc<dyn Table>, Vec<Value>),
+}
+
+/// Writes system tables, remembering how to undo what it wrote.
+struct Writer<'a> {
+    engine: &'a dyn StorageEngine,
+    undo: Vec<Undo>,
+}
+
+impl Writer<'_> {
+    fn write_changes(&mut self, old: &MemoryCatalog, new: &MemoryCatalog) -> Result<(), Error> {
+        let (removed, added) = changes(old.tables(), new.tables(), |t| &t.name);
+        for table in removed {
+            for system in [TABLES, COLUMNS, CONSTRAINTS] {
+                self.delete(system, &table.name)?;
+            }
+        }
+        for table in added {
+            self.insert(TABLES, vec![text(&table.name)])?;
+            for (i, column) in table.columns.iter().enumerate() {
+                self.insert(
+                    COLUMNS,
+                    vec![
+                        text(&table.name),
+                        Value::Int(i as i64),
+                        text(&column.name),
+                        text(&column.data_type.to_string()),
+                        Value::Bool(column.nullable),
+                        Value::Bool(column.primary_key),
+                        column.default.as_ref().map_or(Value::Null, |default| text(&default.to_string())),
+                        match &column.missing {
+                            Some(value) => {
+                                Value::Bytes(RowCodec::encode_value(&column.data_type, &table.types, value)?)
+                            }
+                            None => Value::Null,
+                        },
+                    ],
+                )?;
+            }
+            for (i, constraint) in table.constraints.iter().enumerate() {
+                let name = constraint.name().unwrap_or_default();
+                let row = vec![
+                    text(&table.name),
+                    Value::Int(i as i64),
+                    text(name),
+                    text(constraint.kind()),
+                    text(&constraint.to_string()),
+                ];
+                self.insert(CONSTRAINTS, row)?;
+            }
+        }
+
+        let (removed, added) = changes(old.all_indexes(), new.all_indexes(), |i| &i.name);
+        for index in removed {
+            self.delete(INDEXES, &index.name)?;
+        }
+        for index in added {
+            self.insert(INDEXES, vec![text(&index.name), text(&index.table), text(&index.to_sql())])?;
+        }
+
+        let (removed, added) = changes(old.types(), new.types(), |t| &t.name);
+        for schema in removed {
+            self.delete(TYPES, &schema.name)?;
+        }
+        for schema in added {
+            for row in schema.to_rows()? {
+                self.insert(TYPES, row)?;
+            }
+        }
+
+        let (removed, added) = changes(old.views(), new.views(), |v| &v.name);
+        for view in removed {
+            self.delete(VIEWS, &view.name)?;
+        }
+        for view in added {
+            let columns = if view.columns.is_empty() { Value::Null } else { text(&view.columns.join(",")) };
+            self.insert(VIEWS, vec![text(&view.name), columns, text(&view.query)])?;
+        }
+
+        let (removed, added) = changes(old.sequences(), new.sequences(), |s| &s.name);
+        for sequence in removed {
+            self.delete(SEQUENCES, &sequence.name)?;
+        }
+        for s in added {
+            let row = vec![
+                text(&s.name),
+                Value::Int(s.start),
+                Value::Int(s.increment),
+                Value::Int(s.min),
+                Value::Int(s.max),
+                Value::Bool(s.cycle),
+                Value::Null,
+            ];
+            self.insert(SEQUENCES, row)?;
+        }
+
+        let (removed, added) = changes(old.migrations(), new.migrations(), |m| &m.name);
+        for migration in removed {
+            self.delete(MIGRATIONS, &migration.name)?;
+        }
+        for m in added {
+            let checksum = Value::Int(m.checksum.into());
+            let row = vec![text(&m.name), Value::Int(m.version), checksum, Value::DateTime(m.applied_at)];
+            self.insert(MIGRATIONS, row)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, system: &str, row: Vec<Value>) -> Result<(), Error> {
+        let table = system_table(self.engine, system)?;
+        let id = table.insert(row)?;
+        self.undo.push(Undo::Inserted(table, id));
+        Ok(())
+    }
+
+    fn delete(&mut self, system: &str, owner: &str) -> Result<(), Error> {
+        let table = system_table(self.engine, system)?;
+        for (id, row) in owned_rows(table.as_ref(), owner)? {
+            table.delete(id)?;
+            self.undo.push(Undo::Deleted(table.clone(), row));
+        }
+        Ok(())
+    }
+
+    fn undo(self) -> Result<(), Error> {
+        for undo in self.undo.into_iter().rev() {
+            match undo {
+                Undo::Inserted(table, id) => {
+                    table.delete(id)?;
+                }
+                Undo::Deleted(table, row) => {
+                    table.insert(row)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The objects of `old` that are gone or changed in `new`, and those of
+/// `new` that are new or changed.
+fn changes<'a, T: PartialEq>(
+    old: Vec<&'a T>,
+    new: Vec<&'a T>,
+    name: impl Fn(&T) -> &String,
+) -> (Vec<&'a T>, Vec<&'a T>) {
+    let by_name = |objects: &[&'a T]| -> HashMap<String, &'a T> {
+        objects.iter().map(|object| (name(object).to_lowercase(), *object)).collect()
+    };
+    let (old_names, new_names) = (by_name(&old), by_name(&new));
+    let removed = old.into_iter().filter(|o| new_names.get(&name(o).to_lowercase()) != Some(o)).collect();
+    let added = new.into_iter().filter(|n| old_names.get(&name(n).to_lowercase()) != Some(n)).collect();
+    (removed, added)
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+/// Reads the catalog back from the system tables.
+fn load(engine: &dyn StorageEngine) -> Result<MemoryCatalog, Error> {
+    let corrupt = |table: &str| Error::Storage(format!("corrupt row in {}", table));
+    let rows = |name: &str| -> Result<Vec<Vec<Value>>, Error> {
+        system_table(engine, name)?.scan().map(|row| row.map(|(_, row)| row)).collect()
+    };
+    let get_text = |row: &[Value], i: usize, table: &str| match &row[i] {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(corrupt(table)),
+    };
+    let get_int = |row: &[Value], i: usize, table: &str| match row[i] {
+        Value::Int(n) => Ok(n),
+        _ => Err(corrupt(table)),
+    };
+    let get_bool = |row: &[Value], i: usize, table: &str| match row[i] {
+        Value::Bool(b) => Ok(b),
+        _ => Err(corrupt(table)),
+    };
+    let optional = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    };
+    let mut catalog = MemoryCatalog::new();
+
+    // Tables take the definitions of the types they use.
+    let types = TypeSchema::from_rows(rows(TYPES)?)?;
+    for schema in types.clone() {
+        catalog.create_type(schema)?;
+    }
+
+    let mut tables: HashMap<String, TableSchema> = HashMap::new();
+    for row in rows(TABLES)? {
+        let name = get_text(&row, 0, TABLES)?;
+        tables.insert(name.to_lowercase(), TableSchema::new(name, Vec::new()));
+    }
+    let mut columns = rows(COLUMNS)?;
+    columns.sort_by_key(|row| get_int(row, 1, COLUMNS).unwrap_or_default());
+    for row in columns {
+        let table = tables.get_mut(&get_text(&row, 0, COLUMNS)?.to_lowercase()).ok_or_else(|| corrupt(COLUMNS))?;
+        let mut column = ColumnSchema::new(get_text(&row, 2, COLUMNS)?, parse_data_type(&get_text(&row, 3, COLUMNS)?)?);
+        column.nullable = get_bool(&row, 4, COLUMNS)?;
+        column.primary_key = get_bool(&row, 5, COLUMNS)?;
+        column.default = optional(&row[6]).map(|default| parse_expr(&default)).transpose()?;
+        if let Value::Bytes(bytes) = &row[7] {
+            column.missing = Some(RowCodec::decode_value(&column.data_type, &types, bytes)?);
+        }
+        table.columns.push(column);
+    }
+    let mut constraints = rows(CONSTRAINTS)?;
+    constraints.sort_by_key(|row| get_int(row, 1, CONSTRAINTS).unwrap_or_default());
+    for row in constraints {
+        let key = get_text(&row, 0, CONSTRAINTS)?.to_lowercase();
+        let table = tables.get_mut(&key).ok_or_else(|| corrupt(CONSTRAINTS))?;
+        table.constraints.push(parse_table_constraint(&get_text(&row, 4, CONSTRAINTS)?)?);
+    }
+    for (_, table) in tables {
+        catalog.create_table(table)?;
+    }
+
+    for row in rows(INDEXES)? {
+        let Statement::CreateIndex(create) = parse_sql(&get_text(&row, 2, INDEXES)?)? else {
+            return Err(corrupt(INDEXES));
+        };
+        catalog.create_index(IndexSchema::from_create(&create))?;
+    }
+
+    for row in rows(VIEWS)? {
+        let columns = optional(&row[1]).map_or_else(Vec::new, |c| c.split(',').map(str::to_string).collect());
+        catalog.create_view(ViewSchema { name: get_text(&row, 0, VIEWS)?, columns, query: get_text(&row, 2, VIEWS)? })?;
+    }
+
+    for row in rows(SEQUENCES)? {
+        catalog.create_sequence(SequenceSchema {
+            name: get_text(&row, 0, SEQUENCES)?,
+            start: get_int(&row, 1, SEQUENCES)?,
+            increment: get_int(&row, 2, SEQUENCES)?,
+            min: get_int(&row, 3, SEQUENCES)?,
+            max: get_int(&row, 4, SEQUENCES)?,
+            cycle: get_bool(&row, 5, SEQUENCES)?,
+        })?;
+    }
+
+    for row in rows(MIGRATIONS)? {
+        let Value::DateTime(applied_at) = row[3] else { return Err(corrupt(MIGRATIONS)) };
+        catalog.record_migration(AppliedMigration {
+            name: get_text(&row, 0, MIGRATIONS)?,
+            version: get_int(&row, 1, MIGRATIONS)?,
+            checksum: u32::try_from(get_int(&row, 2, MIGRATIONS)?).map_err(|_| corrupt(MIGRATIONS))?,
+            applied_at,
+        })?;
+    }
+    Ok(catalog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::engine::{DiskEngine, MemoryEngine};
+    use crate::storage::{Pager, PagerOptions};
+
+    fn table(sql: &str) -> TableSchema {
+        let Statement::Create(create) = parse_sql(sql).unwrap() else { panic!("Expected CREATE TABLE") };
+        TableSchema::from_create(&create)
+    }
+
+    fn index(sql: &str) -> IndexSchema {
+        let Statement::CreateIndex(create) = parse_sql(sql).unwrap() else { panic!("Expected CREATE INDEX") };
+        IndexSchema::from_create(&create)
+    }
+
+    fn names(table: &dyn Table, column: usize) -> Vec<String> {
+        let name = |value: &Value| match value {
+            Value::String(name) => name.clone(),
+            other => panic!("Expected a name, got {:?}", other),
+        };
+        table.scan().map(|row| name(&row.unwrap().1[column])).collect()
+    }
+
+    #[test]
+    fn test_rolled_back_changes_leave_no_trace() {
+        let engine: Arc<dyn StorageEngine> = Arc::new(MemoryEngine::new());
+        let catalog = SystemCatalog::open(engine.clone()).unwrap();
+        let mut txn = catalog.begin();
+        txn.create_table(table("CREATE TABLE kept (id INT PRIMARY KEY)")).unwrap();
+        txn.commit().unwrap();
+
+        let mut txn = catalog.begin();
+        txn.create_table(table("CREATE TABLE t (id INT)")).unwrap();
+        txn.create_index(index("CREATE INDEX kept_id ON kept (id)")).unwrap();
+        txn.drop_table("kept").unwrap();
+        assert!(txn.table("t").is_some());
+        assert!(catalog.table("t").is_none());
+        txn.rollback().unwrap();
+        assert!(catalog.table("t").is_none());
+        assert!(engine.table("t").is_none());
+        assert!(catalog.table("kept").is_some());
+        assert!(engine.table("kept").unwrap().indexes().is_empty());
+
+        // Dropping a transaction rolls it back too.
+        {
+            let mut txn = catalog.begin();
+            txn.create_table(table("CREATE TABLE t (id INT)")).unwrap();
+        }
+        assert!(engine.table("t").is_none());
+
+        let mut txn = catalog.begin();
+        assert!(txn.create_table(table("CREATE TABLE rustdb_tables (id INT)")).is_err());
+        txn.drop_table("kept").unwrap();
+        txn.commit().unwrap();
+        assert!(engine.table("kept").is_none());
+        assert!(names(catalog.information_schema("tables").unwrap().as_ref(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_catalog_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || {
+            let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+            let engine = DiskEngine::open(Arc::new(BufferPool::new(pager, 64))).unwrap();
+            SystemCatalog::open(Arc::new(engine)).unwrap()
+        };
+        let users = table(
+            "CREATE TABLE users (id BIGINT PRIMARY KEY, email VARCHAR(200) NOT NULL UNIQUE, \
+             age INT CHECK (age >= 0) DEFAULT 18, manager BIGINT REFERENCES users (id) ON DELETE SET NULL)",
+        );
+        let fields = vec![("x".into(), DataType::Real), ("y".into(), DataType::Real)];
+        let point = TypeSchema::new("point", TypeKind::Struct(fields));
+        let shape = TypeSchema::new(
+            "shape",
+            TypeKind::Enum(vec![("empty".into(), None), ("circle".into(), Some(DataType::Real))]),
+        );
+        let adults =
+            ViewSchema { name: "adults".into(), columns: vec![], query: "SELECT * FROM users WHERE age >= 18".into() };
+        let ids = SequenceSchema { start: 10, increment: 5, max: 20, ..SequenceSchema::new("ids") };
+        {
+            let catalog = open();
+            let mut txn = catalog.begin();
+            txn.create_table(users.clone()).unwrap();
+            txn.create_index(index("CREATE INDEX by_age ON users USING HASH (age)")).unwrap();
+            txn.create_type(point.clone()).unwrap();
+            txn.create_type(shape.clone()).unwrap();
+            txn.create_view(adults.clone()).unwrap();
+            txn.create_sequence(ids.clone()).unwrap();
+            assert!(txn.create_view(ViewSchema { name: "users".into(), ..adults.clone() }).is_err());
+            txn.commit().unwrap();
+            assert_eq!(catalog.next_value("ids").unwrap(), 10);
+        }
+
+        let catalog = open();
+        assert_eq!(catalog.table("USERS").unwrap().to_sql(), users.to_sql());
+        assert_eq!(
+            catalog.table("users").unwrap().constraint("users_manager_fkey").unwrap().to_string(),
+            "CONSTRAINT users_manager_fkey FOREIGN KEY (manager) REFERENCES users (id) ON DELETE SET NULL"
+        );
+        assert_eq!(catalog.indexes("users")[0].name, "by_age");
+        assert_eq!(catalog.custom_type("point"), Some(point));
+        assert_eq!(catalog.custom_type("shape"), Some(shape));
+        assert_eq!(catalog.view("adults"), Some(adults));
+        assert_eq!(catalog.sequence("ids"), Some(ids));
+        assert_eq!(catalog.next_value("ids").unwrap(), 15);
+        assert_eq!(catalog.next_value("ids").unwrap(), 20);
+        assert!(catalog.next_value("ids").is_err());
+    }
+
+    #[test]
+    fn test_alter_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || {
+            let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+            let engine = DiskEngine::open(Arc::new(BufferPool::new(pager, 64))).unwrap();
+            Arc::new(SystemCatalog::open(Arc::new(engine)).unwrap())
+        };
+        let alter = |catalog: &SystemCatalog, sql: &str| {
+            let Statement::AlterType(alter) = parse_sql(sql).unwrap() else { panic!("Expected ALTER TYPE") };
+            let mut txn = catalog.begin();
+            txn.alter_type(&alter)?;
+            txn.commit()
+        };
+        let homes = |catalog: &SystemCatalog| -> Vec<Value> {
+            let table = catalog.engine().table("people").unwrap();
+            table.scan().map(|row| row.unwrap().1[1].clone()).collect()
+        };
+        let address = |fields: &[(&str, &str)]| {
+            Value::Struct(fields.iter().map(|(n, v)| (n.to_string(), Value::String(v.to_string()))).collect())
+        };
+        let old = address(&[("street", "1 High St"), ("zip", "AB1")]);
+        for catalog in [Arc::new(SystemCatalog::open(Arc::new(MemoryEngine::new())).unwrap()), open()] {
+            let mut txn = catalog.begin();
+            let fields = vec![("street".into(), DataType::Text), ("zip".into(), DataType::Text)];
+            txn.create_type(TypeSchema::new("address", TypeKind::Struct(fields))).unwrap();
+            txn.create_table(table("CREATE TABLE people (id BIGINT PRIMARY KEY, home address)")).unwrap();
+            assert!(txn.create_table(table("CREATE TABLE t (a nowhere)")).is_err());
+            txn.commit().unwrap();
+            catalog.engine().table("people").unwrap().insert(vec![Value::Int(1), old.clone()]).unwrap();
+
+            alter(&catalog, "ALTER TYPE address ADD FIELD country: VARCHAR(2) DEFAULT 'GB'").unwrap();
+            alter(&catalog, "ALTER TYPE address RENAME FIELD zip TO postcode").unwrap();
+            let new = address(&[("street", "1 High St"), ("postcode", "AB1"), ("country", "GB")]);
+            assert_eq!(homes(&catalog), vec![new.clone()]);
+            assert_eq!(catalog.table("people").unwrap().types[0].version(), 2);
+
+            let err = alter(&catalog, "ALTER TYPE address ADD VARIANT flat").unwrap_err();
+            assert!(err.to_string().contains("not an enum"), "{}", err);
+            let err = alter(&catalog, "ALTER TYPE address ADD FIELD home: address").unwrap_err();
+            assert!(err.to_string().contains("cannot contain itself"), "{}", err);
+            let mut txn = catalog.begin();
+            txn.create_index(index("CREATE INDEX by_street ON people (home.street)")).unwrap();
+            txn.commit().unwrap();
+            // Adding a field leaves the index be; renaming one, which its path may name, does not.
+            let Statement::AlterType(add) = parse_sql("ALTER TYPE address ADD FIELD city: TEXT").unwrap() else {
+                panic!("Expected ALTER TYPE")
+            };
+            let mut txn = catalog.begin();
+            txn.alter_type(&add).unwrap();
+            txn.rollback().unwrap();
+            let err = alter(&catalog, "ALTER TYPE address RENAME FIELD street TO road").unwrap_err();
+            assert!(err.to_string().contains("index by_street"), "{}", err);
+            let mut txn = catalog.begin();
+            assert!(txn.drop_type("address").is_err());
+            txn.drop_index("by_street").unwrap();
+            txn.commit().unwrap();
+
+            catalog.rewrite_type_in_background("address").join().unwrap().unwrap();
+            assert_eq!(homes(&catalog), vec![new]);
+        }
+
+        // The types' versions, and the rows stored under each, survive.
+        let catalog = open();
+        let address = catalog.custom_type("address").unwrap();
+        assert_eq!((address.version(), address.added.clone()), (2, vec![Some(Value::String("GB".into()))]));
+        let table = catalog.engine().table("people").unwrap();
+        let home = Value::Struct(vec![("postcode".into(), Value::String("CD2".into()))]);
+        table.insert(vec![Value::Int(2), home]).unwrap();
+        let countries: Vec<Value> = homes(&catalog).iter().map(|home| home.field("country").unwrap().clone()).collect();
+        assert_eq!(countries, vec![Value::String("GB".into()); 2]);
+    }
+
+    #[test]
+    fn test_information_schema() {
+        let catalog = SystemCatalog::open(Arc::new(MemoryEngine::new())).unwrap();
+        let mut txn = catalog.begin();
+        txn.create_table(table("CREATE TABLE t (a INT PRIMARY KEY, b TEXT UNIQUE DEFAULT 'x')")).unwrap();
+        txn.create_view(ViewSchema { name: "v".into(), columns: vec![], query: "SELECT a FROM t".into() }).unwrap();
+        assert_eq!(names(&txn.information_schema("TABLES").unwrap(), 1), vec!["BASE TABLE", "VIEW"]);
+        txn.commit().unwrap();
+
+        let columns = catalog.information_schema("columns").unwrap();
+        let rows: Vec<Vec<Value>> = columns.scan().map(|row| row.unwrap().1).collect();
+        assert_eq!(rows[1], vec![text("t"), text("b"), Value::Int(2), text("TEXT"), text("YES"), text("'x'")]);
+        let constraints = catalog.information_schema("table_constraints").unwrap();
+        assert_eq!(names(constraints.as_ref(), 0), vec!["t_pkey", "t_b_key"]);
+        assert!(columns.insert(rows[0].clone()).is_err());
+        assert!(catalog.table("information_schema.sequences").is_some());
+        assert!(catalog.information_schema("nonesuch").is_none());
+    }
+}
@@ -0,0 +1,302 @@
+// src/database.rs
+//! Opening a database over the storage engine of choice, with its
+//! catalog.
+//!
+//! A disk database logs its changes ahead to the directory next to its
+//! file, named after it with `-wal` appended, and recovers from the log on
+//! opening. It can take checkpoints of the log in the background, until
+//! the last handle to it is dropped.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::buffer::BufferPool;
+use crate::catalog::SystemCatalog;
+use crate::engine::{DiskEngine, MemoryEngine, StorageEngine};
+use crate::error::Error;
+use crate::storage::{Pager, PagerOptions};
+use crate::wal::{Checkpointer, TxnManager, Wal};
+
+/// Where a database keeps its tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineKind {
+    /// In memory, gone when the database is dropped.
+    Memory,
+    /// In the page file at the path, created if missing.
+    Disk(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseOptions {
+    pub engine: EngineKind,
+    /// Page options for a new disk file.
+    pub pager: PagerOptions,
+    /// Pages the buffer pool of a disk database holds.
+    pub buffer_pages: usize,
+    /// How often a disk database takes a checkpoint in the background, if
+    /// at all. Opening such a database needs a tokio runtime.
+    pub checkpoint_interval: Option<Duration>,
+}
+
+impl DatabaseOptions {
+    pub fn memory() -> Self {
+        DatabaseOptions {
+            engine: EngineKind::Memory,
+            pager: PagerOptions::default(),
+            buffer_pages: 1024,
+            checkpoint_interval: None,
+        }
+    }
+
+    pub fn disk(path: impl Into<PathBuf>) -> Self {
+        DatabaseOptions { engine: EngineKind::Disk(path.into()), ..DatabaseOptions::memory() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    engine: Arc<dyn StorageEngine>,
+    catalog: Arc<SystemCatalog>,
+    /// The log of a disk database.
+    txns: Option<Arc<TxnManager>>,
+    /// Stops when the last handle drops.
+    _checkpointer: Option<Arc<Checkpointer>>,
+}
+
+impl Database {
+    pub fn open(options: DatabaseOptions) -> Result<Self, Error> {
+        let (engine, txns): (Arc<dyn StorageEngine>, _) = match options.engine {
+            EngineKind::Memory => (Arc::new(MemoryEngine::new()), None),
+            EngineKind::Disk(path) => {
+                let wal = Arc::new(Wal::open(wal_path(&path))?);
+                let pager = Pager::open_with(path, options.pager)?;
+                let pool = Arc::new(BufferPool::new(pager, options.buffer_pages).with_wal(wal.clone()));
+                let txns = Arc::new(TxnManager::open(pool, wal)?.0);
+                (Arc::new(DiskEngine::open_logged(txns.clone())?), Some(txns))
+            }
+        };
+        let catalog = match &txns {
+            Some(txns) => SystemCatalog::open_logged(engine.clone(), txns.clone())?,
+            None => SystemCatalog::open(engine.clone())?,
+        };
+        let catalog = Arc::new(catalog);
+        let checkpointer = match (&txns, options.checkpoint_interval) {
+            (Some(txns), Some(interval)) => {
+                if tokio::runtime::Handle::try_current().is_err() {
+                    return Err(Error::Execution("background checkpoints need a tokio runtime".to_string()));
+                }
+                Some(Arc::new(Checkpointer::spawn(txns.clone(), interval)))
+            }
+            _ => None,
+        };
+        Ok(Database { engine, catalog, txns, _checkpointer: checkpointer })
+    }
+
+    /// Takes a checkpoint of a disk database's log, so that recovery
+    /// starts from there; see [`TxnManager::checkpoint`]. Other engines
+    /// just flush.
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        match &self.txns {
+            Some(txns) => txns.checkpoint().map(|_| ()),
+            None => self.engine.flush(),
+        }
+    }
+
+    /// Runs `f` as one transaction of a disk database's log, so that a
+    /// crash part way through it is rolled back on recovery; see
+    /// [`TxnManager::atomic`]. Other engines just run it.
+    pub(crate) fn atomic<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        match &self.txns {
+            Some(txns) => txns.atomic(f),
+            None => f(),
+        }
+    }
+
+    pub fn engine(&self) -> &Arc<dyn StorageEngine> {
+        &self.engine
+    }
+
+    /// Tables are created and dropped through the catalog, never the
+    /// engine: storage the catalog does not know of is dropped on opening.
+    pub fn catalog(&self) -> &Arc<SystemCatalog> {
+        &self.catalog
+    }
+}
+
+/// The log directory of the database file at `path`.
+fn wal_path(path: &std::path::Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push("-wal");
+    name.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Catalog, ColumnSchema, TableSchema};
+    use crate::engine::RowId;
+    use crate::parser::ast::DataType;
+    use crate::types::Value;
+    use crate::wal::segment::{segment_name, SEGMENT_HEADER};
+
+    fn create(db: &Database) {
+        let schema = TableSchema::new("t", vec![ColumnSchema::new("a", DataType::Integer(None))]);
+        let mut txn = db.catalog().begin();
+        txn.create_table(schema).unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_open_either_engine() {
+        let db = Database::open(DatabaseOptions::memory()).unwrap();
+        create(&db);
+        assert!(db.engine().table("t").is_some());
+        assert!(Database::open(DatabaseOptions::memory()).unwrap().catalog().table("t").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let options = DatabaseOptions::disk(dir.path().join("db"));
+        create(&Database::open(options.clone()).unwrap());
+        let db = Database::open(options).unwrap();
+        assert!(db.catalog().table("t").is_some());
+        assert!(db.engine().table("t").is_some());
+
+        // A checkpoint lets recovery start past the log before it.
+        let wal = db.txns.as_ref().unwrap().wal().clone();
+        assert!(wal.checkpoint_lsn().unwrap().is_none());
+        db.checkpoint().unwrap();
+        assert!(wal.checkpoint_lsn().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_catalog_commit_survives_a_crash_part_way() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let options = DatabaseOptions::disk(&path);
+        let db = Database::open(options.clone()).unwrap();
+        create(&db);
+        let columns = (0..20).map(|i| ColumnSchema::new(format!("c{}", i), DataType::Integer(None))).collect();
+        let mut txn = db.catalog().begin();
+        txn.create_table(TableSchema::new("u", columns)).unwrap();
+
+        // The file as it is before the commit, and the log cut off in the
+        // middle of the commit's records.
+        db.checkpoint().unwrap();
+        let copy = tempfile::tempdir().unwrap();
+        std::fs::copy(&path, copy.path().join("db")).unwrap();
+        let wal = db.txns.as_ref().unwrap().wal().clone();
+        let start = wal.next_lsn();
+        txn.commit().unwrap();
+        let records = wal.records_from(start).unwrap();
+        assert!(records.len() > 2, "{:?}", records);
+        let cut = records[records.len() / 2].lsn;
+        let log = copy.path().join("db-wal");
+        std::fs::create_dir(&log).unwrap();
+        for entry in std::fs::read_dir(wal_path(&path)).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), log.join(entry.file_name())).unwrap();
+        }
+        let segment = std::fs::read_dir(&log)
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                u64::from_str_radix(name.strip_suffix(".wal")?, 16).ok()
+            })
+            .filter(|&first| first <= cut)
+            .max()
+            .unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(log.join(segment_name(segment))).unwrap();
+        file.set_len(SEGMENT_HEADER + cut - segment).unwrap();
+        drop(db);
+
+        let db = Database::open(DatabaseOptions { engine: EngineKind::Disk(copy.path().join("db")), ..options });
+        let db = db.unwrap();
+        assert!(db.catalog().table("t").is_some());
+        assert!(db.catalog().table("u").is_none());
+        assert!(db.engine().table("u").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_in_the_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DatabaseOptions {
+            checkpoint_interval: Some(Duration::from_millis(10)),
+            ..DatabaseOptions::disk(dir.path().join("db"))
+        };
+        assert!(std::thread::spawn({
+            let options = options.clone();
+            move || Database::open(options).is_err()
+        })
+        .join()
+        .unwrap());
+
+        let db = Database::open(options).unwrap();
+        create(&db);
+        let txns = db.txns.clone().unwrap();
+        while txns.wal().checkpoint_lsn().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // The checkpointer lets go of the log once the database is dropped.
+        drop(db);
+        let released = async {
+            while Arc::strong_count(&txns) > 1 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), released).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_statements_log_one_transaction() {
+        use crate::executor::Executor;
+        use crate::parser::parse_sql;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(DatabaseOptions::disk(dir.path().join("db"))).unwrap();
+        create(&db);
+        let wal = db.txns.as_ref().unwrap().wal().clone();
+        let start = wal.next_lsn();
+        let insert = parse_sql("INSERT INTO t VALUES (1), (2), (3)").unwrap();
+        assert_eq!(db.execute(&insert).await.unwrap().affected_rows, 3);
+        let mut txns: Vec<_> = wal.records_from(start).unwrap().iter().map(|r| r.txn).collect();
+        txns.dedup();
+        assert_eq!(txns.len(), 1, "{:?}", txns);
+    }
+
+    #[test]
+    fn test_recover_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        // A small pool, so that changes reach the file before they commit.
+        let options = DatabaseOptions { buffer_pages: 16, ..DatabaseOptions::disk(&path) };
+        let db = Database::open(options.clone()).unwrap();
+        create(&db);
+        let table = db.engine().table("t").unwrap();
+        let ids: Vec<RowId> = (0..300).map(|i| table.insert(vec![Value::Int32(i)]).unwrap()).collect();
+        for &id in &ids[..100] {
+            table.delete(id).unwrap();
+        }
+
+        // Copy the file and the log as a crash would leave them: nothing
+        // is flushed beyond what the commits and evictions wrote.
+        let copy = tempfile::tempdir().unwrap();
+        std::fs::copy(&path, copy.path().join("db")).unwrap();
+        let wal = copy.path().join("db-wal");
+        std::fs::create_dir(&wal).unwrap();
+        for entry in std::fs::read_dir(wal_path(&path)).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), wal.join(entry.file_name())).unwrap();
+        }
+        drop(table);
+        drop(db);
+
+        let db = Database::open(DatabaseOptions { engine: EngineKind::Disk(copy.path().join("db")), ..options });
+        let db = db.unwrap();
+        assert!(db.catalog().table("t").is_some());
+        let table = db.engine().table("t").unwrap();
+        let mut rows: Vec<Value> = table.scan().map(|row| row.unwrap().1[0].clone()).collect();
+        rows.sort();
+        assert_eq!(rows, (100..300).map(Value::Int32).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,495 @@
+// src/engine.rs
+//! Storage engines: where tables keep their rows and indexes.
+//!
+//! The executor reaches table data only through [`StorageEngine`] and
+//! [`Table`], so the same SQL runs over [`DiskEngine`], which keeps heap
+//! files and indexes in a page file, and [`MemoryEngine`], which keeps
+//! everything in memory until it is dropped. Both store rows as their
+//! [`RowCodec`](crate::row::codec::RowCodec) decodes them, so a value reads
+//! back the same whichever engine holds it, and both keep every index in
+//! step with every change: a change that an index rejects is not made.
+//!
+//! A table's definition changes under [`StorageEngine::alter_table`], which
+//! holds writers back only while the change itself is made. Whatever needs
+//! to see the table's rows first, such as copying them into a rebuilt table,
+//! does most of its work beforehand with writes going on, then catches up
+//! with the rows changed meanwhile, which the table records for it
+//! ([`Table::track_changes`]).
+
+pub mod disk;
+pub mod memory;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub use disk::DiskEngine;
+pub use memory::MemoryEngine;
+
+use crate::catalog::{IndexSchema, TableSchema};
+use crate::error::Error;
+use crate::index::{KeyRange, TableIndex};
+use crate::row::codec::RowCodec;
+use crate::storage::RecordId;
+use crate::types::Value;
+
+/// Identifies a row of a table for as long as the row exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RowId(pub u64);
+
+/// Record ids pack into row ids as `page << 16 | slot`, leaving room for
+/// files of 2^48 pages.
+impl From<RecordId> for RowId {
+    fn from(rid: RecordId) -> Self {
+        RowId(rid.page << 16 | rid.slot as u64)
+    }
+}
+
+impl From<RowId> for RecordId {
+    fn from(id: RowId) -> Self {
+        RecordId::new(id.0 >> 16, id.0 as u16)
+    }
+}
+
+impl fmt::Display for RowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A row with its id, as a scan returns it.
+pub type ScanItem = Result<(RowId, Vec<Value>), Error>;
+
+/// How [`StorageEngine::alter_table`] changes a table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableChange {
+    /// Gives the table a schema its stored rows still fit: columns renamed
+    /// or given other constraints, or appended, with the rows already
+    /// stored reading each new column as its `missing` value. Each index is
+    /// kept under the definition of the same name in `indexes`, which must
+    /// pick out the same values as before; the others are dropped.
+    Schema { schema: TableSchema, indexes: Vec<IndexSchema> },
+    /// Replaces the table with the named one, which takes over its name
+    /// and keeps its own rows and indexes.
+    Replace(String),
+}
+
+/// A set of tables. Table names are matched case-insensitively.
+pub trait StorageEngine: fmt::Debug + Send + Sync {
+    /// Creates an empty table. Fails if there is one with the same name.
+    fn create_table(&self, schema: TableSchema) -> Result<Arc<dyn Table>, Error>;
+
+    fn table(&self, name: &str) -> Option<Arc<dyn Table>>;
+
+    /// Drops a table with its rows and indexes, returning whether there
+    /// was one. Handles to it already given out must no longer be used.
+    fn drop_table(&self, name: &str) -> Result<bool, Error>;
+
+    fn table_names(&self) -> Vec<String>;
+
+    /// Changes a table's definition. Writes to the table wait from when
+    /// `finish` is called until the change is made, so `finish` sees the
+    /// table's rows as they are when the change is made, and can call the
+    /// change off by failing; reads go on throughout. A replaced table is
+    /// dropped, and handles to it, or to the table that replaced it, must be
+    /// looked up again.
+    fn alter_table(
+        &self,
+        name: &str,
+        change: TableChange,
+        finish: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error>;
+
+    /// Makes every change so far durable, if the engine can.
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The rows and indexes of one table.
+pub trait Table: fmt::Debug + Send + Sync {
+    fn schema(&self) -> TableSchema;
+
+    /// Every row, in no particular order.
+    fn scan(&self) -> Box<dyn Iterator<Item = ScanItem> + '_>;
+
+    fn get(&self, id: RowId) -> Result<Option<Vec<Value>>, Error>;
+
+    /// Adds a row, converting its values to the column types.
+    fn insert(&self, row: Vec<Value>) -> Result<RowId, Error>;
+
+    /// Replaces a row, which keeps its id.
+    fn update(&self, id: RowId, row: Vec<Value>) -> Result<(), Error>;
+
+    /// Removes a row, returning whether there was one.
+    fn delete(&self, id: RowId) -> Result<bool, Error>;
+
+    /// Builds an index over the rows already in the table.
+    fn create_index(&self, schema: IndexSchema) -> Result<(), Error>;
+
+    /// Drops an index, returning whether there was one.
+    fn drop_index(&self, name: &str) -> Result<bool, Error>;
+
+    fn indexes(&self) -> Vec<IndexSchema>;
+
+    /// The ids of the rows whose keys in the named index are in `range`,
+    /// in key order for a B+tree index.
+    fn index_scan(&self, index: &str, range: &KeyRange) -> Result<Vec<RowId>, Error>;
+
+    /// Starts or stops recording which rows are inserted, updated and
+    /// deleted, for [`Table::take_changes`].
+    fn track_changes(&self, on: bool);
+
+    /// The ids of the rows changed since tracking started or this was last
+    /// called, each once.
+    fn take_changes(&self) -> Vec<RowId>;
+}
+
+/// A table's schema, with the codec for it.
+#[derive(Debug)]
+struct Shape {
+    schema: TableSchema,
+    codec: RowCodec,
+}
+
+impl Shape {
+    fn new(schema: TableSchema, codec: RowCodec) -> RwLock<Shape> {
+        RwLock::new(Shape { schema, codec })
+    }
+}
+
+/// What a table of either engine keeps to be altered while in use: the
+/// gate that holds writers back, and the record of changed rows.
+#[derive(Debug, Default)]
+struct Gate {
+    writes: RwLock<()>,
+    /// Set once the table is dropped or replaced.
+    retired: AtomicBool,
+    changes: Mutex<Option<BTreeSet<RowId>>>,
+}
+
+impl Gate {
+    /// Fails if the table is gone.
+    fn check(&self, table: impl FnOnce() -> String) -> Result<(), Error> {
+        if self.retired.load(Ordering::Acquire) {
+            return Err(Error::Execution(format!("table {} was dropped or rebuilt; look it up again", table())));
+        }
+        Ok(())
+    }
+
+    /// Lets a writer in, once no change is being made.
+    fn enter(&self, table: impl FnOnce() -> String) -> Result<RwLockReadGuard<'_, ()>, Error> {
+        let guard = self.writes.read();
+        self.check(table)?;
+        Ok(guard)
+    }
+
+    /// Holds writers back.
+    fn close(&self, table: impl FnOnce() -> String) -> Result<RwLockWriteGuard<'_, ()>, Error> {
+        let guard = self.writes.write();
+        self.check(table)?;
+        Ok(guard)
+    }
+
+    fn retire(&self) {
+        self.retired.store(true, Ordering::Release);
+    }
+
+    fn record(&self, id: RowId) {
+        if let Some(changes) = self.changes.lock().as_mut() {
+            changes.insert(id);
+        }
+    }
+
+    fn track(&self, on: bool) {
+        *self.changes.lock() = on.then(BTreeSet::new);
+    }
+
+    fn take(&self) -> Vec<RowId> {
+        self.changes.lock().as_mut().map(|changes| std::mem::take(changes).into_iter().collect()).unwrap_or_default()
+    }
+}
+
+/// Checks that rows stored under `old` read correctly under `new`.
+fn check_fits(old: &TableSchema, new: &TableSchema) -> Result<(), Error> {
+    let fits = new.columns.len() >= old.columns.len()
+        && old
+            .columns
+            .iter()
+            .zip(&new.columns)
+            .all(|(old, new)| old.data_type == new.data_type && old.missing == new.missing)
+        && old.types.iter().all(|old| {
+            new.types.iter().any(|new| new.name.eq_ignore_ascii_case(&old.name) && new.extends(old))
+        });
+    if !fits {
+        return Err(Error::Execution(format!(
+            "the rows of table {} must be rewritten to change its columns this way",
+            old.name
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if `change` does not fit a table with `schema` and `indexes`, so
+/// that it can fail before writers are held back.
+fn check_change(schema: &TableSchema, indexes: &[TableIndex], change: &TableChange) -> Result<(), Error> {
+    if let TableChange::Schema { schema: new, indexes: schemas } = change {
+        check_fits(schema, new)?;
+        for index in indexes {
+            if let Some(schema) = schemas.iter().find(|schema| schema.name.eq_ignore_ascii_case(&index.schema().name)) {
+                index.check_rebind(new, schema)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rebinds the indexes to their definitions in `schemas`, dropping those
+/// without one; returns the ones dropped. Fails without changing anything
+/// if a definition does not fit.
+fn index_reshape(
+    indexes: &mut Vec<TableIndex>,
+    table: &TableSchema,
+    schemas: Vec<IndexSchema>,
+) -> Result<Vec<TableIndex>, Error> {
+    let schema_of = |index: &TableIndex| {
+        schemas.iter().find(|schema| schema.name.eq_ignore_ascii_case(&index.schema().name)).cloned()
+    };
+    for index in indexes.iter() {
+        if let Some(schema) = schema_of(index) {
+            index.check_rebind(table, &schema)?;
+        }
+    }
+    let mut dropped = Vec::new();
+    for mut index in std::mem::take(indexes) {
+        match schema_of(&index) {
+            Some(schema) => {
+                index.rebind(table, schema)?;
+                indexes.push(index);
+            }
+            None => dropped.push(index),
+        }
+    }
+    Ok(dropped)
+}
+
+fn no_index(table: &TableSchema, index: &str) -> Error {
+    Error::Execution(format!("table {} has no index {}", table.name, index))
+}
+
+/// Adds a new row to every index, or to none if one rejects it.
+fn index_insert(indexes: &[TableIndex], row: &[Value], id: RowId) -> Result<(), Error> {
+    for (i, index) in indexes.iter().enumerate() {
+        if let Err(err) = index.insert(row, id.into()) {
+            for index in &indexes[..i] {
+                index.delete(row, id.into())?;
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Moves a changed row in every index, or in none if one rejects it.
+fn index_update(indexes: &[TableIndex], old: &[Value], new: &[Value], id: RowId) -> Result<(), Error> {
+    for (i, index) in indexes.iter().enumerate() {
+        if let Err(err) = index.update(old, new, id.into()) {
+            for index in &indexes[..i] {
+                index.update(new, old, id.into())?;
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Removes a row from every index, or from none if one fails.
+fn index_delete(indexes: &[TableIndex], row: &[Value], id: RowId) -> Result<(), Error> {
+    for (i, index) in indexes.iter().enumerate() {
+        if let Err(err) = index.delete(row, id.into()) {
+            for index in &indexes[..i] {
+                index.insert(row, id.into())?;
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Fills a new index from the rows of `table`.
+fn index_fill(index: &TableIndex, table: &dyn Table) -> Result<(), Error> {
+    for row in table.scan() {
+        let (id, row) = row?;
+        index.insert(&row, id.into())?;
+    }
+    Ok(())
+}
+
+fn index_position(indexes: &[TableIndex], name: &str) -> Option<usize> {
+    indexes.iter().position(|index| index.schema().name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Bound;
+    use crate::buffer::BufferPool;
+    use crate::catalog::ColumnSchema;
+    use crate::parser::ast::{DataType, FieldPath, Statement};
+    use crate::parser::parse_sql;
+    use crate::storage::{Pager, PagerOptions};
+
+    fn users() -> TableSchema {
+        TableSchema::new(
+            "users",
+            vec![
+                ColumnSchema::new("id", DataType::BigInt).primary_key(),
+                ColumnSchema::new("email", DataType::Text).not_null(),
+                ColumnSchema::new("age", DataType::SmallInt),
+            ],
+        )
+    }
+
+    fn index(sql: &str) -> IndexSchema {
+        let Statement::CreateIndex(create) = parse_sql(sql).unwrap() else { panic!("Expected CREATE INDEX") };
+        IndexSchema::from_create(&create)
+    }
+
+    fn row(id: i64, email: &str, age: Option<i64>) -> Vec<Value> {
+        vec![Value::Int(id), Value::String(email.into()), age.map_or(Value::Null, Value::Int)]
+    }
+
+    fn sorted(mut ids: Vec<RowId>) -> Vec<RowId> {
+        ids.sort();
+        ids
+    }
+
+    /// Runs the same statements against an engine, checking what every
+    /// engine must do alike.
+    fn exercise(engine: &dyn StorageEngine) {
+        let table = engine.create_table(users()).unwrap();
+        assert!(engine.create_table(users()).is_err());
+        assert!(engine.table("USERS").is_some());
+
+        let a = table.insert(row(1, "a@x", Some(30))).unwrap();
+        let b = table.insert(row(2, "b@x", None)).unwrap();
+        // Values are stored as the column types have them.
+        assert_eq!(table.get(a).unwrap().unwrap(), row(1, "a@x", Some(30)));
+        assert!(table.insert(vec![Value::Int(3), Value::Null, Value::Null]).is_err());
+
+        table.create_index(index("CREATE UNIQUE INDEX by_email ON users (email)")).unwrap();
+        table.create_index(index("CREATE INDEX by_age ON users USING HASH (age)")).unwrap();
+        assert!(table.create_index(index("CREATE INDEX by_email ON users (age)")).is_err());
+        let c = table.insert(row(3, "c@x", Some(30))).unwrap();
+
+        // A rejected change leaves the table and its indexes as they were.
+        assert!(table.insert(row(4, "a@x", Some(30))).is_err());
+        assert!(table.update(b, row(2, "c@x", None)).is_err());
+        assert_eq!(table.scan().count(), 3);
+        let thirty = KeyRange::eq(vec![Value::Int(30)]);
+        assert_eq!(sorted(table.index_scan("by_age", &thirty).unwrap()), sorted(vec![a, c]));
+
+        table.update(a, row(1, "a@y", Some(31))).unwrap();
+        assert_eq!(table.index_scan("BY_AGE", &thirty).unwrap(), vec![c]);
+        let emails =
+            KeyRange { eq: vec![], lower: Bound::Excluded(Value::String("a@y".into())), upper: Bound::Unbounded };
+        assert_eq!(table.index_scan("by_email", &emails).unwrap(), vec![b, c]);
+
+        assert!(table.delete(c).unwrap());
+        assert!(!table.delete(c).unwrap());
+        assert!(table.get(c).unwrap().is_none());
+        assert!(table.index_scan("by_age", &thirty).unwrap().is_empty());
+        assert!(table.drop_index("by_age").unwrap());
+        assert!(table.index_scan("by_age", &thirty).is_err());
+        assert_eq!(table.indexes().len(), 1);
+
+        assert_eq!(engine.table_names(), vec!["users".to_string()]);
+        assert!(engine.drop_table("Users").unwrap());
+        assert!(!engine.drop_table("users").unwrap());
+        assert!(engine.table("users").is_none());
+    }
+
+    /// Alters a table both ways an engine can, checking that writes wait
+    /// only for the change itself.
+    fn exercise_alter(engine: &dyn StorageEngine) {
+        let table = engine.create_table(users()).unwrap();
+        table.create_index(index("CREATE UNIQUE INDEX by_email ON users (email)")).unwrap();
+        table.track_changes(true);
+        let a = table.insert(row(1, "a@x", Some(30))).unwrap();
+        assert_eq!(table.take_changes(), vec![a]);
+
+        // Appending a column leaves the stored rows as they are: they read
+        // it as its missing value.
+        let mut schema = users();
+        schema.columns[1].name = "mail".into();
+        let mut active = ColumnSchema::new("active", DataType::Boolean).not_null();
+        active.missing = Some(Value::Bool(true));
+        schema.columns.push(active);
+        let by_mail = index("CREATE UNIQUE INDEX by_email ON users (mail)");
+        let change = TableChange::Schema { schema: schema.clone(), indexes: vec![by_mail] };
+        let mut finished = false;
+        engine
+            .alter_table("users", change, &mut || {
+                finished = true;
+                Ok(())
+            })
+            .unwrap();
+        assert!(finished);
+        assert_eq!(table.schema(), schema);
+        assert_eq!(table.get(a).unwrap().unwrap()[3], Value::Bool(true));
+        let b = table
+            .insert(vec![Value::Int(2), Value::String("b@x".into()), Value::Null, Value::Bool(false)])
+            .unwrap();
+        let mail = KeyRange::eq(vec![Value::String("a@x".into())]);
+        assert_eq!(table.index_scan("by_email", &mail).unwrap(), vec![a]);
+        assert_eq!(table.indexes()[0].columns, vec![FieldPath::column("mail")]);
+
+        let mut retyped = schema.clone();
+        retyped.columns[2].data_type = DataType::BigInt;
+        let change = TableChange::Schema { schema: retyped.clone(), indexes: vec![] };
+        assert!(engine.alter_table("users", change, &mut || Ok(())).is_err());
+        let change = TableChange::Schema { schema: schema.clone(), indexes: vec![] };
+        assert!(engine.alter_table("users", change, &mut || Err(Error::Execution("no".into()))).is_err());
+        assert_eq!(table.indexes().len(), 1);
+
+        // A table rebuilt under another name replaces it.
+        retyped.name = "rebuilt".into();
+        let rebuilt = engine.create_table(retyped).unwrap();
+        rebuilt.create_index(index("CREATE INDEX by_age ON rebuilt (age)")).unwrap();
+        for (_, row) in table.scan().map(Result::unwrap) {
+            rebuilt.insert(row).unwrap();
+        }
+        engine.alter_table("users", TableChange::Replace("rebuilt".into()), &mut || Ok(())).unwrap();
+        assert!(table.get(b).is_err());
+        assert!(table.insert(row(3, "c@x", None)).is_err());
+        assert!(engine.table("rebuilt").is_none());
+        assert_eq!(engine.table_names(), vec!["users".to_string()]);
+        let table = engine.table("users").unwrap();
+        assert_eq!(table.schema().name, "users");
+        assert_eq!(table.schema().columns[2].data_type, DataType::BigInt);
+        assert_eq!(table.indexes()[0].table, "users");
+        assert_eq!(table.scan().count(), 2);
+        assert_eq!(table.index_scan("by_age", &KeyRange::eq(vec![Value::Int(30)])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_engines_behave_alike() {
+        exercise(&MemoryEngine::new());
+        exercise_alter(&MemoryEngine::new());
+
+        let dir = tempfile::tempdir().unwrap();
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let engine = DiskEngine::open(Arc::new(BufferPool::new(pager, 64))).unwrap();
+        exercise(&engine);
+        exercise_alter(&engine);
+    }
+
+    #[test]
+    fn test_row_ids_round_trip() {
+        let rid = RecordId::new(123_456, 789);
+        assert_eq!(RecordId::from(RowId::from(rid)), rid);
+    }
+}
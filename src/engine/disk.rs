@@ -0,0 +1,698 @@
+// src/engine/disk.rs
+//! The page-file engine.
+//!
+//! Each table is a heap file of rows in [`RowCodec`] format, with large
+//! values moved to overflow chains, and each of its indexes a B+tree or hash
+//! index in the same file. A directory B+tree, whose meta page is always
+//! page 1, maps every table's lowercased name to its definition: the
+//! `CREATE TABLE` and `CREATE INDEX` statements it was built from, each with
+//! the page its storage opens from. Definitions can outgrow an index entry,
+//! so the directory only holds a reference to an overflow chain. The
+//! statements are followed by the missing values of the columns that have
+//! one, each [encoded on its own](RowCodec::encode_value), and then by the
+//! custom types the table uses, as the rows of
+//! [`TypeSchema::to_rows`](crate::catalog::TypeSchema::to_rows) in
+//! [`RowCodec`] format:
+//!
+//! ```text
+//! definition: count u16 | (page u64, sql_len u32, sql)*    (the table first)
+//!             | missing u16 | (column u16, len u32, value)*
+//!             | types u16 | (len u32, row)*
+//! ```
+//!
+//! With a [`TxnManager`], every change to the engine's pages is one logged
+//! transaction, so that after a crash recovery leaves none half made.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::buffer::BufferPool;
+use crate::catalog::{IndexSchema, TableSchema, TypeSchema};
+use crate::engine::{
+    check_change, index_delete, index_fill, index_insert, index_position, index_reshape, index_update, no_index,
+    Gate, RowId, ScanItem, Shape, StorageEngine, Table, TableChange,
+};
+use crate::error::Error;
+use crate::index::{BTree, BTreeOptions, HashIndex, IndexTree, KeyRange, TableIndex};
+use crate::parser::ast::{IndexMethod, Statement};
+use crate::parser::parse_sql;
+use crate::row::codec::RowCodec;
+use crate::storage::{HeapFile, OverflowRef, OverflowStore, PageId};
+use crate::types::Value;
+use crate::wal::TxnManager;
+
+/// A table's definition: the page its heap opens from and its `CREATE
+/// TABLE`, the meta page and `CREATE INDEX` of each index, and the missing
+/// value of each column that has one, and the rows defining the custom types
+/// it uses.
+type Definition = (Vec<(PageId, String)>, Vec<(usize, Vec<u8>)>, Vec<Vec<u8>>);
+
+/// Meta page of the directory of tables.
+const DIRECTORY: PageId = 1;
+
+#[derive(Debug)]
+pub struct DiskEngine {
+    pool: Arc<BufferPool>,
+    store: Arc<OverflowStore>,
+    directory: Arc<BTree>,
+    tables: RwLock<HashMap<String, Arc<DiskTable>>>,
+    txns: Option<Arc<TxnManager>>,
+}
+
+impl DiskEngine {
+    /// Opens the tables of the pool's file, setting up an empty file to
+    /// hold tables.
+    pub fn open(pool: Arc<BufferPool>) -> Result<Self, Error> {
+        Self::open_with(pool, None)
+    }
+
+    /// Like [`DiskEngine::open`], but makes every change as a transaction
+    /// of `txns`, over its pool.
+    pub fn open_logged(txns: Arc<TxnManager>) -> Result<Self, Error> {
+        Self::open_with(txns.pool().clone(), Some(txns))
+    }
+
+    fn open_with(pool: Arc<BufferPool>, txns: Option<Arc<TxnManager>>) -> Result<Self, Error> {
+        let store = Arc::new(OverflowStore::new(pool.clone()));
+        let directory = if pool.page_count() == 1 {
+            let tree = atomic(&txns, || BTree::create(pool.clone(), BTreeOptions { unique: true, order: None }))?;
+            if tree.meta() != DIRECTORY {
+                return Err(Error::Storage(format!("table directory landed on page {}", tree.meta())));
+            }
+            tree
+        } else {
+            BTree::open(pool.clone(), DIRECTORY)?
+        };
+        let directory = Arc::new(directory);
+        let mut tables = HashMap::new();
+        for entry in directory.scan() {
+            let (key, reference) = entry?;
+            let table = DiskTable::open(&pool, &store, &directory, &txns, &reference)?;
+            tables.insert(String::from_utf8_lossy(&key).into_owned(), Arc::new(table));
+        }
+        Ok(DiskEngine { pool, store, directory, tables: RwLock::new(tables), txns })
+    }
+}
+
+/// Runs `f` as one transaction of `txns`, if changes are logged.
+fn atomic<T>(txns: &Option<Arc<TxnManager>>, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    match txns {
+        Some(txns) => txns.atomic(f),
+        None => f(),
+    }
+}
+
+impl DiskEngine {
+    fn get(&self, name: &str) -> Result<Arc<DiskTable>, Error> {
+        self.tables
+            .read()
+            .get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))
+    }
+}
+
+impl StorageEngine for DiskEngine {
+    fn create_table(&self, schema: TableSchema) -> Result<Arc<dyn Table>, Error> {
+        atomic(&self.txns, || {
+            let mut tables = self.tables.write();
+            let key = schema.name.to_lowercase();
+            if tables.contains_key(&key) {
+                return Err(Error::Execution(format!("table {} already exists", schema.name)));
+            }
+            let table = Arc::new(DiskTable {
+                shape: Shape::new(schema.clone(), RowCodec::new(&schema).with_store(self.store.clone())),
+                heap: HeapFile::create(self.pool.clone())?,
+                pool: self.pool.clone(),
+                store: self.store.clone(),
+                directory: self.directory.clone(),
+                indexes: RwLock::new(Vec::new()),
+                gate: Gate::default(),
+                txns: self.txns.clone(),
+            });
+            table.save(&schema, &table.indexes.read())?;
+            tables.insert(key, table.clone());
+            Ok(table as Arc<dyn Table>)
+        })
+    }
+
+    fn table(&self, name: &str) -> Option<Arc<dyn Table>> {
+        self.tables.read().get(&name.to_lowercase()).map(|table| table.clone() as Arc<dyn Table>)
+    }
+
+    fn drop_table(&self, name: &str) -> Result<bool, Error> {
+        atomic(&self.txns, || {
+            let Some(table) = self.tables.write().remove(&name.to_lowercase()) else { return Ok(false) };
+            table.gate.retire();
+            table.unlist(&name.to_lowercase())?;
+            table.destroy()?;
+            Ok(true)
+        })
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.read().values().map(|table| table.name()).collect();
+        names.sort();
+        names
+    }
+
+    fn alter_table(
+        &self,
+        name: &str,
+        change: TableChange,
+        finish: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        atomic(&self.txns, || {
+            let table = self.get(name)?;
+            let _closed = table.gate.close(|| name.to_string())?;
+            check_change(&table.shape.read().schema, &table.indexes.read(), &change)?;
+            finish()?;
+            match change {
+                TableChange::Schema { schema, indexes } => {
+                    let mut shape = table.shape.write();
+                    let mut kept = table.indexes.write();
+                    let dropped = index_reshape(&mut kept, &schema, indexes)?;
+                    table.save(&schema, &kept)?;
+                    *shape = Shape { codec: RowCodec::new(&schema).with_store(self.store.clone()), schema };
+                    for index in dropped {
+                        index.destroy()?;
+                    }
+                }
+                TableChange::Replace(other) => {
+                    let mut tables = self.tables.write();
+                    let replacement = tables
+                        .get(&other.to_lowercase())
+                        .cloned()
+                        .ok_or_else(|| Error::Execution(format!("table {} does not exist", other)))?;
+                    let _replacement_closed = replacement.gate.close(|| other.clone())?;
+                    // The replacement's definition takes over the directory
+                    // entry before the table replaced is freed.
+                    let name = table.name();
+                    replacement.rename(&name)?;
+                    replacement.unlist(&other.to_lowercase())?;
+                    tables.remove(&other.to_lowercase());
+                    tables.insert(name.to_lowercase(), replacement.clone());
+                    table.gate.retire();
+                    table.destroy()?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.pool.flush_all()
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskTable {
+    shape: RwLock<Shape>,
+    heap: HeapFile,
+    pool: Arc<BufferPool>,
+    store: Arc<OverflowStore>,
+    directory: Arc<BTree>,
+    indexes: RwLock<Vec<TableIndex>>,
+    gate: Gate,
+    txns: Option<Arc<TxnManager>>,
+}
+
+impl DiskTable {
+    fn open(
+        pool: &Arc<BufferPool>,
+        store: &Arc<OverflowStore>,
+        directory: &Arc<BTree>,
+        txns: &Option<Arc<TxnManager>>,
+        reference: &[u8],
+    ) -> Result<Self, Error> {
+        let (definition, missing, types) = decode_definition(&store.read(&OverflowRef::from_bytes(reference)?)?)?;
+        let Some(((root, sql), indexes)) = definition.split_first() else {
+            return Err(Error::Storage("empty table definition".to_string()));
+        };
+        let Statement::Create(create) = parse_sql(sql)? else {
+            return Err(Error::Storage(format!("table definition is not CREATE TABLE: {}", sql)));
+        };
+        let mut schema = TableSchema::from_create(&create);
+        let type_codec = type_codec();
+        let types = types.iter().map(|row| type_codec.decode(row)).collect::<Result<_, _>>()?;
+        schema.types = TypeSchema::from_rows(types)?;
+        for (column, bytes) in missing {
+            let column = schema
+                .columns
+                .get_mut(column)
+                .ok_or_else(|| Error::Storage(format!("table {} has no column {}", schema.name, column)))?;
+            column.missing = Some(RowCodec::decode_value(&column.data_type, &schema.types, &bytes)?);
+        }
+        let indexes = indexes
+            .iter()
+            .map(|(meta, sql)| {
+                let Statement::CreateIndex(create) = parse_sql(sql)? else {
+                    return Err(Error::Storage(format!("index definition is not CREATE INDEX: {}", sql)));
+                };
+                let index = IndexSchema::from_create(&create);
+                let tree: IndexTree = match index.method {
+                    IndexMethod::BTree => BTree::open(pool.clone(), *meta)?.into(),
+                    IndexMethod::Hash => HashIndex::open(pool.clone(), *meta)?.into(),
+                };
+                TableIndex::new(&schema, index, tree)
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(DiskTable {
+            shape: Shape::new(schema.clone(), RowCodec::new(&schema).with_store(store.clone())),
+            heap: HeapFile::open(pool.clone(), *root)?,
+            pool: pool.clone(),
+            store: store.clone(),
+            directory: directory.clone(),
+            indexes: RwLock::new(indexes),
+            gate: Gate::default(),
+            txns: txns.clone(),
+        })
+    }
+
+    fn name(&self) -> String {
+        self.shape.read().schema.name.clone()
+    }
+
+    /// Records the table's definition, as `schema` with `indexes`, in the
+    /// directory.
+    fn save(&self, schema: &TableSchema, indexes: &[TableIndex]) -> Result<(), Error> {
+        let mut statements = vec![(self.heap.root(), schema.to_sql())];
+        for index in indexes {
+            let meta = index.tree().meta().expect("disk indexes are stored in pages");
+            statements.push((meta, index.schema().to_sql()));
+        }
+        let mut missing = Vec::new();
+        for (i, column) in schema.columns.iter().enumerate() {
+            if let Some(value) = &column.missing {
+                missing.push((i, RowCodec::encode_value(&column.data_type, &schema.types, value)?));
+            }
+        }
+        let type_codec = type_codec();
+        let mut types = Vec::new();
+        for ty in &schema.types {
+            for row in ty.to_rows()? {
+                types.push(type_codec.encode(&row)?);
+            }
+        }
+        let reference = self.store.write(&encode_definition(&(statements, missing, types)))?.to_bytes();
+        let key = schema.name.to_lowercase().into_bytes();
+        let old = self.directory.get(&key)?;
+        if let Some(old) = &old {
+            self.directory.delete(&key, old)?;
+        }
+        self.directory.insert(&key, &reference)?;
+        if let Some(old) = old {
+            self.store.free(&OverflowRef::from_bytes(&old)?)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the directory entry under `key`.
+    fn unlist(&self, key: &str) -> Result<(), Error> {
+        if let Some(reference) = self.directory.get(key.as_bytes())? {
+            self.directory.delete(key.as_bytes(), &reference)?;
+            self.store.free(&OverflowRef::from_bytes(&reference)?)?;
+        }
+        Ok(())
+    }
+
+    /// Takes `name` as the table's name, in the directory too.
+    fn rename(&self, name: &str) -> Result<(), Error> {
+        let mut shape = self.shape.write();
+        let mut schema = shape.schema.clone();
+        schema.name = name.to_string();
+        let mut indexes = self.indexes.write();
+        let renamed =
+            indexes.iter().map(|index| IndexSchema { table: name.to_string(), ..index.schema().clone() }).collect();
+        index_reshape(&mut indexes, &schema, renamed)?;
+        self.save(&schema, &indexes)?;
+        shape.schema = schema;
+        Ok(())
+    }
+
+    /// Frees the rows and indexes of a table no longer in the directory.
+    fn destroy(&self) -> Result<(), Error> {
+        for row in self.heap.scan() {
+            self.free_external(&row?.1)?;
+        }
+        self.heap.destroy()?;
+        for index in std::mem::take(&mut *self.indexes.write()) {
+            index.destroy()?;
+        }
+        Ok(())
+    }
+
+    /// Frees the overflow chains of an encoded row.
+    fn free_external(&self, bytes: &[u8]) -> Result<(), Error> {
+        for reference in self.shape.read().codec.external_refs(bytes)? {
+            self.store.free(&OverflowRef::from_bytes(reference)?)?;
+        }
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<Value>, Error> {
+        self.shape.read().codec.decode(bytes)
+    }
+
+    /// Encodes a row, also returning the values it decodes back to.
+    fn encode(&self, row: &[Value]) -> Result<(Vec<u8>, Vec<Value>), Error> {
+        let bytes = self.shape.read().codec.encode(row)?;
+        match self.decode(&bytes) {
+            Ok(row) => Ok((bytes, row)),
+            Err(err) => {
+                self.free_external(&bytes)?;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Table for DiskTable {
+    fn schema(&self) -> TableSchema {
+        self.shape.read().schema.clone()
+    }
+
+    fn scan(&self) -> Box<dyn Iterator<Item = ScanItem> + '_> {
+        if let Err(err) = self.gate.check(|| self.name()) {
+            return Box::new(std::iter::once(Err(err)));
+        }
+        Box::new(self.heap.scan().map(|row| {
+            let (rid, bytes) = row?;
+            Ok((rid.into(), self.decode(&bytes)?))
+        }))
+    }
+
+    fn get(&self, id: RowId) -> Result<Option<Vec<Value>>, Error> {
+        self.gate.check(|| self.name())?;
+        self.heap.get(id.into())?.map(|bytes| self.decode(&bytes)).transpose()
+    }
+
+    fn insert(&self, row: Vec<Value>) -> Result<RowId, Error> {
+        atomic(&self.txns, || {
+            let _entered = self.gate.enter(|| self.name())?;
+            let (bytes, row) = self.encode(&row)?;
+            let id = match self.heap.insert(&bytes) {
+                Ok(rid) => RowId::from(rid),
+                Err(err) => {
+                    self.free_external(&bytes)?;
+                    return Err(err);
+                }
+            };
+            if let Err(err) = index_insert(&self.indexes.read(), &row, id) {
+                self.heap.delete(id.into())?;
+                self.free_external(&bytes)?;
+                return Err(err);
+            }
+            self.gate.record(id);
+            Ok(id)
+        })
+    }
+
+    fn update(&self, id: RowId, row: Vec<Value>) -> Result<(), Error> {
+        atomic(&self.txns, || {
+            let _entered = self.gate.enter(|| self.name())?;
+            let old_bytes =
+                self.heap.get(id.into())?.ok_or_else(|| Error::Execution(format!("no row {} in {}", id, self.name())))?;
+            let old = self.decode(&old_bytes)?;
+            let (bytes, row) = self.encode(&row)?;
+            let indexes = self.indexes.read();
+            let changed = index_update(&indexes, &old, &row, id).and_then(|()| {
+                self.heap.update(id.into(), &bytes).or_else(|err| {
+                    index_update(&indexes, &row, &old, id)?;
+                    Err(err)
+                })
+            });
+            if let Err(err) = changed {
+                self.free_external(&bytes)?;
+                return Err(err);
+            }
+            self.gate.record(id);
+            self.free_external(&old_bytes)
+        })
+    }
+
+    fn delete(&self, id: RowId) -> Result<bool, Error> {
+        atomic(&self.txns, || {
+            let _entered = self.gate.enter(|| self.name())?;
+            let Some(bytes) = self.heap.get(id.into())? else { return Ok(false) };
+            let (indexes, row) = (self.indexes.read(), self.decode(&bytes)?);
+            index_delete(&indexes, &row, id)?;
+            // A row the heap keeps stays in its indexes.
+            if let Err(err) = self.heap.delete(id.into()) {
+                index_insert(&indexes, &row, id)?;
+                return Err(err);
+            }
+            self.free_external(&bytes)?;
+            self.gate.record(id);
+            Ok(true)
+        })
+    }
+
+    fn create_index(&self, schema: IndexSchema) -> Result<(), Error> {
+        atomic(&self.txns, || {
+            let _entered = self.gate.enter(|| self.name())?;
+            let mut indexes = self.indexes.write();
+            if index_position(&indexes, &schema.name).is_some() {
+                return Err(Error::Execution(format!("index {} already exists", schema.name)));
+            }
+            let tree: IndexTree = match schema.method {
+                IndexMethod::BTree => {
+                    BTree::create(self.pool.clone(), BTreeOptions { unique: schema.unique, order: None })?.into()
+                }
+                IndexMethod::Hash => HashIndex::create(self.pool.clone(), schema.unique)?.into(),
+            };
+            let table = self.schema();
+            let index = TableIndex::new(&table, schema, tree)?;
+            if let Err(err) = index_fill(&index, self) {
+                index.destroy()?;
+                return Err(err);
+            }
+            indexes.push(index);
+            if let Err(err) = self.save(&table, &indexes) {
+                indexes.pop().unwrap().destroy()?;
+                return Err(err);
+            }
+            Ok(())
+        })
+    }
+
+    fn drop_index(&self, name: &str) -> Result<bool, Error> {
+        atomic(&self.txns, || {
+            let _entered = self.gate.enter(|| self.name())?;
+            let mut indexes = self.indexes.write();
+            let Some(i) = index_position(&indexes, name) else { return Ok(false) };
+            let index = indexes.remove(i);
+            self.save(&self.schema(), &indexes)?;
+            index.destroy()?;
+            Ok(true)
+        })
+    }
+
+    fn indexes(&self) -> Vec<IndexSchema> {
+        self.indexes.read().iter().map(|index| index.schema().clone()).collect()
+    }
+
+    fn index_scan(&self, index: &str, range: &KeyRange) -> Result<Vec<RowId>, Error> {
+        self.gate.check(|| self.name())?;
+        let indexes = self.indexes.read();
+        let index = &indexes[index_position(&indexes, index).ok_or_else(|| no_index(&self.schema(), index))?];
+        let ids = index.scan(range)?.map(|rid| rid.map(RowId::from)).collect();
+        ids
+    }
+
+    fn track_changes(&self, on: bool) {
+        self.gate.track(on);
+    }
+
+    fn take_changes(&self) -> Vec<RowId> {
+        self.gate.take()
+    }
+}
+
+/// The codec of the rows defining custom types.
+fn type_codec() -> RowCodec {
+    RowCodec::new(&TableSchema::new("", TypeSchema::row_columns()))
+}
+
+fn encode_definition((statements, missing, types): &Definition) -> Vec<u8> {
+    let mut bytes = (statements.len() as u16).to_le_bytes().to_vec();
+    for (page, sql) in statements {
+        bytes.extend_from_slice(&page.to_le_bytes());
+        bytes.extend_from_slice(&(sql.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(sql.as_bytes());
+    }
+    bytes.extend_from_slice(&(missing.len() as u16).to_le_bytes());
+    for (column, value) in missing {
+        bytes.extend_from_slice(&(*column as u16).to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value);
+    }
+    bytes.extend_from_slice(&(types.len() as u16).to_le_bytes());
+    for row in types {
+        bytes.extend_from_slice(&(row.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(row);
+    }
+    bytes
+}
+
+fn decode_definition(bytes: &[u8]) -> Result<Definition, Error> {
+    let corrupt = || Error::Storage("corrupt table definition".to_string());
+    let u16_at = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()));
+    let count = u16_at(0).ok_or_else(corrupt)?;
+    let mut at = 2;
+    let mut statements = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let header = bytes.get(at..at + 12).ok_or_else(corrupt)?;
+        let page = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        let sql = bytes.get(at + 12..at + 12 + len).ok_or_else(corrupt)?;
+        statements.push((page, String::from_utf8(sql.to_vec()).map_err(|_| corrupt())?));
+        at += 12 + len;
+    }
+    let count = u16_at(at).ok_or_else(corrupt)?;
+    at += 2;
+    let mut missing = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let header = bytes.get(at..at + 6).ok_or_else(corrupt)?;
+        let column = u16::from_le_bytes(header[..2].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(header[2..].try_into().unwrap()) as usize;
+        missing.push((column, bytes.get(at + 6..at + 6 + len).ok_or_else(corrupt)?.to_vec()));
+        at += 6 + len;
+    }
+    let count = u16_at(at).ok_or_else(corrupt)?;
+    at += 2;
+    let mut types = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = bytes.get(at..at + 4).ok_or_else(corrupt)?;
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        types.push(bytes.get(at + 4..at + 4 + len).ok_or_else(corrupt)?.to_vec());
+        at += 4 + len;
+    }
+    if at != bytes.len() {
+        return Err(corrupt());
+    }
+    Ok((statements, missing, types))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::ColumnSchema;
+    use crate::parser::ast::{DataType, FieldPath};
+    use crate::storage::{Pager, PagerOptions};
+    use crate::wal::Wal;
+    use std::path::Path;
+
+    fn open(dir: &tempfile::TempDir) -> DiskEngine {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        DiskEngine::open(Arc::new(BufferPool::new(pager, 64))).unwrap()
+    }
+
+    fn open_logged(dir: &Path, frames: usize) -> DiskEngine {
+        let wal = Arc::new(Wal::open(dir.join("log")).unwrap());
+        let pager = Pager::open_with(dir.join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = Arc::new(BufferPool::new(pager, frames).with_wal(wal.clone()));
+        DiskEngine::open_logged(Arc::new(TxnManager::open(pool, wal).unwrap().0)).unwrap()
+    }
+
+    fn notes() -> TableSchema {
+        TableSchema::new(
+            "notes",
+            vec![ColumnSchema::new("id", DataType::BigInt).primary_key(), ColumnSchema::new("body", DataType::Text)],
+        )
+    }
+
+    #[test]
+    fn test_tables_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let long = "x".repeat(2000);
+        let ids = {
+            let engine = open(&dir);
+            let table = engine.create_table(notes()).unwrap();
+            let ids: Vec<RowId> = (0..50)
+                .map(|i| table.insert(vec![Value::Int(i), Value::String(format!("{}{}", i, long))]).unwrap())
+                .collect();
+            let index = IndexSchema {
+                name: "by_id".into(),
+                table: "notes".into(),
+                columns: vec![FieldPath::column("id")],
+                unique: true,
+                method: IndexMethod::Hash,
+            };
+            table.create_index(index.clone()).unwrap();
+            let mut schema = table.schema();
+            let mut pinned = ColumnSchema::new("pinned", DataType::Boolean).not_null();
+            pinned.missing = Some(Value::Bool(false));
+            schema.columns.push(pinned);
+            let change = TableChange::Schema { schema, indexes: vec![index] };
+            engine.alter_table("notes", change, &mut || Ok(())).unwrap();
+            engine.flush().unwrap();
+            ids
+        };
+
+        let engine = open(&dir);
+        assert_eq!(engine.table_names(), vec!["notes".to_string()]);
+        let table = engine.table("notes").unwrap();
+        assert_eq!(table.scan().count(), 50);
+        assert_eq!(table.get(ids[7]).unwrap().unwrap()[1], Value::String(format!("7{}", long)));
+        assert_eq!(table.get(ids[7]).unwrap().unwrap()[2], Value::Bool(false));
+        assert_eq!(table.index_scan("by_id", &KeyRange::eq(vec![Value::Int(42)])).unwrap(), vec![ids[42]]);
+        assert!(table.insert(vec![Value::Int(42), Value::Null, Value::Bool(true)]).is_err());
+
+        // Dropping the table returns its pages, overflow chains included,
+        // for the next table to reuse.
+        let pages = engine.pool.page_count();
+        engine.drop_table("notes").unwrap();
+        let schema = TableSchema::new("again", vec![ColumnSchema::new("body", DataType::Text)]);
+        let table = engine.create_table(schema).unwrap();
+        for _ in 0..50 {
+            table.insert(vec![Value::String(long.clone())]).unwrap();
+        }
+        assert_eq!(engine.pool.page_count(), pages);
+    }
+
+    #[test]
+    fn test_crash_part_way_through_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let copy = tempfile::tempdir().unwrap();
+        let long = "x".repeat(2000);
+        let engine = open_logged(dir.path(), 16);
+        let table = engine.create_table(notes()).unwrap();
+        for i in 0..20 {
+            table.insert(vec![Value::Int(i), Value::String(long.clone())]).unwrap();
+        }
+
+        // Far more pages change than the pool holds, so uncommitted changes
+        // reach the file before the copy is taken.
+        engine
+            .txns
+            .as_ref()
+            .unwrap()
+            .atomic(|| {
+                for i in 20..200 {
+                    table.insert(vec![Value::Int(i), Value::String(long.clone())])?;
+                }
+                std::fs::copy(dir.path().join("db"), copy.path().join("db"))?;
+                std::fs::create_dir(copy.path().join("log"))?;
+                for entry in std::fs::read_dir(dir.path().join("log"))? {
+                    let entry = entry?;
+                    std::fs::copy(entry.path(), copy.path().join("log").join(entry.file_name()))?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert!(engine.pool.stats().writebacks > 0);
+        assert_eq!(table.scan().count(), 200);
+
+        let engine = open_logged(copy.path(), 16);
+        let table = engine.table("notes").unwrap();
+        let mut ids: Vec<Value> = table.scan().map(|row| row.unwrap().1[0].clone()).collect();
+        ids.sort();
+        assert_eq!(ids, (0..20).map(Value::Int).collect::<Vec<_>>());
+        table.insert(vec![Value::Int(20), Value::String(long)]).unwrap();
+        assert_eq!(table.scan().count(), 21);
+    }
+}
@@ -0,0 +1,251 @@
+// src/engine/memory.rs
+//! The in-memory engine, for tests and data that need not outlive the
+//! process.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::catalog::{IndexSchema, TableSchema};
+use crate::engine::{
+    check_change, index_delete, index_fill, index_insert, index_position, index_reshape, index_update, no_index,
+    Gate, RowId, ScanItem, Shape, StorageEngine, Table, TableChange,
+};
+use crate::error::Error;
+use crate::index::{KeyRange, MemoryIndex, TableIndex};
+use crate::row::codec::RowCodec;
+use crate::types::Value;
+
+#[derive(Debug, Default)]
+pub struct MemoryEngine {
+    tables: RwLock<HashMap<String, Arc<MemoryTable>>>,
+}
+
+impl MemoryEngine {
+    pub fn new() -> Self {
+        MemoryEngine::default()
+    }
+
+    fn get(&self, name: &str) -> Result<Arc<MemoryTable>, Error> {
+        self.tables
+            .read()
+            .get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))
+    }
+}
+
+impl StorageEngine for MemoryEngine {
+    fn create_table(&self, schema: TableSchema) -> Result<Arc<dyn Table>, Error> {
+        let mut tables = self.tables.write();
+        let key = schema.name.to_lowercase();
+        if tables.contains_key(&key) {
+            return Err(Error::Execution(format!("table {} already exists", schema.name)));
+        }
+        let table = Arc::new(MemoryTable::new(schema));
+        tables.insert(key, table.clone());
+        Ok(table)
+    }
+
+    fn table(&self, name: &str) -> Option<Arc<dyn Table>> {
+        self.tables.read().get(&name.to_lowercase()).map(|table| table.clone() as Arc<dyn Table>)
+    }
+
+    fn drop_table(&self, name: &str) -> Result<bool, Error> {
+        let Some(table) = self.tables.write().remove(&name.to_lowercase()) else { return Ok(false) };
+        table.gate.retire();
+        Ok(true)
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.read().values().map(|table| table.name()).collect();
+        names.sort();
+        names
+    }
+
+    fn alter_table(
+        &self,
+        name: &str,
+        change: TableChange,
+        finish: &mut dyn FnMut() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let table = self.get(name)?;
+        let _closed = table.gate.close(|| name.to_string())?;
+        check_change(&table.shape.read().schema, &table.indexes.read(), &change)?;
+        finish()?;
+        match change {
+            TableChange::Schema { schema, indexes } => {
+                let mut shape = table.shape.write();
+                index_reshape(&mut table.indexes.write(), &schema, indexes)?;
+                *shape = Shape { codec: RowCodec::new(&schema), schema };
+            }
+            TableChange::Replace(other) => {
+                let mut tables = self.tables.write();
+                let replacement = tables
+                    .get(&other.to_lowercase())
+                    .cloned()
+                    .ok_or_else(|| Error::Execution(format!("table {} does not exist", other)))?;
+                let _replacement_closed = replacement.gate.close(|| other.clone())?;
+                let name = table.name();
+                replacement.rename(&name)?;
+                tables.remove(&other.to_lowercase());
+                tables.insert(name.to_lowercase(), replacement.clone());
+                table.gate.retire();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct MemoryTable {
+    shape: RwLock<Shape>,
+    /// Rows as the codec decodes them; rows stored before columns were
+    /// added are short of them, and before members were added to custom
+    /// types, of those.
+    rows: RwLock<BTreeMap<RowId, Vec<Value>>>,
+    next_id: AtomicU64,
+    indexes: RwLock<Vec<TableIndex>>,
+    gate: Gate,
+}
+
+impl MemoryTable {
+    fn new(schema: TableSchema) -> Self {
+        MemoryTable {
+            shape: Shape::new(schema.clone(), RowCodec::new(&schema)),
+            rows: RwLock::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            indexes: RwLock::new(Vec::new()),
+            gate: Gate::default(),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.shape.read().schema.name.clone()
+    }
+
+    /// The row as it reads back from disk: the same checks, the same
+    /// conversions.
+    fn normalize(&self, row: Vec<Value>) -> Result<Vec<Value>, Error> {
+        let shape = self.shape.read();
+        shape.codec.decode(&shape.codec.encode(&row)?)
+    }
+
+    /// A stored row with the columns added since it was stored, and its
+    /// custom-typed values upgraded to the types' latest versions.
+    fn complete(&self, mut row: Vec<Value>) -> Vec<Value> {
+        let shape = self.shape.read();
+        for column in &shape.schema.columns[row.len()..] {
+            row.push(column.missing.clone().unwrap_or(Value::Null));
+        }
+        shape.codec.upgrade(row)
+    }
+
+    /// Takes `name` as the table's name.
+    fn rename(&self, name: &str) -> Result<(), Error> {
+        let mut shape = self.shape.write();
+        let mut schema = shape.schema.clone();
+        schema.name = name.to_string();
+        let mut indexes = self.indexes.write();
+        let renamed =
+            indexes.iter().map(|index| IndexSchema { table: name.to_string(), ..index.schema().clone() }).collect();
+        index_reshape(&mut indexes, &schema, renamed)?;
+        shape.schema = schema;
+        Ok(())
+    }
+}
+
+impl Table for MemoryTable {
+    fn schema(&self) -> TableSchema {
+        self.shape.read().schema.clone()
+    }
+
+    fn scan(&self) -> Box<dyn Iterator<Item = ScanItem> + '_> {
+        if let Err(err) = self.gate.check(|| self.name()) {
+            return Box::new(std::iter::once(Err(err)));
+        }
+        let rows: Vec<(RowId, Vec<Value>)> = self.rows.read().iter().map(|(id, row)| (*id, row.clone())).collect();
+        Box::new(rows.into_iter().map(|(id, row)| Ok((id, self.complete(row)))))
+    }
+
+    fn get(&self, id: RowId) -> Result<Option<Vec<Value>>, Error> {
+        self.gate.check(|| self.name())?;
+        let row = self.rows.read().get(&id).cloned();
+        Ok(row.map(|row| self.complete(row)))
+    }
+
+    fn insert(&self, row: Vec<Value>) -> Result<RowId, Error> {
+        let _entered = self.gate.enter(|| self.name())?;
+        let row = self.normalize(row)?;
+        let id = RowId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        index_insert(&self.indexes.read(), &row, id)?;
+        self.rows.write().insert(id, row);
+        self.gate.record(id);
+        Ok(id)
+    }
+
+    fn update(&self, id: RowId, row: Vec<Value>) -> Result<(), Error> {
+        let _entered = self.gate.enter(|| self.name())?;
+        let row = self.normalize(row)?;
+        let indexes = self.indexes.read();
+        let mut rows = self.rows.write();
+        let stored =
+            rows.get_mut(&id).ok_or_else(|| Error::Execution(format!("no row {} in {}", id, self.name())))?;
+        index_update(&indexes, &self.complete(stored.clone()), &row, id)?;
+        *stored = row;
+        self.gate.record(id);
+        Ok(())
+    }
+
+    fn delete(&self, id: RowId) -> Result<bool, Error> {
+        let _entered = self.gate.enter(|| self.name())?;
+        let indexes = self.indexes.read();
+        let Some(row) = self.rows.write().remove(&id) else { return Ok(false) };
+        if let Err(err) = index_delete(&indexes, &self.complete(row.clone()), id) {
+            self.rows.write().insert(id, row);
+            return Err(err);
+        }
+        self.gate.record(id);
+        Ok(true)
+    }
+
+    fn create_index(&self, schema: IndexSchema) -> Result<(), Error> {
+        let _entered = self.gate.enter(|| self.name())?;
+        let mut indexes = self.indexes.write();
+        if index_position(&indexes, &schema.name).is_some() {
+            return Err(Error::Execution(format!("index {} already exists", schema.name)));
+        }
+        let index = TableIndex::new(&self.schema(), schema.clone(), MemoryIndex::new(schema.unique))?;
+        index_fill(&index, self)?;
+        indexes.push(index);
+        Ok(())
+    }
+
+    fn drop_index(&self, name: &str) -> Result<bool, Error> {
+        let _entered = self.gate.enter(|| self.name())?;
+        let mut indexes = self.indexes.write();
+        Ok(index_position(&indexes, name).map(|i| indexes.remove(i)).is_some())
+    }
+
+    fn indexes(&self) -> Vec<IndexSchema> {
+        self.indexes.read().iter().map(|index| index.schema().clone()).collect()
+    }
+
+    fn index_scan(&self, index: &str, range: &KeyRange) -> Result<Vec<RowId>, Error> {
+        self.gate.check(|| self.name())?;
+        let indexes = self.indexes.read();
+        let index = &indexes[index_position(&indexes, index).ok_or_else(|| no_index(&self.schema(), index))?];
+        let ids = index.scan(range)?.map(|rid| rid.map(RowId::from)).collect();
+        ids
+    }
+
+    fn track_changes(&self, on: bool) {
+        self.gate.track(on);
+    }
+
+    fn take_changes(&self) -> Vec<RowId> {
+        self.gate.take()
+    }
+}
@@ -16,4 +16,13 @@ pub enum Error {
     
     #[error("Transaction error: {0}")]
     Transaction(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Storage(err.to_string())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,579 @@
+// src/eval.rs
+//! Evaluating expressions against rows.
+//!
+//! An expression is compiled once with [`compile`], its columns found in
+//! the rows by [`Resolve`], then evaluated against each row rather than
+//! walked again for every one: a query's expressions against the rows the
+//! executor's operators pass along, a CHECK constraint against every row of
+//! a table when DDL changes it. A DEFAULT is a [`constant`]. NULL follows
+//! SQL's three-valued logic: it propagates through arithmetic and
+//! comparisons, `NULL AND FALSE` is FALSE and `NULL OR TRUE` is TRUE.
+//! Integer arithmetic is checked, and numbers of different kinds meet at the
+//! wider one: integers, then floats, then decimals.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
+
+use crate::catalog::TableSchema;
+use crate::error::Error;
+use crate::parser::ast::{BinaryOp, ColumnRef, DataType, Expr, UnaryOp};
+use crate::types::cast::{cast, try_cast};
+use crate::types::interval::{temporal_add, temporal_sub};
+use crate::types::Value;
+
+/// Finds the value a column reference reads in the rows expressions are
+/// evaluated against.
+pub trait Resolve {
+    fn position(&self, column: &ColumnRef) -> Option<usize>;
+}
+
+/// A row of a table holds its columns in order.
+impl Resolve for TableSchema {
+    fn position(&self, column: &ColumnRef) -> Option<usize> {
+        self.column_index(&column.name)
+    }
+}
+
+/// Evaluates an expression that refers to no columns, such as a DEFAULT.
+pub fn constant(expr: &Expr) -> Result<Value, Error> {
+    compile(expr, &TableSchema::new("", Vec::new()))?.eval(&[])
+}
+
+type Eval = Box<dyn Fn(&[Value]) -> Result<Value, Error> + Send + Sync>;
+
+/// An expression compiled for evaluation against many rows: a tree of
+/// closures, with column references bound to positions in the row and
+/// constant LIKE patterns parsed, so nothing is looked up per row.
+pub struct CompiledExpr(Eval);
+
+impl CompiledExpr {
+    pub fn eval(&self, row: &[Value]) -> Result<Value, Error> {
+        (self.0)(row)
+    }
+
+    /// Evaluates a condition, which holds only when TRUE.
+    pub fn holds(&self, row: &[Value]) -> Result<bool, Error> {
+        Ok(truth(self.eval(row)?)? == Some(true))
+    }
+}
+
+impl fmt::Debug for CompiledExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledExpr").finish_non_exhaustive()
+    }
+}
+
+/// Compiles `expr` for rows whose columns `columns` finds. A column it
+/// cannot find is an error here rather than when evaluating.
+pub fn compile(expr: &Expr, columns: &dyn Resolve) -> Result<CompiledExpr, Error> {
+    compile_expr(expr, columns).map(CompiledExpr)
+}
+
+fn compile_expr(expr: &Expr, columns: &dyn Resolve) -> Result<Eval, Error> {
+    let compile = |expr: &Expr| compile_expr(expr, columns);
+    Ok(match expr {
+        Expr::Column(column) => {
+            let missing = format!("column {} does not exist", column.name);
+            let i = columns.position(column).ok_or_else(|| Error::Execution(missing.clone()))?;
+            Box::new(move |row| row.get(i).cloned().ok_or_else(|| Error::Execution(missing.clone())))
+        }
+        Expr::Literal(value) => {
+            let value = value.clone();
+            Box::new(move |_| Ok(value.clone()))
+        }
+        Expr::Unary { op, expr, .. } => {
+            let (op, expr) = (op.clone(), compile(expr)?);
+            Box::new(move |row| unary(&op, expr(row)?))
+        }
+        Expr::Binary { left, op: BinaryOp::And, right, .. } => {
+            let (left, right) = (compile(left)?, compile(right)?);
+            Box::new(move |row| {
+                let left = truth(left(row)?)?;
+                if left == Some(false) {
+                    return Ok(Value::Bool(false));
+                }
+                Ok(match (left, truth(right(row)?)?) {
+                    (_, Some(false)) => Value::Bool(false),
+                    (Some(true), Some(true)) => Value::Bool(true),
+                    _ => Value::Null,
+                })
+            })
+        }
+        Expr::Binary { left, op: BinaryOp::Or, right, .. } => {
+            let (left, right) = (compile(left)?, compile(right)?);
+            Box::new(move |row| {
+                let left = truth(left(row)?)?;
+                if left == Some(true) {
+                    return Ok(Value::Bool(true));
+                }
+                Ok(match (left, truth(right(row)?)?) {
+                    (_, Some(true)) => Value::Bool(true),
+                    (Some(false), Some(false)) => Value::Bool(false),
+                    _ => Value::Null,
+                })
+            })
+        }
+        Expr::Binary { left, op: op @ (BinaryOp::In | BinaryOp::NotIn), right, .. } => {
+            let Expr::List(items) = right.as_ref() else {
+                return Err(Error::Execution("IN needs a list of values here".to_string()));
+            };
+            let (left, items) = (compile(left)?, items.iter().map(compile).collect::<Result<Vec<_>, _>>()?);
+            let negated = *op == BinaryOp::NotIn;
+            Box::new(move |row| {
+                let left = left(row)?;
+                if left.is_null() {
+                    return Ok(Value::Null);
+                }
+                let mut found = Some(false);
+                for item in &items {
+                    match compare(&left, &item(row)?)? {
+                        Some(Ordering::Equal) => {
+                            found = Some(true);
+                            break;
+                        }
+                        None => found = None,
+                        Some(_) => {}
+                    }
+                }
+                Ok(found.map_or(Value::Null, |found| Value::Bool(found != negated)))
+            })
+        }
+        Expr::Binary { left, op: op @ (BinaryOp::Like | BinaryOp::NotLike), right, .. } => {
+            let (pattern, escape) = match right.as_ref() {
+                Expr::List(items) if items.len() == 2 => (&items[0], Some(&items[1])),
+                pattern => (pattern, None),
+            };
+            let (left, negated) = (compile(left)?, *op == BinaryOp::NotLike);
+            let matches = move |text: Value, pattern: &Pattern| match text {
+                Value::String(text) => Value::Bool(pattern.matches(&text) != negated),
+                _ => Value::Null,
+            };
+            match (pattern, escape) {
+                (Expr::Literal(Value::String(pattern)), None | Some(Expr::Literal(Value::String(_)))) => {
+                    let escape = escape.map(|e| constant(e).and_then(escape_char)).transpose()?.flatten();
+                    let pattern = Pattern::new(pattern, escape);
+                    Box::new(move |row| Ok(matches(left(row)?, &pattern)))
+                }
+                _ => {
+                    let pattern = compile(pattern)?;
+                    let escape = escape.map(compile).transpose()?;
+                    Box::new(move |row| {
+                        let (text, pattern) = (left(row)?, pattern(row)?);
+                        let escape = escape.as_ref().map(|e| e(row).and_then(escape_char)).transpose()?.flatten();
+                        let Value::String(pattern) = pattern else { return Ok(Value::Null) };
+                        Ok(matches(text, &Pattern::new(&pattern, escape)))
+                    })
+                }
+            }
+        }
+        Expr::Binary { left, op, right, .. } => {
+            let (left, op, right) = (compile(left)?, op.clone(), compile(right)?);
+            Box::new(move |row| binary(&op, left(row)?, right(row)?))
+        }
+        Expr::Function { name, args, .. } => {
+            let (name, args) = (name.clone(), args.iter().map(compile).collect::<Result<Vec<_>, _>>()?);
+            Box::new(move |row| function(&name, args.iter().map(|arg| arg(row)).collect::<Result<_, _>>()?))
+        }
+        Expr::Cast { expr, data_type, try_cast: tried } => {
+            let (expr, data_type) = (compile(expr)?, data_type.clone());
+            match tried {
+                false => Box::new(move |row| cast(&expr(row)?, &data_type)),
+                true => Box::new(move |row| Ok(try_cast(&expr(row)?, &data_type))),
+            }
+        }
+        Expr::Case { operand, when_clauses, else_result } => {
+            let operand = operand.as_deref().map(compile).transpose()?;
+            let when_clauses = when_clauses
+                .iter()
+                .map(|(when, then)| Ok((compile(when)?, compile(then)?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let else_result = else_result.as_deref().map(compile).transpose()?;
+            Box::new(move |row| {
+                let operand = operand.as_ref().map(|operand| operand(row)).transpose()?;
+                for (when, then) in &when_clauses {
+                    let when = when(row)?;
+                    let matched = match &operand {
+                        Some(operand) => compare(operand, &when)? == Some(Ordering::Equal),
+                        None => truth(when)? == Some(true),
+                    };
+                    if matched {
+                        return then(row);
+                    }
+                }
+                else_result.as_ref().map_or(Ok(Value::Null), |result| result(row))
+            })
+        }
+        Expr::Field { expr, step, .. } => {
+            let (expr, step) = (compile(expr)?, step.clone());
+            Box::new(move |row| Ok(expr(row)?.path(std::slice::from_ref(&step))))
+        }
+        Expr::List(_) | Expr::Wildcard { .. } | Expr::Exists(_) | Expr::Subquery(_) => {
+            return Err(Error::Execution(format!("{} cannot be evaluated against a single row", expr)));
+        }
+    })
+}
+
+/// A boolean's truth, `None` for NULL.
+pub fn truth(value: Value) -> Result<Option<bool>, Error> {
+    match value {
+        Value::Bool(b) => Ok(Some(b)),
+        Value::Null => Ok(None),
+        other => Err(Error::Type(format!("expected a boolean, got {}", other.type_name()))),
+    }
+}
+
+fn unary(op: &UnaryOp, value: Value) -> Result<Value, Error> {
+    Ok(match op {
+        UnaryOp::IsNull => Value::Bool(value.is_null()),
+        UnaryOp::IsNotNull => Value::Bool(!value.is_null()),
+        UnaryOp::Not => truth(value)?.map_or(Value::Null, |b| Value::Bool(!b)),
+        UnaryOp::Negative => match number(&value) {
+            _ if value.is_null() => Value::Null,
+            Some(Number::Int(i)) => int(-i)?,
+            Some(Number::Float(f)) => Value::Float(-f),
+            Some(Number::Decimal(d)) => Value::Decimal(-d),
+            None => return Err(Error::Type(format!("cannot negate {}", value.type_name()))),
+        },
+    })
+}
+
+/// The digits a decimal quotient keeps past its finer operand's scale.
+const DIVISION_SCALE: i64 = 16;
+
+/// A number widened to the kind arithmetic is done in.
+enum Number {
+    Int(i128),
+    Float(f64),
+    Decimal(BigDecimal),
+}
+
+fn number(value: &Value) -> Option<Number> {
+    match value {
+        Value::Decimal(d) => Some(Number::Decimal(d.clone())),
+        _ => value.as_i128().map(Number::Int).or_else(|| value.as_f64().map(Number::Float)),
+    }
+}
+
+fn decimal(number: Number) -> Option<BigDecimal> {
+    match number {
+        Number::Int(i) => Some(BigDecimal::from(BigInt::from(i))),
+        // The shortest decimal form, as a cast takes, so 0.1 stays 0.1.
+        Number::Float(f) if f.is_finite() => f.to_string().parse().ok(),
+        Number::Float(_) => None,
+        Number::Decimal(d) => Some(d),
+    }
+}
+
+fn float(number: &Number) -> f64 {
+    match number {
+        Number::Int(i) => *i as f64,
+        Number::Float(f) => *f,
+        Number::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+    }
+}
+
+fn int(i: i128) -> Result<Value, Error> {
+    i64::try_from(i).map(Value::Int).map_err(|_| out_of_range())
+}
+
+fn unsigned(value: &Value) -> bool {
+    matches!(value, Value::UInt8(_) | Value::UInt16(_) | Value::UInt32(_) | Value::UInt64(_))
+}
+
+fn out_of_range() -> Error {
+    Error::Execution("integer out of range".to_string())
+}
+
+/// Orders two values, `None` if either is NULL. Numbers compare by value
+/// whatever their kind, and a string compared with a value of another type
+/// is read as that type.
+pub fn compare(left: &Value, right: &Value) -> Result<Option<Ordering>, Error> {
+    if left.is_null() || right.is_null() {
+        return Ok(None);
+    }
+    let read = |text: &Value, other: &Value| match scalar_type(other) {
+        Some(data_type) => cast(text, &data_type),
+        None => Ok(text.clone()),
+    };
+    Ok(Some(match (left, right) {
+        (Value::String(_), other) if !matches!(other, Value::String(_)) => read(left, right)?.cmp(right),
+        (other, Value::String(_)) if !matches!(other, Value::String(_)) => left.cmp(&read(right, left)?),
+        _ => left.cmp(right),
+    }))
+}
+
+/// The type a string is cast to when compared with `value`.
+fn scalar_type(value: &Value) -> Option<DataType> {
+    Some(match value {
+        Value::Bool(_) => DataType::Boolean,
+        Value::Uuid(_) => DataType::Uuid,
+        Value::Date(_) => DataType::Date,
+        Value::Time(_) => DataType::Time,
+        Value::DateTime(_) => DataType::Timestamp,
+        Value::Interval(_) => DataType::Interval,
+        _ => return None,
+    })
+}
+
+pub(crate) fn binary(op: &BinaryOp, left: Value, right: Value) -> Result<Value, Error> {
+    let ordering = |test: fn(Ordering) -> bool| -> Result<Value, Error> {
+        Ok(compare(&left, &right)?.map_or(Value::Null, |ordering| Value::Bool(test(ordering))))
+    };
+    match op {
+        BinaryOp::Eq => ordering(Ordering::is_eq),
+        BinaryOp::NotEq => ordering(Ordering::is_ne),
+        BinaryOp::Lt => ordering(Ordering::is_lt),
+        BinaryOp::LtEq => ordering(Ordering::is_le),
+        BinaryOp::Gt => ordering(Ordering::is_gt),
+        BinaryOp::GtEq => ordering(Ordering::is_ge),
+        _ if left.is_null() || right.is_null() => Ok(Value::Null),
+        _ => arithmetic(op, &left, &right),
+    }
+}
+
+fn arithmetic(op: &BinaryOp, left: &Value, right: &Value) -> Result<Value, Error> {
+    let temporal = |v: &Value| matches!(v, Value::Date(_) | Value::DateTime(_) | Value::Time(_) | Value::Interval(_));
+    match op {
+        BinaryOp::Add if temporal(left) || temporal(right) => return temporal_add(left, right),
+        BinaryOp::Subtract if temporal(left) || temporal(right) => return temporal_sub(left, right),
+        _ => {}
+    }
+    let (Some(a), Some(b)) = (number(left), number(right)) else {
+        return Err(Error::Type(format!(
+            "cannot apply {:?} to {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        )));
+    };
+    let by_zero = || Error::Execution("division by zero".to_string());
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => {
+            let n = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Subtract => a - b,
+                BinaryOp::Multiply => a * b,
+                BinaryOp::Divide => a.checked_div(b).ok_or_else(by_zero)?,
+                BinaryOp::Modulo => a.checked_rem(b).ok_or_else(by_zero)?,
+                _ => unreachable!("not an arithmetic operator"),
+            };
+            // Unsigned operands give an unsigned result.
+            match unsigned(left) && unsigned(right) {
+                true => u64::try_from(n).map(Value::UInt64).map_err(|_| out_of_range()),
+                false => int(n),
+            }
+        }
+        (a @ Number::Decimal(_), b) | (a, b @ Number::Decimal(_)) => {
+            let not_finite = || Error::Type("a float that is not finite has no decimal value".to_string());
+            let (a, b) = (decimal(a).ok_or_else(not_finite)?, decimal(b).ok_or_else(not_finite)?);
+            if matches!(op, BinaryOp::Divide | BinaryOp::Modulo) && b.is_zero() {
+                return Err(by_zero());
+            }
+            Ok(Value::Decimal(match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Subtract => a - b,
+                BinaryOp::Multiply => a * b,
+                BinaryOp::Divide => {
+                    // A quotient that does not terminate stops a few digits
+                    // past the finer operand rather than at the library's
+                    // hundred-digit precision.
+                    let scale = a.fractional_digit_count().max(b.fractional_digit_count()).max(0) + DIVISION_SCALE;
+                    let q = a / b;
+                    match q.fractional_digit_count() > scale {
+                        true => q.with_scale_round(scale, RoundingMode::HalfUp),
+                        false => q,
+                    }
+                }
+                BinaryOp::Modulo => a % b,
+                _ => unreachable!("not an arithmetic operator"),
+            }))
+        }
+        (a, b) => {
+            let (a, b) = (float(&a), float(&b));
+            if matches!(op, BinaryOp::Divide | BinaryOp::Modulo) && b == 0.0 {
+                return Err(by_zero());
+            }
+            Ok(Value::Float(match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Subtract => a - b,
+                BinaryOp::Multiply => a * b,
+                BinaryOp::Divide => a / b,
+                BinaryOp::Modulo => a % b,
+                _ => unreachable!("not an arithmetic operator"),
+            }))
+        }
+    }
+}
+
+fn function(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+    let one = |args: &[Value]| match args {
+        [arg] => Ok(arg.clone()),
+        _ => Err(Error::Execution(format!("{} takes one argument", name.to_uppercase()))),
+    };
+    Ok(match name.to_uppercase().as_str() {
+        "COALESCE" => args.into_iter().find(|arg| !arg.is_null()).unwrap_or(Value::Null),
+        "LOWER" | "UPPER" | "LENGTH" => match one(&args)? {
+            Value::String(s) if name.eq_ignore_ascii_case("lower") => Value::String(s.to_lowercase()),
+            Value::String(s) if name.eq_ignore_ascii_case("upper") => Value::String(s.to_uppercase()),
+            Value::String(s) => Value::Int(s.chars().count() as i64),
+            Value::Null => Value::Null,
+            other => return Err(Error::Type(format!("{} expects text, got {}", name, other.type_name()))),
+        },
+        "ABS" => match one(&args)? {
+            value if value.is_null() => Value::Null,
+            value => match number(&value) {
+                Some(Number::Int(i)) => int(i.abs())?,
+                Some(Number::Float(f)) => Value::Float(f.abs()),
+                Some(Number::Decimal(d)) => Value::Decimal(d.abs()),
+                None => return Err(Error::Type(format!("ABS expects a number, got {}", value.type_name()))),
+            },
+        },
+        _ => return Err(Error::Execution(format!("function {} cannot be evaluated here", name))),
+    })
+}
+
+/// The escape character of a LIKE pattern.
+fn escape_char(value: Value) -> Result<Option<char>, Error> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(e) if e.chars().count() == 1 => Ok(e.chars().next()),
+        _ => Err(Error::Execution("ESCAPE must be a single character".to_string())),
+    }
+}
+
+/// A SQL `LIKE` pattern: `%` matches any run of characters, `_` any one,
+/// and the escape character makes the one after it literal.
+struct Pattern {
+    tokens: Vec<Token>,
+}
+
+enum Token {
+    Any,
+    One,
+    Char(char),
+}
+
+impl Pattern {
+    fn new(pattern: &str, escape: Option<char>) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                c if Some(c) == escape => Token::Char(chars.next().unwrap_or(c)),
+                '%' => Token::Any,
+                '_' => Token::One,
+                c => Token::Char(c),
+            });
+        }
+        Pattern { tokens }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        // matched[j]: whether the tokens so far match the first j characters.
+        let mut matched = vec![false; text.len() + 1];
+        matched[0] = true;
+        for token in &self.tokens {
+            let mut next = vec![false; text.len() + 1];
+            for j in 0..=text.len() {
+                next[j] = match token {
+                    Token::Any => matched[j] || (j > 0 && next[j - 1]),
+                    Token::One => j > 0 && matched[j - 1],
+                    Token::Char(c) => j > 0 && matched[j - 1] && text[j - 1] == *c,
+                };
+            }
+            matched = next;
+        }
+        matched[text.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::ColumnSchema;
+    use crate::parser::parse_expr;
+
+    fn eval(sql: &str) -> Result<Value, Error> {
+        let schema = TableSchema::new(
+            "t",
+            vec![ColumnSchema::new("a", DataType::Integer(None)), ColumnSchema::new("b", DataType::Text)],
+        );
+        compile(&parse_expr(sql).unwrap(), &schema)?.eval(&[Value::Int32(7), Value::Null])
+    }
+
+    #[test]
+    fn test_three_valued_logic() {
+        assert_eq!(eval("a > 5 AND b IS NULL").unwrap(), Value::Bool(true));
+        assert_eq!(eval("b = 'x' AND a < 0").unwrap(), Value::Bool(false));
+        assert_eq!(eval("b = 'x' OR a < 0").unwrap(), Value::Null);
+        assert_eq!(eval("NOT (b = 'x')").unwrap(), Value::Null);
+        assert_eq!(eval("a IN (1, 7)").unwrap(), Value::Bool(true));
+        assert_eq!(eval("a NOT IN (1, NULL)").unwrap(), Value::Null);
+        assert_eq!(eval("CASE WHEN b IS NULL THEN 'none' ELSE b END").unwrap(), Value::String("none".into()));
+    }
+
+    #[test]
+    fn test_arithmetic_and_patterns() {
+        assert_eq!(eval("a * 2 + 1").unwrap(), Value::Int(15));
+        assert_eq!(eval("a / 2.0").unwrap(), Value::Decimal("3.5".parse().unwrap()));
+        assert_eq!(eval("1.0 / 3").unwrap().to_string(), "0.33333333333333333");
+        assert_eq!(eval("2.50 / 3").unwrap().to_string(), "0.833333333333333333");
+        assert_eq!(eval("1.0 / 8").unwrap(), Value::Decimal("0.125".parse().unwrap()));
+        assert_eq!(eval("CAST(1.5 AS DOUBLE) + 0.25").unwrap(), Value::Decimal("1.75".parse().unwrap()));
+        assert_eq!(eval("CAST(0.1 AS DOUBLE) * 3.0").unwrap(), Value::Decimal("0.3".parse().unwrap()));
+        assert_eq!(eval("a * CAST(0.5 AS DOUBLE)").unwrap(), Value::Float(3.5));
+        assert!(eval("a / 0").unwrap_err().to_string().contains("division by zero"));
+        assert!(eval("9223372036854775807 + a").unwrap_err().to_string().contains("out of range"));
+        assert_eq!(eval("'a_b%' LIKE 'a!_b!%' ESCAPE '!'").unwrap(), Value::Bool(true));
+        assert_eq!(eval("'axb' LIKE 'a!_b' ESCAPE '!'").unwrap(), Value::Bool(false));
+        assert_eq!(eval("'hello' LIKE 'h%o'").unwrap(), Value::Bool(true));
+        assert_eq!(constant(&parse_expr("UPPER('x')").unwrap()).unwrap(), Value::String("X".into()));
+        assert!(constant(&parse_expr("a + 1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_temporal_and_unsigned_arithmetic() {
+        let date = |s: &str| Value::Date(s.parse().unwrap());
+        assert_eq!(eval("CAST('2024-01-31' AS DATE) + INTERVAL '1 month'").unwrap(), date("2024-02-29"));
+        assert_eq!(eval("CAST('2024-03-01' AS DATE) - a").unwrap(), date("2024-02-23"));
+        assert_eq!(eval("a + CAST('2024-03-01' AS DATE)").unwrap(), date("2024-03-08"));
+        let at = |s: &str| Value::DateTime(s.parse().unwrap());
+        let sql = "CAST('2024-03-01 12:00:00' AS TIMESTAMP) - INTERVAL '1 day 02:00:00'";
+        assert_eq!(eval(sql).unwrap(), at("2024-02-29T10:00:00Z"));
+        assert!(eval("CAST('2024-03-01' AS DATE) * 2").is_err());
+
+        let schema = TableSchema::new(
+            "t",
+            vec![ColumnSchema::new("a", DataType::Integer(None)), ColumnSchema::new("b", DataType::Integer(None))],
+        );
+        let compiled = |sql: &str| compile(&parse_expr(sql).unwrap(), &schema).unwrap();
+        let row = [Value::UInt64(u64::MAX - 1), Value::UInt8(1)];
+        assert_eq!(compiled("a + b").eval(&row).unwrap(), Value::UInt64(u64::MAX));
+        assert_eq!(compiled("b - 2").eval(&row).unwrap(), Value::Int(-1));
+        assert!(compiled("b - a").eval(&row).unwrap_err().to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn test_compiled_expressions() {
+        let schema = TableSchema::new(
+            "t",
+            vec![ColumnSchema::new("a", DataType::Integer(None)), ColumnSchema::new("b", DataType::Text)],
+        );
+        let compiled = compile(&parse_expr("CASE WHEN b LIKE 'x%' THEN a * 2 ELSE -a END").unwrap(), &schema).unwrap();
+        let row = |a: i32, b: &str| [Value::Int32(a), Value::String(b.to_string())];
+        assert_eq!(compiled.eval(&row(3, "xy")).unwrap(), Value::Int(6));
+        assert_eq!(compiled.eval(&row(3, "yx")).unwrap(), Value::Int(-3));
+        assert_eq!(compiled.eval(&[Value::Null, Value::Null]).unwrap(), Value::Null);
+
+        let compiled = compile(&parse_expr("b LIKE c").unwrap(), &schema);
+        assert!(compiled.unwrap_err().to_string().contains("column c does not exist"));
+        let pattern = compile(&parse_expr("'50%' LIKE b ESCAPE '#'").unwrap(), &schema).unwrap();
+        assert_eq!(pattern.eval(&[Value::Null, Value::String("50#%".into())]).unwrap(), Value::Bool(true));
+        assert_eq!(pattern.eval(&[Value::Null, Value::String("5_#%".into())]).unwrap(), Value::Bool(true));
+        assert_eq!(pattern.eval(&[Value::Null, Value::String("5#_%".into())]).unwrap(), Value::Bool(false));
+    }
+}
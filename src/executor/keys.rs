@@ -0,0 +1,407 @@
+// src/executor/keys.rs
+//! PRIMARY KEY, UNIQUE and FOREIGN KEY constraints, enforced on the rows a
+//! statement changes.
+//!
+//! The changes a statement makes to a table are checked together, before
+//! any is made: once they are, no two rows of the table may share a unique
+//! key, and every foreign key must refer to a row. Deleting a row, or
+//! changing its key, while rows of some table refer to it does what the
+//! foreign key's `ON DELETE` or `ON UPDATE` says, by default failing; the
+//! rows that changes are checked the same way in turn. Keys holding a NULL
+//! are never checked, as in SQL.
+//!
+//! Rows are looked up by key through an index on the key's columns where
+//! the table has one, and otherwise by reading the table once per key.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, MemoryCatalog, TableSchema};
+use crate::engine::{RowId, StorageEngine, Table};
+use crate::error::Error;
+use crate::eval::constant;
+use crate::index::KeyRange;
+use crate::parser::ast::{IndexMethod, ReferentialAction, TableConstraint};
+use crate::types::cast::cast;
+use crate::types::Value;
+
+/// A stored row, with its id.
+pub type Stored = (RowId, Vec<Value>);
+
+/// The rows a statement changes in one table: each with its id and values
+/// before, unless it is inserted, and its values after, unless it is
+/// deleted.
+pub struct Changes {
+    pub schema: TableSchema,
+    pub table: Arc<dyn Table>,
+    pub rows: Vec<(Option<Stored>, Option<Vec<Value>>)>,
+}
+
+impl Changes {
+    pub fn new(schema: TableSchema, table: Arc<dyn Table>) -> Self {
+        Changes { schema, table, rows: Vec::new() }
+    }
+
+    /// Makes the changes, returning how many rows changed and the id of the
+    /// last row inserted. Each change made is noted in `made`, even if a
+    /// later one fails.
+    pub fn apply(&self, made: &mut Vec<Made>) -> Result<(u64, Option<RowId>), Error> {
+        let (mut count, mut last) = (0, None);
+        let table = &self.schema.name;
+        for row in &self.rows {
+            match row {
+                (None, Some(new)) => {
+                    let id = self.table.insert(new.clone())?;
+                    made.push(Made { table: table.clone(), id, old: None, kept: true });
+                    last = Some(id);
+                    count += 1;
+                }
+                (Some((id, old)), Some(new)) => {
+                    self.table.update(*id, new.clone())?;
+                    made.push(Made { table: table.clone(), id: *id, old: Some(old.clone()), kept: true });
+                    count += 1;
+                }
+                (Some((id, old)), None) => {
+                    if self.table.delete(*id)? {
+                        made.push(Made { table: table.clone(), id: *id, old: Some(old.clone()), kept: false });
+                        count += 1;
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        Ok((count, last))
+    }
+}
+
+/// A change made to a row of a table, to be undone.
+#[derive(Debug)]
+pub struct Made {
+    pub table: String,
+    id: RowId,
+    /// The row's values before, unless it was inserted.
+    old: Option<Vec<Value>>,
+    /// Whether the row is still there: it was not deleted.
+    kept: bool,
+}
+
+/// Undoes changes to rows, latest first. A row deleted is inserted again
+/// under a new id, which the changes made to it before then are undone on.
+pub fn undo(engine: &dyn StorageEngine, made: Vec<Made>) -> Result<(), Error> {
+    let mut moved: HashMap<(String, RowId), RowId> = HashMap::new();
+    for made in made.into_iter().rev() {
+        let table = engine
+            .table(&made.table)
+            .ok_or_else(|| Error::Storage(format!("table {} has no storage", made.table)))?;
+        let key = (made.table.to_lowercase(), made.id);
+        let id = moved.get(&key).copied().unwrap_or(made.id);
+        match (made.old, made.kept) {
+            (None, _) => {
+                table.delete(id)?;
+            }
+            (Some(old), true) => table.update(id, old)?,
+            (Some(old), false) => {
+                moved.insert(key, table.insert(old)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks `changes` against the keys of its table and the foreign keys
+/// referring to it, returning the changes the foreign keys' actions make to
+/// other rows, checked too, to be made along with them.
+pub fn check(catalog: &MemoryCatalog, engine: &dyn StorageEngine, changes: &Changes) -> Result<Vec<Changes>, Error> {
+    let mut plan = Plan { catalog, engine, going: HashSet::new(), coming: HashMap::new(), cascades: Vec::new() };
+    plan.check(changes)?;
+    Ok(plan.cascades)
+}
+
+/// What the statement and the changes it cascades to do, by table.
+struct Plan<'a> {
+    catalog: &'a MemoryCatalog,
+    engine: &'a dyn StorageEngine,
+    /// The rows changed or deleted.
+    going: HashSet<(String, RowId)>,
+    /// The rows inserted or changed, as they will be.
+    coming: HashMap<String, Vec<Vec<Value>>>,
+    cascades: Vec<Changes>,
+}
+
+impl Plan<'_> {
+    fn check(&mut self, changes: &Changes) -> Result<(), Error> {
+        let table = changes.schema.name.to_lowercase();
+        for (old, new) in &changes.rows {
+            if let Some((id, _)) = old {
+                self.going.insert((table.clone(), *id));
+            }
+            if let Some(new) = new {
+                self.coming.entry(table.clone()).or_default().push(new.clone());
+            }
+        }
+        self.check_unique(changes)?;
+        self.check_references(changes)?;
+        for cascade in self.referring(changes)? {
+            self.check(&cascade)?;
+            self.cascades.push(cascade);
+        }
+        Ok(())
+    }
+
+    /// Whether a row of the finder's table, other than those going, holds
+    /// `key` or will.
+    fn holds(&self, finder: &mut Finder<'_>, key: &[Value]) -> Result<bool, Error> {
+        let table = finder.schema.name.to_lowercase();
+        let coming = self.coming.get(&table).into_iter().flatten();
+        if coming.into_iter().any(|row| key_of(row, &finder.columns).as_deref() == Some(key)) {
+            return Ok(true);
+        }
+        Ok(finder.find(key)?.iter().any(|(id, _)| !self.going.contains(&(table.clone(), *id))))
+    }
+
+    fn check_unique(&self, changes: &Changes) -> Result<(), Error> {
+        let schema = &changes.schema;
+        for (name, columns) in unique_keys(schema)? {
+            let mut finder = Finder::new(&*changes.table, schema, columns);
+            let mut seen = BTreeSet::new();
+            for new in changes.rows.iter().filter_map(|(_, new)| new.as_ref()) {
+                let Some(key) = key_of(new, &finder.columns) else { continue };
+                let taken = !seen.insert(key.clone())
+                    || finder
+                        .find(&key)?
+                        .iter()
+                        .any(|(id, _)| !self.going.contains(&(schema.name.to_lowercase(), *id)));
+                if taken {
+                    return Err(Error::Execution(format!(
+                        "duplicate key ({}) violates unique constraint {} of table {}",
+                        show(&key),
+                        name,
+                        schema.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the foreign keys of the rows changed refer to rows.
+    fn check_references(&self, changes: &Changes) -> Result<(), Error> {
+        let schema = &changes.schema;
+        for constraint in &schema.constraints {
+            let TableConstraint::ForeignKey { name, columns, ref_table, ref_columns, .. } = constraint else {
+                continue;
+            };
+            let columns = positions(schema, columns)?;
+            let (referenced, table) = self.referenced(changes, ref_table)?;
+            let mut finder = Finder::new(&*table, &referenced, positions(&referenced, ref_columns)?);
+            for (old, new) in &changes.rows {
+                let Some(key) = new.as_ref().and_then(|new| key_of(new, &columns)) else { continue };
+                if old.as_ref().is_some_and(|(_, old)| key_of(old, &columns).as_ref() == Some(&key)) {
+                    continue;
+                }
+                if !self.holds(&mut finder, &key)? {
+                    return Err(Error::Execution(format!(
+                        "foreign key constraint {} of table {} is violated: key ({}) is not in table {}",
+                        name.as_deref().unwrap_or_default(),
+                        schema.name,
+                        show(&key),
+                        referenced.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The changes to rows referring to the keys the changes take away, as
+    /// their foreign keys say.
+    fn referring(&self, changes: &Changes) -> Result<Vec<Changes>, Error> {
+        let schema = &changes.schema;
+        let mut cascades = Vec::new();
+        for other in self.catalog.tables() {
+            for constraint in &other.constraints {
+                let TableConstraint::ForeignKey { name, columns, ref_table, ref_columns, on_delete, on_update } =
+                    constraint
+                else {
+                    continue;
+                };
+                if !ref_table.eq_ignore_ascii_case(&schema.name) {
+                    continue;
+                }
+                let other = if other.name.eq_ignore_ascii_case(&schema.name) { schema } else { other };
+                let table = match other.name.eq_ignore_ascii_case(&schema.name) {
+                    true => changes.table.clone(),
+                    false => self.storage(&other.name)?,
+                };
+                let mut keys = Finder::new(&*changes.table, schema, positions(schema, ref_columns)?);
+                let mut rows = Finder::new(&*table, other, positions(other, columns)?);
+                let mut cascade = Changes::new(other.clone(), table.clone());
+                let mut changed = HashSet::new();
+                for (old, new) in &changes.rows {
+                    let Some((_, old)) = old else { continue };
+                    let Some(key) = key_of(old, &keys.columns) else { continue };
+                    let new_key = new.as_ref().map(|new| keys.columns.iter().map(|&i| new[i].clone()).collect());
+                    if new_key.as_ref() == Some(&key) || self.holds(&mut keys, &key)? {
+                        continue;
+                    }
+                    let going = |id: &RowId| self.going.contains(&(other.name.to_lowercase(), *id));
+                    let referring: Vec<_> = rows.find(&key)?.into_iter().filter(|(id, _)| !going(id)).collect();
+                    if referring.is_empty() {
+                        continue;
+                    }
+                    let action = if new.is_some() { on_update } else { on_delete };
+                    let values: Vec<Value> = match (action, &new_key) {
+                        (Some(ReferentialAction::Cascade), None) => Vec::new(),
+                        (Some(ReferentialAction::Cascade), Some(new_key)) => new_key.clone(),
+                        (Some(ReferentialAction::SetNull), _) => vec![Value::Null; rows.columns.len()],
+                        (Some(ReferentialAction::SetDefault), _) => rows
+                            .columns
+                            .iter()
+                            .map(|&i| match &other.columns[i].default {
+                                Some(default) => cast(&constant(default)?, &other.columns[i].data_type),
+                                None => Ok(Value::Null),
+                            })
+                            .collect::<Result<_, _>>()?,
+                        _ => {
+                            return Err(Error::Execution(format!(
+                                "foreign key constraint {} of table {} is violated: rows still refer to key ({}) \
+                                 of table {}",
+                                name.as_deref().unwrap_or_default(),
+                                other.name,
+                                show(&key),
+                                schema.name
+                            )))
+                        }
+                    };
+                    for (id, row) in referring {
+                        if !changed.insert(id) {
+                            continue;
+                        }
+                        let new = (!values.is_empty()).then(|| {
+                            let mut new = row.clone();
+                            for (&i, value) in rows.columns.iter().zip(&values) {
+                                new[i] = value.clone();
+                            }
+                            new
+                        });
+                        cascade.rows.push((Some((id, row)), new));
+                    }
+                }
+                if !cascade.rows.is_empty() {
+                    cascades.push(cascade);
+                }
+            }
+        }
+        Ok(cascades)
+    }
+
+    /// The schema and storage of the table a foreign key refers to.
+    fn referenced(&self, changes: &Changes, name: &str) -> Result<(TableSchema, Arc<dyn Table>), Error> {
+        if name.eq_ignore_ascii_case(&changes.schema.name) {
+            return Ok((changes.schema.clone(), changes.table.clone()));
+        }
+        let schema = self
+            .catalog
+            .table(name)
+            .ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))?;
+        Ok((schema, self.storage(name)?))
+    }
+
+    fn storage(&self, name: &str) -> Result<Arc<dyn Table>, Error> {
+        self.engine.table(name).ok_or_else(|| Error::Storage(format!("table {} has no storage", name)))
+    }
+}
+
+/// The table's unique keys, the primary key first, by constraint name.
+fn unique_keys(schema: &TableSchema) -> Result<Vec<(String, Vec<usize>)>, Error> {
+    let mut keys = Vec::new();
+    let key: Vec<usize> = (0..schema.columns.len()).filter(|&i| schema.columns[i].primary_key).collect();
+    if !key.is_empty() {
+        keys.push((format!("{}_pkey", schema.name), key));
+    }
+    for constraint in &schema.constraints {
+        if let TableConstraint::Unique { name, columns } = constraint {
+            keys.push((name.clone().unwrap_or_default(), positions(schema, columns)?));
+        }
+    }
+    Ok(keys)
+}
+
+fn positions(schema: &TableSchema, columns: &[String]) -> Result<Vec<usize>, Error> {
+    columns
+        .iter()
+        .map(|c| {
+            schema
+                .column_index(c)
+                .ok_or_else(|| Error::Execution(format!("column {} does not exist in table {}", c, schema.name)))
+        })
+        .collect()
+}
+
+/// The values of `columns` in a row, unless one is NULL.
+fn key_of(row: &[Value], columns: &[usize]) -> Option<Vec<Value>> {
+    columns.iter().map(|&i| Some(row[i].clone()).filter(|v| !v.is_null())).collect()
+}
+
+fn show(key: &[Value]) -> String {
+    key.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Finds the rows of a table holding a key in some of its columns.
+struct Finder<'a> {
+    table: &'a dyn Table,
+    schema: &'a TableSchema,
+    columns: Vec<usize>,
+    /// An index leading with the columns, with where each of its key
+    /// columns is among them.
+    index: Option<(String, Vec<usize>)>,
+    /// The table's rows by key, read once if there is no index.
+    rows: Option<BTreeMap<Vec<Value>, Vec<Stored>>>,
+}
+
+impl<'a> Finder<'a> {
+    fn new(table: &'a dyn Table, schema: &'a TableSchema, columns: Vec<usize>) -> Self {
+        let index = table.indexes().into_iter().find_map(|index| {
+            let n = columns.len();
+            if index.columns.len() < n || (index.method == IndexMethod::Hash && index.columns.len() != n) {
+                return None;
+            }
+            let order = index.columns[..n]
+                .iter()
+                .map(|path| match path.steps.is_empty() {
+                    true => columns.iter().position(|&i| schema.columns[i].name.eq_ignore_ascii_case(&path.column)),
+                    false => None,
+                })
+                .collect::<Option<Vec<usize>>>()?;
+            let distinct: HashSet<&usize> = order.iter().collect();
+            (distinct.len() == n).then_some((index.name, order))
+        });
+        Finder { table, schema, columns, index, rows: None }
+    }
+
+    fn find(&mut self, key: &[Value]) -> Result<Vec<Stored>, Error> {
+        if let Some((index, order)) = &self.index {
+            let range = KeyRange::eq(order.iter().map(|&k| key[k].clone()).collect());
+            let mut found = Vec::new();
+            for id in self.table.index_scan(index, &range)? {
+                if let Some(row) = self.table.get(id)? {
+                    if self.columns.iter().zip(key).all(|(&i, value)| row[i] == *value) {
+                        found.push((id, row));
+                    }
+                }
+            }
+            return Ok(found);
+        }
+        if self.rows.is_none() {
+            let mut rows: BTreeMap<Vec<Value>, Vec<Stored>> = BTreeMap::new();
+            for row in self.table.scan() {
+                let (id, row) = row?;
+                if let Some(key) = key_of(&row, &self.columns) {
+                    rows.entry(key).or_default().push((id, row));
+                }
+            }
+            self.rows = Some(rows);
+        }
+        Ok(self.rows.as_ref().and_then(|rows| rows.get(key)).cloned().unwrap_or_default())
+    }
+}
@@ -1,100 +1,727 @@
-use crate::{Error, Value};
+// src/executor/mod.rs
+//! Running statements against a database.
+//!
+//! A query is analyzed, then planned as a tree of [`operator`]s: a scan of
+//! each table, through an index where [`access_path`] finds one, joined
+//! left to right, then the filter, grouping, ordering, projection and limit
+//! the query asks for. The executor pulls the rows of the result from the
+//! root of the tree. Subqueries are not supported yet.
+//!
+//! `INSERT`, `UPDATE` and `DELETE` check the NOT NULL and CHECK constraints
+//! of the rows they store, and the [`keys`] of the tables they change. Schema
+//! changes run in a catalog transaction of their own.
+
+pub mod keys;
+pub mod operator;
+
+use std::sync::Arc;
+
 use async_trait::async_trait;
 
+use crate::analyzer::{Analyzer, OutputColumn, Type};
+use crate::catalog::{information_schema, Catalog, MemoryCatalog, TableSchema};
+use crate::database::Database;
+use crate::engine::{RowId, StorageEngine, Table};
+use crate::error::Error;
+use crate::eval::{compile, constant, truth, CompiledExpr};
+use crate::parser::ast::{
+    BinaryOp, ColumnRef, DeleteStatement, Expr, InsertStatement, LimitClause, OrderByExpr, SelectColumn,
+    SelectStatement, Span, Statement, TableConstraint, TableReference, UpdateStatement,
+};
+use crate::planner::{access_path, AccessPath};
+use crate::types::cast::cast;
+use crate::Value;
+use keys::{Changes, Made};
+use operator::{
+    Aggregate, AggregateCall, AggregateFunction, BoxedOperator, Distinct, Filter, IndexScan, Layout, Limit,
+    NestedLoopJoin, Project, SeqScan, Sort, SortOrder,
+};
+
 #[async_trait]
 pub trait Executor: Send + Sync {
     async fn execute(&self, stmt: &Statement) -> Result<QueryResult, Error>;
 }
 
+#[derive(Debug, Default)]
 pub struct QueryResult {
     pub affected_rows: u64,
+    /// The id of the last row an `INSERT` stored. Tables have no
+    /// auto-increment columns, so this is the row's id in the engine.
     pub last_insert_id: Option<u64>,
     pub columns: Vec<Column>,
     pub rows: Vec<Row>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
-    pub type_info: TypeInfo,
+    /// `None` for a column that is always NULL.
+    pub data_type: Type,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Row {
-    values: Vec<Value>,
+    pub values: Vec<Value>,
+}
+
+#[async_trait]
+impl Executor for Database {
+    async fn execute(&self, stmt: &Statement) -> Result<QueryResult, Error> {
+        match stmt {
+            Statement::Create(_)
+            | Statement::Drop(_)
+            | Statement::CreateIndex(_)
+            | Statement::Alter(_)
+            | Statement::CreateType(_)
+            | Statement::AlterType(_) => {
+                let mut txn = self.catalog().begin();
+                txn.execute(stmt)?;
+                txn.commit()?;
+                Ok(QueryResult::default())
+            }
+            Statement::Checkpoint => {
+                self.checkpoint()?;
+                Ok(QueryResult::default())
+            }
+            _ => {
+                let analyzed = Analyzer::new(&**self.catalog()).analyze(stmt)?;
+                match analyzed.statement {
+                    Statement::Select(select) => self.select(&select, analyzed.columns),
+                    Statement::Insert(_) | Statement::Update(_) | Statement::Delete(_) => {
+                        self.write(&analyzed.statement)
+                    }
+                    _ => unreachable!("schema changes are handled above"),
+                }
+            }
+        }
+    }
+}
+
+impl Database {
+    fn select(&self, select: &SelectStatement, columns: Vec<OutputColumn>) -> Result<QueryResult, Error> {
+        let references = std::iter::once(&select.from).chain(select.joins.iter().map(|j| &j.table));
+        let tables = references.map(|r| self.source(r)).collect::<Result<Vec<_>, _>>()?;
+        let mut plan = plan(select, &tables)?;
+        let mut rows = Vec::new();
+        while let Some(values) = plan.next()? {
+            rows.push(Row { values });
+        }
+        let columns = columns.into_iter().map(|c| Column { name: c.name, data_type: c.data_type }).collect();
+        Ok(QueryResult { columns, rows, ..QueryResult::default() })
+    }
+
+    /// A table or information schema view to read.
+    fn source(&self, reference: &TableReference) -> Result<Arc<dyn Table>, Error> {
+        let name = qualified_name(reference);
+        let table = match information_schema::view_name(&name) {
+            Some(view) => self.catalog().information_schema(view),
+            None => self.engine().table(&name),
+        };
+        table.ok_or_else(|| Error::Execution(format!("table {} does not exist", name)))
+    }
+
+    /// Changes rows, undoing the changes made if the statement fails part
+    /// way through. On disk the statement is one transaction of the log, so
+    /// a crash part way through it leaves none of its changes either.
+    fn write(&self, statement: &Statement) -> Result<QueryResult, Error> {
+        self.atomic(|| {
+            let _writes = self.catalog().lock_writes();
+            let mut made = Vec::new();
+            let written = change_rows(&self.catalog().snapshot(), &**self.engine(), statement, &mut made);
+            if written.is_err() {
+                keys::undo(&**self.engine(), made)?;
+            }
+            written
+        })
+    }
+}
+
+/// Makes the changes an analyzed `INSERT`, `UPDATE` or `DELETE` asks for,
+/// to the tables as `catalog` has them, noting each change made in `made`
+/// so that the caller can undo them. The caller holds
+/// [`SystemCatalog::lock_writes`](crate::catalog::SystemCatalog::lock_writes) throughout.
+pub(crate) fn change_rows(
+    catalog: &MemoryCatalog,
+    engine: &dyn StorageEngine,
+    statement: &Statement,
+    made: &mut Vec<Made>,
+) -> Result<QueryResult, Error> {
+    let mut writer = Writer { catalog, engine, made };
+    match statement {
+        Statement::Insert(insert) => writer.insert(insert),
+        Statement::Update(update) => writer.update(update),
+        Statement::Delete(delete) => writer.delete(delete),
+        _ => Err(Error::Execution("only INSERT, UPDATE and DELETE change rows".to_string())),
+    }
+}
+
+/// Runs a statement that changes rows.
+struct Writer<'a> {
+    catalog: &'a MemoryCatalog,
+    engine: &'a dyn StorageEngine,
+    made: &'a mut Vec<Made>,
+}
+
+impl Writer<'_> {
+    fn insert(&mut self, insert: &InsertStatement) -> Result<QueryResult, Error> {
+        if insert.on_duplicate.is_some() {
+            return Err(Error::Execution("ON DUPLICATE KEY UPDATE is not supported yet".to_string()));
+        }
+        let (schema, table) = self.target(&insert.table)?;
+        let targets: Vec<usize> = match insert.columns.is_empty() {
+            true => (0..schema.columns.len()).collect(),
+            false => insert.columns.iter().filter_map(|name| schema.column_index(name)).collect(),
+        };
+        // Every row is checked before any is stored.
+        let checks = Checks::new(&schema)?;
+        let mut rows = Vec::new();
+        for values in &insert.values {
+            let mut row = vec![Value::Null; schema.columns.len()];
+            for (value, &i) in values.iter().zip(&targets) {
+                row[i] = constant(value)?;
+            }
+            for (i, column) in schema.columns.iter().enumerate() {
+                if let (false, Some(default)) = (targets.contains(&i), &column.default) {
+                    row[i] = cast(&constant(default)?, &column.data_type)?;
+                }
+            }
+            checks.check(&row)?;
+            rows.push(row);
+        }
+        let mut changes = Changes::new(schema, table);
+        changes.rows = rows.into_iter().map(|row| (None, Some(row))).collect();
+        let (affected_rows, last) = self.change(changes)?;
+        Ok(QueryResult { affected_rows, last_insert_id: last.map(|id| id.0), ..QueryResult::default() })
+    }
+
+    fn update(&mut self, update: &UpdateStatement) -> Result<QueryResult, Error> {
+        let (schema, table) = self.target(&update.table)?;
+        let layout = Layout::table(binding(&update.table), &schema);
+        let mut sets = Vec::new();
+        for (name, expr) in &update.sets {
+            if let Some(i) = schema.column_index(name) {
+                sets.push((i, compile(expr, &layout)?));
+            }
+        }
+        let checks = Checks::new(&schema)?;
+        let filter = update.where_clause.as_ref();
+        let mut changes = Changes::new(schema.clone(), table.clone());
+        for (id, row) in matching(&*table, &update.table, &layout, filter, &update.order_by, update.limit.as_ref())? {
+            let mut new = row.clone();
+            for (i, expr) in &sets {
+                new[*i] = expr.eval(&row)?;
+            }
+            checks.check(&new)?;
+            changes.rows.push((Some((id, row)), Some(new)));
+        }
+        let (affected_rows, _) = self.change(changes)?;
+        Ok(QueryResult { affected_rows, ..QueryResult::default() })
+    }
+
+    fn delete(&mut self, delete: &DeleteStatement) -> Result<QueryResult, Error> {
+        let (schema, table) = self.target(&delete.table)?;
+        let layout = Layout::table(binding(&delete.table), &schema);
+        let filter = delete.where_clause.as_ref();
+        let mut changes = Changes::new(schema, table.clone());
+        for (id, row) in matching(&*table, &delete.table, &layout, filter, &delete.order_by, delete.limit.as_ref())? {
+            changes.rows.push((Some((id, row)), None));
+        }
+        let (affected_rows, _) = self.change(changes)?;
+        Ok(QueryResult { affected_rows, ..QueryResult::default() })
+    }
+
+    /// Checks the changes a statement makes against the keys of the tables
+    /// involved, then makes them, along with those the foreign keys
+    /// cascade to.
+    fn change(&mut self, changes: Changes) -> Result<(u64, Option<RowId>), Error> {
+        let cascades = keys::check(self.catalog, self.engine, &changes)?;
+        let changed = changes.apply(self.made)?;
+        for cascade in cascades {
+            cascade.apply(self.made)?;
+        }
+        Ok(changed)
+    }
+
+    /// A table to change, with its schema as the catalog has it.
+    fn target(&self, reference: &TableReference) -> Result<(TableSchema, Arc<dyn Table>), Error> {
+        let name = qualified_name(reference);
+        let missing = || Error::Execution(format!("table {} cannot be changed", name));
+        let schema = self.catalog.table(&name).ok_or_else(missing)?;
+        let table = self.engine.table(&schema.name).ok_or_else(missing)?;
+        Ok((schema, table))
+    }
+}
+
+fn qualified_name(reference: &TableReference) -> String {
+    match &reference.schema {
+        Some(schema) => format!("{}.{}", schema, reference.name),
+        None => reference.name.clone(),
+    }
+}
+
+/// The name a table is known by in a statement: its alias or its name.
+fn binding(reference: &TableReference) -> &str {
+    reference.alias.as_deref().unwrap_or(&reference.name)
+}
+
+/// Reads the table a statement is bound to, through an index if one serves
+/// the filter.
+fn scan<'a>(table: &'a dyn Table, binding: &str, filter: Option<&Expr>) -> Result<BoxedOperator<'a>, Error> {
+    Ok(match access_path(binding, &table.indexes(), filter) {
+        AccessPath::SeqScan => Box::new(SeqScan::new(table)),
+        AccessPath::IndexScan { index, range } => Box::new(IndexScan::new(table, &index.name, &range)?),
+    })
+}
+
+/// Builds the operator tree of a query. `tables` are the tables it reads,
+/// in the order of its FROM clause and joins.
+fn plan<'a>(select: &SelectStatement, tables: &'a [Arc<dyn Table>]) -> Result<BoxedOperator<'a>, Error> {
+    let filter = select.where_clause.as_ref();
+    let mut layout = Layout::table(binding(&select.from), &tables[0].schema());
+    let mut input = scan(&*tables[0], binding(&select.from), filter)?;
+    for (join, table) in select.joins.iter().zip(&tables[1..]) {
+        let name = binding(&join.table);
+        let joined = Layout::table(name, &table.schema());
+        // A WHERE conjunct on the inner side of an outer join rejects the
+        // rows NULL-filled for it, so the joined table can still be read
+        // through an index.
+        let right = scan(&**table, name, filter)?;
+        let condition = match &join.using {
+            // The earlier column comes first in the joined row, so an
+            // unqualified reference finds it.
+            Some(using) => using
+                .iter()
+                .map(|column| equal(column_ref(column, None), column_ref(column, Some(name))))
+                .reduce(|a, b| binary_expr(a, BinaryOp::And, b)),
+            None => join.on.clone(),
+        };
+        let next = layout.join(&joined);
+        input = Box::new(NestedLoopJoin::new((input, &layout), (right, &joined), join.join_type.clone(), condition)?);
+        layout = next;
+    }
+    if let Some(filter) = filter {
+        input = Box::new(Filter::new(input, filter, &layout)?);
+    }
+
+    let mut exprs = Vec::new();
+    for column in &select.columns {
+        match &column.expr {
+            Expr::Wildcard { table } => exprs.extend(layout.expand(table.as_deref())),
+            expr => exprs.push(expr.clone()),
+        }
+    }
+    // ORDER BY may name a column of the result by its alias or its position.
+    let mut order_by = select.order_by.clone();
+    for term in &mut order_by {
+        match &term.expr {
+            Expr::Column(ColumnRef { name, table: None, .. }) => {
+                let alias = |c: &&SelectColumn| c.alias.as_ref().is_some_and(|a| a.eq_ignore_ascii_case(name));
+                if let Some(column) = select.columns.iter().find(alias) {
+                    term.expr = column.expr.clone();
+                }
+            }
+            Expr::Literal(value) if value.as_i128().is_some() => {
+                let position = value.as_i128().unwrap();
+                let index = usize::try_from(position).ok().and_then(|n| n.checked_sub(1));
+                let expr = index.and_then(|i| exprs.get(i)).ok_or_else(|| {
+                    Error::Execution(format!("ORDER BY position {} is not in the select list", position))
+                })?;
+                term.expr = expr.clone();
+            }
+            _ => {}
+        }
+    }
+
+    let grouped = !select.group_by.is_empty()
+        || select.having.is_some()
+        || exprs.iter().chain(order_by.iter().map(|o| &o.expr)).any(has_aggregate);
+    if grouped {
+        // Past the aggregation, group expressions and aggregate calls are
+        // read from the columns it computes.
+        let mut calls = Vec::new();
+        let mut regroup = |expr: &Expr| {
+            rewrite(expr, &mut |e| {
+                if let Some(i) = select.group_by.iter().position(|g| g == e) {
+                    return Some(column_ref(&format!("#group{}", i), None));
+                }
+                let Expr::Function { name, .. } = e else { return None };
+                AggregateFunction::from_name(name)?;
+                let j = calls.iter().position(|c| c == e).unwrap_or_else(|| {
+                    calls.push(e.clone());
+                    calls.len() - 1
+                });
+                Some(column_ref(&format!("#agg{}", j), None))
+            })
+        };
+        exprs = exprs.iter().map(&mut regroup).collect();
+        let having = select.having.as_ref().map(&mut regroup);
+        for term in &mut order_by {
+            term.expr = regroup(&term.expr);
+        }
+
+        let names = (0..select.group_by.len()).map(|i| format!("#group{}", i));
+        let grouped = Layout::computed(names.chain((0..calls.len()).map(|j| format!("#agg{}", j))));
+        let calls = calls.iter().map(aggregate_call).collect();
+        input = Box::new(Aggregate::new(input, &select.group_by, calls, &layout)?);
+        layout = grouped;
+        if let Some(having) = having {
+            input = Box::new(Filter::new(input, &having, &layout)?);
+        }
+    }
+
+    if !order_by.is_empty() {
+        input = Box::new(Sort::new(input, SortOrder::new(&order_by, &layout)?));
+    }
+    input = Box::new(Project::new(input, &exprs, &layout)?);
+    if select.distinct {
+        input = Box::new(Distinct::new(input));
+    }
+    if let Some(limit) = &select.limit {
+        input = Box::new(Limit::new(input, limit.offset.unwrap_or(0), Some(limit.limit)));
+    }
+    Ok(input)
+}
+
+fn aggregate_call(expr: &Expr) -> AggregateCall {
+    let Expr::Function { name, args, distinct, .. } = expr else { unreachable!("not an aggregate call") };
+    AggregateCall {
+        function: AggregateFunction::from_name(name).expect("not an aggregate function"),
+        arg: args.first().filter(|arg| !matches!(arg, Expr::Wildcard { .. })).cloned(),
+        distinct: *distinct,
+    }
+}
+
+fn has_aggregate(expr: &Expr) -> bool {
+    let mut found = false;
+    rewrite(expr, &mut |e| match e {
+        Expr::Function { name, .. } if AggregateFunction::from_name(name).is_some() => {
+            found = true;
+            Some(e.clone())
+        }
+        _ => None,
+    });
+    found
+}
+
+/// Copies an expression, replacing the subexpressions for which `replace`
+/// returns a replacement, outermost first.
+fn rewrite(expr: &Expr, replace: &mut dyn FnMut(&Expr) -> Option<Expr>) -> Expr {
+    if let Some(replaced) = replace(expr) {
+        return replaced;
+    }
+    let mut boxed = |e: &Expr| Box::new(rewrite(e, replace));
+    match expr {
+        Expr::Binary { left, op, right, span } => {
+            Expr::Binary { left: boxed(left), op: op.clone(), right: boxed(right), span: *span }
+        }
+        Expr::Unary { op, expr, span } => Expr::Unary { op: op.clone(), expr: boxed(expr), span: *span },
+        Expr::Cast { expr, data_type, try_cast } => {
+            Expr::Cast { expr: boxed(expr), data_type: data_type.clone(), try_cast: *try_cast }
+        }
+        Expr::Field { expr, step, span } => Expr::Field { expr: boxed(expr), step: step.clone(), span: *span },
+        Expr::Function { name, args, distinct, span } => Expr::Function {
+            name: name.clone(),
+            args: args.iter().map(|a| *boxed(a)).collect(),
+            distinct: *distinct,
+            span: *span,
+        },
+        Expr::Case { operand, when_clauses, else_result } => Expr::Case {
+            operand: operand.as_deref().map(&mut boxed),
+            when_clauses: when_clauses.iter().map(|(when, then)| (*boxed(when), *boxed(then))).collect(),
+            else_result: else_result.as_deref().map(&mut boxed),
+        },
+        Expr::List(items) => Expr::List(items.iter().map(|i| *boxed(i)).collect()),
+        Expr::Column(_) | Expr::Literal(_) | Expr::Wildcard { .. } | Expr::Exists(_) | Expr::Subquery(_) => {
+            expr.clone()
+        }
+    }
+}
+
+fn column_ref(name: &str, table: Option<&str>) -> Expr {
+    Expr::Column(ColumnRef {
+        name: name.to_string(),
+        table: table.map(str::to_string),
+        schema: None,
+        span: Span::default(),
+    })
+}
+
+fn binary_expr(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+    Expr::Binary { left: Box::new(left), op, right: Box::new(right), span: Span::default() }
+}
+
+fn equal(left: Expr, right: Expr) -> Expr {
+    binary_expr(left, BinaryOp::Eq, right)
+}
+
+/// The rows an `UPDATE` or `DELETE` changes, with their ids, read in full
+/// before any is changed.
+fn matching(
+    table: &dyn Table,
+    reference: &TableReference,
+    layout: &Layout,
+    filter: Option<&Expr>,
+    order_by: &[OrderByExpr],
+    limit: Option<&LimitClause>,
+) -> Result<Vec<(RowId, Vec<Value>)>, Error> {
+    let rows: Vec<(RowId, Vec<Value>)> = match access_path(binding(reference), &table.indexes(), filter) {
+        AccessPath::SeqScan => table.scan().collect::<Result<_, _>>()?,
+        AccessPath::IndexScan { index, range } => {
+            let mut rows = Vec::new();
+            for id in table.index_scan(&index.name, &range)? {
+                if let Some(row) = table.get(id)? {
+                    rows.push((id, row));
+                }
+            }
+            rows
+        }
+    };
+    let filter = filter.map(|filter| compile(filter, layout)).transpose()?;
+    let order = SortOrder::new(order_by, layout)?;
+    let mut keyed = Vec::new();
+    for (id, row) in rows {
+        if let Some(filter) = &filter {
+            if !filter.holds(&row)? {
+                continue;
+            }
+        }
+        keyed.push((order.key(&row)?, (id, row)));
+    }
+    keyed.sort_by(|(a, _), (b, _)| order.compare(a, b));
+    let rows = keyed.into_iter().map(|(_, row)| row);
+    Ok(match limit {
+        Some(limit) => rows.skip(limit.offset.unwrap_or(0) as usize).take(limit.limit as usize).collect(),
+        None => rows.collect(),
+    })
+}
+
+/// The NOT NULL and CHECK constraints of a table, which the rows stored in
+/// it are checked against. A CHECK that is NULL passes.
+struct Checks<'a> {
+    schema: &'a TableSchema,
+    checks: Vec<(&'a str, CompiledExpr)>,
+}
+
+impl<'a> Checks<'a> {
+    fn new(schema: &'a TableSchema) -> Result<Self, Error> {
+        let mut checks = Vec::new();
+        for constraint in &schema.constraints {
+            if let TableConstraint::Check { name, expr } = constraint {
+                checks.push((name.as_deref().unwrap_or_default(), compile(expr, schema)?));
+            }
+        }
+        Ok(Checks { schema, checks })
+    }
+
+    fn check(&self, row: &[Value]) -> Result<(), Error> {
+        let table = &self.schema.name;
+        for (column, value) in self.schema.columns.iter().zip(row) {
+            if !column.nullable && value.is_null() {
+                return Err(Error::Execution(format!("column {} of table {} cannot be NULL", column.name, table)));
+            }
+        }
+        for (name, check) in &self.checks {
+            if truth(check.eval(row)?)? == Some(false) {
+                return Err(Error::Execution(format!("check constraint {} of table {} is violated", name, table)));
+            }
+        }
+        Ok(())
+    }
 }
 
-// Implement MySQL protocol
-pub mod mysql {
-    use super::*;
-    use tokio::net::TcpStream;
-    
-    pub struct MySQLConnection {
-        stream: TcpStream,
-        // Add necessary fields
-    }
-    
-    impl MySQLConnection {
-        pub async fn connect(
-            host: &str,
-            port: u16,
-            user: &str,
-            password: &str,
-            database: &str,
-        ) -> Result<Self, Error> {
-            // Implement MySQL connection handshake
-            unimplemented!()
-        }
-    }
-    
-    #[async_trait]
-    impl Connection for MySQLConnection {
-        async fn execute(&self, query: &str) -> Result<QueryResult, Error> {
-            // Implement MySQL query execution
-            unimplemented!()
-        }
-        
-        async fn prepare(&self, query: &str) -> Result<PreparedStatement, Error> {
-            // Implement prepared statement
-            unimplemented!()
-        }
-        
-        async fn transaction(&self) -> Result<Transaction, Error> {
-            // Implement transaction handling
-            unimplemented!()
-        }
-    }
-}
-
-// Example usage
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+    use crate::database::DatabaseOptions;
+    use crate::parser::parse_sql;
+
+    async fn run(db: &Database, sql: &str) -> QueryResult {
+        db.execute(&parse_sql(sql).unwrap()).await.unwrap()
+    }
+
+    async fn query(db: &Database, sql: &str) -> Vec<Vec<Value>> {
+        run(db, sql).await.rows.into_iter().map(|row| row.values).collect()
+    }
+
+    async fn setup() -> Database {
+        let db = Database::open(DatabaseOptions::memory()).unwrap();
+        run(&db, "CREATE TABLE users (id INT PRIMARY KEY, name TEXT NOT NULL, age INT CHECK (age >= 0))").await;
+        run(&db, "CREATE TABLE orders (id INT, user_id INT, total INT DEFAULT 0)").await;
+        run(&db, "CREATE INDEX users_age ON users (age)").await;
+        run(&db, "INSERT INTO users VALUES (1, 'ann', 30), (2, 'bob', 25), (3, 'cy', NULL)").await;
+        run(&db, "INSERT INTO orders VALUES (1, 1, 10), (2, 1, 20), (3, 2, 5)").await;
+        db
+    }
+
+    fn ints(rows: &[&[i64]]) -> Vec<Vec<Value>> {
+        rows.iter().map(|row| row.iter().map(|&v| Value::Int(v)).collect()).collect()
+    }
+
     #[tokio::test]
-    async fn test_basic_query() {
-        let conn = mysql::MySQLConnection::connect(
-            "localhost",
-            3306,
-            "user",
-            "password",
-            "test_db",
-        ).await.unwrap();
-        
-        let result = conn.execute("SELECT * FROM users WHERE id = 1").await.unwrap();
-        assert_eq!(result.affected_rows, 1);
-    }
-    
-    #[test]
-    fn test_parser() {
-        let sql = "SELECT id, name FROM users WHERE age > 18 ORDER BY name LIMIT 10";
-        let stmt = parse_sql(sql).unwrap();
-        match stmt {
-            Statement::Select(select) => {
-                assert_eq!(select.columns, vec!["id", "name"]);
-                assert_eq!(select.from, "users");
-            }
-            _ => panic!("Expected SELECT statement"),
+    async fn test_select() {
+        let db = setup().await;
+        let result = run(&db, "SELECT id, name AS who FROM users WHERE age > 20 ORDER BY who DESC").await;
+        let names: Vec<&str> = result.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "who"]);
+        let rows: Vec<&Value> = result.rows.iter().map(|row| &row.values[0]).collect();
+        assert_eq!(rows, [&Value::Int32(2), &Value::Int32(1)]);
+
+        let rows = query(&db, "SELECT * FROM users WHERE age = 25").await;
+        assert_eq!(rows, vec![vec![Value::Int32(2), Value::String("bob".to_string()), Value::Int32(25)]]);
+        assert_eq!(query(&db, "SELECT id FROM users ORDER BY id LIMIT 1 OFFSET 1").await, vec![vec![Value::Int32(2)]]);
+        let rows = query(&db, "SELECT DISTINCT user_id FROM orders ORDER BY user_id").await;
+        assert_eq!(rows, vec![vec![Value::Int32(1)], vec![Value::Int32(2)]]);
+
+        let rows = query(&db, "SELECT name, id FROM users ORDER BY 2 DESC").await;
+        let ids: Vec<&Value> = rows.iter().map(|row| &row[1]).collect();
+        assert_eq!(ids, [&Value::Int32(3), &Value::Int32(2), &Value::Int32(1)]);
+        let rows = query(&db, "SELECT * FROM users WHERE age IS NOT NULL ORDER BY 3").await;
+        assert_eq!(rows[0][0], Value::Int32(2));
+        for sql in ["SELECT id FROM users ORDER BY 0", "SELECT id FROM users ORDER BY 2"] {
+            let stmt = parse_sql(sql).unwrap();
+            assert!(matches!(db.execute(&stmt).await, Err(Error::Execution(_))), "{}", sql);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_join_and_aggregate() {
+        let db = setup().await;
+        let sql = "SELECT u.id, COUNT(o.id), SUM(o.total) FROM users u LEFT JOIN orders o ON o.user_id = u.id \
+                   GROUP BY u.id ORDER BY u.id";
+        let rows = query(&db, sql).await;
+        let id = |i| Value::Int32(i);
+        assert_eq!(rows[0], vec![id(1), Value::Int(2), Value::Int(30)]);
+        assert_eq!(rows[1], vec![id(2), Value::Int(1), Value::Int(5)]);
+        assert_eq!(rows[2], vec![id(3), Value::Int(0), Value::Null]);
+
+        let sql = "SELECT user_id, SUM(total) AS spent FROM orders GROUP BY user_id HAVING SUM(total) > 5";
+        assert_eq!(query(&db, sql).await, vec![vec![id(1), Value::Int(30)]]);
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM orders WHERE total > 100").await, ints(&[&[0]]));
+    }
+
+    #[tokio::test]
+    async fn test_dml() {
+        let db = setup().await;
+        let result = run(&db, "INSERT INTO orders (id, user_id) VALUES (4, 3), (5, 3)").await;
+        assert_eq!(result.affected_rows, 2);
+        assert!(result.last_insert_id.is_some());
+        assert_eq!(query(&db, "SELECT SUM(total) FROM orders WHERE user_id = 3").await, ints(&[&[0]]));
+
+        assert_eq!(run(&db, "UPDATE orders SET total = total + 1 WHERE user_id = 1").await.affected_rows, 2);
+        assert_eq!(query(&db, "SELECT SUM(total) FROM orders").await, ints(&[&[37]]));
+        assert_eq!(run(&db, "DELETE FROM orders WHERE total < 10").await.affected_rows, 3);
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM orders").await, ints(&[&[2]]));
+
+        let stmt = parse_sql("INSERT INTO users VALUES (4, 'dee', -1)").unwrap();
+        assert!(matches!(db.execute(&stmt).await, Err(Error::Execution(_))));
+        let stmt = parse_sql("UPDATE users SET age = age - 40").unwrap();
+        assert!(matches!(db.execute(&stmt).await, Err(Error::Execution(_))));
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM users WHERE age < 0").await, ints(&[&[0]]));
+    }
+
+    async fn fails(db: &Database, sql: &str) -> String {
+        match db.execute(&parse_sql(sql).unwrap()).await {
+            Err(Error::Execution(message)) => message,
+            other => panic!("{} did not fail: {:?}", sql, other),
         }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_unique_keys() {
+        let db = setup().await;
+        let message = fails(&db, "INSERT INTO users VALUES (1, 'dee', 40)").await;
+        assert!(message.contains("users_pkey"), "{}", message);
+        fails(&db, "INSERT INTO users VALUES (4, 'dee', 40), (4, 'eve', 41)").await;
+        fails(&db, "UPDATE users SET id = 2 WHERE id = 1").await;
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM users").await, ints(&[&[3]]));
+
+        // Keys may be swapped, and shifted onto each other, in one statement.
+        assert_eq!(run(&db, "UPDATE users SET id = id + 1").await.affected_rows, 3);
+        assert_eq!(run(&db, "UPDATE users SET id = 5 - id").await.affected_rows, 3);
+        let rows = query(&db, "SELECT id, name FROM users ORDER BY id").await;
+        let names: Vec<&Value> = rows.iter().map(|row| &row[1]).collect();
+        assert_eq!(names, ["cy", "bob", "ann"].map(|n| Value::String(n.to_string())).iter().collect::<Vec<_>>());
+
+        run(&db, "CREATE TABLE tags (id INT, label TEXT UNIQUE)").await;
+        run(&db, "CREATE UNIQUE INDEX tags_id ON tags (id)").await;
+        run(&db, "INSERT INTO tags VALUES (1, 'a'), (2, NULL), (3, NULL)").await;
+        let message = fails(&db, "INSERT INTO tags VALUES (4, 'a')").await;
+        assert!(message.contains("tags_label_key"), "{}", message);
+        run(&db, "DELETE FROM tags WHERE label = 'a'").await;
+        run(&db, "INSERT INTO tags VALUES (4, 'a')").await;
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM tags").await, ints(&[&[3]]));
+    }
+
+    #[tokio::test]
+    async fn test_foreign_keys() {
+        let db = setup().await;
+        run(&db, "CREATE TABLE notes (id INT, user_id INT REFERENCES users (id))").await;
+        run(&db, "INSERT INTO notes VALUES (1, 1), (2, 2), (3, NULL)").await;
+        let message = fails(&db, "INSERT INTO notes VALUES (4, 9)").await;
+        assert!(message.contains("notes_user_id_fkey"), "{}", message);
+        fails(&db, "UPDATE notes SET user_id = 9 WHERE id = 1").await;
+        fails(&db, "DELETE FROM users WHERE id = 1").await;
+        fails(&db, "UPDATE users SET id = 10 WHERE id = 2").await;
+        assert_eq!(run(&db, "DELETE FROM users WHERE id = 3").await.affected_rows, 1);
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM users").await, ints(&[&[2]]));
+
+        run(&db, "CREATE TABLE posts (id INT, user_id INT REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE)")
+            .await;
+        run(&db, "CREATE TABLE likes (id INT, user_id INT REFERENCES users (id) ON DELETE SET NULL)").await;
+        run(&db, "DELETE FROM notes").await;
+        run(&db, "INSERT INTO posts VALUES (1, 1), (2, 2), (3, 2)").await;
+        run(&db, "INSERT INTO likes VALUES (1, 1), (2, 1)").await;
+        run(&db, "UPDATE users SET id = 20 WHERE id = 2").await;
+        let rows = query(&db, "SELECT id FROM posts WHERE user_id = 20 ORDER BY id").await;
+        assert_eq!(rows, [[2], [3]].map(|r| r.map(Value::Int32).to_vec()));
+        fails(&db, "UPDATE users SET id = 10 WHERE id = 1").await;
+        run(&db, "DELETE FROM users WHERE id = 1").await;
+        let rows = query(&db, "SELECT id FROM posts ORDER BY id").await;
+        assert_eq!(rows, [[2], [3]].map(|r| r.map(Value::Int32).to_vec()));
+        assert_eq!(query(&db, "SELECT user_id FROM likes").await, vec![vec![Value::Null]; 2]);
+    }
+
+    #[tokio::test]
+    async fn test_keys_added_by_alter_table() {
+        let db = setup().await;
+        run(&db, "ALTER TABLE orders ADD CONSTRAINT orders_pk PRIMARY KEY (id)").await;
+        let message = fails(&db, "INSERT INTO orders VALUES (1, 2, 0)").await;
+        assert!(message.contains("orders_pkey"), "{}", message);
+
+        run(&db, "CREATE TABLE c (id INT, n INT)").await;
+        run(&db, "INSERT INTO c VALUES (1, 1)").await;
+        run(&db, "ALTER TABLE c ADD CONSTRAINT cu UNIQUE (id)").await;
+        let message = fails(&db, "INSERT INTO c VALUES (1, 2)").await;
+        assert!(message.contains("cu"), "{}", message);
+        run(&db, "INSERT INTO c VALUES (2, 2)").await;
+        fails(&db, "UPDATE c SET id = 1 WHERE n = 2").await;
+
+        run(&db, "ALTER TABLE orders ADD CONSTRAINT orders_user FOREIGN KEY (user_id) REFERENCES users (id)").await;
+        let message = fails(&db, "INSERT INTO orders VALUES (4, 9, 0)").await;
+        assert!(message.contains("orders_user"), "{}", message);
+        fails(&db, "DELETE FROM users WHERE id = 2").await;
+        run(&db, "INSERT INTO orders VALUES (4, 3, 0)").await;
+        assert_eq!(query(&db, "SELECT COUNT(*) FROM orders").await, ints(&[&[4]]));
+    }
+
+    #[tokio::test]
+    async fn test_create_type() {
+        let db = setup().await;
+        run(&db, "CREATE TYPE point AS STRUCT (x: INT, y: INT)").await;
+        run(&db, "CREATE TYPE shape AS ENUM (empty, circle (point))").await;
+        run(&db, "CREATE TYPE IF NOT EXISTS point AS STRUCT (z: INT)").await;
+        run(&db, "CREATE TABLE shapes (id INT PRIMARY KEY, centre point, kind shape)").await;
+        let point = db.catalog().custom_type("point").unwrap();
+        assert_eq!(point.definition(), "STRUCT(x INTEGER, y INTEGER)");
+        assert_eq!(db.catalog().custom_type("shape").unwrap().definition(), "ENUM(empty, circle(point))");
+
+        let stmt = parse_sql("CREATE TYPE point AS STRUCT (z: INT)").unwrap();
+        assert!(db.execute(&stmt).await.unwrap_err().to_string().contains("already exists"));
+        let stmt = parse_sql("CREATE TYPE line AS STRUCT (start: point, finish: nowhere)").unwrap();
+        assert!(db.execute(&stmt).await.unwrap_err().to_string().contains("type nowhere does not exist"));
+        let stmt = parse_sql("CREATE TYPE tree AS ENUM (leaf, node (tree))").unwrap();
+        assert!(db.execute(&stmt).await.unwrap_err().to_string().contains("cannot contain itself"));
+        let stmt = parse_sql("CREATE TYPE pair AS STRUCT (a: INT, A: INT)").unwrap();
+        assert!(db.execute(&stmt).await.unwrap_err().to_string().contains("more than once"));
+    }
+}
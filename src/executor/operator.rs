@@ -0,0 +1,612 @@
+// src/executor/operator.rs
+//! The operators query plans are built from.
+//!
+//! Operators are pulled from, Volcano style: [`Operator::next`] on the root
+//! of a plan pulls as many rows from the operators below as it needs for one
+//! row of its own. Scans, filters, projections and limits pass rows along
+//! one at a time; sorting, aggregation and the inner side of a join hold
+//! their input in memory. The rows an operator produces are described by a
+//! [`Layout`], against which the operators above it compile their
+//! expressions when they are built.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use bigdecimal::BigDecimal;
+
+use crate::catalog::TableSchema;
+use crate::engine::{RowId, ScanItem, Table};
+use crate::error::Error;
+use crate::eval::{binary, compare, compile, CompiledExpr, Resolve};
+use crate::index::KeyRange;
+use crate::parser::ast::{BinaryOp, ColumnRef, Expr, JoinType, OrderByExpr, Span};
+use crate::types::Value;
+
+/// A node of a query plan, producing rows on demand.
+pub trait Operator {
+    /// The next row, `None` once there are no more.
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error>;
+}
+
+pub type BoxedOperator<'a> = Box<dyn Operator + 'a>;
+
+/// The columns of the rows an operator produces, each with the name its
+/// table is bound to in the query. Columns computed by an operator belong
+/// to no table.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    columns: Vec<(Option<String>, String)>,
+}
+
+impl Layout {
+    /// The columns of a table bound to `binding`, its alias or name.
+    pub fn table(binding: &str, schema: &TableSchema) -> Self {
+        let columns = schema.columns.iter().map(|c| (Some(binding.to_string()), c.name.clone())).collect();
+        Layout { columns }
+    }
+
+    pub fn computed(names: impl IntoIterator<Item = String>) -> Self {
+        Layout { columns: names.into_iter().map(|name| (None, name)).collect() }
+    }
+
+    /// The columns of `self` followed by those of `other`, as a join
+    /// produces them.
+    pub fn join(&self, other: &Layout) -> Layout {
+        Layout { columns: self.columns.iter().chain(&other.columns).cloned().collect() }
+    }
+
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// What `*`, or `table.*`, stands for: references to every column, or
+    /// to those of the table bound to `table`, in order.
+    pub fn expand(&self, table: Option<&str>) -> Vec<Expr> {
+        self.columns
+            .iter()
+            .filter(|(binding, _)| table.is_none_or(|t| binding.as_ref().is_some_and(|b| b.eq_ignore_ascii_case(t))))
+            .map(|(binding, name)| {
+                let span = Span::default();
+                Expr::Column(ColumnRef { name: name.clone(), table: binding.clone(), schema: None, span })
+            })
+            .collect()
+    }
+}
+
+/// An unqualified reference finds the first column of that name.
+impl Resolve for Layout {
+    fn position(&self, column: &ColumnRef) -> Option<usize> {
+        self.columns.iter().position(|(binding, name)| {
+            name.eq_ignore_ascii_case(&column.name)
+                && column.table.as_ref().is_none_or(|t| binding.as_ref().is_some_and(|b| b.eq_ignore_ascii_case(t)))
+        })
+    }
+}
+
+/// Reads every row of a table.
+pub struct SeqScan<'a> {
+    rows: Box<dyn Iterator<Item = ScanItem> + 'a>,
+}
+
+impl<'a> SeqScan<'a> {
+    pub fn new(table: &'a dyn Table) -> Self {
+        SeqScan { rows: table.scan() }
+    }
+}
+
+impl Operator for SeqScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        self.rows.next().transpose().map(|row| row.map(|(_, row)| row))
+    }
+}
+
+/// Reads the rows of a table whose keys in an index are in a range, in
+/// the index's order.
+pub struct IndexScan<'a> {
+    table: &'a dyn Table,
+    ids: std::vec::IntoIter<RowId>,
+}
+
+impl<'a> IndexScan<'a> {
+    pub fn new(table: &'a dyn Table, index: &str, range: &KeyRange) -> Result<Self, Error> {
+        Ok(IndexScan { table, ids: table.index_scan(index, range)?.into_iter() })
+    }
+}
+
+impl Operator for IndexScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        for id in self.ids.by_ref() {
+            // A row deleted since the index was read is skipped.
+            if let Some(row) = self.table.get(id)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Passes on the rows for which a predicate is TRUE.
+pub struct Filter<'a> {
+    input: BoxedOperator<'a>,
+    predicate: CompiledExpr,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new(input: BoxedOperator<'a>, predicate: &Expr, layout: &Layout) -> Result<Self, Error> {
+        Ok(Filter { input, predicate: compile(predicate, layout)? })
+    }
+}
+
+impl Operator for Filter<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        while let Some(row) = self.input.next()? {
+            if self.predicate.holds(&row)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Computes a row of expressions from each row.
+pub struct Project<'a> {
+    input: BoxedOperator<'a>,
+    exprs: Vec<CompiledExpr>,
+}
+
+impl<'a> Project<'a> {
+    pub fn new(input: BoxedOperator<'a>, exprs: &[Expr], layout: &Layout) -> Result<Self, Error> {
+        Ok(Project { input, exprs: compile_all(exprs, layout)? })
+    }
+}
+
+impl Operator for Project<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        let Some(row) = self.input.next()? else { return Ok(None) };
+        self.exprs.iter().map(|expr| expr.eval(&row)).collect::<Result<_, _>>().map(Some)
+    }
+}
+
+/// Skips `offset` rows, then passes on at most `limit`.
+pub struct Limit<'a> {
+    input: BoxedOperator<'a>,
+    offset: u64,
+    limit: Option<u64>,
+}
+
+impl<'a> Limit<'a> {
+    pub fn new(input: BoxedOperator<'a>, offset: u64, limit: Option<u64>) -> Self {
+        Limit { input, offset, limit }
+    }
+}
+
+impl Operator for Limit<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        while self.offset > 0 {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.offset -= 1;
+        }
+        match &mut self.limit {
+            Some(0) => return Ok(None),
+            Some(limit) => *limit -= 1,
+            None => {}
+        }
+        self.input.next()
+    }
+}
+
+/// Passes on each distinct row once, the first time it comes.
+pub struct Distinct<'a> {
+    input: BoxedOperator<'a>,
+    seen: BTreeSet<Vec<Value>>,
+}
+
+impl<'a> Distinct<'a> {
+    pub fn new(input: BoxedOperator<'a>) -> Self {
+        Distinct { input, seen: BTreeSet::new() }
+    }
+}
+
+impl Operator for Distinct<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        while let Some(row) = self.input.next()? {
+            if self.seen.insert(row.clone()) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Sorts its whole input. Rows that sort equal keep the order they came
+/// in.
+pub struct Sort<'a> {
+    input: Option<BoxedOperator<'a>>,
+    order: SortOrder,
+    sorted: VecDeque<Vec<Value>>,
+}
+
+impl<'a> Sort<'a> {
+    pub fn new(input: BoxedOperator<'a>, order: SortOrder) -> Self {
+        Sort { input: Some(input), order, sorted: VecDeque::new() }
+    }
+}
+
+impl Operator for Sort<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        if let Some(mut input) = self.input.take() {
+            let mut keyed = Vec::new();
+            while let Some(row) = input.next()? {
+                keyed.push((self.order.key(&row)?, row));
+            }
+            keyed.sort_by(|(a, _), (b, _)| self.order.compare(a, b));
+            self.sorted = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+        Ok(self.sorted.pop_front())
+    }
+}
+
+/// ORDER BY terms, compiled: each expression, whether it is ascending and
+/// whether NULLs come first.
+#[derive(Debug)]
+pub struct SortOrder {
+    terms: Vec<(CompiledExpr, bool, bool)>,
+}
+
+impl SortOrder {
+    pub fn new(order: &[OrderByExpr], layout: &Layout) -> Result<Self, Error> {
+        let term = |t: &OrderByExpr| Ok((compile(&t.expr, layout)?, t.asc, t.nulls_first));
+        Ok(SortOrder { terms: order.iter().map(term).collect::<Result<_, Error>>()? })
+    }
+
+    /// The values a row is sorted by.
+    pub fn key(&self, row: &[Value]) -> Result<Vec<Value>, Error> {
+        self.terms.iter().map(|(expr, _, _)| expr.eval(row)).collect()
+    }
+
+    /// Orders two sort keys.
+    pub fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for ((&(_, asc, nulls_first), a), b) in self.terms.iter().zip(a).zip(b) {
+            let nulls = if nulls_first { Ordering::Less } else { Ordering::Greater };
+            let ordering = match (a.is_null(), b.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => nulls,
+                (false, true) => nulls.reverse(),
+                (false, false) if asc => a.cmp(b),
+                (false, false) => b.cmp(a),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+fn compile_all(exprs: &[Expr], layout: &Layout) -> Result<Vec<CompiledExpr>, Error> {
+    exprs.iter().map(|expr| compile(expr, layout)).collect()
+}
+
+/// A call of an aggregate function. `COUNT(*)` has no argument.
+#[derive(Debug, Clone)]
+pub struct AggregateCall {
+    pub function: AggregateFunction,
+    pub arg: Option<Expr>,
+    pub distinct: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_uppercase().as_str() {
+            "COUNT" => AggregateFunction::Count,
+            "SUM" => AggregateFunction::Sum,
+            "AVG" => AggregateFunction::Avg,
+            "MIN" => AggregateFunction::Min,
+            "MAX" => AggregateFunction::Max,
+            _ => return None,
+        })
+    }
+}
+
+/// Groups its whole input by the GROUP BY expressions and produces a row
+/// per group: the group's values, then the result of each aggregate call.
+/// Without GROUP BY the input is one group, even when it is empty.
+pub struct Aggregate<'a> {
+    input: Option<BoxedOperator<'a>>,
+    groups: Vec<CompiledExpr>,
+    calls: Vec<AggregateCall>,
+    args: Vec<Option<CompiledExpr>>,
+    output: VecDeque<Vec<Value>>,
+}
+
+impl<'a> Aggregate<'a> {
+    pub fn new(
+        input: BoxedOperator<'a>,
+        groups: &[Expr],
+        calls: Vec<AggregateCall>,
+        layout: &Layout,
+    ) -> Result<Self, Error> {
+        let args = calls.iter().map(|call| call.arg.as_ref().map(|arg| compile(arg, layout)).transpose());
+        let args = args.collect::<Result<_, _>>()?;
+        let groups = compile_all(groups, layout)?;
+        Ok(Aggregate { input: Some(input), groups, calls, args, output: VecDeque::new() })
+    }
+}
+
+impl Operator for Aggregate<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        if let Some(mut input) = self.input.take() {
+            let start = || self.calls.iter().map(Accumulator::new).collect::<Vec<_>>();
+            let mut groups: BTreeMap<Vec<Value>, Vec<Accumulator>> = BTreeMap::new();
+            while let Some(row) = input.next()? {
+                let key = self.groups.iter().map(|group| group.eval(&row)).collect::<Result<_, _>>()?;
+                let accumulators = groups.entry(key).or_insert_with(start);
+                for (accumulator, arg) in accumulators.iter_mut().zip(&self.args) {
+                    let value = arg.as_ref().map(|arg| arg.eval(&row)).transpose()?;
+                    accumulator.add(value)?;
+                }
+            }
+            if groups.is_empty() && self.groups.is_empty() {
+                groups.insert(Vec::new(), start());
+            }
+            for (mut row, accumulators) in groups {
+                for accumulator in accumulators {
+                    row.push(accumulator.finish()?);
+                }
+                self.output.push_back(row);
+            }
+        }
+        Ok(self.output.pop_front())
+    }
+}
+
+/// The running state of one aggregate call over one group.
+struct Accumulator {
+    function: AggregateFunction,
+    count: i64,
+    value: Value,
+    /// The values seen so far, for a DISTINCT call.
+    seen: Option<BTreeSet<Value>>,
+}
+
+impl Accumulator {
+    fn new(call: &AggregateCall) -> Self {
+        let seen = call.distinct.then(BTreeSet::new);
+        Accumulator { function: call.function, count: 0, value: Value::Null, seen }
+    }
+
+    /// Adds a value, `None` for a row counted by `COUNT(*)`. NULLs are
+    /// ignored.
+    fn add(&mut self, value: Option<Value>) -> Result<(), Error> {
+        let Some(value) = value else {
+            self.count += 1;
+            return Ok(());
+        };
+        if value.is_null() || self.seen.as_mut().is_some_and(|seen| !seen.insert(value.clone())) {
+            return Ok(());
+        }
+        self.count += 1;
+        let current = std::mem::replace(&mut self.value, Value::Null);
+        self.value = match self.function {
+            AggregateFunction::Count => Value::Null,
+            // Starting from 0 makes a sum of integers of any width a BIGINT.
+            AggregateFunction::Sum | AggregateFunction::Avg if current.is_null() => {
+                binary(&BinaryOp::Add, Value::Int(0), value)?
+            }
+            AggregateFunction::Sum | AggregateFunction::Avg => binary(&BinaryOp::Add, current, value)?,
+            _ if current.is_null() => value,
+            AggregateFunction::Min if compare(&value, &current)? == Some(Ordering::Less) => value,
+            AggregateFunction::Max if compare(&value, &current)? == Some(Ordering::Greater) => value,
+            AggregateFunction::Min | AggregateFunction::Max => current,
+        };
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        match self.function {
+            AggregateFunction::Count => Ok(Value::Int(self.count)),
+            AggregateFunction::Avg if self.count == 0 => Ok(Value::Null),
+            // The average of integers is a decimal.
+            AggregateFunction::Avg => {
+                let total = match self.value {
+                    Value::Int(i) => Value::Decimal(BigDecimal::from(i)),
+                    other => other,
+                };
+                binary(&BinaryOp::Divide, total, Value::Int(self.count))
+            }
+            AggregateFunction::Sum | AggregateFunction::Min | AggregateFunction::Max => Ok(self.value),
+        }
+    }
+}
+
+/// Joins each row of its left input with the rows of its right input,
+/// which it holds in memory, for which the condition is TRUE. Outer joins
+/// fill in NULLs for the rows of the outer side that match nothing.
+pub struct NestedLoopJoin<'a> {
+    left: BoxedOperator<'a>,
+    right: Vec<Vec<Value>>,
+    join_type: JoinType,
+    condition: Option<CompiledExpr>,
+    left_width: usize,
+    right_width: usize,
+    /// Which right rows have matched a left row, for RIGHT and FULL joins.
+    matched: Vec<bool>,
+    pending: VecDeque<Vec<Value>>,
+    done: bool,
+}
+
+impl<'a> NestedLoopJoin<'a> {
+    pub fn new(
+        left: (BoxedOperator<'a>, &Layout),
+        right: (BoxedOperator<'a>, &Layout),
+        join_type: JoinType,
+        condition: Option<Expr>,
+    ) -> Result<Self, Error> {
+        let (left, left_layout) = left;
+        let (mut input, right_layout) = right;
+        let mut right = Vec::new();
+        while let Some(row) = input.next()? {
+            right.push(row);
+        }
+        let condition = condition.map(|c| compile(&c, &left_layout.join(right_layout))).transpose()?;
+        Ok(NestedLoopJoin {
+            left,
+            matched: vec![false; right.len()],
+            right,
+            join_type,
+            condition,
+            left_width: left_layout.width(),
+            right_width: right_layout.width(),
+            pending: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn join_row(&mut self, left: Vec<Value>) -> Result<(), Error> {
+        let mut matched = false;
+        for (i, right) in self.right.iter().enumerate() {
+            let row: Vec<Value> = left.iter().chain(right).cloned().collect();
+            let accepted = match &self.condition {
+                Some(condition) => condition.holds(&row)?,
+                None => true,
+            };
+            if accepted {
+                matched = true;
+                self.matched[i] = true;
+                self.pending.push_back(row);
+            }
+        }
+        if !matched && matches!(self.join_type, JoinType::Left | JoinType::Full) {
+            let nulls = std::iter::repeat_n(Value::Null, self.right_width);
+            self.pending.push_back(left.into_iter().chain(nulls).collect());
+        }
+        Ok(())
+    }
+}
+
+impl Operator for NestedLoopJoin<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            match self.left.next()? {
+                Some(left) => self.join_row(left)?,
+                None => {
+                    self.done = true;
+                    if matches!(self.join_type, JoinType::Right | JoinType::Full) {
+                        for (right, _) in self.right.iter().zip(&self.matched).filter(|(_, matched)| !**matched) {
+                            let nulls = std::iter::repeat_n(Value::Null, self.left_width);
+                            self.pending.push_back(nulls.chain(right.iter().cloned()).collect());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    /// Yields the rows it was made with.
+    struct Rows(VecDeque<Vec<Value>>);
+
+    impl Operator for Rows {
+        fn next(&mut self) -> Result<Option<Vec<Value>>, Error> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    fn rows(rows: &[&[i64]]) -> BoxedOperator<'static> {
+        let values = |row: &&[i64]| row.iter().map(|&v| if v < 0 { Value::Null } else { Value::Int(v) }).collect();
+        Box::new(Rows(rows.iter().map(values).collect()))
+    }
+
+    fn drain(mut operator: impl Operator) -> Vec<Vec<Value>> {
+        let mut rows = Vec::new();
+        while let Some(row) = operator.next().unwrap() {
+            rows.push(row);
+        }
+        rows
+    }
+
+    fn layout(names: &[&str]) -> Layout {
+        Layout::computed(names.iter().map(|n| n.to_string()))
+    }
+
+    #[test]
+    fn test_sort_limit_and_distinct() {
+        let order = vec![OrderByExpr { expr: parse_expr("a").unwrap(), asc: false, nulls_first: true }];
+        let order = SortOrder::new(&order, &layout(&["a"])).unwrap();
+        let sorted = Sort::new(rows(&[&[2], &[-1], &[3], &[2], &[1]]), order);
+        let limited = Limit::new(Box::new(Distinct::new(Box::new(sorted))), 1, Some(2));
+        assert_eq!(drain(limited), vec![vec![Value::Int(3)], vec![Value::Int(2)]]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let call = |function, arg: Option<&str>, distinct| AggregateCall {
+            function,
+            arg: arg.map(|a| parse_expr(a).unwrap()),
+            distinct,
+        };
+        let calls = vec![
+            call(AggregateFunction::Count, None, false),
+            call(AggregateFunction::Count, Some("b"), true),
+            call(AggregateFunction::Sum, Some("b"), false),
+            call(AggregateFunction::Avg, Some("b"), false),
+            call(AggregateFunction::Max, Some("b"), false),
+        ];
+        let input = rows(&[&[1, 10], &[2, 5], &[1, 20], &[1, 30], &[1, 20], &[2, -1]]);
+        let groups = [parse_expr("a").unwrap()];
+        let grouped = Aggregate::new(input, &groups, calls.clone(), &layout(&["a", "b"])).unwrap();
+        let (i, decimal) = (Value::Int, |d: i64| Value::Decimal(BigDecimal::from(d)));
+        assert_eq!(
+            drain(grouped),
+            vec![
+                vec![i(1), i(4), i(3), i(80), decimal(20), i(30)],
+                vec![i(2), i(2), i(1), i(5), decimal(5), i(5)],
+            ]
+        );
+
+        let empty = Aggregate::new(rows(&[]), &[], calls, &layout(&["a", "b"])).unwrap();
+        let nulls = vec![Value::Null, Value::Null, Value::Null];
+        assert_eq!(drain(empty), vec![[vec![Value::Int(0), Value::Int(0)], nulls].concat()]);
+    }
+
+    #[test]
+    fn test_outer_joins() {
+        let left = layout(&["a"]);
+        let right = Layout::computed(vec!["b".to_string()]);
+        let on = || Some(parse_expr("a = b").unwrap());
+        let join = |join_type| {
+            let input = (rows(&[&[1], &[2]]), &left);
+            NestedLoopJoin::new(input, (rows(&[&[2], &[3]]), &right), join_type, on()).unwrap()
+        };
+        let (n, i) = (Value::Null, Value::Int);
+        assert_eq!(drain(join(JoinType::Inner)), vec![vec![i(2), i(2)]]);
+        assert_eq!(drain(join(JoinType::Left)), vec![vec![i(1), n.clone()], vec![i(2), i(2)]]);
+        assert_eq!(drain(join(JoinType::Right)), vec![vec![i(2), i(2)], vec![n.clone(), i(3)]]);
+        assert_eq!(
+            drain(join(JoinType::Full)),
+            vec![vec![i(1), n.clone()], vec![i(2), i(2)], vec![n, i(3)]]
+        );
+    }
+}
@@ -0,0 +1,16 @@
+// src/index.rs
+//! Secondary access paths over table data.
+//!
+//! Indexes map byte keys, usually built with [`crate::types::key`] so that
+//! they sort with `memcmp`, to byte values such as encoded
+//! [`RecordId`](crate::storage::RecordId)s.
+
+pub mod btree;
+pub mod hash;
+pub mod memory;
+pub mod table;
+
+pub use btree::{BTree, BTreeOptions, BTreeScan, Entry};
+pub use hash::HashIndex;
+pub use memory::MemoryIndex;
+pub use table::{IndexTree, KeyRange, TableIndex};
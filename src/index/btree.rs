@@ -0,0 +1,1259 @@
+// src/index/btree.rs
+//! A disk-backed B+tree on top of the buffer pool.
+//!
+//! Every node is one page. Leaves hold the entries in order and are linked
+//! both ways for range scans; inner nodes hold separators, each the lowest
+//! entry that may appear under the child to its right. A fixed meta page
+//! points at the root, so a tree is identified by its meta page however its
+//! root moves.
+//!
+//! In a unique tree entries are ordered by key alone. Otherwise they are
+//! ordered by key and then value, so duplicates of a key are told apart by
+//! their values and can be found and deleted one at a time.
+//!
+//! Node layouts (within the page data):
+//!
+//! ```text
+//! leaf:  count u16 | prev u64 | (key_len u16, value_len u16, key, value)*
+//! inner: count u16 | child0 u64 | (key_len u16, value_len u16, key, value, child u64)*
+//! meta:  root u64 | unique u8 | order u32
+//! ```
+//!
+//! A leaf's next sibling is the page header's `next`. Nodes are split and
+//! merged by size in bytes, and also by entry count when the tree has an
+//! order.
+//!
+//! Concurrent access uses latch coupling on the buffer pool's page latches.
+//! Readers descend holding at most a node and its child, and scans latch one
+//! leaf at a time, finding the next from the root. A writer first latches
+//! only the leaf, with read latches on the way down; if the change would
+//! split or underflow the leaf, it starts again with write latches, keeping
+//! those from the lowest node the change cannot spread above. A delete also
+//! latches, on the way down, the sibling each node on the path would be
+//! merged with, before the node if it is the left one. Latches are thus
+//! taken a level at a time from the top, and left to right within a level,
+//! so the next leaf latched to relink leaves never closes a cycle.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use parking_lot::{MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock};
+
+use crate::buffer::{BufferPool, PageGuard};
+use crate::error::Error;
+use crate::storage::{Page, PageId, PageType, PAGE_HEADER_SIZE};
+
+/// A key and the value stored with it.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+const NODE_HEADER: usize = 10;
+const LEAF_ENTRY_HEADER: usize = 4;
+const INNER_ENTRY_HEADER: usize = 12;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BTreeOptions {
+    /// Rejects a second entry with the same key.
+    pub unique: bool,
+    /// Most entries a node may hold; `None` fills nodes to the page size.
+    /// Small orders (the design's B = 6) give deep trees, which mostly
+    /// helps testing.
+    pub order: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    id: PageId,
+    leaf: bool,
+    /// A leaf's entries, or an inner node's separators: `entries[i]` is the
+    /// lowest entry that may appear under `children[i + 1]`.
+    entries: Vec<Entry>,
+    children: Vec<PageId>,
+    prev: PageId,
+    next: PageId,
+}
+
+impl Node {
+    fn new(id: PageId, leaf: bool) -> Self {
+        Node { id, leaf, entries: Vec::new(), children: Vec::new(), prev: 0, next: 0 }
+    }
+
+    fn size(&self) -> usize {
+        let header = if self.leaf { LEAF_ENTRY_HEADER } else { INNER_ENTRY_HEADER };
+        NODE_HEADER + self.entries.iter().map(|(k, v)| header + k.len() + v.len()).sum::<usize>()
+    }
+
+    fn position(&self, child: PageId) -> usize {
+        self.children.iter().position(|&c| c == child).expect("child is not under its parent")
+    }
+}
+
+/// A node latched for reading.
+struct ReadLatch<'a> {
+    node: Node,
+    _page: MappedRwLockReadGuard<'a, Page>,
+    _pin: PageGuard<'a>,
+}
+
+/// A node latched for writing. Changes to `node` reach the page on `store`.
+struct WriteLatch<'a> {
+    node: Node,
+    page: MappedRwLockWriteGuard<'a, Page>,
+    _pin: PageGuard<'a>,
+}
+
+impl WriteLatch<'_> {
+    fn store(&mut self) {
+        encode(&self.node, &mut self.page);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Insert,
+    Delete,
+}
+
+#[derive(Debug)]
+pub struct BTree {
+    pool: Arc<BufferPool>,
+    meta: PageId,
+    options: BTreeOptions,
+    /// The root page, held exclusively by writers that may replace it.
+    root: RwLock<PageId>,
+    /// Pages dropped from the tree that were pinned when freed.
+    garbage: Mutex<Vec<PageId>>,
+}
+
+impl BTree {
+    /// Creates an empty tree.
+    pub fn create(pool: Arc<BufferPool>, options: BTreeOptions) -> Result<Self, Error> {
+        if options.order.is_some_and(|order| order < 3) {
+            return Err(Error::Storage("B+tree order must be at least 3".to_string()));
+        }
+        let meta = pool.new_page(PageType::IndexMeta)?.id();
+        let root = pool.new_page(PageType::BTreeLeaf)?.id();
+        let tree = BTree { pool, meta, options, root: RwLock::new(root), garbage: Mutex::new(Vec::new()) };
+        tree.write_node(&Node::new(root, true))?;
+        tree.write_meta(root)?;
+        Ok(tree)
+    }
+
+    /// Opens the tree whose meta page is `meta`.
+    pub fn open(pool: Arc<BufferPool>, meta: PageId) -> Result<Self, Error> {
+        let (root, options) = {
+            let guard = pool.fetch(meta)?;
+            let page = guard.read();
+            if page.header.page_type != PageType::IndexMeta {
+                return Err(Error::Storage(format!("page {} is not an index", meta)));
+            }
+            let order = u32::from_le_bytes(page.data[9..13].try_into().unwrap()) as usize;
+            let options = BTreeOptions { unique: page.data[8] != 0, order: (order != 0).then_some(order) };
+            (u64::from_le_bytes(page.data[0..8].try_into().unwrap()), options)
+        };
+        Ok(BTree { pool, meta, options, root: RwLock::new(root), garbage: Mutex::new(Vec::new()) })
+    }
+
+    /// Builds a tree from entries in ascending order, filling nodes
+    /// left to right instead of inserting one entry at a time.
+    pub fn bulk_load(
+        pool: Arc<BufferPool>,
+        options: BTreeOptions,
+        entries: impl IntoIterator<Item = Entry>,
+    ) -> Result<Self, Error> {
+        let tree = Self::create(pool, options)?;
+        let mut last: Option<Entry> = None;
+        let leaves = entries.into_iter().map(|entry| {
+            tree.check_size(&entry.0, &entry.1)?;
+            if let Some(last) = &last {
+                match tree.compare(last, &entry) {
+                    Ordering::Less => {}
+                    Ordering::Equal => return Err(tree.duplicate(&entry)),
+                    Ordering::Greater => {
+                        return Err(Error::Storage("bulk load input is not in ascending order".to_string()))
+                    }
+                }
+            }
+            last = Some(entry.clone());
+            Ok((entry, 0))
+        });
+        let mut level = tree.build_level(true, leaves)?;
+        while level.len() > 1 {
+            level = tree.build_level(false, level.into_iter().map(Ok))?;
+        }
+        if let Some(&(_, root)) = level.first() {
+            let mut latch = tree.root.write();
+            tree.pool.free_page(*latch)?;
+            *latch = root;
+            tree.write_meta(root)?;
+        }
+        Ok(tree)
+    }
+
+    /// The page id to pass to [`BTree::open`].
+    pub fn meta(&self) -> PageId {
+        self.meta
+    }
+
+    /// Frees every page of the tree.
+    pub fn destroy(self) -> Result<(), Error> {
+        self.collect_garbage()?;
+        let mut pending = vec![*self.root.read()];
+        while let Some(id) = pending.pop() {
+            let node = self.read_latch(id)?.node;
+            pending.extend(node.children);
+            self.pool.free_page(id)?;
+        }
+        self.pool.free_page(self.meta)
+    }
+
+    pub fn options(&self) -> BTreeOptions {
+        self.options
+    }
+
+    /// Largest key and value, together, that an entry may have.
+    pub fn max_entry_size(&self) -> usize {
+        (self.capacity() - NODE_HEADER) / 4 - INNER_ENTRY_HEADER
+    }
+
+    /// Adds an entry. Fails if a unique tree already has the key, or any
+    /// tree already has the same key and value.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.check_size(key, value)?;
+        self.modify(&(key.to_vec(), value.to_vec()), Change::Insert).map(|_| ())
+    }
+
+    /// Removes the entry with this key and value, returning whether there
+    /// was one.
+    pub fn delete(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        self.modify(&(key.to_vec(), value.to_vec()), Change::Delete)
+    }
+
+    /// The value of the first entry with this key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.range(Bound::Included(key), Bound::Included(key))
+            .next()
+            .transpose()
+            .map(|entry| entry.map(|(_, value)| value))
+    }
+
+    /// Every entry, in order.
+    pub fn scan(&self) -> BTreeScan<'_> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// The entries with keys between `lower` and `upper`, in order. Iterate
+    /// with `.rev()` for descending order.
+    pub fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> BTreeScan<'_> {
+        self.between(lower.map(<[u8]>::to_vec), upper.map(<[u8]>::to_vec))
+    }
+
+    /// The entries whose keys start with `prefix`.
+    pub fn prefix(&self, prefix: &[u8]) -> BTreeScan<'_> {
+        let upper = prefix_end(prefix).map_or(Bound::Unbounded, Bound::Excluded);
+        self.between(Bound::Included(prefix.to_vec()), upper)
+    }
+
+    fn between(&self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>) -> BTreeScan<'_> {
+        BTreeScan {
+            tree: self,
+            lower,
+            upper,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            front_last: None,
+            back_last: None,
+            done: false,
+        }
+    }
+
+    /// Bytes available to a node.
+    fn capacity(&self) -> usize {
+        self.pool.page_size() - PAGE_HEADER_SIZE
+    }
+
+    fn check_size(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let size = key.len() + value.len();
+        if size > self.max_entry_size() {
+            return Err(Error::Storage(format!(
+                "index entry of {} bytes exceeds the maximum of {}",
+                size,
+                self.max_entry_size()
+            )));
+        }
+        Ok(())
+    }
+
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        if self.options.unique {
+            a.0.cmp(&b.0)
+        } else {
+            a.cmp(b)
+        }
+    }
+
+    fn duplicate(&self, entry: &Entry) -> Error {
+        if self.options.unique {
+            Error::Execution(format!("duplicate key {:?} in unique index", entry.0))
+        } else {
+            Error::Execution(format!("entry {:?} is already in the index", entry))
+        }
+    }
+
+    /// Index of the child of an inner node that may hold `entry`.
+    fn child_index(&self, node: &Node, entry: &Entry) -> usize {
+        node.entries.partition_point(|separator| self.compare(separator, entry) != Ordering::Greater)
+    }
+
+    fn overflows(&self, node: &Node) -> bool {
+        node.size() > self.capacity() || self.options.order.is_some_and(|order| node.entries.len() > order)
+    }
+
+    fn underflows(&self, node: &Node) -> bool {
+        match self.options.order {
+            Some(order) => node.entries.len() < order / 2,
+            None => node.entries.is_empty() || node.size() < self.capacity() / 4,
+        }
+    }
+
+    /// Applies a change to the leaf that may hold `entry`, returning whether
+    /// there was anything to change.
+    fn modify(&self, entry: &Entry, change: Change) -> Result<bool, Error> {
+        {
+            let (mut leaf, is_root) = self.latch_leaf(entry)?;
+            if !self.apply(&mut leaf.node, entry, change)? {
+                return Ok(false);
+            }
+            if !self.overflows(&leaf.node) && (is_root || !self.underflows(&leaf.node)) {
+                leaf.store();
+                return Ok(true);
+            }
+        }
+
+        // The change reaches above the leaf: latch the path for writing,
+        // letting go of everything above a node it cannot spread past.
+        self.collect_garbage()?;
+        let mut root = Some(self.root.write());
+        let mut path: Vec<(WriteLatch<'_>, Option<WriteLatch<'_>>)> = Vec::new();
+        let mut next = (self.write_latch(**root.as_ref().unwrap())?, None);
+        loop {
+            let (latch, mut sibling) = next;
+            if self.safe(&latch.node, root.is_some() && path.is_empty(), change) {
+                path.clear();
+                root = None;
+                sibling = None;
+            }
+            if latch.node.leaf {
+                path.push((latch, sibling));
+                break;
+            }
+            let at = self.child_index(&latch.node, entry);
+            let children = &latch.node.children;
+            next = match change {
+                Change::Delete if at > 0 => {
+                    let left = self.write_latch(children[at - 1])?;
+                    (self.write_latch(children[at])?, Some(left))
+                }
+                Change::Delete => {
+                    let child = self.write_latch(children[at])?;
+                    (child, children.get(1).map(|&id| self.write_latch(id)).transpose()?)
+                }
+                Change::Insert => (self.write_latch(children[at])?, None),
+            };
+            path.push((latch, sibling));
+        }
+        if !self.apply(&mut path.last_mut().unwrap().0.node, entry, change)? {
+            return Ok(false);
+        }
+        let mut garbage = Vec::new();
+        self.fix(&mut path, root.as_deref_mut(), &mut garbage)?;
+        drop(path);
+        drop(root);
+        for id in garbage {
+            self.free(id)?;
+        }
+        Ok(true)
+    }
+
+    fn apply(&self, node: &mut Node, entry: &Entry, change: Change) -> Result<bool, Error> {
+        match (change, node.entries.binary_search_by(|e| self.compare(e, entry))) {
+            (Change::Insert, Ok(_)) => Err(self.duplicate(entry)),
+            (Change::Insert, Err(at)) => {
+                node.entries.insert(at, entry.clone());
+                Ok(true)
+            }
+            (Change::Delete, Ok(at)) if node.entries[at].1 == entry.1 => {
+                node.entries.remove(at);
+                Ok(true)
+            }
+            (Change::Delete, _) => Ok(false),
+        }
+    }
+
+    /// Whether a change below `node` is sure to stop at it: the node has
+    /// room for another separator (a separator may also grow when entries
+    /// move between siblings) and, for deletes, can lose one.
+    fn safe(&self, node: &Node, is_root: bool, change: Change) -> bool {
+        let item = (self.capacity() - NODE_HEADER) / 4;
+        let room = node.size() + item <= self.capacity()
+            && self.options.order.is_none_or(|order| node.entries.len() < order);
+        room && match change {
+            Change::Insert => true,
+            Change::Delete if is_root => node.leaf || node.entries.len() > 1,
+            Change::Delete => match self.options.order {
+                Some(order) => node.entries.len() > order / 2,
+                None => node.entries.len() > 1 && node.size() >= self.capacity() / 4 + item,
+            },
+        }
+    }
+
+    /// Restores the tree's shape bottom-up along a latched path whose leaf
+    /// changed, each node with the sibling it would be merged with: splits
+    /// overfull nodes, rebalances underfull ones and replaces a root left
+    /// with one child. `root` is given if the path starts at the root.
+    fn fix(
+        &self,
+        path: &mut [(WriteLatch<'_>, Option<WriteLatch<'_>>)],
+        mut root: Option<&mut PageId>,
+        garbage: &mut Vec<PageId>,
+    ) -> Result<(), Error> {
+        for i in (0..path.len()).rev() {
+            let (above, rest) = path.split_at_mut(i);
+            let (latch, sibling) = &mut rest[0];
+            if self.overflows(&latch.node) {
+                let (separator, right) = self.split(&mut latch.node)?;
+                if let Some((parent, _)) = above.last_mut() {
+                    let at = parent.node.position(latch.node.id);
+                    parent.node.entries.insert(at, separator);
+                    parent.node.children.insert(at + 1, right);
+                } else {
+                    let root = root
+                        .as_deref_mut()
+                        .ok_or_else(|| Error::Storage(format!("B+tree node {} overflowed", latch.node.id)))?;
+                    let mut node = self.allocate(false)?;
+                    node.node.entries.push(separator);
+                    node.node.children = vec![latch.node.id, right];
+                    node.store();
+                    *root = node.node.id;
+                    self.write_meta(*root)?;
+                }
+            } else if let Some((parent, _)) = above.last_mut() {
+                if self.underflows(&latch.node) {
+                    let sibling = sibling
+                        .as_mut()
+                        .ok_or_else(|| Error::Storage(format!("B+tree node {} underflowed", latch.node.id)))?;
+                    let at = parent.node.position(latch.node.id);
+                    self.rebalance(&mut parent.node, at, &mut latch.node, sibling, garbage)?;
+                }
+            } else if let Some(root) = root.as_deref_mut() {
+                if !latch.node.leaf && latch.node.entries.is_empty() {
+                    // The root has a single child left, which takes its place.
+                    *root = latch.node.children[0];
+                    self.write_meta(*root)?;
+                    garbage.push(latch.node.id);
+                }
+            }
+            if !garbage.contains(&latch.node.id) {
+                latch.store();
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits an overfull node in two, returning the separator and the new
+    /// right-hand node.
+    fn split(&self, left: &mut Node) -> Result<(Entry, PageId), Error> {
+        let mut right = self.allocate(left.leaf)?;
+        let separator = self.divide(left, &mut right.node, None);
+        if left.leaf {
+            right.node.prev = left.id;
+            right.node.next = left.next;
+            left.next = right.node.id;
+            if right.node.next != 0 {
+                let mut next = self.write_latch(right.node.next)?;
+                next.node.prev = right.node.id;
+                next.store();
+            }
+        }
+        right.store();
+        Ok((separator, right.node.id))
+    }
+
+    /// Shares the entries of `left`, then `separator` for inner nodes, then
+    /// `right` out evenly between the two, returning the new separator.
+    fn divide(&self, left: &mut Node, right: &mut Node, separator: Option<Entry>) -> Entry {
+        let mut entries = std::mem::take(&mut left.entries);
+        entries.extend(separator);
+        entries.append(&mut right.entries);
+        left.children.append(&mut right.children);
+
+        let header = if left.leaf { LEAF_ENTRY_HEADER } else { INNER_ENTRY_HEADER };
+        let sizes: Vec<usize> = entries.iter().map(|(k, v)| header + k.len() + v.len()).collect();
+        let total: usize = sizes.iter().sum();
+        let n = entries.len();
+        // Leaves keep entries[..at] and entries[at..]; inner nodes send
+        // entries[at] up and keep at least one separator on each side.
+        let skip = usize::from(!left.leaf);
+        let (mut lo, mut hi) = (1, n - 1 - skip);
+        if let Some(order) = self.options.order {
+            // Neither side over the order, nor under half of it.
+            let min = order / 2;
+            hi = hi.min(order).min((n - skip).saturating_sub(min));
+            lo = lo.max((n - skip).saturating_sub(order)).max(min);
+        }
+        let mut at = lo;
+        let mut best = usize::MAX;
+        let mut before: usize = sizes[..lo].iter().sum();
+        for (k, &size) in sizes.iter().enumerate().take(hi.max(lo) + 1).skip(lo) {
+            let after = total - before - if left.leaf { 0 } else { size };
+            let diff = before.abs_diff(after);
+            if diff < best {
+                best = diff;
+                at = k;
+            }
+            before += size;
+        }
+
+        right.entries = entries.split_off(at + skip);
+        if !left.leaf {
+            right.children = left.children.split_off(at + 1);
+        }
+        let separator = if left.leaf { right.entries[0].clone() } else { entries.pop().unwrap() };
+        left.entries = entries;
+        separator
+    }
+
+    /// Fixes the underfull child `at` of `parent` by merging it with
+    /// `sibling`, the child before it or else the one after, or by moving
+    /// entries over from the sibling if both do not fit in one node. A node
+    /// merged away is added to `garbage`.
+    fn rebalance(
+        &self,
+        parent: &mut Node,
+        at: usize,
+        child: &mut Node,
+        sibling: &mut WriteLatch<'_>,
+        garbage: &mut Vec<PageId>,
+    ) -> Result<(), Error> {
+        let index = if at > 0 { at - 1 } else { at };
+        let (left, right) = if at > 0 { (&mut sibling.node, child) } else { (child, &mut sibling.node) };
+        let separator = (!left.leaf).then(|| parent.entries[index].clone());
+
+        let separator_size = separator.as_ref().map_or(0, |(k, v)| INNER_ENTRY_HEADER + k.len() + v.len());
+        let size = left.size() + right.size() - NODE_HEADER + separator_size;
+        let count = left.entries.len() + right.entries.len() + usize::from(separator.is_some());
+        if size <= self.capacity() && self.options.order.is_none_or(|order| count <= order) {
+            left.entries.extend(separator);
+            left.entries.append(&mut right.entries);
+            left.children.append(&mut right.children);
+            left.next = right.next;
+            if left.leaf && left.next != 0 {
+                let mut next = self.write_latch(left.next)?;
+                next.node.prev = left.id;
+                next.store();
+            }
+            parent.entries.remove(index);
+            parent.children.remove(index + 1);
+            garbage.push(right.id);
+            if at == 0 {
+                return Ok(());
+            }
+        } else {
+            parent.entries[index] = self.divide(left, right, separator);
+        }
+        sibling.store();
+        Ok(())
+    }
+
+    /// Returns a page that is no longer part of the tree to the pager. One
+    /// still pinned by someone else (a flush, say) is kept for later.
+    fn free(&self, id: PageId) -> Result<(), Error> {
+        if !self.pool.try_free_page(id)? {
+            self.garbage.lock().push(id);
+        }
+        Ok(())
+    }
+
+    fn collect_garbage(&self) -> Result<(), Error> {
+        let pending = std::mem::take(&mut *self.garbage.lock());
+        for id in pending {
+            self.free(id)?;
+        }
+        Ok(())
+    }
+
+    /// Packs one level of a bulk-loaded tree: entries into leaves, or the
+    /// nodes of the level below into inner nodes. Returns each new node with
+    /// the lowest entry under it.
+    fn build_level(
+        &self,
+        leaf: bool,
+        items: impl Iterator<Item = Result<(Entry, PageId), Error>>,
+    ) -> Result<Vec<(Entry, PageId)>, Error> {
+        let page_type = if leaf { PageType::BTreeLeaf } else { PageType::BTreeInternal };
+        let mut level = Vec::new();
+        // The node being filled and the full one before it, held back in
+        // case the last node needs some of its entries, each with the lowest
+        // entry under it.
+        let mut previous: Option<(Entry, Node)> = None;
+        let mut current: Option<(Entry, Node)> = None;
+        for item in items {
+            let (entry, child) = item?;
+            if let Some((_, node)) = &mut current {
+                node.entries.push(entry.clone());
+                if !leaf {
+                    node.children.push(child);
+                }
+                if !self.overflows(node) {
+                    continue;
+                }
+                node.entries.pop();
+                if !leaf {
+                    node.children.pop();
+                }
+            }
+            let mut node = Node::new(self.pool.new_page(page_type)?.id(), leaf);
+            if leaf {
+                node.entries.push(entry.clone());
+            } else {
+                node.children.push(child);
+            }
+            if let Some((_, full)) = &mut current {
+                if leaf {
+                    full.next = node.id;
+                    node.prev = full.id;
+                }
+            }
+            if let Some((first, full)) = previous.take() {
+                self.write_node(&full)?;
+                level.push((first, full.id));
+            }
+            previous = current.replace((entry, node));
+        }
+
+        let Some((first, mut last)) = current else {
+            return Ok(level);
+        };
+        if let Some((previous_first, mut full)) = previous {
+            let first = if self.underflows(&last) {
+                let separator = (!leaf).then_some(first);
+                self.divide(&mut full, &mut last, separator)
+            } else {
+                first
+            };
+            self.write_node(&full)?;
+            level.push((previous_first, full.id));
+            self.write_node(&last)?;
+            level.push((first, last.id));
+        } else {
+            self.write_node(&last)?;
+            level.push((first, last.id));
+        }
+        Ok(level)
+    }
+
+    fn read_latch(&self, id: PageId) -> Result<ReadLatch<'_>, Error> {
+        let pin = self.pool.fetch(id)?;
+        let page = pin.read();
+        Ok(ReadLatch { node: decode(&page)?, _page: page, _pin: pin })
+    }
+
+    fn write_latch(&self, id: PageId) -> Result<WriteLatch<'_>, Error> {
+        let pin = self.pool.fetch(id)?;
+        let page = pin.write();
+        Ok(WriteLatch { node: decode(&page)?, page, _pin: pin })
+    }
+
+    fn allocate(&self, leaf: bool) -> Result<WriteLatch<'_>, Error> {
+        let pin = self.pool.new_page(if leaf { PageType::BTreeLeaf } else { PageType::BTreeInternal })?;
+        let page = pin.write();
+        Ok(WriteLatch { node: Node::new(pin.id(), leaf), page, _pin: pin })
+    }
+
+    fn write_node(&self, node: &Node) -> Result<(), Error> {
+        let pin = self.pool.fetch(node.id)?;
+        encode(node, &mut pin.write());
+        Ok(())
+    }
+
+    /// Crabs down to the leaf that may hold `entry` with read latches and
+    /// latches it for writing. Also returns whether the leaf is the root.
+    fn latch_leaf(&self, entry: &Entry) -> Result<(WriteLatch<'_>, bool), Error> {
+        let mut root = Some(self.root.read());
+        let mut parent: Option<ReadLatch<'_>> = None;
+        let mut id = **root.as_ref().unwrap();
+        loop {
+            let latch = self.read_latch(id)?;
+            if latch.node.leaf {
+                // With the parent (or the root pointer) still held, the
+                // leaf cannot be split or merged away in between.
+                drop(latch);
+                return Ok((self.write_latch(id)?, parent.is_none()));
+            }
+            id = latch.node.children[self.child_index(&latch.node, entry)];
+            parent = Some(latch);
+            drop(root.take());
+        }
+    }
+
+    fn write_meta(&self, root: PageId) -> Result<(), Error> {
+        let guard = self.pool.fetch(self.meta)?;
+        let mut page = guard.write();
+        page.data[0..8].copy_from_slice(&root.to_le_bytes());
+        page.data[8] = self.options.unique as u8;
+        page.data[9..13].copy_from_slice(&(self.options.order.unwrap_or(0) as u32).to_le_bytes());
+        Ok(())
+    }
+
+    /// The entries `admits` accepts from the first leaf holding any, going
+    /// forward (`admits` must reject a prefix of the entries) or backward
+    /// (accept a prefix), in the order visited.
+    fn seek(&self, forward: bool, admits: impl Fn(&Entry) -> bool) -> Result<Vec<Entry>, Error> {
+        // Where the last leaf visited ends; the next one is found from the
+        // root by looking past it.
+        let mut fence: Option<Entry> = None;
+        loop {
+            let (entries, next) = self.seek_leaf(forward, &admits, fence.as_ref())?;
+            if !entries.is_empty() || next.is_none() {
+                return Ok(entries);
+            }
+            fence = next;
+        }
+    }
+
+    /// The entries past `fence` that `admits` accepts from the one leaf
+    /// `seek` would look at next, and the separator bounding that leaf in
+    /// the direction of travel, if any.
+    fn seek_leaf(
+        &self,
+        forward: bool,
+        admits: &dyn Fn(&Entry) -> bool,
+        fence: Option<&Entry>,
+    ) -> Result<(Vec<Entry>, Option<Entry>), Error> {
+        let order = |e: &Entry| fence.map(|fence| self.compare(e, fence));
+        let accept = |e: &Entry| {
+            admits(e)
+                && match order(e) {
+                    None => true,
+                    Some(order) => (order == Ordering::Less) != forward,
+                }
+        };
+        let root = self.root.read();
+        let mut latch = self.read_latch(*root)?;
+        drop(root);
+        let mut bound = None;
+        while !latch.node.leaf {
+            let node = &latch.node;
+            // Entries equal to a separator are in the subtree to its right,
+            // so going forward, a separator equal to the fence is passed.
+            let at = node.entries.partition_point(|separator| {
+                (accept(separator) != forward) || (forward && order(separator) == Some(Ordering::Equal))
+            });
+            if forward && at < node.entries.len() {
+                bound = Some(node.entries[at].clone());
+            } else if !forward && at > 0 {
+                bound = Some(node.entries[at - 1].clone());
+            }
+            latch = self.read_latch(node.children[at])?;
+        }
+        let mut entries: Vec<Entry> = latch.node.entries.iter().filter(|e| accept(e)).cloned().collect();
+        if !forward {
+            entries.reverse();
+        }
+        Ok((entries, bound))
+    }
+}
+
+/// The first key past every key that starts with `prefix`, if there is one:
+/// `prefix` with trailing 0xFF bytes dropped and the last one left
+/// incremented.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while end.last() == Some(&0xFF) {
+        end.pop();
+    }
+    *end.last_mut()? += 1;
+    Some(end)
+}
+
+fn decode(page: &Page) -> Result<Node, Error> {
+    let id = page.header.page_id;
+    let leaf = match page.header.page_type {
+        PageType::BTreeLeaf => true,
+        PageType::BTreeInternal => false,
+        other => return Err(Error::Storage(format!("page {} is a {:?} page, not a B+tree node", id, other))),
+    };
+    let data = &page.data;
+    let u16_at = |at: usize| u16::from_le_bytes(data[at..at + 2].try_into().unwrap()) as usize;
+    let u64_at = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+
+    let mut node = Node::new(id, leaf);
+    let count = u16_at(0);
+    if leaf {
+        node.prev = u64_at(2);
+        node.next = page.header.next;
+    } else {
+        node.children.push(u64_at(2));
+    }
+    let mut at = NODE_HEADER;
+    for _ in 0..count {
+        let (key_len, value_len) = (u16_at(at), u16_at(at + 2));
+        at += 4;
+        let key = data[at..at + key_len].to_vec();
+        at += key_len;
+        let value = data[at..at + value_len].to_vec();
+        at += value_len;
+        node.entries.push((key, value));
+        if !leaf {
+            node.children.push(u64_at(at));
+            at += 8;
+        }
+    }
+    Ok(node)
+}
+
+fn encode(node: &Node, page: &mut Page) {
+    page.header.page_type = if node.leaf { PageType::BTreeLeaf } else { PageType::BTreeInternal };
+    page.header.next = node.next;
+    let data = &mut page.data;
+    data[0..2].copy_from_slice(&(node.entries.len() as u16).to_le_bytes());
+    let first = if node.leaf { node.prev } else { node.children[0] };
+    data[2..10].copy_from_slice(&first.to_le_bytes());
+    let mut at = NODE_HEADER;
+    for (i, (key, value)) in node.entries.iter().enumerate() {
+        data[at..at + 2].copy_from_slice(&(key.len() as u16).to_le_bytes());
+        data[at + 2..at + 4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        at += 4;
+        data[at..at + key.len()].copy_from_slice(key);
+        at += key.len();
+        data[at..at + value.len()].copy_from_slice(value);
+        at += value.len();
+        if !node.leaf {
+            data[at..at + 8].copy_from_slice(&node.children[i + 1].to_le_bytes());
+            at += 8;
+        }
+    }
+    page.header.free_space = (data.len() - at) as u16;
+}
+
+/// An iterator over a range of a [`BTree`], in either direction.
+///
+/// The scan reads one leaf at a time and holds no latches in between, so the
+/// tree may change during a scan; each leaf is found afresh from the root,
+/// after the last entry returned.
+#[derive(Debug)]
+pub struct BTreeScan<'a> {
+    tree: &'a BTree,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+    front: VecDeque<Entry>,
+    back: VecDeque<Entry>,
+    front_last: Option<Entry>,
+    back_last: Option<Entry>,
+    done: bool,
+}
+
+impl BTreeScan<'_> {
+    fn above_lower(&self, key: &[u8]) -> bool {
+        match &self.lower {
+            Bound::Included(lower) => key >= &lower[..],
+            Bound::Excluded(lower) => key > &lower[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn below_upper(&self, key: &[u8]) -> bool {
+        match &self.upper {
+            Bound::Included(upper) => key <= &upper[..],
+            Bound::Excluded(upper) => key < &upper[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn take(&mut self, forward: bool) -> Option<Result<Entry, Error>> {
+        if self.done {
+            return None;
+        }
+        let tree = self.tree;
+        let buffered = if forward { &self.front } else { &self.back };
+        if buffered.is_empty() {
+            let loaded = if forward {
+                match &self.front_last {
+                    Some(last) => tree.seek(true, |e| tree.compare(e, last) == Ordering::Greater),
+                    None => tree.seek(true, |e| self.above_lower(&e.0)),
+                }
+            } else {
+                match &self.back_last {
+                    Some(last) => tree.seek(false, |e| tree.compare(e, last) == Ordering::Less),
+                    None => tree.seek(false, |e| self.below_upper(&e.0)),
+                }
+            };
+            match loaded {
+                Ok(entries) if forward => self.front = entries.into(),
+                Ok(entries) => self.back = entries.into(),
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        let entry = if forward { self.front.pop_front() } else { self.back.pop_front() };
+        let Some(entry) = entry else {
+            self.done = true;
+            return None;
+        };
+        // Stop at the far bound, or where the other end of the scan got to.
+        let (in_range, other) = if forward {
+            (self.below_upper(&entry.0), &self.back_last)
+        } else {
+            (self.above_lower(&entry.0), &self.front_last)
+        };
+        let beyond = if forward { Ordering::Less } else { Ordering::Greater };
+        if !in_range || other.as_ref().is_some_and(|other| tree.compare(&entry, other) != beyond) {
+            self.done = true;
+            return None;
+        }
+        if forward {
+            self.front_last = Some(entry.clone());
+        } else {
+            self.back_last = Some(entry.clone());
+        }
+        Some(Ok(entry))
+    }
+}
+
+impl Iterator for BTreeScan<'_> {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.take(true)
+    }
+}
+
+impl DoubleEndedIterator for BTreeScan<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.take(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Pager, PagerOptions};
+    use proptest::prelude::*;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    fn pool(dir: &tempfile::TempDir) -> Arc<BufferPool> {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        Arc::new(BufferPool::new(pager, 32))
+    }
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key{:06}", i).into_bytes()
+    }
+
+    fn entries(scan: BTreeScan<'_>) -> Vec<Entry> {
+        scan.collect::<Result<_, _>>().unwrap()
+    }
+
+    /// Checks ordering, separators, fill, sibling links and that all leaves
+    /// are at the same depth, returning every entry.
+    fn check(tree: &BTree) -> Vec<Entry> {
+        fn walk(
+            tree: &BTree,
+            id: PageId,
+            lower: Option<&Entry>,
+            root: bool,
+            depth: usize,
+            leaves: &mut Vec<(Node, usize)>,
+        ) {
+            let node = tree.read_latch(id).unwrap().node;
+            assert!(!tree.overflows(&node), "node {} overflows", id);
+            assert!(root || !tree.underflows(&node), "node {} underflows", id);
+            assert!(node.entries.windows(2).all(|w| tree.compare(&w[0], &w[1]) == Ordering::Less));
+            if let (Some(lower), Some(first)) = (lower, node.entries.first()) {
+                assert_ne!(tree.compare(first, lower), Ordering::Less);
+            }
+            if node.leaf {
+                leaves.push((node, depth));
+                return;
+            }
+            assert_eq!(node.children.len(), node.entries.len() + 1);
+            for (i, &child) in node.children.iter().enumerate() {
+                let lower = if i == 0 { lower } else { Some(&node.entries[i - 1]) };
+                walk(tree, child, lower, false, depth + 1, leaves);
+            }
+        }
+        let root = *tree.root.read();
+        let mut leaves = Vec::new();
+        walk(tree, root, None, true, 0, &mut leaves);
+        assert!(leaves.iter().all(|(_, depth)| *depth == leaves[0].1));
+        for (i, (leaf, _)) in leaves.iter().enumerate() {
+            assert_eq!(leaf.prev, if i == 0 { 0 } else { leaves[i - 1].0.id });
+            assert_eq!(leaf.next, leaves.get(i + 1).map_or(0, |(next, _)| next.id));
+        }
+        let all: Vec<Entry> = leaves.into_iter().flat_map(|(leaf, _)| leaf.entries).collect();
+        assert!(all.windows(2).all(|w| tree.compare(&w[0], &w[1]) == Ordering::Less));
+        all
+    }
+
+    #[test]
+    fn test_insert_get_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = BTree::create(pool(&dir), BTreeOptions { unique: true, order: None }).unwrap();
+        for i in (0..500).rev() {
+            tree.insert(&key(i), &i.to_le_bytes()).unwrap();
+        }
+        assert_eq!(check(&tree).len(), 500);
+        assert_eq!(tree.get(&key(42)).unwrap(), Some(42u32.to_le_bytes().to_vec()));
+        assert!(tree.insert(&key(42), b"other").unwrap_err().to_string().contains("duplicate key"));
+        assert!(tree.insert(&[0; 200], b"").is_err());
+
+        // Deleting needs the value too.
+        assert!(!tree.delete(&key(42), b"other").unwrap());
+        for i in (0..500).filter(|i| i % 3 != 0) {
+            assert!(tree.delete(&key(i), &i.to_le_bytes()).unwrap());
+        }
+        assert_eq!(tree.get(&key(42)).unwrap(), Some(42u32.to_le_bytes().to_vec()));
+        assert_eq!(tree.get(&key(43)).unwrap(), None);
+        assert_eq!(check(&tree).len(), 167);
+        for i in (0..500).step_by(3) {
+            assert!(tree.delete(&key(i), &i.to_le_bytes()).unwrap());
+        }
+        assert!(check(&tree).is_empty());
+        assert!(tree.read_latch(*tree.root.read()).unwrap().node.leaf);
+    }
+
+    #[test]
+    fn test_range_and_prefix_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = BTree::create(pool(&dir), BTreeOptions { unique: false, order: Some(6) }).unwrap();
+        for i in 0..100u8 {
+            tree.insert(&[i / 10, i % 10], &[i]).unwrap();
+            tree.insert(&[i / 10, i % 10], &[i, 1]).unwrap();
+        }
+        check(&tree);
+        let keys = |scan: BTreeScan<'_>| entries(scan).into_iter().map(|(_, v)| v[0]).collect::<Vec<_>>();
+
+        let lower: &[u8] = &[2, 5];
+        let upper: &[u8] = &[3, 2];
+        let expected = [25, 25, 26, 26, 27, 27, 28, 28, 29, 29, 30, 30, 31, 31];
+        assert_eq!(keys(tree.range(Bound::Excluded(lower), Bound::Excluded(upper))), expected[2..]);
+        let mut backward: Vec<u8> =
+            tree.range(Bound::Included(lower), Bound::Excluded(upper)).rev().map(|e| e.unwrap().1[0]).collect();
+        backward.reverse();
+        assert_eq!(backward, expected);
+        assert_eq!(keys(tree.prefix(&[7])).len(), 20);
+        assert_eq!(tree.prefix(&[7]).next_back().unwrap().unwrap(), (vec![7, 9], vec![79, 1]));
+        assert_eq!(keys(tree.range(Bound::Included(upper), Bound::Excluded(lower))), Vec::<u8>::new());
+
+        // Both ends meet in the middle without repeating entries.
+        let mut scan = tree.prefix(&[4]);
+        let mut seen = Vec::new();
+        while let (Some(a), Some(b)) = (scan.next(), scan.next_back()) {
+            seen.push(a.unwrap().1);
+            seen.push(b.unwrap().1);
+        }
+        seen.extend(scan.map(|e| e.unwrap().1));
+        assert_eq!(seen.len(), 20);
+
+        // Duplicate keys are deleted one value at a time.
+        assert!(tree.delete(&[4, 2], &[42, 1]).unwrap());
+        assert_eq!(entries(tree.prefix(&[4, 2])), vec![(vec![4, 2], vec![42])]);
+        assert!(tree.insert(&[4, 2], &[42]).is_err());
+    }
+
+    #[test]
+    fn test_bulk_load_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = pool(&dir);
+        for order in [None, Some(3), Some(6)] {
+            for n in [0, 1, 7, 1000] {
+                let options = BTreeOptions { unique: true, order };
+                let input: Vec<Entry> = (0..n).map(|i| (key(i), vec![i as u8])).collect();
+                let tree = BTree::bulk_load(pool.clone(), options, input.clone()).unwrap();
+                assert_eq!(check(&tree), input, "order {:?}, {} entries", order, n);
+                let reopened = BTree::open(pool.clone(), tree.meta()).unwrap();
+                assert_eq!(reopened.options(), options);
+                assert_eq!(reopened.insert(&key(n / 2), b"x").is_err(), n > 0);
+                reopened.insert(b"zzz", b"").unwrap();
+                assert_eq!(check(&reopened).len(), n as usize + 1 + usize::from(n == 0));
+            }
+        }
+        let unsorted = vec![(key(2), vec![]), (key(1), vec![])];
+        assert!(BTree::bulk_load(pool.clone(), BTreeOptions::default(), unsorted).is_err());
+        let duplicate = vec![(key(1), vec![]), (key(1), vec![])];
+        assert!(BTree::bulk_load(pool, BTreeOptions { unique: true, order: None }, duplicate).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_writers_and_scanners() {
+        for order in [None, Some(4)] {
+            let dir = tempfile::tempdir().unwrap();
+            let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+            let pool = Arc::new(BufferPool::new(pager, 512));
+            let tree = Arc::new(BTree::create(pool, BTreeOptions { unique: true, order }).unwrap());
+            // Keys from 100000 are deleted while others go in; those from
+            // 200000 stay put, so every scan must see all of them.
+            for i in (100_000..100_800).chain(200_000..200_100) {
+                tree.insert(&key(i), &i.to_le_bytes()).unwrap();
+            }
+
+            let writing = Arc::new(AtomicUsize::new(6));
+            let mut writers = Vec::new();
+            for w in 0..6u32 {
+                let (tree, writing) = (tree.clone(), writing.clone());
+                writers.push(tokio::task::spawn_blocking(move || {
+                    for i in 0..600 {
+                        if w < 4 {
+                            let k = i * 4 + w;
+                            tree.insert(&key(k), &k.to_le_bytes()).unwrap();
+                        } else if i < 400 {
+                            let k = 100_000 + i * 2 + w - 4;
+                            assert!(tree.delete(&key(k), &k.to_le_bytes()).unwrap());
+                        }
+                    }
+                    writing.fetch_sub(1, SeqCst);
+                }));
+            }
+            let mut scanners = Vec::new();
+            for s in 0..4 {
+                let (tree, writing) = (tree.clone(), writing.clone());
+                scanners.push(tokio::task::spawn_blocking(move || {
+                    let mut scans = 0;
+                    loop {
+                        let done = writing.load(SeqCst) == 0;
+                        let mut found = if s % 2 == 0 { entries(tree.scan()) } else { entries_rev(tree.scan()) };
+                        if s % 2 == 1 {
+                            found.reverse();
+                        }
+                        assert!(found.windows(2).all(|w| w[0].0 < w[1].0));
+                        assert_eq!(found.iter().filter(|e| e.0 >= key(200_000)).count(), 100);
+                        let range = tree.range(Bound::Included(&key(200_010)), Bound::Excluded(&key(200_020)));
+                        assert_eq!(entries(range).len(), 10);
+                        scans += 1;
+                        if done {
+                            return scans;
+                        }
+                    }
+                }));
+            }
+            for writer in writers {
+                writer.await.unwrap();
+            }
+            for scanner in scanners {
+                assert!(scanner.await.unwrap() > 0);
+            }
+
+            let expected: Vec<Entry> =
+                (0..2400).chain(200_000..200_100).map(|i: u32| (key(i), i.to_le_bytes().to_vec())).collect();
+            assert_eq!(check(&tree), expected, "order {:?}", order);
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(u16, u8),
+        Delete(u16, u8),
+        Range(u16, u16, bool),
+    }
+
+    fn ops() -> impl Strategy<Value = Vec<Op>> {
+        prop::collection::vec(
+            prop_oneof![
+                4 => (0..300u16, 0..3u8).prop_map(|(k, v)| Op::Insert(k, v)),
+                3 => (0..300u16, 0..3u8).prop_map(|(k, v)| Op::Delete(k, v)),
+                1 => (0..300u16, 0..300u16, any::<bool>()).prop_map(|(a, b, rev)| Op::Range(a, b, rev)),
+            ],
+            1..400,
+        )
+    }
+
+    /// Keys of varying length, so nodes split by size as well as by count.
+    fn model_key(k: u16) -> Vec<u8> {
+        let mut key = k.to_be_bytes().to_vec();
+        key.resize(2 + (k % 7) as usize * 5, b'.');
+        key
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn prop_unique_tree_matches_btreemap(
+            ops in ops(),
+            order in prop_oneof![Just(None), (3..8usize).prop_map(Some)],
+        ) {
+            let dir = tempfile::tempdir().unwrap();
+            let tree = BTree::create(pool(&dir), BTreeOptions { unique: true, order }).unwrap();
+            let mut model = BTreeMap::new();
+            for op in ops {
+                match op {
+                    Op::Insert(k, v) => {
+                        let inserted = tree.insert(&model_key(k), &[v]).is_ok();
+                        prop_assert_eq!(inserted, !model.contains_key(&model_key(k)));
+                        model.entry(model_key(k)).or_insert(vec![v]);
+                    }
+                    Op::Delete(k, v) => {
+                        let expected = model.get(&model_key(k)) == Some(&vec![v]);
+                        prop_assert_eq!(tree.delete(&model_key(k), &[v]).unwrap(), expected);
+                        if expected {
+                            model.remove(&model_key(k));
+                        }
+                    }
+                    Op::Range(a, b, rev) => {
+                        let (a, b) = (model_key(a), model_key(b));
+                        if a > b { continue; }
+                        let range = (Bound::Included(&a[..]), Bound::Excluded(&b[..]));
+                        let expected: Vec<Entry> =
+                            model.range::<[u8], _>(range).map(|(k, v)| (k.clone(), v.clone())).collect();
+                        let scan = tree.range(range.0, range.1);
+                        let mut found = if rev { entries_rev(scan) } else { entries(scan) };
+                        if rev { found.reverse(); }
+                        prop_assert_eq!(found, expected);
+                    }
+                }
+            }
+            let expected: Vec<Entry> = model.into_iter().collect();
+            prop_assert_eq!(check(&tree), expected.clone());
+            prop_assert_eq!(entries(tree.scan()), expected);
+        }
+
+        #[test]
+        fn prop_duplicate_keys_match_btreeset(ops in ops()) {
+            let dir = tempfile::tempdir().unwrap();
+            let tree = BTree::create(pool(&dir), BTreeOptions { unique: false, order: None }).unwrap();
+            let mut model = BTreeSet::new();
+            for op in ops {
+                match op {
+                    Op::Insert(k, v) => {
+                        let entry = (model_key(k % 20), vec![v; 30]);
+                        prop_assert_eq!(tree.insert(&entry.0, &entry.1).is_ok(), model.insert(entry));
+                    }
+                    Op::Delete(k, v) => {
+                        let entry = (model_key(k % 20), vec![v; 30]);
+                        prop_assert_eq!(tree.delete(&entry.0, &entry.1).unwrap(), model.remove(&entry));
+                    }
+                    Op::Range(k, _, rev) => {
+                        let key = model_key(k % 20);
+                        let expected: Vec<Entry> = model.iter().filter(|e| e.0 == key).cloned().collect();
+                        let mut found = if rev { entries_rev(tree.prefix(&key)) } else { entries(tree.prefix(&key)) };
+                        if rev { found.reverse(); }
+                        // Keys are not prefix-free, so a prefix scan may see longer ones.
+                        found.retain(|e| e.0 == key);
+                        prop_assert_eq!(found, expected);
+                    }
+                }
+            }
+            prop_assert_eq!(check(&tree), model.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    fn entries_rev(scan: BTreeScan<'_>) -> Vec<Entry> {
+        scan.rev().collect::<Result<_, _>>().unwrap()
+    }
+}
@@ -0,0 +1,639 @@
+// src/index/hash.rs
+//! A disk-backed extendible hash index, for lookups by equality only.
+//!
+//! Keys are hashed with CRC32C, and a directory of `2^depth` slots maps the
+//! low `depth` bits of a hash to a bucket page. A bucket has its own local
+//! depth and is shared by every slot agreeing on that many bits. A full
+//! bucket splits in two on its next hash bit, moving only its own entries;
+//! when its local depth already equals the directory's, the directory first
+//! doubles by copying itself, so that each new slot points where its twin
+//! does. Nothing is ever rehashed as a whole.
+//!
+//! Splitting cannot separate entries with the same hash (duplicates of a
+//! key in a non-unique index, say), so a full bucket holding nothing else
+//! grows a chain of overflow pages instead, linked through the page header's
+//! `next`, as does every bucket once the directory is [`MAX_DEPTH`] deep.
+//! A bucket that empties merges back into its buddy, and the directory
+//! halves when no bucket needs its full depth.
+//!
+//! As in the B+tree, a unique index rejects a second entry with the same
+//! key; otherwise only the same key and value together are rejected.
+//!
+//! Page layouts (within the page data):
+//!
+//! ```text
+//! meta:      depth u8 | unique u8            (next: first directory page)
+//! directory: bucket u64*                     (next: following directory page)
+//! bucket:    count u16 | depth u8 | (key_len u16, value_len u16, key, value)*
+//! ```
+//!
+//! Lookups and changes to entries hold the directory shared and latch the
+//! bucket's first page, which stands for its whole chain. Splits, merges
+//! and overflow pages hold the directory exclusively.
+
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::buffer::BufferPool;
+use crate::error::Error;
+use crate::index::Entry;
+use crate::storage::{Page, PageId, PageType, PAGE_HEADER_SIZE};
+
+/// Most hash bits the directory uses, making for `2^MAX_DEPTH` slots.
+pub const MAX_DEPTH: u8 = 24;
+
+const BUCKET_HEADER: usize = 3;
+const ENTRY_HEADER: usize = 4;
+
+#[derive(Debug)]
+struct Directory {
+    depth: u8,
+    /// The bucket for each value of the low `depth` bits of a hash.
+    slots: Vec<PageId>,
+    /// Pages the slots are stored in, in order.
+    pages: Vec<PageId>,
+}
+
+impl Directory {
+    fn slot(&self, hash: u32) -> usize {
+        hash as usize & ((1 << self.depth) - 1)
+    }
+}
+
+/// One page of a bucket. Only the first page's depth is meaningful.
+#[derive(Debug, Clone)]
+struct Bucket {
+    id: PageId,
+    depth: u8,
+    entries: Vec<Entry>,
+    next: PageId,
+}
+
+impl Bucket {
+    fn new(id: PageId, depth: u8) -> Self {
+        Bucket { id, depth, entries: Vec::new(), next: 0 }
+    }
+
+    fn size(&self) -> usize {
+        BUCKET_HEADER + self.entries.iter().map(|(k, v)| ENTRY_HEADER + k.len() + v.len()).sum::<usize>()
+    }
+}
+
+#[derive(Debug)]
+pub struct HashIndex {
+    pool: Arc<BufferPool>,
+    meta: PageId,
+    unique: bool,
+    /// Held exclusively while buckets split or merge.
+    directory: RwLock<Directory>,
+    /// Pages dropped from the index that were pinned when freed.
+    garbage: Mutex<Vec<PageId>>,
+}
+
+impl HashIndex {
+    /// Creates an empty index of one bucket.
+    pub fn create(pool: Arc<BufferPool>, unique: bool) -> Result<Self, Error> {
+        let meta = pool.new_page(PageType::HashMeta)?.id();
+        let bucket = pool.new_page(PageType::HashBucket)?.id();
+        let index = HashIndex {
+            pool,
+            meta,
+            unique,
+            directory: RwLock::new(Directory { depth: 0, slots: vec![bucket], pages: Vec::new() }),
+            garbage: Mutex::new(Vec::new()),
+        };
+        index.write_bucket(&Bucket::new(bucket, 0))?;
+        index.store_directory(&mut index.directory.write(), [0])?;
+        Ok(index)
+    }
+
+    /// Opens the index whose meta page is `meta`.
+    pub fn open(pool: Arc<BufferPool>, meta: PageId) -> Result<Self, Error> {
+        let (depth, unique, mut next) = {
+            let guard = pool.fetch(meta)?;
+            let page = guard.read();
+            if page.header.page_type != PageType::HashMeta {
+                return Err(Error::Storage(format!("page {} is not a hash index", meta)));
+            }
+            (page.data[0], page.data[1] != 0, page.header.next)
+        };
+        let mut directory = Directory { depth, slots: Vec::with_capacity(1 << depth), pages: Vec::new() };
+        while next != 0 && directory.slots.len() < 1 << depth {
+            let guard = pool.fetch(next)?;
+            let page = guard.read();
+            if page.header.page_type != PageType::HashDirectory {
+                return Err(Error::Storage(format!("page {} is not a hash index directory", next)));
+            }
+            let count = (page.data.len() / 8).min((1 << depth) - directory.slots.len());
+            let slot = |i: usize| u64::from_le_bytes(page.data[i * 8..i * 8 + 8].try_into().unwrap());
+            directory.slots.extend((0..count).map(slot));
+            directory.pages.push(next);
+            next = page.header.next;
+        }
+        if directory.slots.len() != 1 << depth {
+            return Err(Error::Storage(format!("directory of hash index {} is truncated", meta)));
+        }
+        Ok(HashIndex { pool, meta, unique, directory: RwLock::new(directory), garbage: Mutex::new(Vec::new()) })
+    }
+
+    /// The page id to pass to [`HashIndex::open`].
+    pub fn meta(&self) -> PageId {
+        self.meta
+    }
+
+    /// Frees every page of the index.
+    pub fn destroy(self) -> Result<(), Error> {
+        self.collect_garbage()?;
+        let directory = self.directory.into_inner();
+        let buckets: BTreeSet<PageId> = directory.slots.iter().copied().collect();
+        for id in buckets {
+            let mut next = id;
+            while next != 0 {
+                let bucket = decode(&self.pool.fetch(next)?.read())?;
+                self.pool.free_page(next)?;
+                next = bucket.next;
+            }
+        }
+        for id in directory.pages {
+            self.pool.free_page(id)?;
+        }
+        self.pool.free_page(self.meta)
+    }
+
+    /// Whether a second entry with the same key is rejected.
+    pub fn unique(&self) -> bool {
+        self.unique
+    }
+
+    /// How many hash bits the directory uses.
+    pub fn depth(&self) -> u8 {
+        self.directory.read().depth
+    }
+
+    /// Largest key and value, together, that an entry may have.
+    pub fn max_entry_size(&self) -> usize {
+        self.capacity() - BUCKET_HEADER - ENTRY_HEADER
+    }
+
+    /// Adds an entry. Fails if a unique index already has the key, or any
+    /// index already has the same key and value.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let size = key.len() + value.len();
+        if size > self.max_entry_size() {
+            return Err(Error::Storage(format!(
+                "index entry of {} bytes exceeds the maximum of {}",
+                size,
+                self.max_entry_size()
+            )));
+        }
+        let entry = (key.to_vec(), value.to_vec());
+        if self.try_insert(&self.directory.read(), &entry)? {
+            return Ok(());
+        }
+        let mut directory = self.directory.write();
+        self.collect_garbage()?;
+        while !self.try_insert(&directory, &entry)? {
+            self.grow(&mut directory, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the entry with this key and value, returning whether there
+    /// was one.
+    pub fn delete(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        let hash = hash(key);
+        let (found, emptied) = {
+            let directory = self.directory.read();
+            let pin = self.pool.fetch(directory.slots[directory.slot(hash)])?;
+            let mut page = pin.write();
+            let mut chain = self.chain(decode(&page)?)?;
+            let Some((at, i)) = chain.iter().enumerate().find_map(|(at, bucket)| {
+                bucket.entries.iter().position(|(k, v)| k == key && v == value).map(|i| (at, i))
+            }) else {
+                return Ok(false);
+            };
+            chain[at].entries.remove(i);
+            if at == 0 {
+                encode(&chain[0], &mut page);
+            } else {
+                self.write_bucket(&chain[at])?;
+            }
+            let emptied = chain[at].entries.is_empty() && (at > 0 || (chain.len() == 1 && chain[0].depth > 0));
+            (true, emptied)
+        };
+        if emptied {
+            let mut directory = self.directory.write();
+            self.collect_garbage()?;
+            self.shrink(&mut directory, hash)?;
+        }
+        Ok(found)
+    }
+
+    /// The value of every entry with this key.
+    pub fn get_all(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let directory = self.directory.read();
+        let pin = self.pool.fetch(directory.slots[directory.slot(hash(key))])?;
+        let page = pin.read();
+        let chain = self.chain(decode(&page)?)?;
+        Ok(chain.into_iter().flat_map(|bucket| bucket.entries).filter(|(k, _)| k == key).map(|(_, v)| v).collect())
+    }
+
+    /// The value of an entry with this key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get_all(key)?.into_iter().next())
+    }
+
+    /// Bytes available to a page.
+    fn capacity(&self) -> usize {
+        self.pool.page_size() - PAGE_HEADER_SIZE
+    }
+
+    /// Adds `entry` to its bucket if one of the bucket's pages has room,
+    /// returning whether it did.
+    fn try_insert(&self, directory: &Directory, entry: &Entry) -> Result<bool, Error> {
+        let pin = self.pool.fetch(directory.slots[directory.slot(hash(&entry.0))])?;
+        let mut page = pin.write();
+        let mut chain = self.chain(decode(&page)?)?;
+        let taken = |(k, v): &Entry| *k == entry.0 && (self.unique || *v == entry.1);
+        if chain.iter().any(|bucket| bucket.entries.iter().any(taken)) {
+            return Err(if self.unique {
+                Error::Execution(format!("duplicate key {:?} in unique index", entry.0))
+            } else {
+                Error::Execution(format!("entry {:?} is already in the index", entry))
+            });
+        }
+        let size = ENTRY_HEADER + entry.0.len() + entry.1.len();
+        let Some(at) = chain.iter().position(|bucket| bucket.size() + size <= self.capacity()) else {
+            return Ok(false);
+        };
+        chain[at].entries.push(entry.clone());
+        if at == 0 {
+            encode(&chain[0], &mut page);
+        } else {
+            self.write_bucket(&chain[at])?;
+        }
+        Ok(true)
+    }
+
+    /// Makes room in the bucket `entry` belongs in: splits it, doubling the
+    /// directory if need be, or gives it another overflow page when
+    /// splitting cannot separate its entries.
+    fn grow(&self, directory: &mut Directory, entry: &Entry) -> Result<(), Error> {
+        let hash = hash(&entry.0);
+        let slot = directory.slot(hash);
+        let primary = directory.slots[slot];
+        let chain = self.chain(self.read_bucket(primary)?)?;
+        let depth = chain[0].depth;
+        let mask = (1u32 << MAX_DEPTH) - 1;
+        let entries = || chain.iter().flat_map(|bucket| &bucket.entries);
+        if depth == MAX_DEPTH || entries().all(|(k, _)| (self::hash(k) ^ hash) & mask == 0) {
+            let overflow = self.pool.new_page(PageType::HashBucket)?.id();
+            self.write_bucket(&Bucket::new(overflow, depth))?;
+            let mut last = chain.last().unwrap().clone();
+            last.next = overflow;
+            return self.write_bucket(&last);
+        }
+
+        let mut changed = Vec::new();
+        if depth == directory.depth {
+            changed.extend(directory.slots.len()..directory.slots.len() * 2);
+            directory.slots.extend_from_within(..);
+            directory.depth += 1;
+        }
+        let bit = 1 << depth;
+        let sibling = self.pool.new_page(PageType::HashBucket)?.id();
+        let (ones, zeros): (Vec<Entry>, Vec<Entry>) =
+            entries().cloned().partition(|(k, _)| self::hash(k) as usize & bit != 0);
+        for bucket in &chain[1..] {
+            self.free(bucket.id)?;
+        }
+        self.store_chain(primary, depth + 1, zeros)?;
+        self.store_chain(sibling, depth + 1, ones)?;
+        for i in ((slot & (bit - 1)) | bit..directory.slots.len()).step_by(bit * 2) {
+            directory.slots[i] = sibling;
+            changed.push(i);
+        }
+        self.store_directory(directory, changed)
+    }
+
+    /// Tidies up the bucket of `hash` after a delete emptied one of its
+    /// pages: packs its entries into fewer pages, or if it is empty merges
+    /// it into its buddy, then halves the directory while it can.
+    fn shrink(&self, directory: &mut Directory, hash: u32) -> Result<(), Error> {
+        let slot = directory.slot(hash);
+        let primary = directory.slots[slot];
+        let chain = self.chain(self.read_bucket(primary)?)?;
+        let depth = chain[0].depth;
+        if chain[1..].iter().any(|bucket| bucket.entries.is_empty()) {
+            for bucket in &chain[1..] {
+                self.free(bucket.id)?;
+            }
+            return self.store_chain(primary, depth, chain.into_iter().flat_map(|bucket| bucket.entries).collect());
+        }
+        // Merge pairs of buddies one of which is empty, for as long as the
+        // bucket left over has an empty buddy too.
+        let mut slot = slot;
+        let mut bucket = chain.into_iter().next().unwrap();
+        let mut changed = Vec::new();
+        while bucket.depth > 0 {
+            let depth = bucket.depth;
+            let buddy_slot = slot ^ (1 << (depth - 1));
+            let buddy = self.read_bucket(directory.slots[buddy_slot])?;
+            let lone = |bucket: &Bucket| bucket.entries.is_empty() && bucket.next == 0;
+            if buddy.depth != depth || !(lone(&bucket) || lone(&buddy)) {
+                break;
+            }
+            let (mut kept, gone, gone_slot) =
+                if lone(&bucket) { (buddy, bucket, slot) } else { (bucket, buddy, buddy_slot) };
+            kept.depth -= 1;
+            self.write_bucket(&kept)?;
+            let stride = 1 << depth;
+            for i in (gone_slot & (stride - 1)..directory.slots.len()).step_by(stride) {
+                directory.slots[i] = kept.id;
+                changed.push(i);
+            }
+            self.free(gone.id)?;
+            slot &= (stride >> 1) - 1;
+            bucket = kept;
+        }
+        while directory.depth > 0 {
+            let half = directory.slots.len() / 2;
+            if (0..half).any(|i| directory.slots[i] != directory.slots[i + half]) {
+                break;
+            }
+            directory.slots.truncate(half);
+            directory.depth -= 1;
+        }
+        changed.retain(|&i| i < directory.slots.len());
+        self.store_directory(directory, changed)
+    }
+
+    /// A bucket's pages, starting from its first.
+    fn chain(&self, first: Bucket) -> Result<Vec<Bucket>, Error> {
+        let mut chain = vec![first];
+        loop {
+            let next = chain.last().unwrap().next;
+            if next == 0 {
+                return Ok(chain);
+            }
+            chain.push(self.read_bucket(next)?);
+        }
+    }
+
+    /// Writes `entries` as a bucket starting at page `first`, adding
+    /// overflow pages if they do not fit in one.
+    fn store_chain(&self, first: PageId, depth: u8, entries: Vec<Entry>) -> Result<(), Error> {
+        let mut chain = vec![Bucket::new(first, depth)];
+        for entry in entries {
+            let size = ENTRY_HEADER + entry.0.len() + entry.1.len();
+            if chain.last().unwrap().size() + size > self.capacity() {
+                let overflow = self.pool.new_page(PageType::HashBucket)?.id();
+                chain.last_mut().unwrap().next = overflow;
+                chain.push(Bucket::new(overflow, depth));
+            }
+            chain.last_mut().unwrap().entries.push(entry);
+        }
+        chain.iter().try_for_each(|bucket| self.write_bucket(bucket))
+    }
+
+    /// Writes the meta page and the directory pages holding `changed`
+    /// slots, first adding or freeing pages to fit the directory's size.
+    fn store_directory(
+        &self,
+        directory: &mut Directory,
+        changed: impl IntoIterator<Item = usize>,
+    ) -> Result<(), Error> {
+        let per_page = self.capacity() / 8;
+        let needed = directory.slots.len().div_ceil(per_page);
+        let mut dirty: BTreeSet<usize> = changed.into_iter().map(|slot| slot / per_page).collect();
+        let old = directory.pages.len();
+        if old > needed {
+            for id in directory.pages.drain(needed..) {
+                self.free(id)?;
+            }
+            dirty.insert(needed - 1);
+        }
+        while directory.pages.len() < needed {
+            directory.pages.push(self.pool.new_page(PageType::HashDirectory)?.id());
+        }
+        dirty.extend(old.saturating_sub(1)..needed);
+
+        for at in dirty {
+            let guard = self.pool.fetch(directory.pages[at])?;
+            let mut page = guard.write();
+            page.header.page_type = PageType::HashDirectory;
+            page.header.next = directory.pages.get(at + 1).copied().unwrap_or(0);
+            for (i, bucket) in directory.slots.iter().skip(at * per_page).take(per_page).enumerate() {
+                page.data[i * 8..i * 8 + 8].copy_from_slice(&bucket.to_le_bytes());
+            }
+        }
+        let guard = self.pool.fetch(self.meta)?;
+        let mut page = guard.write();
+        page.data[0] = directory.depth;
+        page.data[1] = self.unique as u8;
+        page.header.next = directory.pages[0];
+        Ok(())
+    }
+
+    fn read_bucket(&self, id: PageId) -> Result<Bucket, Error> {
+        decode(&self.pool.fetch(id)?.read())
+    }
+
+    fn write_bucket(&self, bucket: &Bucket) -> Result<(), Error> {
+        let pin = self.pool.fetch(bucket.id)?;
+        encode(bucket, &mut pin.write());
+        Ok(())
+    }
+
+    fn free(&self, id: PageId) -> Result<(), Error> {
+        if !self.pool.try_free_page(id)? {
+            self.garbage.lock().push(id);
+        }
+        Ok(())
+    }
+
+    fn collect_garbage(&self) -> Result<(), Error> {
+        let pending = std::mem::take(&mut *self.garbage.lock());
+        for id in pending {
+            self.free(id)?;
+        }
+        Ok(())
+    }
+}
+
+fn hash(key: &[u8]) -> u32 {
+    crc32c::crc32c(key)
+}
+
+fn decode(page: &Page) -> Result<Bucket, Error> {
+    let id = page.header.page_id;
+    if page.header.page_type != PageType::HashBucket {
+        return Err(Error::Storage(format!(
+            "page {} is a {:?} page, not a hash bucket",
+            id, page.header.page_type
+        )));
+    }
+    let data = &page.data;
+    let u16_at = |at: usize| u16::from_le_bytes(data[at..at + 2].try_into().unwrap()) as usize;
+
+    let mut bucket = Bucket::new(id, data[2]);
+    bucket.next = page.header.next;
+    let mut at = BUCKET_HEADER;
+    for _ in 0..u16_at(0) {
+        let (key_len, value_len) = (u16_at(at), u16_at(at + 2));
+        at += ENTRY_HEADER;
+        let key = data[at..at + key_len].to_vec();
+        at += key_len;
+        bucket.entries.push((key, data[at..at + value_len].to_vec()));
+        at += value_len;
+    }
+    Ok(bucket)
+}
+
+fn encode(bucket: &Bucket, page: &mut Page) {
+    page.header.page_type = PageType::HashBucket;
+    page.header.next = bucket.next;
+    let data = &mut page.data;
+    data[0..2].copy_from_slice(&(bucket.entries.len() as u16).to_le_bytes());
+    data[2] = bucket.depth;
+    let mut at = BUCKET_HEADER;
+    for (key, value) in &bucket.entries {
+        data[at..at + 2].copy_from_slice(&(key.len() as u16).to_le_bytes());
+        data[at + 2..at + 4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        at += ENTRY_HEADER;
+        data[at..at + key.len()].copy_from_slice(key);
+        at += key.len();
+        data[at..at + value.len()].copy_from_slice(value);
+        at += value.len();
+    }
+    page.header.free_space = (data.len() - at) as u16;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Pager, PagerOptions};
+    use std::collections::HashMap;
+
+    fn pool(dir: &tempfile::TempDir) -> Arc<BufferPool> {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        Arc::new(BufferPool::new(pager, 32))
+    }
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("session{:06}", i).into_bytes()
+    }
+
+    /// Checks that every slot leads to a bucket whose local depth it
+    /// agrees with and whose entries hash there, returning every entry.
+    fn check(index: &HashIndex) -> Vec<Entry> {
+        let directory = index.directory.read();
+        assert_eq!(directory.slots.len(), 1 << directory.depth);
+        let mut seen = HashMap::new();
+        for (slot, &id) in directory.slots.iter().enumerate() {
+            let chain = index.chain(index.read_bucket(id).unwrap()).unwrap();
+            let depth = chain[0].depth;
+            assert!(depth <= directory.depth);
+            let first = *seen.entry(id).or_insert(slot);
+            assert_eq!(first & ((1 << depth) - 1), slot & ((1 << depth) - 1), "slot {} shares bucket {}", slot, id);
+            if first == slot {
+                for (k, _) in chain.iter().flat_map(|bucket| &bucket.entries) {
+                    assert_eq!(hash(k) as usize & ((1 << depth) - 1), slot & ((1 << depth) - 1));
+                }
+            }
+        }
+        // Buckets are counted once, by the first slot pointing at them.
+        let mut entries = Vec::new();
+        for (&id, _) in seen.iter() {
+            for bucket in index.chain(index.read_bucket(id).unwrap()).unwrap() {
+                entries.extend(bucket.entries);
+            }
+        }
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_grows_splits_and_shrinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = pool(&dir);
+        let index = HashIndex::create(pool.clone(), true).unwrap();
+        for i in 0..2000 {
+            index.insert(&key(i), &i.to_le_bytes()).unwrap();
+        }
+        // 512-byte pages hold about twenty of these entries.
+        assert!(index.depth() >= 6, "depth {}", index.depth());
+        assert_eq!(check(&index).len(), 2000);
+        assert_eq!(index.get(&key(1234)).unwrap(), Some(1234u32.to_le_bytes().to_vec()));
+        assert_eq!(index.get(b"missing").unwrap(), None);
+        let err = index.insert(&key(7), b"other").unwrap_err();
+        assert!(err.to_string().contains("duplicate key"));
+
+        // Reopening reads the directory back.
+        let meta = index.meta();
+        drop(index);
+        let index = HashIndex::open(pool, meta).unwrap();
+        assert_eq!(index.get(&key(1999)).unwrap(), Some(1999u32.to_le_bytes().to_vec()));
+
+        for i in 0..2000 {
+            assert!(index.delete(&key(i), &i.to_le_bytes()).unwrap());
+        }
+        assert!(!index.delete(&key(0), &0u32.to_le_bytes()).unwrap());
+        assert!(check(&index).is_empty());
+        assert_eq!(index.depth(), 0);
+    }
+
+    #[test]
+    fn test_duplicates_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = HashIndex::create(pool(&dir), false).unwrap();
+        for i in 0..100u32 {
+            index.insert(b"hot", &i.to_le_bytes()).unwrap();
+            index.insert(&key(i), &i.to_le_bytes()).unwrap();
+        }
+        let err = index.insert(b"hot", &5u32.to_le_bytes()).unwrap_err();
+        assert!(err.to_string().contains("already in the index"));
+        // Every copy of a key stays in one bucket, whatever its size.
+        let mut values = index.get_all(b"hot").unwrap();
+        values.sort();
+        assert_eq!(values, (0..100u32).map(|i| i.to_le_bytes().to_vec()).collect::<Vec<_>>());
+        assert_eq!(check(&index).len(), 200);
+
+        for i in (0..100u32).step_by(2) {
+            assert!(index.delete(b"hot", &i.to_le_bytes()).unwrap());
+        }
+        assert_eq!(index.get_all(b"hot").unwrap().len(), 50);
+        assert_eq!(check(&index).len(), 150);
+
+        let big = vec![0; index.max_entry_size() + 1];
+        assert!(index.insert(&big, b"").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_inserts_and_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let index = Arc::new(HashIndex::create(Arc::new(BufferPool::new(pager, 256)), true).unwrap());
+        let mut tasks = Vec::new();
+        for t in 0..4u32 {
+            let index = index.clone();
+            tasks.push(tokio::task::spawn_blocking(move || {
+                for i in (t..2000).step_by(4) {
+                    index.insert(&key(i), &i.to_le_bytes()).unwrap();
+                    assert_eq!(index.get(&key(i)).unwrap(), Some(i.to_le_bytes().to_vec()));
+                    if i % 3 == 0 {
+                        assert!(index.delete(&key(i), &i.to_le_bytes()).unwrap());
+                    }
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let expected = (0..2000u32).filter(|i| i % 3 != 0).count();
+        assert_eq!(check(&index).len(), expected);
+    }
+}
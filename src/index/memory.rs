@@ -0,0 +1,95 @@
+// src/index/memory.rs
+//! An index kept in memory, for tables that live in memory.
+//!
+//! Entries are held in order whatever the index's method, with the same
+//! uniqueness rules as the on-disk structures: a unique index rejects a
+//! second entry with the same key, any index the same key and value.
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use parking_lot::RwLock;
+
+use crate::error::Error;
+use crate::index::Entry;
+
+#[derive(Debug, Default)]
+pub struct MemoryIndex {
+    unique: bool,
+    entries: RwLock<BTreeSet<Entry>>,
+}
+
+impl MemoryIndex {
+    pub fn new(unique: bool) -> Self {
+        MemoryIndex { unique, entries: RwLock::new(BTreeSet::new()) }
+    }
+
+    pub fn unique(&self) -> bool {
+        self.unique
+    }
+
+    /// Adds an entry. Fails if a unique index already has the key, or any
+    /// index already has the same key and value.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let mut entries = self.entries.write();
+        let entry = (key.to_vec(), value.to_vec());
+        if self.unique && entries.range((key.to_vec(), Vec::new())..).next().is_some_and(|(k, _)| k == key) {
+            return Err(Error::Execution(format!("duplicate key {:?} in unique index", key)));
+        }
+        if !entries.insert(entry.clone()) {
+            return Err(Error::Execution(format!("entry {:?} is already in the index", entry)));
+        }
+        Ok(())
+    }
+
+    /// Removes the entry with this key and value, returning whether there
+    /// was one.
+    pub fn delete(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        Ok(self.entries.write().remove(&(key.to_vec(), value.to_vec())))
+    }
+
+    /// The entries with keys between `lower` and `upper`, in order.
+    pub fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Vec<Entry> {
+        let start = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => Bound::Included((key.to_vec(), Vec::new())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.entries
+            .read()
+            .range((start, Bound::Unbounded))
+            .skip_while(|(k, _)| matches!(lower, Bound::Excluded(key) if k.as_slice() == key))
+            .take_while(|(k, _)| match upper {
+                Bound::Included(key) => k.as_slice() <= key,
+                Bound::Excluded(key) => k.as_slice() < key,
+                Bound::Unbounded => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges_and_uniqueness() {
+        let index = MemoryIndex::new(false);
+        for (key, value) in [("b", "1"), ("a", "1"), ("b", "2"), ("c", "1")] {
+            index.insert(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        assert!(index.insert(b"b", b"2").is_err());
+        let keys = |entries: Vec<Entry>| -> Vec<String> {
+            entries.into_iter().map(|(k, v)| String::from_utf8([k, v].concat()).unwrap()).collect()
+        };
+        assert_eq!(keys(index.range(Bound::Excluded(b"a"), Bound::Included(b"b"))), vec!["b1", "b2"]);
+        assert_eq!(keys(index.range(Bound::Unbounded, Bound::Excluded(b"b"))), vec!["a1"]);
+        assert!(index.delete(b"b", b"1").unwrap());
+        assert!(!index.delete(b"b", b"1").unwrap());
+
+        let unique = MemoryIndex::new(true);
+        unique.insert(b"k", b"1").unwrap();
+        let err = unique.insert(b"k", b"2").unwrap_err();
+        assert!(err.to_string().contains("duplicate key"));
+    }
+}
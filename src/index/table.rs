@@ -0,0 +1,445 @@
+// src/index/table.rs
+//! Keeping an index in step with the rows of a table.
+//!
+//! An index key is the [`crate::types::key`] encoding of the values the
+//! index's field paths pick out of a row: whole columns, or values nested in
+//! struct and array columns (`phone.country_code`, `tags[0]`). A path that
+//! finds nothing yields NULL. Each entry maps a key to the row's
+//! [`RecordId`].
+//!
+//! As in SQL, NULLs never collide in a unique index: a key holding a NULL
+//! gets the record id appended, which sets it apart from every other key.
+//!
+//! A B+tree index can be scanned by any [`KeyRange`]; a hash index only by
+//! one pinning down every key column, wherever its entries are kept.
+
+use std::ops::Bound;
+
+use crate::catalog::{IndexSchema, TableSchema};
+use crate::error::Error;
+use crate::index::btree::{prefix_end, BTree};
+use crate::index::hash::HashIndex;
+use crate::index::memory::MemoryIndex;
+use crate::parser::ast::{IndexMethod, PathStep};
+use crate::storage::{PageId, RecordId};
+use crate::types::key::encode_key;
+use crate::types::Value;
+
+/// The keys an index scan visits: those equal to `eq` on the leading key
+/// columns and, on the column after them, between `lower` and `upper`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub eq: Vec<Value>,
+    pub lower: Bound<Value>,
+    pub upper: Bound<Value>,
+}
+
+impl KeyRange {
+    pub fn eq(values: Vec<Value>) -> Self {
+        KeyRange { eq: values, lower: Bound::Unbounded, upper: Bound::Unbounded }
+    }
+
+    /// The range as bounds on encoded keys. Keys have more columns than
+    /// the range constrains, so bounds that take in a value also take in
+    /// every key starting with it.
+    fn bounds(&self) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let prefix = encode_key(&self.eq, &[]);
+        let with = |value: &Value| {
+            let mut key = prefix.clone();
+            key.extend(encode_key(std::slice::from_ref(value), &[]));
+            key
+        };
+        let after = |key: Vec<u8>| match prefix_end(&key) {
+            Some(end) => Bound::Included(end),
+            None => Bound::Excluded(key),
+        };
+        let before_end = |key: &[u8]| prefix_end(key).map_or(Bound::Unbounded, Bound::Excluded);
+
+        let lower = match &self.lower {
+            Bound::Included(value) => Bound::Included(with(value)),
+            Bound::Excluded(value) => after(with(value)),
+            // NULL sorts first and is in no range.
+            Bound::Unbounded if !matches!(self.upper, Bound::Unbounded) => after(with(&Value::Null)),
+            Bound::Unbounded => Bound::Included(prefix.clone()),
+        };
+        let upper = match &self.upper {
+            Bound::Included(value) => before_end(&with(value)),
+            Bound::Excluded(value) => Bound::Excluded(with(value)),
+            Bound::Unbounded => before_end(&prefix),
+        };
+        (lower, upper)
+    }
+}
+
+/// The structure holding the entries of a [`TableIndex`].
+#[derive(Debug)]
+pub enum IndexTree {
+    BTree(BTree),
+    Hash(HashIndex),
+    /// Stands in for either method in memory.
+    Memory(MemoryIndex),
+}
+
+impl IndexTree {
+    /// The method the structure implements, `None` for one in memory.
+    pub fn method(&self) -> Option<IndexMethod> {
+        match self {
+            IndexTree::BTree(_) => Some(IndexMethod::BTree),
+            IndexTree::Hash(_) => Some(IndexMethod::Hash),
+            IndexTree::Memory(_) => None,
+        }
+    }
+
+    pub fn unique(&self) -> bool {
+        match self {
+            IndexTree::BTree(tree) => tree.options().unique,
+            IndexTree::Hash(index) => index.unique(),
+            IndexTree::Memory(index) => index.unique(),
+        }
+    }
+
+    /// The page to open the structure from, `None` for one in memory.
+    pub fn meta(&self) -> Option<PageId> {
+        match self {
+            IndexTree::BTree(tree) => Some(tree.meta()),
+            IndexTree::Hash(index) => Some(index.meta()),
+            IndexTree::Memory(_) => None,
+        }
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        match self {
+            IndexTree::BTree(tree) => tree.insert(key, value),
+            IndexTree::Hash(index) => index.insert(key, value),
+            IndexTree::Memory(index) => index.insert(key, value),
+        }
+    }
+
+    fn delete(&self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        match self {
+            IndexTree::BTree(tree) => tree.delete(key, value),
+            IndexTree::Hash(index) => index.delete(key, value),
+            IndexTree::Memory(index) => index.delete(key, value),
+        }
+    }
+
+    /// Frees the pages of the structure.
+    pub fn destroy(self) -> Result<(), Error> {
+        match self {
+            IndexTree::BTree(tree) => tree.destroy(),
+            IndexTree::Hash(index) => index.destroy(),
+            IndexTree::Memory(_) => Ok(()),
+        }
+    }
+}
+
+impl From<BTree> for IndexTree {
+    fn from(tree: BTree) -> Self {
+        IndexTree::BTree(tree)
+    }
+}
+
+impl From<HashIndex> for IndexTree {
+    fn from(index: HashIndex) -> Self {
+        IndexTree::Hash(index)
+    }
+}
+
+impl From<MemoryIndex> for IndexTree {
+    fn from(index: MemoryIndex) -> Self {
+        IndexTree::Memory(index)
+    }
+}
+
+/// The position in the row of each key column of `schema`, with the path
+/// into its value.
+fn key_columns(table: &TableSchema, schema: &IndexSchema) -> Result<Vec<(usize, Vec<PathStep>)>, Error> {
+    schema
+        .columns
+        .iter()
+        .map(|path| {
+            let column = table.column_index(&path.column).ok_or_else(|| {
+                Error::Execution(format!(
+                    "index {} is on column {}, which table {} does not have",
+                    schema.name, path.column, table.name
+                ))
+            })?;
+            Ok((column, path.steps.clone()))
+        })
+        .collect()
+}
+
+/// An index over the rows of one table.
+#[derive(Debug)]
+pub struct TableIndex {
+    schema: IndexSchema,
+    /// Position in the row and path into the value of each key column.
+    columns: Vec<(usize, Vec<PathStep>)>,
+    tree: IndexTree,
+}
+
+impl TableIndex {
+    /// Wraps `tree`, which must be of the index's method and unique
+    /// exactly when the index is.
+    pub fn new(table: &TableSchema, schema: IndexSchema, tree: impl Into<IndexTree>) -> Result<Self, Error> {
+        let tree = tree.into();
+        let columns = key_columns(table, &schema)?;
+        if let Some(method) = tree.method().filter(|&method| method != schema.method) {
+            return Err(Error::Storage(format!(
+                "index {} is a {} index but is stored as {}",
+                schema.name, schema.method, method
+            )));
+        }
+        if tree.unique() != schema.unique {
+            return Err(Error::Storage(format!(
+                "index {} is {}unique but its structure is not",
+                schema.name,
+                if schema.unique { "" } else { "not " }
+            )));
+        }
+        Ok(TableIndex { schema, columns, tree })
+    }
+
+    /// Checks that [`TableIndex::rebind`] would take `schema`.
+    pub fn check_rebind(&self, table: &TableSchema, schema: &IndexSchema) -> Result<(), Error> {
+        key_columns(table, schema)?;
+        if schema.method != self.schema.method || schema.unique != self.schema.unique {
+            return Err(Error::Execution(format!("index {} cannot change its method or uniqueness", schema.name)));
+        }
+        Ok(())
+    }
+
+    /// Keeps the entries under a new definition of the index or of its
+    /// table, such as one with columns renamed. The key columns must pick
+    /// out the same values as before; nothing checks that they do.
+    pub fn rebind(&mut self, table: &TableSchema, schema: IndexSchema) -> Result<(), Error> {
+        self.check_rebind(table, &schema)?;
+        self.columns = key_columns(table, &schema)?;
+        self.schema = schema;
+        Ok(())
+    }
+
+    pub fn schema(&self) -> &IndexSchema {
+        &self.schema
+    }
+
+    pub fn tree(&self) -> &IndexTree {
+        &self.tree
+    }
+
+    /// Frees the pages holding the index.
+    pub fn destroy(self) -> Result<(), Error> {
+        self.tree.destroy()
+    }
+
+    /// The values of the key columns of `row`.
+    pub fn key_values(&self, row: &[Value]) -> Vec<Value> {
+        self.columns
+            .iter()
+            .map(|(column, steps)| row.get(*column).map_or(Value::Null, |value| value.path(steps)))
+            .collect()
+    }
+
+    fn entry(&self, row: &[Value], rid: RecordId) -> (Vec<u8>, Vec<u8>) {
+        let values = self.key_values(row);
+        let mut key = encode_key(&values, &[]);
+        if self.schema.unique && values.iter().any(Value::is_null) {
+            key.extend_from_slice(&rid.to_bytes());
+        }
+        (key, rid.to_bytes().to_vec())
+    }
+
+    /// Adds the entry for a new row.
+    pub fn insert(&self, row: &[Value], rid: RecordId) -> Result<(), Error> {
+        let (key, value) = self.entry(row, rid);
+        self.tree.insert(&key, &value).map_err(|err| match err {
+            Error::Execution(_) if self.schema.unique => {
+                let values: Vec<String> = self.key_values(row).iter().map(Value::to_string).collect();
+                Error::Execution(format!(
+                    "duplicate key ({}) violates unique index {}",
+                    values.join(", "),
+                    self.schema.name
+                ))
+            }
+            err => err,
+        })
+    }
+
+    /// Removes the entry for a deleted row, returning whether it was there.
+    pub fn delete(&self, row: &[Value], rid: RecordId) -> Result<bool, Error> {
+        let (key, value) = self.entry(row, rid);
+        self.tree.delete(&key, &value)
+    }
+
+    /// Moves a row's entry after an update, if its key changed. On error
+    /// the index is left as it was.
+    pub fn update(&self, old: &[Value], new: &[Value], rid: RecordId) -> Result<(), Error> {
+        let (old_key, value) = self.entry(old, rid);
+        if self.entry(new, rid).0 == old_key {
+            return Ok(());
+        }
+        self.tree.delete(&old_key, &value)?;
+        if let Err(err) = self.insert(new, rid) {
+            self.tree.insert(&old_key, &value)?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// The record ids of the rows with keys in `range`, in key order for a
+    /// B+tree index.
+    pub fn scan(
+        &self,
+        range: &KeyRange,
+    ) -> Result<Box<dyn DoubleEndedIterator<Item = Result<RecordId, Error>> + '_>, Error> {
+        if self.schema.method == IndexMethod::Hash {
+            let pinned = range.eq.len() == self.columns.len()
+                && matches!((&range.lower, &range.upper), (Bound::Unbounded, Bound::Unbounded));
+            if !pinned {
+                return Err(Error::Execution(format!(
+                    "hash index {} can only look up all of its columns by equality",
+                    self.schema.name
+                )));
+            }
+        }
+        let rid = |value: Vec<u8>| RecordId::from_bytes(&value);
+        Ok(match &self.tree {
+            IndexTree::Hash(index) => {
+                let values = index.get_all(&encode_key(&range.eq, &[]))?;
+                Box::new(values.into_iter().map(move |value| Ok(rid(value))))
+            }
+            IndexTree::BTree(tree) => {
+                let (lower, upper) = range.bounds();
+                Box::new(
+                    tree.range(lower.as_ref().map(Vec::as_slice), upper.as_ref().map(Vec::as_slice))
+                        .map(move |entry| entry.map(|(_, value)| rid(value))),
+                )
+            }
+            IndexTree::Memory(index) => {
+                let (lower, upper) = range.bounds();
+                let entries = index.range(lower.as_ref().map(Vec::as_slice), upper.as_ref().map(Vec::as_slice));
+                Box::new(entries.into_iter().map(move |(_, value)| Ok(rid(value))))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::catalog::ColumnSchema;
+    use crate::index::BTreeOptions;
+    use crate::parser::ast::{DataType, FieldPath};
+    use crate::storage::{Pager, PagerOptions};
+    use std::sync::Arc;
+
+    fn phone(country_code: i64, area_code: Option<i64>) -> Value {
+        Value::Struct(vec![
+            ("country_code".to_string(), Value::Int(country_code)),
+            ("area_code".to_string(), area_code.map_or(Value::Null, Value::Int)),
+        ])
+    }
+
+    fn index(dir: &tempfile::TempDir, method: IndexMethod, unique: bool, columns: Vec<FieldPath>) -> TableIndex {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = Arc::new(BufferPool::new(pager, 32));
+        let table = TableSchema::new(
+            "users",
+            vec![ColumnSchema::new("id", DataType::BigInt), ColumnSchema::new("phone", DataType::Json)],
+        );
+        let schema = IndexSchema { name: "by_phone".into(), table: "users".into(), columns, unique, method };
+        let tree: IndexTree = match method {
+            IndexMethod::BTree => BTree::create(pool, BTreeOptions { unique, order: None }).unwrap().into(),
+            IndexMethod::Hash => HashIndex::create(pool, unique).unwrap().into(),
+        };
+        TableIndex::new(&table, schema, tree).unwrap()
+    }
+
+    fn field(column: &str, steps: &[&str]) -> FieldPath {
+        FieldPath { column: column.into(), steps: steps.iter().map(|s| PathStep::Field(s.to_string())).collect() }
+    }
+
+    fn rids(index: &TableIndex, range: KeyRange) -> Vec<u64> {
+        index.scan(&range).unwrap().map(|rid| rid.unwrap().page).collect()
+    }
+
+    #[test]
+    fn test_keys_from_struct_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let columns = vec![field("phone", &["country_code"]), field("phone", &["area_code"])];
+        let index = index(&dir, IndexMethod::BTree, false, columns);
+        let rows = [
+            vec![Value::Int(1), phone(44, Some(20))],
+            vec![Value::Int(2), phone(1, Some(415))],
+            vec![Value::Int(3), phone(44, None)],
+            // Rows read back from storage hold structs as JSON.
+            vec![Value::Int(4), Value::Json(serde_json::json!({"country_code": 44, "area_code": 161}))],
+            vec![Value::Int(5), Value::Null],
+        ];
+        for (i, row) in rows.iter().enumerate() {
+            index.insert(row, RecordId::new(i as u64 + 1, 0)).unwrap();
+        }
+        assert_eq!(index.key_values(&rows[3]), vec![Value::Int(44), Value::Int(161)]);
+        assert_eq!(rids(&index, KeyRange::eq(vec![Value::Int(44)])), vec![3, 1, 4]);
+        let range = KeyRange {
+            eq: vec![Value::Int(44)],
+            lower: Bound::Excluded(Value::Int(20)),
+            upper: Bound::Unbounded,
+        };
+        assert_eq!(rids(&index, range), vec![4]);
+        // NULLs are in no range.
+        let range = KeyRange {
+            eq: vec![Value::Int(44)],
+            lower: Bound::Unbounded,
+            upper: Bound::Included(Value::Int(20)),
+        };
+        assert_eq!(rids(&index, range), vec![1]);
+        let range = KeyRange { eq: vec![], lower: Bound::Unbounded, upper: Bound::Excluded(Value::Int(44)) };
+        assert_eq!(rids(&index, range), vec![2]);
+
+        // Updates move the entry only when the key changes.
+        let moved = vec![Value::Int(2), phone(44, Some(1))];
+        index.update(&rows[1], &moved, RecordId::new(2, 0)).unwrap();
+        assert_eq!(rids(&index, KeyRange::eq(vec![Value::Int(44), Value::Int(1)])), vec![2]);
+        assert!(index.delete(&moved, RecordId::new(2, 0)).unwrap());
+        assert!(!index.delete(&rows[1], RecordId::new(2, 0)).unwrap());
+        assert_eq!(rids(&index, KeyRange::eq(vec![])), vec![5, 3, 1, 4]);
+    }
+
+    #[test]
+    fn test_unique_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index(&dir, IndexMethod::BTree, true, vec![field("phone", &["country_code"])]);
+        index.insert(&[Value::Int(1), phone(44, None)], RecordId::new(1, 0)).unwrap();
+        let err = index.insert(&[Value::Int(2), phone(44, Some(20))], RecordId::new(2, 0)).unwrap_err();
+        assert_eq!(err.to_string(), "Execution error: duplicate key (44) violates unique index by_phone");
+
+        // Any number of rows may have no key.
+        index.insert(&[Value::Int(3), Value::Null], RecordId::new(3, 0)).unwrap();
+        index.insert(&[Value::Int(4), Value::Null], RecordId::new(4, 0)).unwrap();
+
+        // A failed update leaves the old entry in place.
+        let old = [Value::Int(3), Value::Null];
+        assert!(index.update(&old, &[Value::Int(3), phone(44, None)], RecordId::new(3, 0)).is_err());
+        assert_eq!(rids(&index, KeyRange::eq(vec![])), vec![3, 4, 1]);
+        assert!(index.delete(&old, RecordId::new(3, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_hash_index_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+        let columns = vec![field("phone", &["country_code"]), field("phone", &["area_code"])];
+        let index = index(&dir, IndexMethod::Hash, false, columns);
+        for (i, area_code) in [20, 161, 20].into_iter().enumerate() {
+            index.insert(&[Value::Int(i as i64), phone(44, Some(area_code))], RecordId::new(i as u64 + 1, 0)).unwrap();
+        }
+        let mut found = rids(&index, KeyRange::eq(vec![Value::Int(44), Value::Int(20)]));
+        found.sort();
+        assert_eq!(found, vec![1, 3]);
+        let err = index.scan(&KeyRange::eq(vec![Value::Int(44)])).err().unwrap();
+        assert!(err.to_string().contains("can only look up all of its columns by equality"));
+        assert!(index.delete(&[Value::Int(0), phone(44, Some(20))], RecordId::new(1, 0)).unwrap());
+        assert_eq!(rids(&index, KeyRange::eq(vec![Value::Int(44), Value::Int(20)])), vec![3]);
+    }
+}
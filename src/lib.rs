@@ -1,16 +1,31 @@
 // src/lib.rs
+pub mod analyzer;
+pub mod buffer;
+pub mod catalog;
+pub mod database;
+pub mod engine;
 pub mod error;
+pub mod eval;
+pub mod executor;
+pub mod index;
+pub mod migrate;
 pub mod parser;
+pub mod planner;
+pub mod row;
+pub mod storage;
 pub mod types;
-//pub mod executor;
+pub mod wal;
 
 use async_trait::async_trait;
 
 //pub mod connection;
 pub use error::Error;
+pub use row::{FromRow, ToRow};
+pub use rustdb_derive::{FromRow, FromValue, ToRow, ToValue};
+pub use types::{FromValue, ToValue, Value};
 
-use std::fmt;
-use async_trait::async_trait;
+// Lets the derive macros refer to `::rustdb` from inside this crate too.
+extern crate self as rustdb;
 
 // Core traits and types
 #[async_trait]
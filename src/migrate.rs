@@ -0,0 +1,431 @@
+// src/migrate.rs
+//! Schema migrations, kept as versioned SQL files in a directory.
+//!
+//! A migration is a file named `<version>_<name>.sql` (or `.up.sql`), with
+//! an optional `<version>_<name>.down.sql` that undoes it. Versions are
+//! integers, unique within the directory, and migrations run in version
+//! order. Each file is a script of statements separated by semicolons.
+//!
+//! The catalog records every applied migration with the checksum of its
+//! files, in the same catalog transaction as the migration's changes, so a
+//! migration is applied entirely or not at all. An applied migration whose
+//! files have since changed or gone is drift, and nothing runs until it is
+//! resolved. A dry run applies the migrations in one transaction and rolls
+//! it back, which checks them against the data without changing anything.
+//!
+//! Migrations hold `CREATE TABLE`, `DROP TABLE`, `CREATE INDEX`,
+//! `ALTER TABLE`, `CREATE TYPE` and `ALTER TYPE`, and `INSERT`, `UPDATE`
+//! and `DELETE` to fill in or fix up rows, run through
+//! [`CatalogTransaction::execute`].
+//! Rows changed by a migration that fails are put back, but other
+//! statements see them in the meantime. A migration cannot change the rows
+//! of a table it alters.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+
+use crate::catalog::{AppliedMigration, CatalogTransaction, SystemCatalog};
+use crate::error::Error;
+use crate::parser::ast::Statement;
+use crate::parser::parse_statements;
+
+/// A migration as read from its files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: i64,
+    /// The name of the up file, without the extension.
+    pub name: String,
+    pub up: String,
+    pub down: Option<String>,
+    /// The CRC-32C of the up file followed by the down file, if any.
+    pub checksum: u32,
+}
+
+/// How a migration stands against the catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    Applied,
+    /// Applied, but its file has changed since.
+    Changed,
+    /// Applied, but its file is gone.
+    Missing,
+}
+
+/// Reads the migrations in a directory, in version order. Files not ending
+/// in `.sql` are ignored.
+pub fn read_dir(dir: impl AsRef<Path>) -> Result<Vec<Migration>, Error> {
+    let mut ups: Vec<(i64, String, String)> = Vec::new();
+    let mut downs: Vec<(String, String)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file) = path.file_name().and_then(|f| f.to_str()) else { continue };
+        let Some(stem) = file.strip_suffix(".sql") else { continue };
+        let (stem, down) = match stem.strip_suffix(".down") {
+            Some(stem) => (stem, true),
+            None => (stem.strip_suffix(".up").unwrap_or(stem), false),
+        };
+        let version = stem
+            .split_once('_')
+            .and_then(|(version, _)| version.parse::<i64>().ok())
+            .ok_or_else(|| Error::Execution(format!("migration file {} is not named <version>_<name>.sql", file)))?;
+        let sql = fs::read_to_string(&path)?;
+        if down {
+            downs.push((stem.to_string(), sql));
+        } else {
+            ups.push((version, stem.to_string(), sql));
+        }
+    }
+    ups.sort_by_key(|(version, _, _)| *version);
+    if let Some(pair) = ups.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(Error::Execution(format!("migrations {} and {} have the same version", pair[0].1, pair[1].1)));
+    }
+    if let Some((name, _)) = downs.iter().find(|(name, _)| !ups.iter().any(|(_, up, _)| up == name)) {
+        return Err(Error::Execution(format!("down migration {} has no up migration", name)));
+    }
+    Ok(ups
+        .into_iter()
+        .map(|(version, name, up)| {
+            let down = downs.iter().find(|(n, _)| *n == name).map(|(_, sql)| sql.clone());
+            Migration { version, checksum: checksum(&up, down.as_deref()), name, up, down }
+        })
+        .collect())
+}
+
+/// The files' lengths go first, so that moving a statement from the end of
+/// the up file to the start of the down file changes the checksum.
+fn checksum(up: &str, down: Option<&str>) -> u32 {
+    let lengths = [up.len(), down.map_or(0, str::len)].map(|n| (n as u64).to_le_bytes()).concat();
+    let checksum = crc32c::crc32c_append(crc32c::crc32c(&lengths), up.as_bytes());
+    crc32c::crc32c_append(checksum, down.unwrap_or_default().as_bytes())
+}
+
+/// Applies and reverts the migrations of a directory.
+#[derive(Debug)]
+pub struct Migrator<'a> {
+    catalog: &'a SystemCatalog,
+    migrations: Vec<Migration>,
+    dry_run: bool,
+}
+
+impl<'a> Migrator<'a> {
+    pub fn new(catalog: &'a SystemCatalog, dir: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Migrator { catalog, migrations: read_dir(dir)?, dry_run: false })
+    }
+
+    /// Makes [`Migrator::up`] and [`Migrator::down`] roll back what they do.
+    /// As a dry run is one catalog transaction, it fails where migrations
+    /// do together what a transaction cannot, such as indexing a table an
+    /// earlier migration altered.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Every migration, read or applied, by name in version order.
+    pub fn status(&self) -> Vec<(String, Status)> {
+        let snapshot = self.catalog.snapshot();
+        let applied = snapshot.migrations();
+        let mut status: Vec<(i64, String, Status)> = self
+            .migrations
+            .iter()
+            .map(|m| {
+                let status = match applied.iter().find(|a| a.name == m.name) {
+                    None => Status::Pending,
+                    Some(a) if a.checksum == m.checksum => Status::Applied,
+                    Some(_) => Status::Changed,
+                };
+                (m.version, m.name.clone(), status)
+            })
+            .collect();
+        for a in applied {
+            if !self.migrations.iter().any(|m| m.name == a.name) {
+                status.push((a.version, a.name.clone(), Status::Missing));
+            }
+        }
+        status.sort_by_key(|(version, _, _)| *version);
+        status.into_iter().map(|(_, name, status)| (name, status)).collect()
+    }
+
+    /// Fails if an applied migration has changed or gone.
+    pub fn check(&self) -> Result<(), Error> {
+        match self.status().into_iter().find(|(_, status)| matches!(status, Status::Changed | Status::Missing)) {
+            Some((name, Status::Changed)) => {
+                Err(Error::Execution(format!("migration {} has changed since it was applied", name)))
+            }
+            Some((name, _)) => Err(Error::Execution(format!("migration {} was applied but its file is gone", name))),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies the pending migrations up to and including version `to`, or
+    /// all of them, each in its own transaction. Returns their names.
+    pub fn up(&self, to: Option<i64>) -> Result<Vec<String>, Error> {
+        self.check()?;
+        let status = self.status();
+        let pending: Vec<&Migration> = self
+            .migrations
+            .iter()
+            .filter(|m| to.is_none_or(|to| m.version <= to))
+            .filter(|m| status.iter().any(|(name, status)| *name == m.name && *status == Status::Pending))
+            .collect();
+        self.run(&pending, |txn, m| {
+            apply(txn, &m.name, &m.up)?;
+            txn.record_migration(AppliedMigration {
+                name: m.name.clone(),
+                version: m.version,
+                checksum: m.checksum,
+                applied_at: Utc::now(),
+            })
+        })
+    }
+
+    /// Reverts the applied migrations after version `to`, latest first and
+    /// each in its own transaction. Returns their names.
+    pub fn down(&self, to: i64) -> Result<Vec<String>, Error> {
+        self.check()?;
+        let snapshot = self.catalog.snapshot();
+        let mut applied: Vec<&Migration> = snapshot
+            .migrations()
+            .into_iter()
+            .filter(|a| a.version > to)
+            .filter_map(|a| self.migrations.iter().find(|m| m.name == a.name))
+            .collect();
+        applied.reverse();
+        if let Some(m) = applied.iter().find(|m| m.down.is_none()) {
+            return Err(Error::Execution(format!("migration {} has no down migration", m.name)));
+        }
+        self.run(&applied, |txn, m| {
+            apply(txn, &format!("{}.down", m.name), m.down.as_deref().unwrap_or_default())?;
+            txn.forget_migration(&m.name)?;
+            Ok(())
+        })
+    }
+
+    /// Runs `step` for each migration in a transaction of its own, or for
+    /// all of them in one that is rolled back on a dry run.
+    fn run(
+        &self,
+        migrations: &[&Migration],
+        step: impl Fn(&mut CatalogTransaction<'_>, &Migration) -> Result<(), Error>,
+    ) -> Result<Vec<String>, Error> {
+        if self.dry_run {
+            let mut txn = self.catalog.begin();
+            for m in migrations {
+                step(&mut txn, m)?;
+            }
+            txn.rollback()?;
+        } else {
+            for m in migrations {
+                let mut txn = self.catalog.begin();
+                step(&mut txn, m)?;
+                txn.commit()?;
+            }
+        }
+        Ok(migrations.iter().map(|m| m.name.clone()).collect())
+    }
+}
+
+/// Runs the statements of a migration file. Errors name the file.
+fn apply(txn: &mut CatalogTransaction<'_>, file: &str, sql: &str) -> Result<(), Error> {
+    let in_file = |err: Error| {
+        let message = |message: String| format!("in migration {}: {}", file, message);
+        match err {
+            Error::Syntax(m) => Error::Syntax(message(m)),
+            Error::Type(m) => Error::Type(message(m)),
+            Error::Connection(m) => Error::Connection(message(m)),
+            Error::Execution(m) => Error::Execution(message(m)),
+            Error::Transaction(m) => Error::Transaction(message(m)),
+            Error::Storage(m) => Error::Storage(message(m)),
+        }
+    };
+    for statement in parse_statements(sql).map_err(in_file)? {
+        execute(txn, &statement).map_err(in_file)?;
+    }
+    Ok(())
+}
+
+fn execute(txn: &mut CatalogTransaction<'_>, statement: &Statement) -> Result<(), Error> {
+    match statement {
+        Statement::Create(create) if create.temporary => {
+            let name = &create.table.name;
+            Err(Error::Execution(format!("temporary table {} cannot outlive a migration", name)))
+        }
+        Statement::Create(_)
+        | Statement::Drop(_)
+        | Statement::CreateIndex(_)
+        | Statement::Alter(_)
+        | Statement::CreateType(_)
+        | Statement::AlterType(_)
+        | Statement::Insert(_)
+        | Statement::Update(_)
+        | Statement::Delete(_) => txn.execute(statement),
+        _ => Err(Error::Execution("migrations can only change the schema and rows".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::database::{Database, DatabaseOptions};
+    use crate::types::Value;
+
+    fn write(dir: &Path, file: &str, sql: &str) {
+        fs::write(dir.join(file), sql).unwrap();
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_migrations_apply_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("migrations");
+        fs::create_dir(&path).unwrap();
+        write(&path, "001_users.up.sql", "-- people\nCREATE TABLE users (id INT PRIMARY KEY, email TEXT);");
+        write(&path, "001_users.down.sql", "DROP TABLE users;");
+        write(&path, "002_email_index.sql", "CREATE UNIQUE INDEX users_email ON users (email);\n");
+        write(&path, "README.md", "Not a migration.");
+        let options = DatabaseOptions::disk(dir.path().join("db"));
+        {
+            let db = Database::open(options.clone()).unwrap();
+            let migrator = Migrator::new(db.catalog(), &path).unwrap();
+            let pending = vec![("001_users".into(), Status::Pending), ("002_email_index".into(), Status::Pending)];
+            assert_eq!(migrator.status(), pending);
+            assert_eq!(migrator.up(Some(1)).unwrap(), names(&["001_users"]));
+            assert_eq!(migrator.up(None).unwrap(), names(&["002_email_index"]));
+            assert!(migrator.up(None).unwrap().is_empty());
+        }
+
+        write(&path, "003_age.sql", "ALTER TABLE users ADD COLUMN age INT NOT NULL DEFAULT 18");
+        let db = Database::open(options).unwrap();
+        assert_eq!(db.catalog().indexes("users")[0].name, "users_email");
+        let migrator = Migrator::new(db.catalog(), &path).unwrap();
+        assert_eq!(migrator.migrations()[0].down.as_deref(), Some("DROP TABLE users;"));
+        assert_eq!(migrator.up(None).unwrap(), names(&["003_age"]));
+        assert!(db.catalog().table("users").unwrap().column("age").is_some());
+        let applied = db.catalog().snapshot().migrations().into_iter().map(|m| m.version).collect::<Vec<_>>();
+        assert_eq!(applied, vec![1, 2, 3]);
+
+        write(&path, "3_age.sql", "");
+        assert!(Migrator::new(db.catalog(), &path).unwrap_err().to_string().contains("same version"));
+        fs::remove_file(path.join("3_age.sql")).unwrap();
+        write(&path, "age.sql", "");
+        assert!(Migrator::new(db.catalog(), &path).is_err());
+    }
+
+    #[test]
+    fn test_failed_migration_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "1_t.sql", "CREATE TABLE t (id INT);");
+        write(dir.path(), "2_broken.sql", "CREATE TABLE u (id INT);\n\nCREATE TABLE v (id INT) oops;");
+        write(dir.path(), "3_rows.sql", "CREATE TABLE w (id INT); INSERT INTO w VALUES (1); SELECT * FROM w;");
+        let db = Database::open(DatabaseOptions::memory()).unwrap();
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        let err = migrator.up(None).unwrap_err();
+        assert!(matches!(err, Error::Syntax(_)));
+        assert!(err.to_string().contains("in migration 2_broken") && err.to_string().contains("line 3"), "{}", err);
+        assert!(db.catalog().table("t").is_some());
+        assert!(db.catalog().table("u").is_none());
+        assert_eq!(migrator.status()[1], ("2_broken".into(), Status::Pending));
+
+        write(dir.path(), "2_broken.sql", "CREATE TABLE u (id INT);");
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        let err = migrator.up(None).unwrap_err().to_string();
+        assert!(err.contains("can only change the schema and rows"), "{}", err);
+        assert!(db.catalog().table("u").is_some());
+        assert!(db.catalog().table("w").is_none());
+        assert!(db.engine().table("w").is_none());
+
+        // Rows changed before a statement fails are put back.
+        let sql = "INSERT INTO t VALUES (1), (2); UPDATE t SET id = 3 WHERE id = 1;\n\
+                   CREATE TABLE w (id INT PRIMARY KEY); INSERT INTO w VALUES (1);\n\
+                   DELETE FROM t WHERE id = 2; INSERT INTO w VALUES (1);";
+        write(dir.path(), "3_rows.sql", sql);
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert!(migrator.up(None).unwrap_err().to_string().contains("duplicate key (1)"));
+        assert!(db.engine().table("w").is_none());
+        assert!(db.engine().table("t").unwrap().scan().next().is_none());
+
+        write(dir.path(), "3_rows.sql", &sql.replacen("INSERT INTO w VALUES (1);", "", 1));
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert_eq!(migrator.up(None).unwrap(), names(&["3_rows"]));
+        let rows: Vec<_> = db.engine().table("t").unwrap().scan().map(|row| row.unwrap().1).collect();
+        assert_eq!(rows, vec![vec![Value::Int32(3)]]);
+        assert_eq!(db.engine().table("w").unwrap().scan().count(), 1);
+
+        write(dir.path(), "4_alter.sql", "ALTER TABLE t ADD COLUMN n INT; UPDATE t SET n = 1;");
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert!(migrator.up(None).unwrap_err().to_string().contains("altered in this transaction"));
+    }
+
+    #[test]
+    fn test_drift_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "1_t.sql", "CREATE TABLE t (id INT);");
+        write(dir.path(), "2_u.sql", "CREATE TABLE u (id INT);");
+        let db = Database::open(DatabaseOptions::memory()).unwrap();
+        Migrator::new(db.catalog(), dir.path()).unwrap().up(Some(1)).unwrap();
+
+        write(dir.path(), "1_t.down.sql", "DROP TABLE t;");
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert_eq!(migrator.status(), vec![("1_t".into(), Status::Changed), ("2_u".into(), Status::Pending)]);
+        fs::remove_file(dir.path().join("1_t.down.sql")).unwrap();
+        assert_eq!(Migrator::new(db.catalog(), dir.path()).unwrap().status()[0].1, Status::Applied);
+
+        write(dir.path(), "1_t.sql", "CREATE TABLE t (id BIGINT);");
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert_eq!(migrator.status(), vec![("1_t".into(), Status::Changed), ("2_u".into(), Status::Pending)]);
+        assert!(migrator.up(None).unwrap_err().to_string().contains("migration 1_t has changed"));
+        assert!(db.catalog().table("u").is_none());
+
+        fs::remove_file(dir.path().join("1_t.sql")).unwrap();
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        assert_eq!(migrator.status(), vec![("1_t".into(), Status::Missing), ("2_u".into(), Status::Pending)]);
+        assert!(migrator.check().is_err());
+        assert!(migrator.down(0).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_and_down() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "1_t.sql", "CREATE TABLE t (id INT PRIMARY KEY, n INT)");
+        write(dir.path(), "1_t.down.sql", "DROP TABLE t");
+        write(dir.path(), "2_index.sql", "CREATE INDEX t_n ON t (n)");
+        write(dir.path(), "3_check.sql", "ALTER TABLE t ADD CONSTRAINT positive CHECK (n > 0)");
+        write(dir.path(), "3_check.down.sql", "ALTER TABLE t DROP CONSTRAINT positive");
+        let db = Database::open(DatabaseOptions::memory()).unwrap();
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+
+        assert_eq!(migrator.up(Some(1)).unwrap(), names(&["1_t"]));
+        let table = db.engine().table("t").unwrap();
+        let id = table.insert(vec![Value::Int(1), Value::Int(-5)]).unwrap();
+        let dry = Migrator::new(db.catalog(), dir.path()).unwrap().dry_run();
+        assert!(dry.up(None).unwrap_err().to_string().contains("check constraint positive"));
+        table.update(id, vec![Value::Int(1), Value::Int(5)]).unwrap();
+        assert_eq!(dry.up(None).unwrap(), names(&["2_index", "3_check"]));
+        assert!(db.catalog().table("t").unwrap().constraint("positive").is_none());
+        assert!(db.catalog().indexes("t").is_empty());
+        assert_eq!(migrator.status()[2], ("3_check".into(), Status::Pending));
+
+        assert_eq!(migrator.up(None).unwrap(), names(&["2_index", "3_check"]));
+        assert!(migrator.down(0).unwrap_err().to_string().contains("2_index has no down migration"));
+        fs::write(dir.path().join("2_index.down.sql"), "").unwrap();
+        let migrator = Migrator::new(db.catalog(), dir.path()).unwrap();
+        // An empty down migration only forgets that it was applied.
+        assert_eq!(migrator.down(1).unwrap(), names(&["3_check", "2_index"]));
+        assert!(db.catalog().table("t").unwrap().constraint("positive").is_none());
+        assert!(db.catalog().indexes("t").iter().any(|i| i.name == "t_n"));
+        assert_eq!(migrator.down(0).unwrap(), names(&["1_t"]));
+        assert!(db.catalog().table("t").is_none());
+        assert!(db.catalog().snapshot().migrations().is_empty());
+    }
+}
@@ -2,6 +2,41 @@
 use std::fmt;
 pub use crate::types::Value;
 
+/// Start position of a syntax node in the query text, used to point error
+/// messages at the offending expression.
+///
+/// Spans never take part in AST equality, so a parsed tree compares equal to
+/// one built by hand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+
+    /// Whether the span points into real query text (hand-built AST nodes use
+    /// the default, unknown span).
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     Select(SelectStatement),
@@ -11,6 +46,11 @@ pub enum Statement {
     Create(CreateStatement),
     Drop(DropStatement),
     Alter(AlterStatement),
+    CreateIndex(CreateIndexStatement),
+    CreateType(CreateTypeStatement),
+    AlterType(AlterTypeStatement),
+    /// Forces a checkpoint of the write-ahead log.
+    Checkpoint,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -60,6 +100,61 @@ pub struct CreateStatement {
     pub constraints: Vec<TableConstraint>,
 }
 
+/// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] name ON table [USING method] (path, ...)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CreateIndexStatement {
+    pub name: String,
+    pub unique: bool,
+    pub if_not_exists: bool,
+    pub table: TableReference,
+    pub method: IndexMethod,
+    pub columns: Vec<FieldPath>,
+}
+
+/// How an index is organized.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum IndexMethod {
+    /// A B+tree, serving equality and range lookups in key order.
+    #[default]
+    BTree,
+    /// A hash table, serving only equality on every key column.
+    Hash,
+}
+
+/// A column, or a value nested inside one: `phone.country_code`,
+/// `tags[0]`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldPath {
+    pub column: String,
+    pub steps: Vec<PathStep>,
+}
+
+/// One step into a composite value.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PathStep {
+    /// A field of a struct (or a key of a JSON object).
+    Field(String),
+    /// An element of an array, counting from 0.
+    Element(usize),
+}
+
+impl FieldPath {
+    pub fn column(name: impl Into<String>) -> Self {
+        FieldPath { column: name.into(), steps: Vec::new() }
+    }
+
+    /// Whether both paths name the same value; like all identifiers,
+    /// names match case-insensitively.
+    pub fn matches(&self, other: &FieldPath) -> bool {
+        self.column.eq_ignore_ascii_case(&other.column)
+            && self.steps.len() == other.steps.len()
+            && self.steps.iter().zip(&other.steps).all(|pair| match pair {
+                (PathStep::Field(a), PathStep::Field(b)) => a.eq_ignore_ascii_case(b),
+                (a, b) => a == b,
+            })
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct DropStatement {
     pub temporary: bool,
@@ -84,6 +179,40 @@ pub enum AlterAction {
     DropConstraint(String),
 }
 
+/// `CREATE TYPE name AS STRUCT (field: type, ...)` or
+/// `CREATE TYPE name AS ENUM (variant [(type)], ...)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CreateTypeStatement {
+    pub name: String,
+    pub if_not_exists: bool,
+    pub definition: TypeDefinition,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeDefinition {
+    /// Named fields, in declaration order.
+    Struct(Vec<(String, DataType)>),
+    /// Variants, each with the type of its payload if it has one.
+    Enum(Vec<(String, Option<DataType>)>),
+}
+
+/// `ALTER TYPE name action`, on a type defined in the catalog.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AlterTypeStatement {
+    pub name: String,
+    pub action: AlterTypeAction,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AlterTypeAction {
+    /// `ADD FIELD name: type [DEFAULT expr]`, on a struct.
+    AddField { name: String, data_type: DataType, default: Option<Expr> },
+    /// `ADD VARIANT name [(type)]`, on an enum.
+    AddVariant { name: String, payload: Option<DataType> },
+    /// `RENAME FIELD from TO to`, on a struct.
+    RenameField(String, String),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct SelectColumn {
     pub expr: Expr,
@@ -122,15 +251,30 @@ pub enum Expr {
         left: Box<Expr>,
         op: BinaryOp,
         right: Box<Expr>,
+        span: Span,
     },
     Unary {
         op: UnaryOp,
         expr: Box<Expr>,
+        span: Span,
     },
     Function {
         name: String,
         args: Vec<Expr>,
         distinct: bool,
+        span: Span,
+    },
+    /// `CAST(expr AS type)`, `expr::type` or `TRY_CAST(expr AS type)`; also
+    /// inserted by the analyzer for implicit casts. A failed `TRY_CAST`
+    /// yields NULL instead of an error.
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+        try_cast: bool,
+    },
+    /// `*` or `table.*` in a select list, or the argument of `COUNT(*)`.
+    Wildcard {
+        table: Option<String>,
     },
     Case {
         operand: Option<Box<Expr>>,
@@ -140,6 +284,14 @@ pub enum Expr {
     Exists(Box<SelectStatement>),
     Subquery(Box<SelectStatement>),
     List(Vec<Expr>),
+    /// `expr.field` or `expr[n]`: a step into a struct or array value.
+    /// Dotted names are parsed as column references first; the analyzer
+    /// turns the trailing parts into fields when they name no table.
+    Field {
+        expr: Box<Expr>,
+        step: PathStep,
+        span: Span,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -147,6 +299,7 @@ pub struct ColumnRef {
     pub name: String,
     pub table: Option<String>,
     pub schema: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -200,7 +353,15 @@ pub struct ColumnDef {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum DataType {
+    TinyInt,
+    SmallInt,
     Integer(Option<u32>),
+    BigInt,
+    UnsignedTinyInt,
+    UnsignedSmallInt,
+    UnsignedInteger,
+    UnsignedBigInt,
+    Real,
     Float(Option<(u32, u32)>),
     Decimal(Option<(u32, u32)>),
     Char(Option<u32>),
@@ -213,6 +374,10 @@ pub enum DataType {
     Boolean,
     Binary(Option<u32>),
     Json,
+    Uuid,
+    Interval,
+    /// A struct or enum type defined in the catalog, by name.
+    Custom(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -264,6 +429,68 @@ pub enum ReferentialAction {
     SetDefault,
 }
 
+impl TableConstraint {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            TableConstraint::PrimaryKey { name, .. }
+            | TableConstraint::Unique { name, .. }
+            | TableConstraint::ForeignKey { name, .. }
+            | TableConstraint::Check { name, .. } => name.as_deref(),
+        }
+    }
+
+    /// The constraint type, as `information_schema` spells it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TableConstraint::PrimaryKey { .. } => "PRIMARY KEY",
+            TableConstraint::Unique { .. } => "UNIQUE",
+            TableConstraint::ForeignKey { .. } => "FOREIGN KEY",
+            TableConstraint::Check { .. } => "CHECK",
+        }
+    }
+}
+
+impl DataType {
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            DataType::TinyInt
+                | DataType::SmallInt
+                | DataType::Integer(_)
+                | DataType::BigInt
+                | DataType::UnsignedTinyInt
+                | DataType::UnsignedSmallInt
+                | DataType::UnsignedInteger
+                | DataType::UnsignedBigInt
+        )
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || matches!(self, DataType::Real | DataType::Float(_) | DataType::Decimal(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, DataType::Char(_) | DataType::Varchar(_) | DataType::Text)
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        matches!(self, DataType::Date | DataType::Time | DataType::DateTime | DataType::Timestamp)
+    }
+
+    /// Whether values of both types share a representation, ignoring length
+    /// and precision parameters (so no conversion is needed between them).
+    pub fn same_family(&self, other: &DataType) -> bool {
+        use DataType::*;
+        match (self, other) {
+            (a, b) if a.is_string() && b.is_string() => true,
+            (Integer(_), Integer(_)) | (Float(_), Float(_)) | (Decimal(_), Decimal(_)) => true,
+            (Binary(_), Binary(_)) => true,
+            (DateTime | Timestamp, DateTime | Timestamp) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
 // Display implementations for debug and error reporting
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -275,10 +502,42 @@ impl fmt::Display for Statement {
             Statement::Create(_) => write!(f, "CREATE"),
             Statement::Drop(_) => write!(f, "DROP"),
             Statement::Alter(_) => write!(f, "ALTER"),
+            Statement::CreateIndex(_) => write!(f, "CREATE INDEX"),
+            Statement::CreateType(_) => write!(f, "CREATE TYPE"),
+            Statement::AlterType(_) => write!(f, "ALTER TYPE"),
+            Statement::Checkpoint => write!(f, "CHECKPOINT"),
         }
     }
 }
 
+impl fmt::Display for IndexMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexMethod::BTree => write!(f, "BTREE"),
+            IndexMethod::Hash => write!(f, "HASH"),
+        }
+    }
+}
+
+impl fmt::Display for PathStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathStep::Field(name) => write!(f, ".{}", name),
+            PathStep::Element(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.column)?;
+        for step in &self.steps {
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for BinaryOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -303,15 +562,83 @@ impl fmt::Display for BinaryOp {
     }
 }
 
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Not => write!(f, "NOT "),
+            UnaryOp::Negative => write!(f, "-"),
+            UnaryOp::IsNull => write!(f, "IS NULL"),
+            UnaryOp::IsNotNull => write!(f, "IS NOT NULL"),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::TinyInt => write!(f, "TINYINT"),
+            DataType::SmallInt => write!(f, "SMALLINT"),
+            DataType::Integer(None) => write!(f, "INTEGER"),
+            DataType::Integer(Some(n)) => write!(f, "INTEGER({})", n),
+            DataType::BigInt => write!(f, "BIGINT"),
+            DataType::UnsignedTinyInt => write!(f, "TINYINT UNSIGNED"),
+            DataType::UnsignedSmallInt => write!(f, "SMALLINT UNSIGNED"),
+            DataType::UnsignedInteger => write!(f, "INTEGER UNSIGNED"),
+            DataType::UnsignedBigInt => write!(f, "BIGINT UNSIGNED"),
+            DataType::Real => write!(f, "REAL"),
+            DataType::Float(None) => write!(f, "FLOAT"),
+            DataType::Float(Some((p, s))) => write!(f, "FLOAT({}, {})", p, s),
+            DataType::Decimal(None) => write!(f, "DECIMAL"),
+            DataType::Decimal(Some((p, s))) => write!(f, "DECIMAL({}, {})", p, s),
+            DataType::Char(None) => write!(f, "CHAR"),
+            DataType::Char(Some(n)) => write!(f, "CHAR({})", n),
+            DataType::Varchar(None) => write!(f, "VARCHAR"),
+            DataType::Varchar(Some(n)) => write!(f, "VARCHAR({})", n),
+            DataType::Text => write!(f, "TEXT"),
+            DataType::Date => write!(f, "DATE"),
+            DataType::Time => write!(f, "TIME"),
+            DataType::DateTime => write!(f, "DATETIME"),
+            DataType::Timestamp => write!(f, "TIMESTAMP"),
+            DataType::Boolean => write!(f, "BOOLEAN"),
+            DataType::Binary(None) => write!(f, "BINARY"),
+            DataType::Binary(Some(n)) => write!(f, "BINARY({})", n),
+            DataType::Json => write!(f, "JSON"),
+            DataType::Uuid => write!(f, "UUID"),
+            DataType::Interval => write!(f, "INTERVAL"),
+            DataType::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Column(col) => write!(f, "{}", col.name),
+            Expr::Literal(Value::Interval(i)) => write!(f, "INTERVAL '{}'", i),
             Expr::Literal(val) => write!(f, "{}", val),
-            Expr::Binary { left, op, right } => write!(f, "({} {} {})", left, op, right),
-            Expr::Unary { op, expr } => write!(f, "{}({})", op, expr),
-            Expr::Function { name, args, .. } => {
-                write!(f, "{}(", name)?;
+            Expr::Binary { left, op: op @ (BinaryOp::Like | BinaryOp::NotLike), right, .. } => match &**right {
+                Expr::List(items) if items.len() == 2 => {
+                    write!(f, "({} {} {} ESCAPE {})", left, op, items[0], items[1])
+                }
+                _ => write!(f, "({} {} {})", left, op, right),
+            },
+            Expr::Binary { left, op, right, .. } => write!(f, "({} {} {})", left, op, right),
+            Expr::Unary { op: UnaryOp::IsNull, expr, .. } => write!(f, "({} IS NULL)", expr),
+            Expr::Unary { op: UnaryOp::IsNotNull, expr, .. } => {
+                write!(f, "({} IS NOT NULL)", expr)
+            }
+            Expr::Unary { op, expr, .. } => write!(f, "{}({})", op, expr),
+            Expr::Cast { expr, data_type, try_cast: false } => {
+                write!(f, "CAST({} AS {})", expr, data_type)
+            }
+            Expr::Cast { expr, data_type, try_cast: true } => {
+                write!(f, "TRY_CAST({} AS {})", expr, data_type)
+            }
+            Expr::Wildcard { table: Some(table) } => write!(f, "{}.*", table),
+            Expr::Wildcard { table: None } => write!(f, "*"),
+            Expr::Field { expr, step, .. } => write!(f, "{}{}", expr, step),
+            Expr::Function { name, args, distinct, .. } => {
+                write!(f, "{}({}", name, if *distinct { "DISTINCT " } else { "" })?;
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
@@ -320,7 +647,67 @@ impl fmt::Display for Expr {
                 }
                 write!(f, ")")
             }
+            Expr::Case { operand, when_clauses, else_result } => {
+                write!(f, "CASE")?;
+                if let Some(operand) = operand {
+                    write!(f, " {}", operand)?;
+                }
+                for (when, then) in when_clauses {
+                    write!(f, " WHEN {} THEN {}", when, then)?;
+                }
+                if let Some(result) = else_result {
+                    write!(f, " ELSE {}", result)?;
+                }
+                write!(f, " END")
+            }
+            Expr::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
             _ => write!(f, "..."),
         }
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for ReferentialAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferentialAction::Restrict => write!(f, "RESTRICT"),
+            ReferentialAction::Cascade => write!(f, "CASCADE"),
+            ReferentialAction::SetNull => write!(f, "SET NULL"),
+            ReferentialAction::NoAction => write!(f, "NO ACTION"),
+            ReferentialAction::SetDefault => write!(f, "SET DEFAULT"),
+        }
+    }
+}
+
+/// Writes the constraint as it appears in `CREATE TABLE`.
+impl fmt::Display for TableConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.name() {
+            write!(f, "CONSTRAINT {} ", name)?;
+        }
+        match self {
+            TableConstraint::PrimaryKey { columns, .. } => write!(f, "PRIMARY KEY ({})", columns.join(", ")),
+            TableConstraint::Unique { columns, .. } => write!(f, "UNIQUE ({})", columns.join(", ")),
+            TableConstraint::ForeignKey { columns, ref_table, ref_columns, on_delete, on_update, .. } => {
+                let (columns, ref_columns) = (columns.join(", "), ref_columns.join(", "));
+                write!(f, "FOREIGN KEY ({}) REFERENCES {} ({})", columns, ref_table, ref_columns)?;
+                if let Some(action) = on_delete {
+                    write!(f, " ON DELETE {}", action)?;
+                }
+                if let Some(action) = on_update {
+                    write!(f, " ON UPDATE {}", action)?;
+                }
+                Ok(())
+            }
+            TableConstraint::Check { expr, .. } => write!(f, "CHECK ({})", expr),
+        }
+    }
+}
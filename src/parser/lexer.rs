@@ -2,6 +2,7 @@
 use std::iter::Peekable;
 use std::str::Chars;
 use crate::error::Error;
+use super::ast::Span;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -13,7 +14,9 @@ pub enum Token {
     Create,
     Drop,
     Alter,
+    Checkpoint,
     Table,
+    Index,
     Into,
     Values,
     From,
@@ -46,9 +49,34 @@ pub enum Token {
     Unique,
     Check,
     Default,
+    Distinct,
+    As,
+    Join,
     LeftJoin,
     RightJoin,
     FullJoin,
+    CrossJoin,
+    On,
+    Using,
+    Asc,
+    Desc,
+    Nulls,
+    First,
+    Last,
+    Exists,
+    Set,
+    If,
+    Temporary,
+    Cascade,
+    Constraint,
+    Add,
+    Column,
+    Modify,
+    Rename,
+    To,
+    Escape,
+    Cast,
+    TryCast,
     
     // Identifiers and literals
     Identifier(String),
@@ -67,18 +95,46 @@ pub enum Token {
     Greater,
     LessEqual,
     GreaterEqual,
+    DoubleColon,
+    Colon,
     
     // Delimiters
     Comma,
     Semicolon,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Period,
     
     // Special
     EOF,
 }
 
+impl Token {
+    /// Keywords that are only reserved in specific positions and may still be
+    /// used as table or column names.
+    pub fn soft_keyword(&self) -> Option<&'static str> {
+        Some(match self {
+            Token::First => "first",
+            Token::Last => "last",
+            Token::Nulls => "nulls",
+            Token::Key => "key",
+            Token::Column => "column",
+            Token::Add => "add",
+            Token::Modify => "modify",
+            Token::Rename => "rename",
+            Token::To => "to",
+            Token::Temporary => "temporary",
+            Token::Cascade => "cascade",
+            Token::If => "if",
+            Token::Escape => "escape",
+            Token::Index => "index",
+            _ => return None,
+        })
+    }
+}
+
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     position: usize,
@@ -96,8 +152,15 @@ impl<'a> Lexer<'a> {
         }
     }
     
+    /// Like [`Lexer::next_token`], also returning where the token starts.
+    pub fn next_spanned_token(&mut self) -> Result<(Token, Span), Error> {
+        self.skip_whitespace()?;
+        let span = Span::new(self.line, self.column);
+        Ok((self.next_token()?, span))
+    }
+
     pub fn next_token(&mut self) -> Result<Token, Error> {
-        self.skip_whitespace();
+        self.skip_whitespace()?;
         
         match self.peek() {
             None => Ok(Token::EOF),
@@ -115,10 +178,13 @@ impl<'a> Lexer<'a> {
                 ';' => self.single_char_token(Token::Semicolon),
                 '(' => self.single_char_token(Token::LeftParen),
                 ')' => self.single_char_token(Token::RightParen),
+                '[' => self.single_char_token(Token::LeftBracket),
+                ']' => self.single_char_token(Token::RightBracket),
                 '.' => self.single_char_token(Token::Period),
                 '<' => self.read_comparison_operator('<'),
                 '>' => self.read_comparison_operator('>'),
                 '!' => self.read_not_operator(),
+                ':' => self.read_colon(),
                 _ => Err(Error::Syntax(format!("Unexpected character: {}", c))),
             }
         }
@@ -141,13 +207,36 @@ impl<'a> Lexer<'a> {
         c
     }
     
-    fn skip_whitespace(&mut self) {
+    /// Skips whitespace and comments, both `-- to the end of the line` and
+    /// `/* delimited */`.
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
         while let Some(c) = self.peek() {
-            if !c.is_whitespace() {
+            let second = || {
+                let mut ahead = self.input.clone();
+                ahead.next();
+                ahead.next()
+            };
+            if c.is_whitespace() {
+                self.next();
+            } else if c == '-' && second() == Some('-') {
+                while self.next().is_some_and(|c| c != '\n') {}
+            } else if c == '/' && second() == Some('*') {
+                let line = self.line;
+                self.next();
+                self.next();
+                let mut last = None;
+                loop {
+                    match self.next() {
+                        Some('/') if last == Some('*') => break,
+                        Some(c) => last = Some(c),
+                        None => return Err(Error::Syntax(format!("Unterminated comment starting on line {}", line))),
+                    }
+                }
+            } else {
                 break;
             }
-            self.next();
         }
+        Ok(())
     }
     
     fn read_identifier(&mut self) -> Result<Token, Error> {
@@ -168,7 +257,9 @@ impl<'a> Lexer<'a> {
             "CREATE"     => Token::Create,
             "DROP"       => Token::Drop,
             "ALTER"      => Token::Alter,
+            "CHECKPOINT" => Token::Checkpoint,
             "TABLE"      => Token::Table,
+            "INDEX"      => Token::Index,
             "INTO"       => Token::Into,
             "VALUES"     => Token::Values,
             "FROM"       => Token::From,
@@ -201,10 +292,73 @@ impl<'a> Lexer<'a> {
             "UNIQUE"     => Token::Unique,
             "CHECK"      => Token::Check,
             "DEFAULT"    => Token::Default,
+            "DISTINCT"   => Token::Distinct,
+            "AS"         => Token::As,
+            "JOIN"       => Token::Join,
+            "INNER" if self.skip_keywords(&["JOIN"]) => Token::Join,
+            "LEFT" if self.skip_join() => Token::LeftJoin,
+            "RIGHT" if self.skip_join() => Token::RightJoin,
+            "FULL" if self.skip_join() => Token::FullJoin,
+            "CROSS" if self.skip_keywords(&["JOIN"]) => Token::CrossJoin,
+            "ON"         => Token::On,
+            "USING"      => Token::Using,
+            "ASC"        => Token::Asc,
+            "DESC"       => Token::Desc,
+            "NULLS"      => Token::Nulls,
+            "FIRST"      => Token::First,
+            "LAST"       => Token::Last,
+            "EXISTS"     => Token::Exists,
+            "SET"        => Token::Set,
+            "IF"         => Token::If,
+            "TEMPORARY"  => Token::Temporary,
+            "CASCADE"    => Token::Cascade,
+            "CONSTRAINT" => Token::Constraint,
+            "ADD"        => Token::Add,
+            "COLUMN"     => Token::Column,
+            "MODIFY"     => Token::Modify,
+            "RENAME"     => Token::Rename,
+            "TO"         => Token::To,
+            "ESCAPE"     => Token::Escape,
+            "CAST"       => Token::Cast,
+            "TRY_CAST"   => Token::TryCast,
             _ => Token::Identifier(identifier),
         })
     }
 
+    /// Consumes `[OUTER] JOIN` following LEFT/RIGHT/FULL.
+    fn skip_join(&mut self) -> bool {
+        self.skip_keywords(&["OUTER", "JOIN"]) || self.skip_keywords(&["JOIN"])
+    }
+
+    /// Consumes the given sequence of keywords if (and only if) the input
+    /// continues with all of them.
+    fn skip_keywords(&mut self, keywords: &[&str]) -> bool {
+        let mut ahead = self.input.clone();
+        let mut consumed = 0;
+        for keyword in keywords {
+            while ahead.peek().is_some_and(|c| c.is_whitespace()) {
+                ahead.next();
+                consumed += 1;
+            }
+            let mut word = String::new();
+            while let Some(c) = ahead.peek().copied() {
+                if !c.is_alphanumeric() && c != '_' {
+                    break;
+                }
+                word.push(c);
+                ahead.next();
+                consumed += 1;
+            }
+            if !word.eq_ignore_ascii_case(keyword) {
+                return false;
+            }
+        }
+        for _ in 0..consumed {
+            self.next();
+        }
+        true
+    }
+
     fn read_number(&mut self) -> Result<Token, Error> {
         let mut number = String::new();
         let mut has_decimal = false;
@@ -323,6 +477,17 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    fn read_colon(&mut self) -> Result<Token, Error> {
+        self.next();
+        match self.peek() {
+            Some(':') => {
+                self.next();
+                Ok(Token::DoubleColon)
+            }
+            _ => Ok(Token::Colon),
+        }
+    }
+
     pub fn get_position(&self) -> (usize, usize) {
         (self.line, self.column)
     }
@@ -368,4 +533,18 @@ mod tests {
         assert_eq!(lexer.next_token().unwrap(), Token::Number("45.67".to_string()));
         assert_eq!(lexer.next_token().unwrap(), Token::Number("1.2e-3".to_string()));
     }
+
+    #[test]
+    fn test_comments() {
+        let mut lexer = Lexer::new("-- a table\nSELECT /* every\n column */ * -- all of them\n/**/- 1");
+        assert_eq!(lexer.next_token().unwrap(), Token::Select);
+        assert_eq!(lexer.next_spanned_token().unwrap(), (Token::Multiply, Span::new(3, 12)));
+        assert_eq!(lexer.next_token().unwrap(), Token::Minus);
+        assert_eq!(lexer.next_token().unwrap(), Token::Number("1".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::EOF);
+
+        let mut lexer = Lexer::new("1 /* never closed");
+        assert_eq!(lexer.next_token().unwrap(), Token::Number("1".to_string()));
+        assert!(lexer.next_token().is_err());
+    }
 }
\ No newline at end of file
@@ -1,33 +1,107 @@
 // src/parser.rs
 
-mod lexer;
-mod ast;
+pub mod lexer;
+pub mod ast;
 
 use lexer::{Lexer, Token};
 use ast::{*, Value};
+use bigdecimal::BigDecimal;
 use crate::error::Error;
 
+/// Parses a single SQL statement.
+pub fn parse_sql(sql: &str) -> Result<Statement, Error> {
+    let mut parser = Parser::new(sql)?;
+    let stmt = parser.parse_statement()?;
+    if matches!(parser.current_token, Token::Semicolon) {
+        parser.next_token()?;
+    }
+    if !matches!(parser.current_token, Token::EOF) {
+        return Err(Error::Syntax(format!(
+            "Unexpected token {:?} after end of statement",
+            parser.current_token
+        )));
+    }
+    Ok(stmt)
+}
+
+/// Parses a script of statements separated by semicolons. Empty statements
+/// are skipped, and errors name the line the failing statement starts on.
+pub fn parse_statements(sql: &str) -> Result<Vec<Statement>, Error> {
+    let mut parser = Parser::new(sql)?;
+    let mut statements = Vec::new();
+    loop {
+        while matches!(parser.current_token, Token::Semicolon) {
+            parser.next_token()?;
+        }
+        if matches!(parser.current_token, Token::EOF) {
+            return Ok(statements);
+        }
+        let line = parser.current_span.line;
+        let at_line = |err: Error| match err {
+            Error::Syntax(message) => Error::Syntax(format!("{} (in the statement on line {})", message, line)),
+            other => other,
+        };
+        statements.push(parser.parse_statement().map_err(at_line)?);
+        if !matches!(parser.current_token, Token::Semicolon | Token::EOF) {
+            return Err(at_line(Error::Syntax(format!(
+                "Expected ; after end of statement, got {:?}",
+                parser.current_token
+            ))));
+        }
+    }
+}
+
+/// Parses a data type, as written in a column definition.
+pub fn parse_data_type(sql: &str) -> Result<DataType, Error> {
+    parse_whole(sql, |parser| parser.parse_data_type())
+}
+
+/// Parses a single expression.
+pub fn parse_expr(sql: &str) -> Result<Expr, Error> {
+    parse_whole(sql, |parser| parser.parse_expr(0))
+}
+
+/// Parses a table constraint, as written in `CREATE TABLE`.
+pub fn parse_table_constraint(sql: &str) -> Result<TableConstraint, Error> {
+    parse_whole(sql, |parser| parser.parse_table_constraint())
+}
+
+fn parse_whole<T>(sql: &str, parse: impl FnOnce(&mut Parser<'_>) -> Result<T, Error>) -> Result<T, Error> {
+    let mut parser = Parser::new(sql)?;
+    let parsed = parse(&mut parser)?;
+    if !matches!(parser.current_token, Token::EOF) {
+        return Err(Error::Syntax(format!("Unexpected token {:?} after {}", parser.current_token, sql)));
+    }
+    Ok(parsed)
+}
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
     peek_token: Token,
+    current_span: Span,
+    peek_span: Span,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Result<Self, Error> {
         let mut lexer = Lexer::new(input);
-        let current_token = lexer.next_token()?;
-        let peek_token = lexer.next_token()?;
-        
+        let (current_token, current_span) = lexer.next_spanned_token()?;
+        let (peek_token, peek_span) = lexer.next_spanned_token()?;
+
         Ok(Parser {
             lexer,
             current_token,
             peek_token,
+            current_span,
+            peek_span,
         })
     }
 
     fn next_token(&mut self) -> Result<(), Error> {
-        self.current_token = std::mem::replace(&mut self.peek_token, self.lexer.next_token()?);
+        let (token, span) = self.lexer.next_spanned_token()?;
+        self.current_token = std::mem::replace(&mut self.peek_token, token);
+        self.current_span = std::mem::replace(&mut self.peek_span, span);
         Ok(())
     }
 
@@ -43,6 +117,39 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Consumes the current token if it is `token`.
+    fn consume(&mut self, token: Token) -> Result<bool, Error> {
+        if self.current_token == token {
+            self.next_token()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Consumes an identifier (or a keyword that may be used as one).
+    fn parse_identifier(&mut self) -> Result<String, Error> {
+        let name = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            token => match token.soft_keyword() {
+                Some(name) => name.to_string(),
+                None => {
+                    return Err(Error::Syntax(format!(
+                        "Expected identifier, got {:?}",
+                        self.current_token
+                    )))
+                }
+            },
+        };
+        self.next_token()?;
+        Ok(name)
+    }
+
+    /// Whether the current token is the non-reserved word `word`.
+    fn at_word(&self, word: &str) -> bool {
+        matches!(&self.current_token, Token::Identifier(w) if w.eq_ignore_ascii_case(word))
+    }
+
     pub fn parse_statement(&mut self) -> Result<Statement, Error> {
         match &self.current_token {
             Token::Select => self.parse_select(),
@@ -52,6 +159,10 @@ impl<'a> Parser<'a> {
             Token::Create => self.parse_create(),
             Token::Drop   => self.parse_drop(),
             Token::Alter  => self.parse_alter(),
+            Token::Checkpoint => {
+                self.next_token()?;
+                Ok(Statement::Checkpoint)
+            }
             _ => Err(Error::Syntax(format!(
                 "Unexpected token {:?} at start of statement",
                 self.current_token
@@ -60,8 +171,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_select(&mut self) -> Result<Statement, Error> {
-        self.next_token()?; // consume SELECT
-        
+        Ok(Statement::Select(self.parse_select_body()?))
+    }
+
+    fn parse_select_body(&mut self) -> Result<SelectStatement, Error> {
+        self.expect_token(Token::Select)?;
+
         let distinct = if matches!(self.current_token, Token::Distinct) {
             self.next_token()?;
             true
@@ -81,7 +196,7 @@ impl<'a> Parser<'a> {
         let order_by = self.parse_order_by()?;
         let limit = self.parse_limit()?;
 
-        Ok(Statement::Select(SelectStatement {
+        Ok(SelectStatement {
             distinct,
             columns,
             from,
@@ -91,30 +206,27 @@ impl<'a> Parser<'a> {
             having,
             order_by,
             limit,
-        }))
+        })
     }
 
     fn parse_select_columns(&mut self) -> Result<Vec<SelectColumn>, Error> {
         let mut columns = Vec::new();
-        
+
         loop {
             let expr = self.parse_expr(0)?;
-            let alias = if matches!(self.peek_token, Token::As) {
+            let alias = if matches!(self.current_token, Token::As) {
                 self.next_token()?; // consume AS
-                self.next_token()?; // move to alias
-                match &self.current_token {
-                    Token::Identifier(name) => {
-                        self.next_token()?;
-                        Some(name.clone())
-                    }
-                    _ => return Err(Error::Syntax("Expected identifier after AS".to_string())),
-                }
+                Some(self.parse_identifier()?)
+            } else if let Token::Identifier(name) = &self.current_token {
+                let name = name.clone();
+                self.next_token()?;
+                Some(name)
             } else {
                 None
             };
-            
+
             columns.push(SelectColumn { expr, alias });
-            
+
             match self.current_token {
                 Token::Comma => {
                     self.next_token()?;
@@ -122,7 +234,7 @@ impl<'a> Parser<'a> {
                 _ => break,
             }
         }
-        
+
         Ok(columns)
     }
 
@@ -145,15 +257,16 @@ impl<'a> Parser<'a> {
         };
         self.next_token()?;
 
-        let alias = if matches!(self.current_token, Token::As) || 
+        let alias = if matches!(self.current_token, Token::As) ||
                       matches!(self.current_token, Token::Identifier(_)) {
             if matches!(self.current_token, Token::As) {
                 self.next_token()?;
             }
             match &self.current_token {
                 Token::Identifier(alias) => {
+                    let alias = alias.clone();
                     self.next_token()?;
-                    Some(alias.clone())
+                    Some(alias)
                 }
                 _ => return Err(Error::Syntax("Expected alias after AS".to_string())),
             }
@@ -170,7 +283,7 @@ impl<'a> Parser<'a> {
 
     fn parse_joins(&mut self) -> Result<Vec<JoinClause>, Error> {
         let mut joins = Vec::new();
-        
+
         while matches!(self.current_token,
             Token::Join | Token::LeftJoin | Token::RightJoin | Token::FullJoin | Token::CrossJoin)
         {
@@ -206,7 +319,7 @@ impl<'a> Parser<'a> {
                 using,
             });
         }
-        
+
         Ok(joins)
     }
 
@@ -223,7 +336,7 @@ impl<'a> Parser<'a> {
         let mut left = self.parse_prefix_expr()?;
 
         while !matches!(self.current_token, Token::EOF | Token::Semicolon)
-            && precedence < self.get_precedence(&self.current_token)
+            && precedence < self.current_precedence()
         {
             left = self.parse_infix_expr(left)?;
         }
@@ -231,43 +344,71 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
+    /// Precedence of the current token as an infix operator. `NOT` only acts
+    /// as one when it starts `NOT LIKE`, `NOT IN` or `NOT BETWEEN`.
+    fn current_precedence(&self) -> u8 {
+        match (&self.current_token, &self.peek_token) {
+            (Token::Not, Token::Like | Token::In | Token::Between) => 3,
+            (Token::Not, _) => 0,
+            (token, _) => self.get_precedence(token),
+        }
+    }
+
     fn get_precedence(&self, token: &Token) -> u8 {
         match token {
             Token::Or => 1,
             Token::And => 2,
             Token::Equals | Token::NotEquals => 3,
+            Token::Is | Token::Like | Token::In | Token::Between => 3,
             Token::Less | Token::Greater | Token::LessEqual | Token::GreaterEqual => 4,
             Token::Plus | Token::Minus => 5,
             Token::Multiply | Token::Divide | Token::Modulo => 6,
+            Token::DoubleColon => 8,
             _ => 0,
         }
     }
 
     fn parse_prefix_expr(&mut self) -> Result<Expr, Error> {
+        let span = self.current_span;
         match &self.current_token {
-            Token::Identifier(name) => {
+            // `INTERVAL '1 day'`
+            Token::Identifier(name)
+                if name.eq_ignore_ascii_case("interval") && matches!(self.peek_token, Token::String(_)) =>
+            {
+                self.next_token()?;
+                let Token::String(text) = &self.current_token else { unreachable!() };
+                let interval = text
+                    .parse()
+                    .map_err(|_| Error::Syntax(format!("Invalid interval literal: '{}'", text)))?;
                 self.next_token()?;
-                Ok(Expr::Column(ColumnRef {
-                    name: name.clone(),
-                    table: None,
-                    schema: None,
-                }))
+                Ok(Expr::Literal(Value::Interval(interval)))
+            }
+            Token::Identifier(_) if matches!(self.peek_token, Token::LeftParen) => {
+                self.parse_function_call()
+            }
+            Token::Identifier(_) => self.parse_column_ref(),
+            token if token.soft_keyword().is_some() => self.parse_column_ref(),
+            Token::Multiply => {
+                self.next_token()?;
+                Ok(Expr::Wildcard { table: None })
             }
             Token::Number(n) => {
+                let value = parse_number(n)?;
                 self.next_token()?;
-                Ok(Expr::Literal(Value::Number(n.clone())))
+                Ok(Expr::Literal(value))
             }
             Token::String(s) => {
+                let value = Value::String(s.clone());
                 self.next_token()?;
-                Ok(Expr::Literal(Value::String(s.clone())))
+                Ok(Expr::Literal(value))
             }
             Token::True => {
                 self.next_token()?;
-                Ok(Expr::Literal(Value::Boolean(true)))
+                Ok(Expr::Literal(Value::Bool(true)))
             }
             Token::False => {
                 self.next_token()?;
-                Ok(Expr::Literal(Value::Boolean(false)))
+                Ok(Expr::Literal(Value::Bool(false)))
             }
             Token::Null => {
                 self.next_token()?;
@@ -275,16 +416,51 @@ impl<'a> Parser<'a> {
             }
             Token::LeftParen => {
                 self.next_token()?;
+                if matches!(self.current_token, Token::Select) {
+                    let query = self.parse_select_body()?;
+                    self.expect_token(Token::RightParen)?;
+                    return Ok(Expr::Subquery(Box::new(query)));
+                }
                 let expr = self.parse_expr(0)?;
+                if matches!(self.current_token, Token::Comma) {
+                    self.next_token()?;
+                    let mut items = vec![expr];
+                    items.extend(self.parse_expr_list()?);
+                    self.expect_token(Token::RightParen)?;
+                    return Ok(Expr::List(items));
+                }
                 self.expect_token(Token::RightParen)?;
                 Ok(expr)
             }
+            Token::Exists => {
+                self.next_token()?;
+                self.expect_token(Token::LeftParen)?;
+                let query = self.parse_select_body()?;
+                self.expect_token(Token::RightParen)?;
+                Ok(Expr::Exists(Box::new(query)))
+            }
+            Token::Case => self.parse_case(),
+            Token::Cast | Token::TryCast => {
+                let try_cast = matches!(self.current_token, Token::TryCast);
+                self.next_token()?;
+                self.expect_token(Token::LeftParen)?;
+                let expr = self.parse_expr(0)?;
+                self.expect_token(Token::As)?;
+                let data_type = self.parse_data_type()?;
+                self.expect_token(Token::RightParen)?;
+                Ok(Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type,
+                    try_cast,
+                })
+            }
             Token::Not => {
                 self.next_token()?;
-                let expr = self.parse_expr(7)?;
+                let expr = self.parse_expr(2)?;
                 Ok(Expr::Unary {
                     op: UnaryOp::Not,
                     expr: Box::new(expr),
+                    span,
                 })
             }
             Token::Minus => {
@@ -293,6 +469,7 @@ impl<'a> Parser<'a> {
                 Ok(Expr::Unary {
                     op: UnaryOp::Negative,
                     expr: Box::new(expr),
+                    span,
                 })
             }
             _ => Err(Error::Syntax(format!(
@@ -302,7 +479,124 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses `name`, `table.name`, `schema.table.name` or `table.*`,
+    /// followed by any steps into the column's value (`.field`, `[n]`).
+    /// Names past the third are taken as fields.
+    fn parse_column_ref(&mut self) -> Result<Expr, Error> {
+        let span = self.current_span;
+        let mut parts = vec![self.parse_identifier()?];
+        while matches!(self.current_token, Token::Period) {
+            self.next_token()?;
+            if matches!(self.current_token, Token::Multiply) && parts.len() <= 2 {
+                self.next_token()?;
+                return Ok(Expr::Wildcard { table: parts.pop() });
+            }
+            parts.push(self.parse_identifier()?);
+        }
+
+        let fields = parts.split_off(parts.len().min(3));
+        let name = parts.pop().unwrap();
+        let table = parts.pop();
+        let schema = parts.pop();
+        let mut expr = Expr::Column(ColumnRef {
+            name,
+            table,
+            schema,
+            span,
+        });
+        for field in fields {
+            expr = Expr::Field { expr: Box::new(expr), step: PathStep::Field(field), span };
+        }
+        while matches!(self.current_token, Token::LeftBracket | Token::Period) {
+            let step = self.parse_path_step()?;
+            expr = Expr::Field { expr: Box::new(expr), step, span };
+        }
+        Ok(expr)
+    }
+
+    /// Parses `.field` or `[n]`.
+    fn parse_path_step(&mut self) -> Result<PathStep, Error> {
+        if self.consume(Token::Period)? {
+            return Ok(PathStep::Field(self.parse_identifier()?));
+        }
+        self.expect_token(Token::LeftBracket)?;
+        let index = match &self.current_token {
+            Token::Number(n) => n
+                .parse()
+                .map_err(|_| Error::Syntax(format!("Invalid array index: {}", n)))?,
+            _ => return Err(Error::Syntax(format!(
+                "Expected array index, got {:?}",
+                self.current_token
+            ))),
+        };
+        self.next_token()?;
+        self.expect_token(Token::RightBracket)?;
+        Ok(PathStep::Element(index))
+    }
+
+    /// Parses a column name followed by steps into its value.
+    fn parse_field_path(&mut self) -> Result<FieldPath, Error> {
+        let mut path = FieldPath::column(self.parse_identifier()?);
+        while matches!(self.current_token, Token::LeftBracket | Token::Period) {
+            path.steps.push(self.parse_path_step()?);
+        }
+        Ok(path)
+    }
+
+    fn parse_function_call(&mut self) -> Result<Expr, Error> {
+        let span = self.current_span;
+        let name = self.parse_identifier()?;
+        self.expect_token(Token::LeftParen)?;
+        let distinct = self.consume(Token::Distinct)?;
+        let args = if matches!(self.current_token, Token::RightParen) {
+            Vec::new()
+        } else {
+            self.parse_expr_list()?
+        };
+        self.expect_token(Token::RightParen)?;
+        Ok(Expr::Function {
+            name,
+            args,
+            distinct,
+            span,
+        })
+    }
+
+    fn parse_case(&mut self) -> Result<Expr, Error> {
+        self.expect_token(Token::Case)?;
+        let operand = if matches!(self.current_token, Token::When) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr(0)?))
+        };
+
+        let mut when_clauses = Vec::new();
+        while self.consume(Token::When)? {
+            let condition = self.parse_expr(0)?;
+            self.expect_token(Token::Then)?;
+            let result = self.parse_expr(0)?;
+            when_clauses.push((condition, result));
+        }
+        if when_clauses.is_empty() {
+            return Err(Error::Syntax("Expected WHEN in CASE expression".to_string()));
+        }
+
+        let else_result = if self.consume(Token::Else)? {
+            Some(Box::new(self.parse_expr(0)?))
+        } else {
+            None
+        };
+        self.expect_token(Token::End)?;
+
+        Ok(Expr::Case {
+            operand,
+            when_clauses,
+            else_result,
+        })
+    }
+
     fn parse_infix_expr(&mut self, left: Expr) -> Result<Expr, Error> {
+        let span = self.current_span;
         match &self.current_token {
             Token::Plus | Token::Minus | Token::Multiply | Token::Divide | Token::Modulo |
             Token::Equals | Token::NotEquals | Token::Less | Token::Greater |
@@ -315,6 +609,90 @@ impl<'a> Parser<'a> {
                     left: Box::new(left),
                     op,
                     right: Box::new(right),
+                    span,
+                })
+            }
+            Token::Not | Token::Like | Token::In | Token::Between => {
+                let negated = self.consume(Token::Not)?;
+                match self.current_token {
+                    Token::Like => {
+                        self.next_token()?;
+                        let pattern = self.parse_expr(3)?;
+                        let right = if self.consume(Token::Escape)? {
+                            // `x LIKE p ESCAPE e` is represented as a two item
+                            // list on the right-hand side.
+                            Expr::List(vec![pattern, self.parse_expr(3)?])
+                        } else {
+                            pattern
+                        };
+                        Ok(Expr::Binary {
+                            left: Box::new(left),
+                            op: if negated { BinaryOp::NotLike } else { BinaryOp::Like },
+                            right: Box::new(right),
+                            span,
+                        })
+                    }
+                    Token::In => {
+                        self.next_token()?;
+                        self.expect_token(Token::LeftParen)?;
+                        let right = if matches!(self.current_token, Token::Select) {
+                            Expr::Subquery(Box::new(self.parse_select_body()?))
+                        } else {
+                            Expr::List(self.parse_expr_list()?)
+                        };
+                        self.expect_token(Token::RightParen)?;
+                        Ok(Expr::Binary {
+                            left: Box::new(left),
+                            op: if negated { BinaryOp::NotIn } else { BinaryOp::In },
+                            right: Box::new(right),
+                            span,
+                        })
+                    }
+                    Token::Between => {
+                        // `x BETWEEN a AND b` is sugar for `x >= a AND x <= b`.
+                        self.next_token()?;
+                        let low = self.parse_expr(3)?;
+                        self.expect_token(Token::And)?;
+                        let high = self.parse_expr(3)?;
+                        let range = Expr::Binary {
+                            left: Box::new(Expr::Binary {
+                                left: Box::new(left.clone()),
+                                op: BinaryOp::GtEq,
+                                right: Box::new(low),
+                                span,
+                            }),
+                            op: BinaryOp::And,
+                            right: Box::new(Expr::Binary {
+                                left: Box::new(left),
+                                op: BinaryOp::LtEq,
+                                right: Box::new(high),
+                                span,
+                            }),
+                            span,
+                        };
+                        Ok(if negated {
+                            Expr::Unary {
+                                op: UnaryOp::Not,
+                                expr: Box::new(range),
+                                span,
+                            }
+                        } else {
+                            range
+                        })
+                    }
+                    _ => Err(Error::Syntax(format!(
+                        "Expected LIKE, IN or BETWEEN after NOT, got {:?}",
+                        self.current_token
+                    ))),
+                }
+            }
+            Token::DoubleColon => {
+                self.next_token()?;
+                let data_type = self.parse_data_type()?;
+                Ok(Expr::Cast {
+                    expr: Box::new(left),
+                    data_type,
+                    try_cast: false,
                 })
             }
             Token::Is => {
@@ -326,6 +704,7 @@ impl<'a> Parser<'a> {
                         Ok(Expr::Unary {
                             op: UnaryOp::IsNotNull,
                             expr: Box::new(left),
+                            span,
                         })
                     } else {
                         Err(Error::Syntax("Expected NULL after IS NOT".to_string()))
@@ -335,6 +714,7 @@ impl<'a> Parser<'a> {
                     Ok(Expr::Unary {
                         op: UnaryOp::IsNull,
                         expr: Box::new(left),
+                        span,
                     })
                 } else {
                     Err(Error::Syntax("Expected NULL or NOT NULL after IS".to_string()))
@@ -392,7 +772,7 @@ impl<'a> Parser<'a> {
         if matches!(self.current_token, Token::Order) {
             self.next_token()?;
             self.expect_token(Token::By)?;
-            
+
             let mut order_by = Vec::new();
             loop {
                 let expr = self.parse_expr(0)?;
@@ -405,7 +785,7 @@ impl<'a> Parser<'a> {
                 } else {
                     true
                 };
-                
+
                 let nulls_first = if matches!(self.current_token, Token::Nulls) {
                     self.next_token()?;
                     match self.current_token {
@@ -423,19 +803,19 @@ impl<'a> Parser<'a> {
                     // Default NULLS LAST
                     false
                 };
-                
+
                 order_by.push(OrderByExpr {
                     expr,
                     asc,
                     nulls_first,
                 });
-                
+
                 if !matches!(self.current_token, Token::Comma) {
                     break;
                 }
                 self.next_token()?;
             }
-            
+
             Ok(order_by)
         } else {
             Ok(Vec::new())
@@ -452,7 +832,7 @@ impl<'a> Parser<'a> {
                 _ => return Err(Error::Syntax("Expected number after LIMIT".to_string())),
             };
             self.next_token()?;
-            
+
             let offset = if matches!(self.current_token, Token::Offset) {
                 self.next_token()?;
                 match &self.current_token {
@@ -468,13 +848,612 @@ impl<'a> Parser<'a> {
             } else {
                 None
             };
-            
+
             Ok(Some(LimitClause { limit, offset }))
         } else {
             Ok(None)
         }
     }
 
+    fn parse_insert(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Insert)?;
+        self.expect_token(Token::Into)?;
+        let table = self.parse_table_reference()?;
+
+        let columns = if self.consume(Token::LeftParen)? {
+            let columns = self.parse_identifier_list()?;
+            self.expect_token(Token::RightParen)?;
+            columns
+        } else {
+            Vec::new()
+        };
+
+        self.expect_token(Token::Values)?;
+        let mut values = Vec::new();
+        loop {
+            self.expect_token(Token::LeftParen)?;
+            values.push(self.parse_expr_list()?);
+            self.expect_token(Token::RightParen)?;
+            if !self.consume(Token::Comma)? {
+                break;
+            }
+        }
+
+        let on_duplicate = if matches!(self.current_token, Token::On) {
+            self.next_token()?;
+            if !self.at_word("DUPLICATE") {
+                return Err(Error::Syntax("Expected DUPLICATE KEY UPDATE after ON".to_string()));
+            }
+            self.next_token()?;
+            self.expect_token(Token::Key)?;
+            self.expect_token(Token::Update)?;
+            Some(self.parse_assignments()?)
+        } else {
+            None
+        };
+
+        Ok(Statement::Insert(InsertStatement {
+            table,
+            columns,
+            values,
+            on_duplicate,
+        }))
+    }
+
+    fn parse_assignments(&mut self) -> Result<Vec<(String, Expr)>, Error> {
+        let mut sets = Vec::new();
+        loop {
+            let column = self.parse_identifier()?;
+            self.expect_token(Token::Equals)?;
+            sets.push((column, self.parse_expr(0)?));
+            if !self.consume(Token::Comma)? {
+                break;
+            }
+        }
+        Ok(sets)
+    }
+
+    fn parse_update(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Update)?;
+        let table = self.parse_table_reference()?;
+        self.expect_token(Token::Set)?;
+        let sets = self.parse_assignments()?;
+        let where_clause = self.parse_where_clause()?;
+        let order_by = self.parse_order_by()?;
+        let limit = self.parse_limit()?;
+
+        Ok(Statement::Update(UpdateStatement {
+            table,
+            sets,
+            where_clause,
+            order_by,
+            limit,
+        }))
+    }
+
+    fn parse_delete(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Delete)?;
+        self.expect_token(Token::From)?;
+        let table = self.parse_table_reference()?;
+        let where_clause = self.parse_where_clause()?;
+        let order_by = self.parse_order_by()?;
+        let limit = self.parse_limit()?;
+
+        Ok(Statement::Delete(DeleteStatement {
+            table,
+            where_clause,
+            order_by,
+            limit,
+        }))
+    }
+
+    fn parse_create(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Create)?;
+        if matches!(self.current_token, Token::Unique | Token::Index) {
+            return self.parse_create_index();
+        }
+        if self.at_word("TYPE") {
+            self.next_token()?;
+            return self.parse_create_type();
+        }
+        let temporary = self.consume(Token::Temporary)?;
+        self.expect_token(Token::Table)?;
+        let if_not_exists = if self.consume(Token::If)? {
+            self.expect_token(Token::Not)?;
+            self.expect_token(Token::Exists)?;
+            true
+        } else {
+            false
+        };
+        let table = self.parse_table_reference()?;
+
+        self.expect_token(Token::LeftParen)?;
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        loop {
+            match self.current_token {
+                Token::Constraint | Token::Primary | Token::Unique | Token::Foreign | Token::Check => {
+                    constraints.push(self.parse_table_constraint()?);
+                }
+                _ => columns.push(self.parse_column_def()?),
+            }
+            if !self.consume(Token::Comma)? {
+                break;
+            }
+        }
+        self.expect_token(Token::RightParen)?;
+
+        Ok(Statement::Create(CreateStatement {
+            temporary,
+            if_not_exists,
+            table,
+            columns,
+            constraints,
+        }))
+    }
+
+    fn parse_create_type(&mut self) -> Result<Statement, Error> {
+        let if_not_exists = if self.consume(Token::If)? {
+            self.expect_token(Token::Not)?;
+            self.expect_token(Token::Exists)?;
+            true
+        } else {
+            false
+        };
+        let name = self.parse_identifier()?;
+        self.expect_token(Token::As)?;
+        let is_struct = if self.at_word("STRUCT") {
+            true
+        } else if self.at_word("ENUM") {
+            false
+        } else {
+            return Err(Error::Syntax(format!("Expected STRUCT or ENUM, got {:?}", self.current_token)));
+        };
+        self.next_token()?;
+        self.expect_token(Token::LeftParen)?;
+        let (mut fields, mut variants) = (Vec::new(), Vec::new());
+        loop {
+            let member = self.parse_identifier()?;
+            if is_struct {
+                self.expect_token(Token::Colon)?;
+                fields.push((member, self.parse_data_type()?));
+            } else if self.consume(Token::LeftParen)? {
+                variants.push((member, Some(self.parse_data_type()?)));
+                self.expect_token(Token::RightParen)?;
+            } else {
+                variants.push((member, None));
+            }
+            if !self.consume(Token::Comma)? {
+                break;
+            }
+        }
+        self.expect_token(Token::RightParen)?;
+        let definition = if is_struct { TypeDefinition::Struct(fields) } else { TypeDefinition::Enum(variants) };
+        Ok(Statement::CreateType(CreateTypeStatement { name, if_not_exists, definition }))
+    }
+
+    fn parse_create_index(&mut self) -> Result<Statement, Error> {
+        let unique = self.consume(Token::Unique)?;
+        self.expect_token(Token::Index)?;
+        let if_not_exists = if self.consume(Token::If)? {
+            self.expect_token(Token::Not)?;
+            self.expect_token(Token::Exists)?;
+            true
+        } else {
+            false
+        };
+        let name = self.parse_identifier()?;
+        self.expect_token(Token::On)?;
+        let table = self.parse_table_reference()?;
+        let method = if self.consume(Token::Using)? {
+            match self.parse_identifier()?.to_ascii_lowercase().as_str() {
+                "btree" => IndexMethod::BTree,
+                "hash" => IndexMethod::Hash,
+                other => return Err(Error::Syntax(format!("Unknown index method {}", other))),
+            }
+        } else {
+            IndexMethod::default()
+        };
+        self.expect_token(Token::LeftParen)?;
+        let mut columns = vec![self.parse_field_path()?];
+        while self.consume(Token::Comma)? {
+            columns.push(self.parse_field_path()?);
+        }
+        self.expect_token(Token::RightParen)?;
+
+        Ok(Statement::CreateIndex(CreateIndexStatement {
+            name,
+            unique,
+            if_not_exists,
+            table,
+            method,
+            columns,
+        }))
+    }
+
+    fn parse_column_def(&mut self) -> Result<ColumnDef, Error> {
+        let name = self.parse_identifier()?;
+        let data_type = self.parse_data_type()?;
+
+        let mut constraints = Vec::new();
+        loop {
+            let constraint = match self.current_token {
+                Token::Not => {
+                    self.next_token()?;
+                    self.expect_token(Token::Null)?;
+                    ColumnConstraint::NotNull
+                }
+                Token::Null => {
+                    self.next_token()?;
+                    ColumnConstraint::Null
+                }
+                Token::Primary => {
+                    self.next_token()?;
+                    self.expect_token(Token::Key)?;
+                    ColumnConstraint::PrimaryKey
+                }
+                Token::Unique => {
+                    self.next_token()?;
+                    self.consume(Token::Key)?;
+                    ColumnConstraint::Unique
+                }
+                Token::Default => {
+                    self.next_token()?;
+                    ColumnConstraint::Default(self.parse_expr(0)?)
+                }
+                Token::Check => {
+                    self.next_token()?;
+                    self.expect_token(Token::LeftParen)?;
+                    let expr = self.parse_expr(0)?;
+                    self.expect_token(Token::RightParen)?;
+                    ColumnConstraint::Check(expr)
+                }
+                Token::References => {
+                    self.next_token()?;
+                    let table = self.parse_identifier()?;
+                    self.expect_token(Token::LeftParen)?;
+                    let column = self.parse_identifier()?;
+                    self.expect_token(Token::RightParen)?;
+                    let (on_delete, on_update) = self.parse_referential_actions()?;
+                    ColumnConstraint::ForeignKey {
+                        table,
+                        column,
+                        on_delete,
+                        on_update,
+                    }
+                }
+                _ => break,
+            };
+            constraints.push(constraint);
+        }
+
+        Ok(ColumnDef {
+            name,
+            data_type,
+            constraints,
+        })
+    }
+
+    fn parse_table_constraint(&mut self) -> Result<TableConstraint, Error> {
+        let name = if self.consume(Token::Constraint)? {
+            Some(self.parse_identifier()?)
+        } else {
+            None
+        };
+
+        match self.current_token {
+            Token::Primary => {
+                self.next_token()?;
+                self.expect_token(Token::Key)?;
+                let columns = self.parse_paren_identifier_list()?;
+                Ok(TableConstraint::PrimaryKey { name, columns })
+            }
+            Token::Unique => {
+                self.next_token()?;
+                self.consume(Token::Key)?;
+                let columns = self.parse_paren_identifier_list()?;
+                Ok(TableConstraint::Unique { name, columns })
+            }
+            Token::Foreign => {
+                self.next_token()?;
+                self.expect_token(Token::Key)?;
+                let columns = self.parse_paren_identifier_list()?;
+                self.expect_token(Token::References)?;
+                let ref_table = self.parse_identifier()?;
+                let ref_columns = self.parse_paren_identifier_list()?;
+                let (on_delete, on_update) = self.parse_referential_actions()?;
+                Ok(TableConstraint::ForeignKey {
+                    name,
+                    columns,
+                    ref_table,
+                    ref_columns,
+                    on_delete,
+                    on_update,
+                })
+            }
+            Token::Check => {
+                self.next_token()?;
+                self.expect_token(Token::LeftParen)?;
+                let expr = self.parse_expr(0)?;
+                self.expect_token(Token::RightParen)?;
+                Ok(TableConstraint::Check { name, expr })
+            }
+            _ => Err(Error::Syntax(format!(
+                "Expected PRIMARY KEY, UNIQUE, FOREIGN KEY or CHECK, got {:?}",
+                self.current_token
+            ))),
+        }
+    }
+
+    fn parse_referential_actions(
+        &mut self,
+    ) -> Result<(Option<ReferentialAction>, Option<ReferentialAction>), Error> {
+        let mut on_delete = None;
+        let mut on_update = None;
+        while matches!(self.current_token, Token::On) {
+            self.next_token()?;
+            let is_delete = match self.current_token {
+                Token::Delete => true,
+                Token::Update => false,
+                _ => return Err(Error::Syntax("Expected DELETE or UPDATE after ON".to_string())),
+            };
+            self.next_token()?;
+            let action = if self.consume(Token::Cascade)? {
+                ReferentialAction::Cascade
+            } else if self.consume(Token::Set)? {
+                if self.consume(Token::Null)? {
+                    ReferentialAction::SetNull
+                } else {
+                    self.expect_token(Token::Default)?;
+                    ReferentialAction::SetDefault
+                }
+            } else if self.at_word("RESTRICT") {
+                self.next_token()?;
+                ReferentialAction::Restrict
+            } else if self.at_word("NO") {
+                self.next_token()?;
+                if !self.at_word("ACTION") {
+                    return Err(Error::Syntax("Expected ACTION after NO".to_string()));
+                }
+                self.next_token()?;
+                ReferentialAction::NoAction
+            } else {
+                return Err(Error::Syntax(format!(
+                    "Expected referential action, got {:?}",
+                    self.current_token
+                )));
+            };
+            if is_delete {
+                on_delete = Some(action);
+            } else {
+                on_update = Some(action);
+            }
+        }
+        Ok((on_delete, on_update))
+    }
+
+    fn parse_data_type(&mut self) -> Result<DataType, Error> {
+        let written = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            _ => return Err(Error::Syntax(format!(
+                "Expected data type, got {:?}",
+                self.current_token
+            ))),
+        };
+        self.next_token()?;
+
+        let data_type = match written.to_uppercase().as_str() {
+            "TINYINT" | "I8" => DataType::TinyInt,
+            "SMALLINT" | "I16" => DataType::SmallInt,
+            "INT" | "INTEGER" => DataType::Integer(self.parse_optional_length()?),
+            "I32" => DataType::Integer(None),
+            "BIGINT" | "I64" => DataType::BigInt,
+            "U8" => DataType::UnsignedTinyInt,
+            "U16" => DataType::UnsignedSmallInt,
+            "U32" => DataType::UnsignedInteger,
+            "U64" => DataType::UnsignedBigInt,
+            "REAL" | "F32" => DataType::Real,
+            "FLOAT" | "DOUBLE" => DataType::Float(self.parse_optional_precision()?),
+            "F64" => DataType::Float(None),
+            "DECIMAL" | "NUMERIC" => DataType::Decimal(self.parse_optional_precision()?),
+            "CHAR" => DataType::Char(self.parse_optional_length()?),
+            "VARCHAR" => DataType::Varchar(self.parse_optional_length()?),
+            "TEXT" | "STRING" => DataType::Text,
+            "DATE" => DataType::Date,
+            "TIME" => DataType::Time,
+            "DATETIME" => DataType::DateTime,
+            "TIMESTAMP" => DataType::Timestamp,
+            "BOOLEAN" | "BOOL" => DataType::Boolean,
+            "BINARY" | "VARBINARY" | "BLOB" | "BYTEA" => {
+                DataType::Binary(self.parse_optional_length()?)
+            }
+            "JSON" => DataType::Json,
+            "UUID" => DataType::Uuid,
+            "INTERVAL" => DataType::Interval,
+            _ => DataType::Custom(written),
+        };
+
+        // MySQL-style `UNSIGNED` suffix on integer types.
+        if self.at_word("UNSIGNED") {
+            let unsigned = match data_type {
+                DataType::TinyInt => DataType::UnsignedTinyInt,
+                DataType::SmallInt => DataType::UnsignedSmallInt,
+                DataType::Integer(_) => DataType::UnsignedInteger,
+                DataType::BigInt => DataType::UnsignedBigInt,
+                _ => return Err(Error::Syntax(format!("{} cannot be UNSIGNED", data_type))),
+            };
+            self.next_token()?;
+            return Ok(unsigned);
+        }
+
+        Ok(data_type)
+    }
+
+    fn parse_optional_length(&mut self) -> Result<Option<u32>, Error> {
+        if !self.consume(Token::LeftParen)? {
+            return Ok(None);
+        }
+        let length = self.parse_u32()?;
+        self.expect_token(Token::RightParen)?;
+        Ok(Some(length))
+    }
+
+    fn parse_optional_precision(&mut self) -> Result<Option<(u32, u32)>, Error> {
+        if !self.consume(Token::LeftParen)? {
+            return Ok(None);
+        }
+        let precision = self.parse_u32()?;
+        let scale = if self.consume(Token::Comma)? {
+            self.parse_u32()?
+        } else {
+            0
+        };
+        self.expect_token(Token::RightParen)?;
+        Ok(Some((precision, scale)))
+    }
+
+    fn parse_u32(&mut self) -> Result<u32, Error> {
+        let value = match &self.current_token {
+            Token::Number(n) => n
+                .parse()
+                .map_err(|_| Error::Syntax(format!("Invalid length: {}", n)))?,
+            _ => return Err(Error::Syntax(format!(
+                "Expected number, got {:?}",
+                self.current_token
+            ))),
+        };
+        self.next_token()?;
+        Ok(value)
+    }
+
+    fn parse_drop(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Drop)?;
+        let temporary = self.consume(Token::Temporary)?;
+        self.expect_token(Token::Table)?;
+        let if_exists = if self.consume(Token::If)? {
+            self.expect_token(Token::Exists)?;
+            true
+        } else {
+            false
+        };
+        let table = self.parse_table_reference()?;
+        let cascade = self.consume(Token::Cascade)?;
+
+        Ok(Statement::Drop(DropStatement {
+            temporary,
+            if_exists,
+            table,
+            cascade,
+        }))
+    }
+
+    fn parse_alter(&mut self) -> Result<Statement, Error> {
+        self.expect_token(Token::Alter)?;
+        if self.at_word("TYPE") {
+            self.next_token()?;
+            return self.parse_alter_type();
+        }
+        self.expect_token(Token::Table)?;
+        let table = self.parse_table_reference()?;
+
+        let mut actions = Vec::new();
+        loop {
+            actions.push(self.parse_alter_action()?);
+            if !self.consume(Token::Comma)? {
+                break;
+            }
+        }
+
+        Ok(Statement::Alter(AlterStatement { table, actions }))
+    }
+
+    fn parse_alter_action(&mut self) -> Result<AlterAction, Error> {
+        match self.current_token {
+            Token::Add => {
+                self.next_token()?;
+                match self.current_token {
+                    Token::Constraint | Token::Primary | Token::Unique | Token::Foreign | Token::Check => {
+                        Ok(AlterAction::AddConstraint(self.parse_table_constraint()?))
+                    }
+                    _ => {
+                        self.consume(Token::Column)?;
+                        Ok(AlterAction::AddColumn(self.parse_column_def()?))
+                    }
+                }
+            }
+            Token::Drop => {
+                self.next_token()?;
+                if self.consume(Token::Constraint)? {
+                    Ok(AlterAction::DropConstraint(self.parse_identifier()?))
+                } else {
+                    self.consume(Token::Column)?;
+                    Ok(AlterAction::DropColumn(self.parse_identifier()?))
+                }
+            }
+            Token::Modify => {
+                self.next_token()?;
+                self.consume(Token::Column)?;
+                Ok(AlterAction::ModifyColumn(self.parse_column_def()?))
+            }
+            Token::Rename => {
+                self.next_token()?;
+                self.consume(Token::Column)?;
+                let from = self.parse_identifier()?;
+                self.expect_token(Token::To)?;
+                let to = self.parse_identifier()?;
+                Ok(AlterAction::RenameColumn(from, to))
+            }
+            _ => Err(Error::Syntax(format!(
+                "Expected ADD, DROP, MODIFY or RENAME, got {:?}",
+                self.current_token
+            ))),
+        }
+    }
+
+    fn parse_alter_type(&mut self) -> Result<Statement, Error> {
+        let name = self.parse_identifier()?;
+        let action = match self.current_token {
+            Token::Add => {
+                self.next_token()?;
+                if self.at_word("FIELD") {
+                    self.next_token()?;
+                    let name = self.parse_identifier()?;
+                    self.expect_token(Token::Colon)?;
+                    let data_type = self.parse_data_type()?;
+                    let default = if self.consume(Token::Default)? { Some(self.parse_expr(0)?) } else { None };
+                    AlterTypeAction::AddField { name, data_type, default }
+                } else if self.at_word("VARIANT") {
+                    self.next_token()?;
+                    let name = self.parse_identifier()?;
+                    let payload = if self.consume(Token::LeftParen)? {
+                        let payload = self.parse_data_type()?;
+                        self.expect_token(Token::RightParen)?;
+                        Some(payload)
+                    } else {
+                        None
+                    };
+                    AlterTypeAction::AddVariant { name, payload }
+                } else {
+                    return Err(Error::Syntax(format!("Expected FIELD or VARIANT, got {:?}", self.current_token)));
+                }
+            }
+            Token::Rename if matches!(&self.peek_token, Token::Identifier(w) if w.eq_ignore_ascii_case("FIELD")) => {
+                self.next_token()?;
+                self.next_token()?;
+                let from = self.parse_identifier()?;
+                self.expect_token(Token::To)?;
+                AlterTypeAction::RenameField(from, self.parse_identifier()?)
+            }
+            _ => {
+                return Err(Error::Syntax(format!(
+                    "Expected ADD FIELD, ADD VARIANT or RENAME FIELD, got {:?}",
+                    self.current_token
+                )))
+            }
+        };
+        Ok(Statement::AlterType(AlterTypeStatement { name, action }))
+    }
+
     fn parse_expr_list(&mut self) -> Result<Vec<Expr>, Error> {
         let mut exprs = Vec::new();
         loop {
@@ -487,16 +1466,17 @@ impl<'a> Parser<'a> {
         Ok(exprs)
     }
 
+    fn parse_paren_identifier_list(&mut self) -> Result<Vec<String>, Error> {
+        self.expect_token(Token::LeftParen)?;
+        let idents = self.parse_identifier_list()?;
+        self.expect_token(Token::RightParen)?;
+        Ok(idents)
+    }
+
     fn parse_identifier_list(&mut self) -> Result<Vec<String>, Error> {
         let mut idents = Vec::new();
         loop {
-            match &self.current_token {
-                Token::Identifier(name) => {
-                    idents.push(name.clone());
-                    self.next_token()?;
-                }
-                _ => return Err(Error::Syntax("Expected identifier".to_string())),
-            }
+            idents.push(self.parse_identifier()?);
             if !matches!(self.current_token, Token::Comma) {
                 break;
             }
@@ -506,6 +1486,25 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Integer literals become `Int` (or `UInt64`/`Decimal` when too large),
+/// literals with a decimal point become exact `Decimal`s and literals with an
+/// exponent become `Float`s.
+fn parse_number(n: &str) -> Result<Value, Error> {
+    let invalid = || Error::Syntax(format!("Invalid number: {}", n));
+    if n.contains(['e', 'E']) {
+        return n.parse().map(Value::Float).map_err(|_| invalid());
+    }
+    if !n.contains('.') {
+        if let Ok(i) = n.parse::<i64>() {
+            return Ok(Value::Int(i));
+        }
+        if let Ok(u) = n.parse::<u64>() {
+            return Ok(Value::UInt64(u));
+        }
+    }
+    n.parse::<BigDecimal>().map(Value::Decimal).map_err(|_| invalid())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -515,7 +1514,7 @@ mod tests {
         let input = "SELECT id, name FROM users";
         let mut parser = Parser::new(input).unwrap();
         let stmt = parser.parse_statement().unwrap();
-        
+
         match stmt {
             Statement::Select(select) => {
                 assert_eq!(select.columns.len(), 2);
@@ -531,7 +1530,7 @@ mod tests {
         let input = "SELECT * FROM users WHERE age > 18";
         let mut parser = Parser::new(input).unwrap();
         let stmt = parser.parse_statement().unwrap();
-        
+
         match stmt {
             Statement::Select(select) => {
                 assert!(select.where_clause.is_some());
@@ -553,4 +1552,174 @@ mod tests {
         let mut parser = Parser::new(input).unwrap();
         parser.parse_statement().unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_predicates() {
+        let stmt = parse_sql(
+            "SELECT * FROM t WHERE a NOT IN (1, 2) AND b LIKE 'x%' \
+             AND c BETWEEN 1 AND 5 AND NOT d = 1 AND e IS NOT NULL",
+        )
+        .unwrap();
+        let Statement::Select(select) = stmt else { panic!("Expected SELECT statement") };
+        assert_eq!(
+            select.where_clause.unwrap().to_string(),
+            "(((((a NOT IN (1, 2)) AND (b LIKE 'x%')) AND ((c >= 1) AND (c <= 5))) \
+             AND NOT ((d = 1))) AND (e IS NOT NULL))"
+        );
+    }
+
+    #[test]
+    fn test_dml_and_ddl() {
+        let stmt = parse_sql("INSERT INTO users (id, name) VALUES (1, 'a'), (2, 'b')").unwrap();
+        let Statement::Insert(insert) = stmt else { panic!("Expected INSERT statement") };
+        assert_eq!(insert.columns, vec!["id", "name"]);
+        assert_eq!(insert.values.len(), 2);
+
+        let stmt = parse_sql("UPDATE users SET name = 'c' WHERE id = 1").unwrap();
+        assert!(matches!(stmt, Statement::Update(u) if u.sets.len() == 1));
+
+        let stmt = parse_sql(
+            "CREATE TABLE IF NOT EXISTS users (\
+                id BIGINT UNSIGNED PRIMARY KEY, \
+                name VARCHAR(64) NOT NULL DEFAULT 'anon', \
+                score DECIMAL(10, 2), \
+                CONSTRAINT fk FOREIGN KEY (team) REFERENCES teams (id) ON DELETE CASCADE)",
+        )
+        .unwrap();
+        let Statement::Create(create) = stmt else { panic!("Expected CREATE statement") };
+        assert!(create.if_not_exists);
+        assert_eq!(create.columns[0].data_type, DataType::UnsignedBigInt);
+        assert_eq!(create.columns[1].data_type, DataType::Varchar(Some(64)));
+        assert_eq!(create.columns[2].data_type, DataType::Decimal(Some((10, 2))));
+        assert_eq!(create.constraints.len(), 1);
+
+        let stmt = parse_sql("ALTER TABLE users ADD COLUMN age INT, RENAME COLUMN name TO full_name").unwrap();
+        let Statement::Alter(alter) = stmt else { panic!("Expected ALTER statement") };
+        assert_eq!(alter.actions[1], AlterAction::RenameColumn("name".into(), "full_name".into()));
+
+        assert!(matches!(parse_sql("DROP TABLE IF EXISTS users").unwrap(), Statement::Drop(d) if d.if_exists));
+        assert!(matches!(parse_sql("DELETE FROM users WHERE id = 1").unwrap(), Statement::Delete(_)));
+        assert_eq!(parse_sql("checkpoint").unwrap(), Statement::Checkpoint);
+        assert!(parse_sql("CHECKPOINT users").is_err());
+    }
+
+    #[test]
+    fn test_create_index_and_field_paths() {
+        let stmt = parse_sql("CREATE UNIQUE INDEX by_phone ON users (phone.country_code, tags[0], id)").unwrap();
+        let Statement::CreateIndex(index) = stmt else { panic!("Expected CREATE INDEX statement") };
+        assert!(index.unique);
+        assert_eq!(index.method, IndexMethod::BTree);
+        let columns: Vec<String> = index.columns.iter().map(|c| c.to_string()).collect();
+        assert_eq!(columns, vec!["phone.country_code", "tags[0]", "id"]);
+        assert!(parse_sql("CREATE INDEX i ON t (a[x])").is_err());
+        let stmt = parse_sql("CREATE INDEX by_token ON sessions USING hash (token)").unwrap();
+        let Statement::CreateIndex(index) = stmt else { panic!("Expected CREATE INDEX statement") };
+        assert_eq!((index.table.name.as_str(), index.method), ("sessions", IndexMethod::Hash));
+        assert!(parse_sql("CREATE INDEX i ON t USING gist (a)").is_err());
+
+        // Dotted names past schema.table.column are fields.
+        let stmt = parse_sql("SELECT s.users.phone.country_code, tags[1].name FROM users").unwrap();
+        let Statement::Select(select) = stmt else { panic!("Expected SELECT statement") };
+        let Expr::Field { expr, step, .. } = &select.columns[0].expr else { panic!("Expected field") };
+        assert_eq!(*step, PathStep::Field("country_code".into()));
+        assert!(matches!(&**expr, Expr::Column(c) if c.schema.as_deref() == Some("s") && c.name == "phone"));
+        assert_eq!(select.columns[1].expr.to_string(), "tags[1].name");
+    }
+
+    #[test]
+    fn test_casts() {
+        let stmt = parse_sql(
+            "SELECT CAST(a AS DECIMAL(10, 2)), TRY_CAST('x' AS INT), -b::BIGINT, '2024-01-01'::date FROM t",
+        )
+        .unwrap();
+        let Statement::Select(select) = stmt else { panic!("Expected SELECT statement") };
+        let columns: Vec<String> = select.columns.iter().map(|c| c.expr.to_string()).collect();
+        assert_eq!(
+            columns,
+            vec![
+                "CAST(a AS DECIMAL(10, 2))",
+                "TRY_CAST('x' AS INTEGER)",
+                "-(CAST(b AS BIGINT))",
+                "CAST('2024-01-01' AS DATE)",
+            ]
+        );
+        assert!(parse_sql("SELECT a:b FROM t").is_err());
+
+        let expr = parse_expr("ts + INTERVAL '1 day 02:00:00' > interval").unwrap();
+        assert_eq!(expr.to_string(), "((ts + INTERVAL '1 day 02:00:00') > interval)");
+        assert_eq!(parse_expr(&expr.to_string()).unwrap().to_string(), expr.to_string());
+        assert!(matches!(parse_expr("INTERVAL '3 fortnights'"), Err(Error::Syntax(_))));
+    }
+
+    #[test]
+    fn test_spans() {
+        let stmt = parse_sql("SELECT id\nFROM users WHERE  name > 5").unwrap();
+        let Statement::Select(select) = stmt else { panic!("Expected SELECT statement") };
+        match select.where_clause.unwrap() {
+            Expr::Binary { left, span, .. } => {
+                assert_eq!((span.line, span.column), (2, 24));
+                let Expr::Column(col) = *left else { panic!("Expected column") };
+                assert_eq!((col.span.line, col.span.column), (2, 19));
+            }
+            other => panic!("Expected binary expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_type() {
+        let stmt = parse_sql("CREATE TYPE address AS STRUCT (street: TEXT, zip: VARCHAR(8))").unwrap();
+        let Statement::CreateType(create) = stmt else { panic!("Expected CREATE TYPE statement") };
+        assert_eq!((create.name.as_str(), create.if_not_exists), ("address", false));
+        let fields = vec![("street".into(), DataType::Text), ("zip".into(), DataType::Varchar(Some(8)))];
+        assert_eq!(create.definition, TypeDefinition::Struct(fields));
+
+        let stmt = parse_sql("CREATE TYPE IF NOT EXISTS shape AS ENUM (Empty, Circle (Point))").unwrap();
+        let Statement::CreateType(create) = stmt else { panic!("Expected CREATE TYPE statement") };
+        assert!(create.if_not_exists);
+        let variants = vec![("Empty".into(), None), ("Circle".into(), Some(DataType::Custom("Point".into())))];
+        assert_eq!(create.definition, TypeDefinition::Enum(variants));
+
+        assert!(parse_sql("CREATE TYPE address AS STRUCT (street TEXT)").is_err());
+        assert!(parse_sql("CREATE TYPE address AS RECORD (street: TEXT)").is_err());
+        assert!(parse_sql("CREATE TYPE address AS STRUCT ()").is_err());
+    }
+
+    #[test]
+    fn test_alter_type() {
+        let stmt = parse_sql("ALTER TYPE address ADD FIELD country: VARCHAR(2) DEFAULT 'GB'").unwrap();
+        let Statement::AlterType(alter) = stmt else { panic!("Expected ALTER TYPE statement") };
+        assert_eq!(alter.name, "address");
+        let AlterTypeAction::AddField { name, data_type, default } = alter.action else { panic!("Expected ADD FIELD") };
+        assert_eq!((name.as_str(), data_type), ("country", DataType::Varchar(Some(2))));
+        assert_eq!(default.unwrap().to_string(), "'GB'");
+
+        let stmt = parse_sql("ALTER TYPE shape ADD VARIANT Square (Point)").unwrap();
+        let Statement::AlterType(AlterTypeStatement { action, .. }) = stmt else { panic!("Expected ALTER TYPE") };
+        assert_eq!(
+            action,
+            AlterTypeAction::AddVariant { name: "Square".into(), payload: Some(DataType::Custom("Point".into())) }
+        );
+        let stmt = parse_sql("ALTER TYPE address RENAME FIELD zip TO postcode").unwrap();
+        let Statement::AlterType(AlterTypeStatement { action, .. }) = stmt else { panic!("Expected ALTER TYPE") };
+        assert_eq!(action, AlterTypeAction::RenameField("zip".into(), "postcode".into()));
+
+        assert!(parse_sql("ALTER TYPE address ADD FIELD country VARCHAR(2)").is_err());
+        assert!(parse_sql("ALTER TYPE address DROP FIELD zip").is_err());
+        let stmt = parse_sql("CREATE TABLE people (id INT, home address)").unwrap();
+        let Statement::Create(create) = stmt else { panic!("Expected CREATE statement") };
+        assert_eq!(create.columns[1].data_type, DataType::Custom("address".into()));
+    }
+
+    #[test]
+    fn test_parse_statements() {
+        let script = "-- the first table\nCREATE TABLE a (id INT);;\n\n/* and an index */ CREATE INDEX a_id ON a (id)";
+        let statements = parse_statements(script).unwrap();
+        assert!(matches!(statements[..], [Statement::Create(_), Statement::CreateIndex(_)]));
+        assert!(parse_statements(" ; -- nothing\n").unwrap().is_empty());
+
+        let err = parse_statements("CREATE TABLE a (id INT);\nCREATE TABLE b (id INT) DROP TABLE a").unwrap_err();
+        assert!(err.to_string().contains("on line 2"), "{}", err);
+        let err = parse_statements("DROP TABLE a;\n\nSELECT FROM;").unwrap_err();
+        assert!(err.to_string().contains("on line 3"), "{}", err);
+    }
+}
@@ -0,0 +1,229 @@
+// src/planner.rs
+//
+// Access path selection: whether a query reads a table in full or through
+// one of its indexes.
+//
+// The WHERE clause is split into AND-ed conjuncts, and those comparing a
+// column or a field inside one (`users.phone.country_code = 44`) with a
+// constant are matched against each index's key columns: equalities on a
+// leading run of them, then bounds on the column after. A hash index needs
+// equalities on all of its columns and takes no bounds. The index matching
+// the most columns wins, and a unique index pinned down by equalities on all
+// of its columns wins outright; between otherwise equal matches a hash index
+// wins, being one bucket read away. The filter still runs over the rows an
+// index scan returns, so an index only narrows down which rows are looked at.
+
+use std::ops::Bound;
+
+use crate::catalog::IndexSchema;
+use crate::index::KeyRange;
+use crate::parser::ast::{BinaryOp, Expr, FieldPath, IndexMethod, UnaryOp};
+use crate::types::cast;
+use crate::types::Value;
+
+/// How a query reads one table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessPath {
+    SeqScan,
+    IndexScan { index: IndexSchema, range: KeyRange },
+}
+
+/// A conjunct comparing a field path with a constant.
+struct Comparison {
+    path: FieldPath,
+    op: BinaryOp,
+    value: Value,
+}
+
+/// Picks the access path for the table bound as `table` (its alias or
+/// name) given its indexes and an analyzed filter.
+pub fn access_path(table: &str, indexes: &[IndexSchema], filter: Option<&Expr>) -> AccessPath {
+    let mut conjuncts = Vec::new();
+    if let Some(filter) = filter {
+        split_conjuncts(filter, &mut conjuncts);
+    }
+    let comparisons: Vec<Comparison> = conjuncts.into_iter().filter_map(|expr| comparison(table, expr)).collect();
+
+    let mut best: Option<((bool, usize, bool, bool), AccessPath)> = None;
+    for index in indexes {
+        let mut eq = Vec::new();
+        for column in &index.columns {
+            match comparisons.iter().find(|c| c.op == BinaryOp::Eq && c.path.matches(column)) {
+                Some(c) => eq.push(c.value.clone()),
+                None => break,
+            }
+        }
+        let hash = index.method == IndexMethod::Hash;
+        if hash && eq.len() < index.columns.len() {
+            continue;
+        }
+        // Any one bound keeps every row the filter could accept, so the
+        // last of several wins.
+        let (mut lower, mut upper) = (Bound::Unbounded, Bound::Unbounded);
+        if let Some(column) = index.columns.get(eq.len()) {
+            for c in comparisons.iter().filter(|c| c.path.matches(column)) {
+                match c.op {
+                    BinaryOp::Gt => lower = Bound::Excluded(c.value.clone()),
+                    BinaryOp::GtEq => lower = Bound::Included(c.value.clone()),
+                    BinaryOp::Lt => upper = Bound::Excluded(c.value.clone()),
+                    BinaryOp::LtEq => upper = Bound::Included(c.value.clone()),
+                    _ => {}
+                }
+            }
+        }
+        let ranged = !matches!((&lower, &upper), (Bound::Unbounded, Bound::Unbounded));
+        if eq.is_empty() && !ranged {
+            continue;
+        }
+        let score = (index.unique && eq.len() == index.columns.len(), eq.len(), ranged, hash);
+        if best.as_ref().is_none_or(|(best, _)| score > *best) {
+            let range = KeyRange { eq, lower, upper };
+            best = Some((score, AccessPath::IndexScan { index: index.clone(), range }));
+        }
+    }
+    best.map_or(AccessPath::SeqScan, |(_, path)| path)
+}
+
+fn split_conjuncts<'e>(expr: &'e Expr, out: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary { left, op: BinaryOp::And, right, .. } => {
+            split_conjuncts(left, out);
+            split_conjuncts(right, out);
+        }
+        expr => out.push(expr),
+    }
+}
+
+fn comparison(table: &str, expr: &Expr) -> Option<Comparison> {
+    let Expr::Binary { left, op, right, .. } = expr else { return None };
+    let flipped = match op {
+        BinaryOp::Eq => BinaryOp::Eq,
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::LtEq => BinaryOp::GtEq,
+        BinaryOp::GtEq => BinaryOp::LtEq,
+        _ => return None,
+    };
+    if let (Some(path), Some(value)) = (field_path(table, left), constant(right)) {
+        return Some(Comparison { path, op: op.clone(), value });
+    }
+    let (path, value) = (field_path(table, right)?, constant(left)?);
+    Some(Comparison { path, op: flipped, value })
+}
+
+/// The column or field of `table` an expression reads, if that is all it
+/// does.
+fn field_path(table: &str, expr: &Expr) -> Option<FieldPath> {
+    match expr {
+        Expr::Column(col) if col.table.as_deref().is_none_or(|t| t.eq_ignore_ascii_case(table)) => {
+            Some(FieldPath::column(col.name.clone()))
+        }
+        Expr::Field { expr, step, .. } => {
+            let mut path = field_path(table, expr)?;
+            path.steps.push(step.clone());
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+/// The value of a constant expression; comparisons with NULL match nothing,
+/// so it is not one.
+fn constant(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Literal(Value::Null) => None,
+        Expr::Literal(value) => Some(value.clone()),
+        Expr::Cast { expr, data_type, .. } => cast::cast(&constant(expr)?, data_type).ok(),
+        Expr::Unary { op: UnaryOp::Negative, expr, .. } => match constant(expr)? {
+            Value::Int(i) => i.checked_neg().map(Value::Int),
+            Value::Float(f) => Some(Value::Float(-f)),
+            Value::Decimal(d) => Some(Value::Decimal(-d)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::catalog::{Catalog, ColumnSchema, MemoryCatalog, TableSchema};
+    use crate::parser::ast::{DataType, Statement};
+    use crate::parser::parse_sql;
+
+    fn catalog() -> MemoryCatalog {
+        let mut catalog = MemoryCatalog::new();
+        catalog
+            .create_table(TableSchema::new(
+                "users",
+                vec![
+                    ColumnSchema::new("id", DataType::BigInt).primary_key(),
+                    ColumnSchema::new("age", DataType::TinyInt),
+                    ColumnSchema::new("phone", DataType::Json),
+                ],
+            ))
+            .unwrap();
+        for sql in [
+            "CREATE UNIQUE INDEX by_id ON users (id)",
+            "CREATE INDEX by_phone ON users (phone.country_code, phone.area_code)",
+            "CREATE INDEX by_age ON users USING HASH (age)",
+        ] {
+            let Statement::CreateIndex(create) = parse_sql(sql).unwrap() else { panic!("Expected CREATE INDEX") };
+            catalog.create_index(IndexSchema::from_create(&create)).unwrap();
+        }
+        catalog
+    }
+
+    fn plan(sql: &str) -> AccessPath {
+        let catalog = catalog();
+        let analyzed = Analyzer::new(&catalog).analyze(&parse_sql(sql).unwrap()).unwrap();
+        let Statement::Select(select) = analyzed.statement else { panic!("Expected SELECT") };
+        let table = select.from.alias.as_deref().unwrap_or(&select.from.name);
+        access_path(table, &catalog.indexes("users"), select.where_clause.as_ref())
+    }
+
+    fn index_scan(sql: &str) -> (String, KeyRange) {
+        match plan(sql) {
+            AccessPath::IndexScan { index, range } => (index.name, range),
+            AccessPath::SeqScan => panic!("Expected an index scan for {}", sql),
+        }
+    }
+
+    #[test]
+    fn test_field_path_predicates_use_indexes() {
+        let (index, range) = index_scan("SELECT id FROM users WHERE users.phone.country_code = 44 AND age > 18");
+        assert_eq!(index, "by_phone");
+        assert_eq!(range, KeyRange::eq(vec![Value::Int(44)]));
+
+        let (_, range) = index_scan("SELECT id FROM users u WHERE 415 >= u.phone.area_code AND phone.country_code = 1");
+        assert_eq!(range.eq, vec![Value::Int(1)]);
+        assert_eq!(range.upper, Bound::Included(Value::Int(415)));
+
+        let (_, range) = index_scan("SELECT id FROM users WHERE phone.country_code < -1");
+        assert_eq!((range.lower, range.upper), (Bound::Unbounded, Bound::Excluded(Value::Int(-1))));
+
+        // A unique index pinned down by equalities beats one matching more.
+        let (index, range) = index_scan(
+            "SELECT id FROM users WHERE phone.country_code = 44 AND phone.area_code = 20 AND id = 7",
+        );
+        assert_eq!((index.as_str(), range.eq), ("by_id", vec![Value::Int(7)]));
+
+        let (index, range) = index_scan("SELECT id FROM users WHERE age = 30 AND phone.area_code = 20");
+        assert_eq!((index.as_str(), range), ("by_age", KeyRange::eq(vec![Value::Int(30)])));
+    }
+
+    #[test]
+    fn test_sequential_scans() {
+        for sql in [
+            "SELECT id FROM users",
+            "SELECT id FROM users WHERE age > 3",
+            "SELECT id FROM users WHERE phone.area_code = 20",
+            "SELECT id FROM users WHERE phone.country_code = 44 OR id = 1",
+            "SELECT id FROM users WHERE phone.country_code = NULL",
+            "SELECT id FROM users WHERE phone.country_code + 1 = 45",
+        ] {
+            assert_eq!(plan(sql), AccessPath::SeqScan, "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// src/row.rs
+//! Mapping between result rows and Rust types.
+//!
+//! Most code will use `#[derive(FromRow, ToRow)]` rather than implementing
+//! these traits by hand; see the `rustdb-derive` crate for the attributes.
+
+use crate::error::Error;
+use crate::types::{FromValue, FromValues, Value};
+
+pub mod codec;
+
+/// A borrowed view of a single result row, pairing column names with values.
+#[derive(Debug, Clone)]
+pub struct RowRef<'a> {
+    columns: &'a [String],
+    values: &'a [Value],
+    prefix: String,
+}
+
+impl<'a> RowRef<'a> {
+    pub fn new(columns: &'a [String], values: &'a [Value]) -> Self {
+        RowRef {
+            columns,
+            values,
+            prefix: String::new(),
+        }
+    }
+
+    /// Returns a view in which every lookup of `name` resolves to the column
+    /// `<prefix><name>`. Used for flattened nested structs.
+    pub fn with_prefix(&self, prefix: &str) -> RowRef<'a> {
+        RowRef {
+            columns: self.columns,
+            values: self.values,
+            prefix: format!("{}{}", self.prefix, prefix),
+        }
+    }
+
+    pub fn columns(&self) -> &'a [String] {
+        self.columns
+    }
+
+    pub fn values(&self) -> &'a [Value] {
+        self.values
+    }
+
+    /// Looks up the raw value of a column by name.
+    pub fn value(&self, name: &str) -> Result<&'a Value, Error> {
+        let full = format!("{}{}", self.prefix, name);
+        self.columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(&full))
+            .and_then(|i| self.values.get(i))
+            .ok_or_else(|| Error::Type(format!("no column named \"{}\" in result row", full)))
+    }
+
+    /// Looks up a column and converts it to `T`.
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T, Error> {
+        T::from_value(self.value(name)?).map_err(|e| self.column_error(name, None, e))
+    }
+
+    /// Like [`RowRef::get`], but names the destination struct field in errors.
+    pub fn get_field<T: FromValue>(&self, name: &str, field: &str) -> Result<T, Error> {
+        let value = self
+            .value(name)
+            .map_err(|e| self.column_error(name, Some(field), e))?;
+        T::from_value(value).map_err(|e| self.column_error(name, Some(field), e))
+    }
+
+    fn column_error(&self, name: &str, field: Option<&str>, err: Error) -> Error {
+        let detail = match err {
+            Error::Type(msg) => msg,
+            other => other.to_string(),
+        };
+        match field {
+            Some(field) => Error::Type(format!(
+                "column \"{}{}\" (field {}): {}",
+                self.prefix, name, field, detail
+            )),
+            None => Error::Type(format!("column \"{}{}\": {}", self.prefix, name, detail)),
+        }
+    }
+}
+
+/// Builds a Rust value from a result row.
+pub trait FromRow: Sized {
+    fn from_row(row: &RowRef<'_>) -> Result<Self, Error>;
+}
+
+/// Turns a Rust value into named column values, e.g. for an INSERT.
+pub trait ToRow {
+    fn to_row(&self) -> Vec<(String, Value)>;
+}
+
+macro_rules! impl_tuple_from_row {
+    ($($name:ident),+) => {
+        impl<$($name: FromValue),+> FromRow for ($($name,)+) {
+            fn from_row(row: &RowRef<'_>) -> Result<Self, Error> {
+                FromValues::from_values(row.values())
+            }
+        }
+    };
+}
+
+impl_tuple_from_row!(A);
+impl_tuple_from_row!(A, B);
+impl_tuple_from_row!(A, B, C);
+impl_tuple_from_row!(A, B, C, D);
+impl_tuple_from_row!(A, B, C, D, E);
+impl_tuple_from_row!(A, B, C, D, E, F);
+impl_tuple_from_row!(A, B, C, D, E, F, G);
+impl_tuple_from_row!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromRow, ToRow};
+
+    #[derive(Debug, PartialEq, FromRow, ToRow)]
+    struct Phone {
+        country_code: u16,
+        area_code: Option<u16>,
+    }
+
+    #[derive(Debug, PartialEq, FromRow, ToRow)]
+    struct User {
+        id: u64,
+        #[rustdb(rename = "user_name")]
+        name: String,
+        email: Option<String>,
+        #[rustdb(flatten, prefix = "phone_")]
+        phone: Phone,
+        #[rustdb(skip)]
+        cached: bool,
+    }
+
+    #[derive(Debug, PartialEq, FromRow, ToRow)]
+    struct Keyed<T> {
+        key: String,
+        value: T,
+    }
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_derive_round_trip() {
+        let user = User {
+            id: 7,
+            name: "ada".to_string(),
+            email: None,
+            phone: Phone {
+                country_code: 44,
+                area_code: Some(20),
+            },
+            cached: false,
+        };
+
+        let (names, values): (Vec<String>, Vec<Value>) = user.to_row().into_iter().unzip();
+        assert_eq!(
+            names,
+            columns(&["id", "user_name", "email", "phone_country_code", "phone_area_code"])
+        );
+
+        let back = User::from_row(&RowRef::new(&names, &values)).unwrap();
+        assert_eq!(back, user);
+    }
+
+    #[test]
+    fn test_type_mismatch_message() {
+        let names = columns(&["country_code", "area_code"]);
+        let values = vec![Value::String("44".into()), Value::Null];
+        let err = Phone::from_row(&RowRef::new(&names, &values)).unwrap_err();
+        match err {
+            Error::Type(msg) => {
+                assert!(msg.contains("country_code"), "{}", msg);
+                assert!(msg.contains("Phone::country_code"), "{}", msg);
+                assert!(msg.contains("u16"), "{}", msg);
+            }
+            other => panic!("expected type error, got {:?}", other),
+        }
+
+        let names = columns(&["area_code"]);
+        let values = vec![Value::Int(1)];
+        assert!(Phone::from_row(&RowRef::new(&names, &values)).is_err());
+    }
+
+    #[test]
+    fn test_generic_rows() {
+        let keyed = Keyed { key: "k".to_string(), value: Some(3u32) };
+        let (names, values): (Vec<String>, Vec<Value>) = keyed.to_row().into_iter().unzip();
+        assert_eq!(Keyed::from_row(&RowRef::new(&names, &values)).unwrap(), keyed);
+    }
+
+    #[test]
+    fn test_tuple_rows() {
+        let names = columns(&["a", "b"]);
+        let values = vec![Value::Int(1), Value::String("x".into())];
+        let row: (i32, String) = FromRow::from_row(&RowRef::new(&names, &values)).unwrap();
+        assert_eq!(row, (1, "x".to_string()));
+    }
+}
@@ -0,0 +1,855 @@
+// src/row/codec.rs
+//! Compact on-disk format for table rows.
+//!
+//! A row is laid out as
+//!
+//! ```text
+//! | version | count | null bitmap | fixed-width slots | var offsets | var data |
+//! ```
+//!
+//! - `version` is [`RowCodec::VERSION`];
+//! - `count` is a `u16`, the number of columns the row holds: the first
+//!   `count` columns of the schema. A row stored before columns were added
+//!   to its table holds fewer than the table has, and reads each column it
+//!   lacks as the column's [`missing`](ColumnSchema::missing) value, so
+//!   adding a column never touches the rows already stored;
+//! - the null bitmap has one bit per column held (bit `i % 8` of byte
+//!   `i / 8`);
+//! - every fixed-width column has a slot at an offset known from the schema
+//!   and the count alone (zeroed when NULL), so it can be read without
+//!   touching the rest;
+//! - each variable-width column has a `u32` end offset into the var data
+//!   (a NULL column repeats the previous offset). The top bit of the offset
+//!   marks a value stored out of line by an [`ExternalStore`]; its var data
+//!   is then the reference the store handed back.
+//!
+//! A value of a custom type is variable-width. It starts with the `u16`
+//! [version](TypeSchema::version) of the type it was written under; an
+//! enum's then has the `u16` index of its variant. Then come its members
+//! (the struct's fields, or the variant's payload, if it has one), each a
+//! `0` byte for NULL or a `1` byte, a `u32` length and the member encoded on
+//! its own. A value written before members were added to its type holds
+//! only those it had then, and reads the rest as their defaults.
+//!
+//! All integers are little-endian regardless of the host.
+
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike};
+use uuid::Uuid;
+
+use crate::catalog::{custom_type_name, ColumnSchema, TableSchema, TypeKind, TypeSchema};
+use crate::error::Error;
+use crate::parser::ast::DataType;
+use crate::types::cast::cast;
+use crate::types::{Interval, Value};
+
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Fixed { offset: usize, width: usize },
+    Var { index: usize },
+}
+
+/// Fixed storage width of a type, or `None` if its values vary in length.
+fn fixed_width(data_type: &DataType) -> Option<usize> {
+    use DataType::*;
+    Some(match data_type {
+        Boolean | TinyInt | UnsignedTinyInt => 1,
+        SmallInt | UnsignedSmallInt => 2,
+        Integer(_) | UnsignedInteger | Real | Date => 4,
+        BigInt | UnsignedBigInt | Float(_) | Time => 8,
+        DateTime | Timestamp => 12,
+        Uuid | Interval => 16,
+        Decimal(_) | Char(_) | Varchar(_) | Text | Binary(_) | Json | Custom(_) => return None,
+    })
+}
+
+const EXTERNAL: u32 = 1 << 31;
+
+/// Out-of-line storage for large variable-width values.
+pub trait ExternalStore: fmt::Debug + Send + Sync {
+    /// Encoded values longer than this many bytes are stored out of line.
+    fn threshold(&self) -> usize;
+
+    /// Stores a value, returning the reference to keep in the row.
+    fn put(&self, value: &[u8]) -> Result<Vec<u8>, Error>;
+
+    fn get(&self, reference: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Encodes and decodes rows of one table schema.
+#[derive(Debug, Clone)]
+pub struct RowCodec {
+    columns: Vec<ColumnSchema>,
+    slots: Vec<Slot>,
+    /// Width of the fixed slots, and number of var fields, of the first `i`
+    /// columns, for every `i` up to the number of columns.
+    fixed_lens: Vec<usize>,
+    var_counts: Vec<usize>,
+    /// The custom types the columns use.
+    types: Vec<TypeSchema>,
+    store: Option<Arc<dyn ExternalStore>>,
+}
+
+/// Where the parts of one row start.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    count: usize,
+    bitmap_start: usize,
+    fixed_start: usize,
+    offsets_start: usize,
+    var_start: usize,
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::Execution(format!("corrupt row: {}", what))
+}
+
+impl RowCodec {
+    pub const VERSION: u8 = 1;
+
+    pub fn new(schema: &TableSchema) -> Self {
+        let mut slots = Vec::with_capacity(schema.columns.len());
+        let mut fixed_lens = vec![0];
+        let mut var_counts = vec![0];
+        for column in &schema.columns {
+            let (mut fixed_len, mut var_count) = (*fixed_lens.last().unwrap(), *var_counts.last().unwrap());
+            match fixed_width(&column.data_type) {
+                Some(width) => {
+                    slots.push(Slot::Fixed { offset: fixed_len, width });
+                    fixed_len += width;
+                }
+                None => {
+                    slots.push(Slot::Var { index: var_count });
+                    var_count += 1;
+                }
+            }
+            fixed_lens.push(fixed_len);
+            var_counts.push(var_count);
+        }
+        RowCodec {
+            columns: schema.columns.clone(),
+            slots,
+            fixed_lens,
+            var_counts,
+            types: schema.types.clone(),
+            store: None,
+        }
+    }
+
+    /// Stores large variable-width values through `store` instead of inline.
+    pub fn with_store(mut self, store: Arc<dyn ExternalStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// The layout of a row holding the first `count` columns, with a header
+    /// of `header` bytes before the bitmap.
+    fn layout(&self, count: usize, header: usize) -> Layout {
+        let fixed_start = header + count.div_ceil(8);
+        let offsets_start = fixed_start + self.fixed_lens[count];
+        Layout {
+            count,
+            bitmap_start: header,
+            fixed_start,
+            offsets_start,
+            var_start: offsets_start + 4 * self.var_counts[count],
+        }
+    }
+
+    /// Encodes a single value of `data_type` on its own, for keeping outside
+    /// a row; [`RowCodec::decode_value`] reads it back. `types` are the
+    /// custom types it may use.
+    pub fn encode_value(data_type: &DataType, types: &[TypeSchema], value: &Value) -> Result<Vec<u8>, Error> {
+        Self::single(data_type, types).encode(std::slice::from_ref(value))
+    }
+
+    pub fn decode_value(data_type: &DataType, types: &[TypeSchema], bytes: &[u8]) -> Result<Value, Error> {
+        Self::single(data_type, types).decode_column(bytes, 0)
+    }
+
+    fn single(data_type: &DataType, types: &[TypeSchema]) -> Self {
+        let mut schema = TableSchema::new("", vec![ColumnSchema::new("value", data_type.clone())]);
+        schema.types = types.to_vec();
+        RowCodec::new(&schema)
+    }
+
+    pub fn encode(&self, values: &[Value]) -> Result<Vec<u8>, Error> {
+        if values.len() != self.columns.len() {
+            return Err(Error::Execution(format!(
+                "row has {} values but the table has {} columns",
+                values.len(),
+                self.columns.len()
+            )));
+        }
+
+        let count = u16::try_from(values.len()).map_err(|_| Error::Execution("too many columns".to_string()))?;
+        let layout = self.layout(values.len(), 3);
+        let mut row = vec![0; layout.var_start];
+        row[0] = Self::VERSION;
+        row[1..3].copy_from_slice(&count.to_le_bytes());
+        let mut var_data = Vec::new();
+        let mut var_offsets = Vec::with_capacity(self.var_counts[values.len()]);
+
+        for (i, (value, column)) in values.iter().zip(&self.columns).enumerate() {
+            let mut external = 0;
+            if value.is_null() {
+                if !column.nullable {
+                    return Err(Error::Type(format!("column {} cannot be NULL", column.name)));
+                }
+                row[layout.bitmap_start + i / 8] |= 1 << (i % 8);
+            } else {
+                let converted;
+                let value = if matches_type(value, &column.data_type) || custom_type_name(&column.data_type).is_some() {
+                    value
+                } else {
+                    converted = cast(value, &column.data_type)?;
+                    &converted
+                };
+                match self.slots[i] {
+                    Slot::Fixed { offset, width } => {
+                        let start = layout.fixed_start + offset;
+                        encode_fixed(value, &mut row[start..start + width]);
+                    }
+                    Slot::Var { .. } => {
+                        let start = var_data.len();
+                        match &column.data_type {
+                            DataType::Custom(name) => self.encode_custom(name, value, &mut var_data)?,
+                            _ => encode_var(value, &mut var_data),
+                        }
+                        if let Some(store) = self.store.as_ref() {
+                            if var_data.len() - start > store.threshold() {
+                                let reference = store.put(&var_data[start..])?;
+                                var_data.truncate(start);
+                                var_data.extend(reference);
+                                external = EXTERNAL;
+                            }
+                        }
+                    }
+                }
+            }
+            if let Slot::Var { .. } = self.slots[i] {
+                let end = u32::try_from(var_data.len())
+                    .ok()
+                    .filter(|end| end & EXTERNAL == 0)
+                    .ok_or_else(|| Error::Execution("row too large".to_string()))?;
+                var_offsets.push(end | external);
+            }
+        }
+
+        for (k, end) in var_offsets.into_iter().enumerate() {
+            let at = layout.offsets_start + 4 * k;
+            row[at..at + 4].copy_from_slice(&end.to_le_bytes());
+        }
+        row.extend(var_data);
+        Ok(row)
+    }
+
+    /// The layout of a stored row, checking that its header is whole.
+    fn read_layout(&self, row: &[u8]) -> Result<Layout, Error> {
+        let layout = match row.first() {
+            Some(&Self::VERSION) => {
+                let count = row.get(1..3).ok_or_else(|| corrupt("truncated header"))?;
+                let count = u16::from_le_bytes(count.try_into().unwrap()) as usize;
+                if count > self.columns.len() {
+                    return Err(corrupt(&format!(
+                        "row holds {} columns but the table has {}",
+                        count,
+                        self.columns.len()
+                    )));
+                }
+                self.layout(count, 3)
+            }
+            Some(v) => return Err(corrupt(&format!("unsupported format version {}", v))),
+            None => return Err(corrupt("empty row")),
+        };
+        if row.len() < layout.var_start {
+            return Err(corrupt("truncated header"));
+        }
+        Ok(layout)
+    }
+
+    pub fn is_null(&self, row: &[u8], index: usize) -> Result<bool, Error> {
+        let layout = self.read_layout(row)?;
+        self.column_index(index)?;
+        Ok(self.null_in(row, &layout, index))
+    }
+
+    fn null_in(&self, row: &[u8], layout: &Layout, index: usize) -> bool {
+        if index >= layout.count {
+            return self.columns[index].missing.as_ref().is_none_or(Value::is_null);
+        }
+        row[layout.bitmap_start + index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn column_index(&self, index: usize) -> Result<(), Error> {
+        if index < self.columns.len() {
+            Ok(())
+        } else {
+            Err(Error::Execution(format!("column index {} out of range", index)))
+        }
+    }
+
+    /// End offset of var field `k`, and whether it is stored out of line.
+    fn var_end(&self, row: &[u8], layout: &Layout, k: usize) -> (usize, bool) {
+        let at = layout.offsets_start + 4 * k;
+        let end = u32::from_le_bytes(row[at..at + 4].try_into().unwrap());
+        ((end & !EXTERNAL) as usize, end & EXTERNAL != 0)
+    }
+
+    /// The stored bytes of var field `k`, and whether they are a reference.
+    fn var_field<'r>(&self, row: &'r [u8], layout: &Layout, k: usize) -> Result<(&'r [u8], bool), Error> {
+        let start = if k == 0 { 0 } else { self.var_end(row, layout, k - 1).0 };
+        let (end, external) = self.var_end(row, layout, k);
+        let data = &row[layout.var_start..];
+        let bytes = data.get(start..end).ok_or_else(|| corrupt("bad var offset"))?;
+        Ok((bytes, external))
+    }
+
+    /// Decodes a single column without decoding the rest of the row.
+    pub fn decode_column(&self, row: &[u8], index: usize) -> Result<Value, Error> {
+        let layout = self.read_layout(row)?;
+        self.column_index(index)?;
+        self.decode_in(row, &layout, index)
+    }
+
+    fn decode_in(&self, row: &[u8], layout: &Layout, index: usize) -> Result<Value, Error> {
+        if index >= layout.count {
+            return Ok(self.columns[index].missing.clone().unwrap_or(Value::Null));
+        }
+        if self.null_in(row, layout, index) {
+            return Ok(Value::Null);
+        }
+        let data_type = &self.columns[index].data_type;
+        match self.slots[index] {
+            Slot::Fixed { offset, width } => {
+                let start = layout.fixed_start + offset;
+                decode_fixed(data_type, &row[start..start + width])
+            }
+            Slot::Var { index: k } => match self.var_field(row, layout, k)? {
+                (bytes, false) => self.decode_var(data_type, bytes),
+                (reference, true) => {
+                    let store = self.store.as_ref().ok_or_else(|| {
+                        Error::Execution(format!(
+                            "column {} is stored out of line but no store is attached",
+                            self.columns[index].name
+                        ))
+                    })?;
+                    self.decode_var(data_type, &store.get(reference)?)
+                }
+            },
+        }
+    }
+
+    /// The out-of-line reference held for a column, if it has one. Lets
+    /// callers stream a large value, or release it when the row goes away.
+    pub fn external_ref<'r>(&self, row: &'r [u8], index: usize) -> Result<Option<&'r [u8]>, Error> {
+        let layout = self.read_layout(row)?;
+        self.column_index(index)?;
+        if index >= layout.count || self.null_in(row, &layout, index) {
+            return Ok(None);
+        }
+        match self.slots[index] {
+            Slot::Var { index: k } => match self.var_field(row, &layout, k)? {
+                (reference, true) => Ok(Some(reference)),
+                _ => Ok(None),
+            },
+            Slot::Fixed { .. } => Ok(None),
+        }
+    }
+
+    /// Every out-of-line reference in a row.
+    pub fn external_refs<'r>(&self, row: &'r [u8]) -> Result<Vec<&'r [u8]>, Error> {
+        let mut refs = Vec::new();
+        for i in 0..self.columns.len() {
+            refs.extend(self.external_ref(row, i)?);
+        }
+        Ok(refs)
+    }
+
+    /// Decodes the given columns, in the given order.
+    pub fn project(&self, row: &[u8], columns: &[usize]) -> Result<Vec<Value>, Error> {
+        columns.iter().map(|&i| self.decode_column(row, i)).collect()
+    }
+
+    pub fn decode(&self, row: &[u8]) -> Result<Vec<Value>, Error> {
+        let layout = self.read_layout(row)?;
+        (0..self.columns.len()).map(|i| self.decode_in(row, &layout, i)).collect()
+    }
+
+    /// Brings the custom-typed values of a decoded row, decoded before
+    /// members were added to their types or fields renamed, up to date, as
+    /// though they were decoded again.
+    pub fn upgrade(&self, mut row: Vec<Value>) -> Vec<Value> {
+        for (value, column) in row.iter_mut().zip(&self.columns) {
+            self.upgrade_value(&column.data_type, value);
+        }
+        row
+    }
+
+    fn upgrade_value(&self, data_type: &DataType, value: &mut Value) {
+        let Some(ty) = custom_type_name(data_type).and_then(|name| self.custom(name).ok()) else { return };
+        match (&ty.kind, value) {
+            (TypeKind::Struct(fields), Value::Struct(values)) => {
+                let first_added = fields.len() - ty.added.len();
+                for (i, (name, data_type)) in fields.iter().enumerate() {
+                    match values.get_mut(i) {
+                        Some((field, value)) => {
+                            field.clone_from(name);
+                            self.upgrade_value(data_type, value);
+                        }
+                        None => values.push((name.clone(), ty.added[i - first_added].clone().unwrap_or(Value::Null))),
+                    }
+                }
+            }
+            (TypeKind::Enum(variants), Value::Enum { variant, payload: Some(payload) }) => {
+                if let Some((_, Some(data_type))) = variants.iter().find(|(name, _)| name == variant) {
+                    self.upgrade_value(data_type, payload);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn custom(&self, name: &str) -> Result<&TypeSchema, Error> {
+        self.types
+            .iter()
+            .find(|ty| ty.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::Execution(format!("type {} is not defined here", name)))
+    }
+
+    /// Encodes a value of a custom type at its latest version.
+    fn encode_custom(&self, name: &str, value: &Value, out: &mut Vec<u8>) -> Result<(), Error> {
+        let ty = self.custom(name)?;
+        out.extend_from_slice(&(ty.version() as u16).to_le_bytes());
+        match (&ty.kind, value) {
+            (TypeKind::Struct(fields), Value::Struct(values)) => {
+                if let Some((field, _)) =
+                    values.iter().find(|(field, _)| !fields.iter().any(|(name, _)| name.eq_ignore_ascii_case(field)))
+                {
+                    return Err(Error::Type(format!("type {} has no field {}", ty.name, field)));
+                }
+                let first_added = fields.len() - ty.added.len();
+                for (i, (name, data_type)) in fields.iter().enumerate() {
+                    let value = match values.iter().find(|(field, _)| field.eq_ignore_ascii_case(name)) {
+                        Some((_, value)) => value.clone(),
+                        None => i.checked_sub(first_added).and_then(|k| ty.added[k].clone()).unwrap_or(Value::Null),
+                    };
+                    self.encode_member(data_type, &value, out)?;
+                }
+            }
+            (TypeKind::Enum(variants), Value::Enum { variant, payload }) => {
+                let index = variants
+                    .iter()
+                    .position(|(name, _)| name.eq_ignore_ascii_case(variant))
+                    .ok_or_else(|| Error::Type(format!("type {} has no variant {}", ty.name, variant)))?;
+                out.extend_from_slice(&(index as u16).to_le_bytes());
+                match (&variants[index].1, payload) {
+                    (Some(data_type), payload) => {
+                        self.encode_member(data_type, payload.as_deref().unwrap_or(&Value::Null), out)?
+                    }
+                    (None, None) => {}
+                    (None, Some(_)) => {
+                        return Err(Error::Type(format!("variant {} of type {} has no payload", variant, ty.name)))
+                    }
+                }
+            }
+            (_, value) => {
+                return Err(Error::Type(format!("cannot store {} as type {}", value.type_name(), ty.name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_member(&self, data_type: &DataType, value: &Value, out: &mut Vec<u8>) -> Result<(), Error> {
+        if value.is_null() {
+            out.push(0);
+            return Ok(());
+        }
+        let mut bytes = Vec::new();
+        if let DataType::Custom(name) = data_type {
+            self.encode_custom(name, value, &mut bytes)?;
+        } else {
+            let converted;
+            let value = if matches_type(value, data_type) {
+                value
+            } else {
+                converted = cast(value, data_type)?;
+                &converted
+            };
+            match fixed_width(data_type) {
+                Some(width) => {
+                    bytes.resize(width, 0);
+                    encode_fixed(value, &mut bytes);
+                }
+                None => encode_var(value, &mut bytes),
+            }
+        }
+        let len = u32::try_from(bytes.len()).map_err(|_| Error::Execution("value too large".to_string()))?;
+        out.push(1);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend(bytes);
+        Ok(())
+    }
+
+    fn decode_var(&self, data_type: &DataType, bytes: &[u8]) -> Result<Value, Error> {
+        match data_type {
+            DataType::Custom(name) => self.decode_custom(name, bytes),
+            _ => decode_var(data_type, bytes),
+        }
+    }
+
+    /// Decodes a value of a custom type, written under any of its versions.
+    fn decode_custom(&self, name: &str, bytes: &[u8]) -> Result<Value, Error> {
+        let ty = self.custom(name)?;
+        let mut reader = Reader { bytes, at: 0, type_name: &ty.name };
+        let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+        let count = ty
+            .members_at(version as u32)
+            .ok_or_else(|| corrupt(&format!("type {} has no version {}", ty.name, version)))?;
+        Ok(match &ty.kind {
+            TypeKind::Struct(fields) => {
+                let first_added = fields.len() - ty.added.len();
+                let mut values = Vec::with_capacity(fields.len());
+                for (i, (name, data_type)) in fields.iter().enumerate() {
+                    let value = match i < count {
+                        true => self.decode_member(data_type, &mut reader)?,
+                        false => ty.added[i - first_added].clone().unwrap_or(Value::Null),
+                    };
+                    values.push((name.clone(), value));
+                }
+                Value::Struct(values)
+            }
+            TypeKind::Enum(variants) => {
+                let index = u16::from_le_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+                let (variant, data_type) = variants
+                    .get(index)
+                    .filter(|_| index < count)
+                    .ok_or_else(|| corrupt(&format!("type {} has no variant {}", ty.name, index)))?;
+                let payload = match data_type {
+                    Some(data_type) => Some(self.decode_member(data_type, &mut reader)?),
+                    None => None,
+                };
+                Value::Enum { variant: variant.clone(), payload: payload.filter(|p| !p.is_null()).map(Box::new) }
+            }
+        })
+    }
+
+    fn decode_member(&self, data_type: &DataType, reader: &mut Reader) -> Result<Value, Error> {
+        if reader.take(1)?[0] == 0 {
+            return Ok(Value::Null);
+        }
+        let len = u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as usize;
+        let bytes = reader.take(len)?;
+        match fixed_width(data_type) {
+            Some(width) if width == len => decode_fixed(data_type, bytes),
+            Some(_) => Err(corrupt(&format!("bad member of type {}", reader.type_name))),
+            None => self.decode_var(data_type, bytes),
+        }
+    }
+}
+
+/// Reads the parts of a custom-typed value in turn.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    type_name: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let taken = self
+            .bytes
+            .get(self.at..self.at + n)
+            .ok_or_else(|| corrupt(&format!("truncated value of type {}", self.type_name)))?;
+        self.at += n;
+        Ok(taken)
+    }
+
+}
+
+/// Whether `value` already has the variant a column of `data_type` stores.
+fn matches_type(value: &Value, data_type: &DataType) -> bool {
+    use DataType as T;
+    matches!(
+        (value, data_type),
+        (Value::Bool(_), T::Boolean)
+            | (Value::Int8(_), T::TinyInt)
+            | (Value::Int16(_), T::SmallInt)
+            | (Value::Int32(_), T::Integer(_))
+            | (Value::Int(_), T::BigInt)
+            | (Value::UInt8(_), T::UnsignedTinyInt)
+            | (Value::UInt16(_), T::UnsignedSmallInt)
+            | (Value::UInt32(_), T::UnsignedInteger)
+            | (Value::UInt64(_), T::UnsignedBigInt)
+            | (Value::Float32(_), T::Real)
+            | (Value::Float(_), T::Float(_))
+            | (Value::Decimal(_), T::Decimal(None))
+            | (Value::String(_), T::Text)
+            | (Value::Bytes(_), T::Binary(None))
+            | (Value::Json(_), T::Json)
+            | (Value::Uuid(_), T::Uuid)
+            | (Value::Date(_), T::Date)
+            | (Value::Time(_), T::Time)
+            | (Value::DateTime(_), T::DateTime | T::Timestamp)
+            | (Value::Interval(_), T::Interval)
+    )
+}
+
+fn encode_fixed(value: &Value, slot: &mut [u8]) {
+    match value {
+        Value::Bool(b) => slot[0] = *b as u8,
+        Value::Int8(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::Int16(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::Int32(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::Int(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::UInt8(i) => slot[0] = *i,
+        Value::UInt16(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::UInt32(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::UInt64(i) => slot.copy_from_slice(&i.to_le_bytes()),
+        Value::Float32(f) => slot.copy_from_slice(&f.to_le_bytes()),
+        Value::Float(f) => slot.copy_from_slice(&f.to_le_bytes()),
+        Value::Date(d) => slot.copy_from_slice(&d.num_days_from_ce().to_le_bytes()),
+        Value::Time(t) => {
+            slot[..4].copy_from_slice(&t.num_seconds_from_midnight().to_le_bytes());
+            slot[4..].copy_from_slice(&t.nanosecond().to_le_bytes());
+        }
+        Value::DateTime(dt) => {
+            slot[..8].copy_from_slice(&dt.timestamp().to_le_bytes());
+            slot[8..].copy_from_slice(&dt.timestamp_subsec_nanos().to_le_bytes());
+        }
+        Value::Uuid(u) => slot.copy_from_slice(u.as_bytes()),
+        Value::Interval(i) => {
+            slot[..4].copy_from_slice(&i.months.to_le_bytes());
+            slot[4..8].copy_from_slice(&i.days.to_le_bytes());
+            slot[8..].copy_from_slice(&i.micros.to_le_bytes());
+        }
+        other => unreachable!("{} is not a fixed-width value", other.type_name()),
+    }
+}
+
+fn encode_var(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::String(s) => out.extend_from_slice(s.as_bytes()),
+        Value::Bytes(b) => out.extend_from_slice(b),
+        Value::Json(j) => out.extend_from_slice(j.to_string().as_bytes()),
+        Value::Decimal(d) => {
+            let (int, scale) = d.as_bigint_and_exponent();
+            out.extend_from_slice(&scale.to_le_bytes());
+            out.extend_from_slice(&int.to_signed_bytes_le());
+        }
+        other => unreachable!("{} is not a variable-width value", other.type_name()),
+    }
+}
+
+fn decode_fixed(data_type: &DataType, slot: &[u8]) -> Result<Value, Error> {
+    use DataType as T;
+    fn le<const N: usize>(bytes: &[u8]) -> [u8; N] {
+        bytes[..N].try_into().unwrap()
+    }
+    Ok(match data_type {
+        T::Boolean => Value::Bool(slot[0] != 0),
+        T::TinyInt => Value::Int8(slot[0] as i8),
+        T::SmallInt => Value::Int16(i16::from_le_bytes(le(slot))),
+        T::Integer(_) => Value::Int32(i32::from_le_bytes(le(slot))),
+        T::BigInt => Value::Int(i64::from_le_bytes(le(slot))),
+        T::UnsignedTinyInt => Value::UInt8(slot[0]),
+        T::UnsignedSmallInt => Value::UInt16(u16::from_le_bytes(le(slot))),
+        T::UnsignedInteger => Value::UInt32(u32::from_le_bytes(le(slot))),
+        T::UnsignedBigInt => Value::UInt64(u64::from_le_bytes(le(slot))),
+        T::Real => Value::Float32(f32::from_le_bytes(le(slot))),
+        T::Float(_) => Value::Float(f64::from_le_bytes(le(slot))),
+        T::Date => Value::Date(
+            NaiveDate::from_num_days_from_ce_opt(i32::from_le_bytes(le(slot)))
+                .ok_or_else(|| corrupt("bad date"))?,
+        ),
+        T::Time => Value::Time(
+            NaiveTime::from_num_seconds_from_midnight_opt(
+                u32::from_le_bytes(le(slot)),
+                u32::from_le_bytes(le(&slot[4..])),
+            )
+            .ok_or_else(|| corrupt("bad time"))?,
+        ),
+        T::DateTime | T::Timestamp => Value::DateTime(
+            DateTime::from_timestamp(i64::from_le_bytes(le(slot)), u32::from_le_bytes(le(&slot[8..])))
+                .ok_or_else(|| corrupt("bad timestamp"))?,
+        ),
+        T::Uuid => Value::Uuid(Uuid::from_bytes(le(slot))),
+        T::Interval => Value::Interval(Interval::new(
+            i32::from_le_bytes(le(slot)),
+            i32::from_le_bytes(le(&slot[4..])),
+            i64::from_le_bytes(le(&slot[8..])),
+        )),
+        other => unreachable!("{} is not a fixed-width type", other),
+    })
+}
+
+fn decode_var(data_type: &DataType, bytes: &[u8]) -> Result<Value, Error> {
+    let text = || String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("invalid UTF-8"));
+    Ok(match data_type {
+        DataType::Char(_) | DataType::Varchar(_) | DataType::Text => Value::String(text()?),
+        DataType::Binary(_) => Value::Bytes(bytes.to_vec()),
+        DataType::Json => Value::Json(serde_json::Value::from_str(&text()?).map_err(|_| corrupt("bad JSON"))?),
+        DataType::Decimal(_) => {
+            if bytes.len() < 8 {
+                return Err(corrupt("bad decimal"));
+            }
+            let scale = i64::from_le_bytes(bytes[..8].try_into().unwrap());
+            Value::Decimal(BigDecimal::new(BigInt::from_signed_bytes_le(&bytes[8..]), scale))
+        }
+        other => unreachable!("{} is not a variable-width type", other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn schema() -> TableSchema {
+        TableSchema::new(
+            "t",
+            vec![
+                ColumnSchema::new("id", DataType::Integer(None)).primary_key(),
+                ColumnSchema::new("name", DataType::Varchar(Some(8))),
+                ColumnSchema::new("score", DataType::Decimal(Some((6, 2)))),
+                ColumnSchema::new("flag", DataType::Boolean),
+                ColumnSchema::new("tags", DataType::Json),
+                ColumnSchema::new("at", DataType::Timestamp),
+                ColumnSchema::new("id2", DataType::Uuid),
+                ColumnSchema::new("span", DataType::Interval),
+                ColumnSchema::new("small", DataType::UnsignedSmallInt),
+            ],
+        )
+    }
+
+    fn sample() -> Vec<Value> {
+        vec![
+            Value::Int32(7),
+            Value::String("héllo".into()),
+            Value::Decimal(BigDecimal::from_str("-12.50").unwrap()),
+            Value::Bool(true),
+            Value::Json(serde_json::json!({"a": [1, 2]})),
+            Value::DateTime(Utc.timestamp_opt(1_700_000_000, 123).unwrap()),
+            Value::Uuid(Uuid::from_bytes([7; 16])),
+            Value::Interval(Interval::new(1, 2, 3)),
+            Value::UInt16(65535),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip_and_projection() {
+        let codec = RowCodec::new(&schema());
+        let row = codec.encode(&sample()).unwrap();
+        assert_eq!(row[0], RowCodec::VERSION);
+        // id is the first fixed slot, after the version byte, the column
+        // count and the two-byte bitmap for nine columns.
+        assert_eq!(&row[1..3], &9u16.to_le_bytes());
+        assert_eq!(&row[5..9], &7i32.to_le_bytes());
+        assert_eq!(codec.decode(&row).unwrap(), sample());
+        assert_eq!(
+            codec.project(&row, &[4, 0]).unwrap(),
+            vec![sample()[4].clone(), Value::Int32(7)]
+        );
+    }
+
+    #[test]
+    fn test_nulls_and_coercion() {
+        let codec = RowCodec::new(&schema());
+        let mut values = vec![Value::Null; 9];
+        values[0] = Value::Int(1);
+        values[2] = Value::String("3.14159".into());
+        let row = codec.encode(&values).unwrap();
+        assert!(codec.is_null(&row, 1).unwrap());
+        assert!(!codec.is_null(&row, 2).unwrap());
+        assert_eq!(codec.decode_column(&row, 0).unwrap(), Value::Int32(1));
+        assert_eq!(codec.decode_column(&row, 2).unwrap().to_string(), "3.14");
+        assert_eq!(codec.decode_column(&row, 4).unwrap(), Value::Null);
+
+        values[0] = Value::Null;
+        assert!(codec.encode(&values).unwrap_err().to_string().contains("cannot be NULL"));
+        values[0] = Value::Int(1);
+        values[1] = Value::String("too long!".into());
+        assert!(codec.encode(&values).unwrap_err().to_string().contains("value too long"));
+    }
+
+    #[test]
+    fn test_corrupt_rows() {
+        let codec = RowCodec::new(&schema());
+        let mut row = codec.encode(&sample()).unwrap();
+        assert!(codec.decode(&row[..5]).is_err());
+        row[0] = 99;
+        assert!(codec.decode(&row).unwrap_err().to_string().contains("unsupported format version 99"));
+        assert!(codec.decode_column(&[], 0).is_err());
+    }
+
+    #[test]
+    fn test_rows_from_before_columns_were_added() {
+        let narrow = TableSchema::new("t", schema().columns[..2].to_vec());
+        let row = RowCodec::new(&narrow).encode(&sample()[..2]).unwrap();
+
+        let mut wide = narrow.clone();
+        let mut flag = ColumnSchema::new("flag", DataType::Boolean).not_null();
+        flag.missing = Some(Value::Bool(true));
+        wide.columns.push(flag);
+        wide.columns.push(ColumnSchema::new("tags", DataType::Json));
+        let codec = RowCodec::new(&wide);
+        assert_eq!(
+            codec.decode(&row).unwrap(),
+            vec![Value::Int32(7), Value::String("héllo".into()), Value::Bool(true), Value::Null]
+        );
+        assert!(codec.is_null(&row, 3).unwrap());
+        let empty = RowCodec::new(&TableSchema::new("t", vec![]));
+        assert!(empty.decode(&row).unwrap_err().to_string().contains("holds 2"));
+
+        let value = Value::Decimal(BigDecimal::from_str("2.5").unwrap());
+        let bytes = RowCodec::encode_value(&DataType::Decimal(None), &[], &value).unwrap();
+        assert_eq!(RowCodec::decode_value(&DataType::Decimal(None), &[], &bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_custom_types_across_versions() {
+        let point = TypeSchema::new("point", TypeKind::Struct(vec![("x".into(), DataType::Integer(None))]));
+        let shape = TypeSchema::new(
+            "shape",
+            TypeKind::Enum(vec![("dot".into(), Some(DataType::Custom("point".into()))), ("none".into(), None)]),
+        );
+        let mut old = TableSchema::new("t", vec![ColumnSchema::new("s", DataType::Custom("shape".into()))]);
+        old.types = vec![point.clone(), shape.clone()];
+        let dot = |fields: Vec<(&str, Value)>| Value::Enum {
+            variant: "dot".into(),
+            payload: Some(Box::new(Value::Struct(fields.into_iter().map(|(n, v)| (n.to_string(), v)).collect()))),
+        };
+        let codec = RowCodec::new(&old);
+        let row = codec.encode(&[dot(vec![("X", Value::Int(3))])]).unwrap();
+        assert_eq!(codec.decode(&row).unwrap(), vec![dot(vec![("x", Value::Int32(3))])]);
+        assert!(codec.encode(&[dot(vec![("w", Value::Int(3))])]).unwrap_err().to_string().contains("no field w"));
+        let square = Value::Enum { variant: "square".into(), payload: None };
+        assert!(codec.encode(std::slice::from_ref(&square)).unwrap_err().to_string().contains("no variant square"));
+
+        // A field added with a default and renamed, and a variant added.
+        let mut new = old.clone();
+        let fields = vec![("px".into(), DataType::Integer(None)), ("y".into(), DataType::BigInt)];
+        new.types[0].kind = TypeKind::Struct(fields);
+        new.types[0].added.push(Some(Value::Int(0)));
+        if let TypeKind::Enum(variants) = &mut new.types[1].kind {
+            variants.push(("square".into(), None));
+        }
+        new.types[1].added.push(None);
+        let codec = RowCodec::new(&new);
+        let upgraded = vec![dot(vec![("px", Value::Int32(3)), ("y", Value::Int(0))])];
+        assert_eq!(codec.decode(&row).unwrap(), upgraded);
+        assert_eq!(codec.upgrade(vec![dot(vec![("x", Value::Int32(3))])]), upgraded);
+        let row = codec.encode(std::slice::from_ref(&square)).unwrap();
+        assert_eq!(codec.decode(&row).unwrap(), vec![square]);
+        // Older definitions cannot read newer values.
+        assert!(RowCodec::new(&old).decode(&row).unwrap_err().to_string().contains("no version 2"));
+        assert!(RowCodec::new(&TableSchema::new("t", old.columns.clone())).decode(&row).is_err());
+    }
+
+}
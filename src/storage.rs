@@ -0,0 +1,15 @@
+// src/storage.rs
+//! Page-based storage: a database file is a sequence of fixed-size pages,
+//! memory-mapped and checksummed, with page 0 holding the file header.
+
+pub mod fsm;
+pub mod heap;
+pub mod overflow;
+pub mod page;
+pub mod pager;
+pub mod slotted;
+
+pub use heap::{HeapFile, HeapScan, RecordId};
+pub use overflow::{OverflowRef, OverflowStore};
+pub use page::{Page, PageHeader, PageId, PageType, DEFAULT_PAGE_SIZE, PAGE_HEADER_SIZE};
+pub use pager::{Pager, PagerOptions};
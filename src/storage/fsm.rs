@@ -0,0 +1,172 @@
+// src/storage/fsm.rs
+//! Free-space map for a heap file.
+//!
+//! The map records, for every page of the heap, how full it is in 1/255ths
+//! of the page (rounded down). It doubles as the list of the heap's pages, so
+//! a heap is identified by the id of its first map page.
+//!
+//! Map pages are chained through their `next` header field. Each holds a
+//! `u16` entry count followed by `(page_id u64, category u8)` entries. The
+//! whole map is also kept in memory, so lookups never touch disk.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::buffer::BufferPool;
+use crate::error::Error;
+use crate::storage::page::{PageId, PageType};
+
+const ENTRY: usize = 9;
+
+#[derive(Debug, Clone)]
+pub struct FreeSpaceMap {
+    map_pages: Vec<PageId>,
+    entries: Vec<(PageId, u8)>,
+    index: HashMap<PageId, usize>,
+    data_len: usize,
+}
+
+impl FreeSpaceMap {
+    pub fn create(pool: &BufferPool) -> Result<Self, Error> {
+        let root = pool.new_page(PageType::FreeSpaceMap)?;
+        let data_len = root.read().data.len();
+        Ok(FreeSpaceMap {
+            map_pages: vec![root.id()],
+            entries: Vec::new(),
+            index: HashMap::new(),
+            data_len,
+        })
+    }
+
+    pub fn open(pool: &BufferPool, root: PageId) -> Result<Self, Error> {
+        let mut map = FreeSpaceMap {
+            map_pages: Vec::new(),
+            entries: Vec::new(),
+            index: HashMap::new(),
+            data_len: 0,
+        };
+        let mut next = root;
+        while next != 0 {
+            let guard = pool.fetch(next)?;
+            let page = guard.read();
+            if page.header.page_type != PageType::FreeSpaceMap {
+                return Err(Error::Storage(format!("page {} is not a free-space map page", next)));
+            }
+            map.data_len = page.data.len();
+            let count = u16::from_le_bytes(page.data[0..2].try_into().unwrap()) as usize;
+            for e in 0..count {
+                let at = 2 + e * ENTRY;
+                let id = u64::from_le_bytes(page.data[at..at + 8].try_into().unwrap());
+                map.index.insert(id, map.entries.len());
+                map.entries.push((id, page.data[at + 8]));
+            }
+            map.map_pages.push(next);
+            next = page.header.next;
+        }
+        Ok(map)
+    }
+
+    pub fn root(&self) -> PageId {
+        self.map_pages[0]
+    }
+
+    /// The heap's pages, in the order they were added.
+    pub fn pages(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.entries.iter().map(|(id, _)| *id)
+    }
+
+    pub fn contains(&self, page_id: PageId) -> bool {
+        self.index.contains_key(&page_id)
+    }
+
+    /// Every page this map uses for itself.
+    pub fn map_pages(&self) -> &[PageId] {
+        &self.map_pages
+    }
+
+    fn per_page(&self) -> usize {
+        (self.data_len - 2) / ENTRY
+    }
+
+    fn category(&self, free: usize) -> u8 {
+        (free * 255 / self.data_len) as u8
+    }
+
+    /// A page recorded as having at least `need` bytes free, if any.
+    pub fn find(&self, need: usize) -> Option<PageId> {
+        let wanted = (need * 255).div_ceil(self.data_len);
+        self.entries
+            .iter()
+            .find(|(_, category)| *category as usize >= wanted)
+            .map(|(id, _)| *id)
+    }
+
+    /// Adds a new heap page with `free` bytes available.
+    pub fn add(&mut self, pool: &BufferPool, page_id: PageId, free: usize) -> Result<(), Error> {
+        let n = self.entries.len();
+        if n == self.map_pages.len() * self.per_page() {
+            let new = pool.new_page(PageType::FreeSpaceMap)?;
+            let last = pool.fetch(*self.map_pages.last().unwrap())?;
+            last.write().header.next = new.id();
+            self.map_pages.push(new.id());
+        }
+        self.index.insert(page_id, n);
+        self.entries.push((page_id, self.category(free)));
+        self.store(pool, n)
+    }
+
+    /// Records that a heap page now has `free` bytes available.
+    pub fn update(&mut self, pool: &BufferPool, page_id: PageId, free: usize) -> Result<(), Error> {
+        let n = *self
+            .index
+            .get(&page_id)
+            .ok_or_else(|| Error::Storage(format!("page {} is not in the free-space map", page_id)))?;
+        let category = self.category(free);
+        if self.entries[n].1 != category {
+            self.entries[n].1 = category;
+            self.store(pool, n)?;
+        }
+        Ok(())
+    }
+
+    /// Writes entry `n` (and the entry count of its page) to disk.
+    fn store(&self, pool: &BufferPool, n: usize) -> Result<(), Error> {
+        let per_page = self.per_page();
+        let guard = pool.fetch(self.map_pages[n / per_page])?;
+        let mut page = guard.write();
+        let on_page = (self.entries.len() - n / per_page * per_page).min(per_page);
+        page.data[0..2].copy_from_slice(&(on_page as u16).to_le_bytes());
+        let at = 2 + (n % per_page) * ENTRY;
+        let (id, category) = self.entries[n];
+        page.data[at..at + 8].copy_from_slice(&id.to_le_bytes());
+        page.data[at + 8] = category;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Pager, PagerOptions};
+
+    #[test]
+    fn test_find_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = BufferPool::new(pager, 8);
+        let mut map = FreeSpaceMap::create(&pool).unwrap();
+
+        // Enough entries to spill onto a second map page.
+        for id in 100..200 {
+            map.add(&pool, id, 10).unwrap();
+        }
+        map.update(&pool, 150, 300).unwrap();
+        assert_eq!(map.map_pages().len(), 3);
+        assert_eq!(map.find(200), Some(150));
+        assert_eq!(map.find(400), None);
+
+        let reopened = FreeSpaceMap::open(&pool, map.root()).unwrap();
+        assert_eq!(reopened.pages().collect::<Vec<_>>(), (100..200).collect::<Vec<_>>());
+        assert_eq!(reopened.find(200), Some(150));
+    }
+}
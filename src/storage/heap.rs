@@ -0,0 +1,362 @@
+// src/storage/heap.rs
+//! Heap files: unordered table storage on slotted pages.
+//!
+//! Rows are addressed by a [`RecordId`] that stays valid for the life of the
+//! row. When an update makes a row too big for its page, the row moves to
+//! another page and its original slot is replaced by a forwarding pointer, so
+//! a lookup takes at most one extra hop. Each stored tuple starts with a kind
+//! byte:
+//!
+//! - `0`, a row stored in its home slot;
+//! - `1`, a forwarding pointer (`page u64`, `slot u16`);
+//! - `2`, a row that was forwarded here; scans skip these and report the row
+//!   under its home record id instead.
+
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::buffer::BufferPool;
+use crate::error::Error;
+use crate::storage::fsm::FreeSpaceMap;
+use crate::storage::page::{PageId, PageType};
+use crate::storage::slotted;
+
+const HOME: u8 = 0;
+const FORWARD: u8 = 1;
+const MOVED: u8 = 2;
+
+/// Location of a row: the page it lives on and its slot in that page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordId {
+    pub page: PageId,
+    pub slot: u16,
+}
+
+impl RecordId {
+    pub fn new(page: PageId, slot: u16) -> Self {
+        RecordId { page, slot }
+    }
+
+    pub fn to_bytes(self) -> [u8; 10] {
+        let mut bytes = [0; 10];
+        bytes[..8].copy_from_slice(&self.page.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.slot.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        RecordId {
+            page: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            slot: u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+        }
+    }
+}
+
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.page, self.slot)
+    }
+}
+
+#[derive(Debug)]
+pub struct HeapFile {
+    pool: Arc<BufferPool>,
+    fsm: Mutex<FreeSpaceMap>,
+}
+
+fn tagged(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut tuple = Vec::with_capacity(payload.len() + 1);
+    tuple.push(kind);
+    tuple.extend_from_slice(payload);
+    tuple
+}
+
+fn not_found(rid: RecordId) -> Error {
+    Error::Storage(format!("no row at {}", rid))
+}
+
+impl HeapFile {
+    pub fn create(pool: Arc<BufferPool>) -> Result<Self, Error> {
+        let fsm = FreeSpaceMap::create(&pool)?;
+        Ok(HeapFile { pool, fsm: Mutex::new(fsm) })
+    }
+
+    /// Opens the heap whose free-space map starts at `root`.
+    pub fn open(pool: Arc<BufferPool>, root: PageId) -> Result<Self, Error> {
+        let fsm = FreeSpaceMap::open(&pool, root)?;
+        Ok(HeapFile { pool, fsm: Mutex::new(fsm) })
+    }
+
+    /// The page id to pass to [`HeapFile::open`].
+    pub fn root(&self) -> PageId {
+        self.fsm.lock().root()
+    }
+
+    /// Largest row that fits in a page.
+    pub fn max_row_size(&self) -> usize {
+        slotted::max_tuple(self.pool.page_size() - crate::storage::PAGE_HEADER_SIZE) - 1
+    }
+
+    pub fn insert(&self, row: &[u8]) -> Result<RecordId, Error> {
+        self.store(HOME, row)
+    }
+
+    /// Stores a tuple on the first page with room, adding a page if needed.
+    fn store(&self, kind: u8, row: &[u8]) -> Result<RecordId, Error> {
+        if row.len() > self.max_row_size() {
+            return Err(Error::Storage(format!(
+                "row of {} bytes does not fit in a page (at most {})",
+                row.len(),
+                self.max_row_size()
+            )));
+        }
+        let tuple = tagged(kind, row);
+        let need = slotted::space_needed(tuple.len());
+        let mut fsm = self.fsm.lock();
+        let mut tried = None;
+        loop {
+            // The map can only be stale if another thread freed space we have
+            // not seen yet, so a page is never tried twice.
+            let guard = match fsm.find(need).filter(|&p| Some(p) != tried) {
+                Some(page_id) => self.pool.fetch(page_id)?,
+                None => {
+                    let guard = self.pool.new_page(PageType::Heap)?;
+                    slotted::init(&mut guard.write());
+                    let free = slotted::free_space(&guard.read());
+                    fsm.add(&self.pool, guard.id(), free)?;
+                    guard
+                }
+            };
+            let mut page = guard.write();
+            let slot = slotted::insert(&mut page, &tuple);
+            fsm.update(&self.pool, guard.id(), slotted::free_space(&page))?;
+            match slot {
+                Some(slot) => return Ok(RecordId::new(guard.id(), slot)),
+                None => tried = Some(guard.id()),
+            }
+        }
+    }
+
+    /// Reads a tuple without following forwarding pointers.
+    fn raw(&self, rid: RecordId) -> Result<Option<Vec<u8>>, Error> {
+        if !self.fsm.lock().contains(rid.page) {
+            return Ok(None);
+        }
+        let guard = self.pool.fetch(rid.page)?;
+        let page = guard.read();
+        Ok(slotted::get(&page, rid.slot).map(<[u8]>::to_vec))
+    }
+
+    pub fn get(&self, rid: RecordId) -> Result<Option<Vec<u8>>, Error> {
+        match self.raw(rid)? {
+            Some(tuple) if tuple[0] == HOME => Ok(Some(tuple[1..].to_vec())),
+            Some(tuple) if tuple[0] == FORWARD => {
+                let target = RecordId::from_bytes(&tuple[1..]);
+                match self.raw(target)? {
+                    Some(moved) if moved[0] == MOVED => Ok(Some(moved[1..].to_vec())),
+                    _ => Err(Error::Storage(format!("dangling forward from {} to {}", rid, target))),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Replaces a row. Its record id does not change, even if it has to move
+    /// to another page.
+    pub fn update(&self, rid: RecordId, row: &[u8]) -> Result<(), Error> {
+        let home = self.raw(rid)?.ok_or_else(|| not_found(rid))?;
+        match home[0] {
+            HOME => {
+                if self.replace(rid, &tagged(HOME, row))? {
+                    return Ok(());
+                }
+            }
+            FORWARD => {
+                let target = RecordId::from_bytes(&home[1..]);
+                if self.replace(target, &tagged(MOVED, row))? {
+                    return Ok(());
+                }
+                self.remove(target)?;
+            }
+            _ => return Err(not_found(rid)),
+        }
+        let target = self.store(MOVED, row)?;
+        // A forwarding pointer always fits: every tuple reserves room for one.
+        self.replace(rid, &tagged(FORWARD, &target.to_bytes()))?;
+        Ok(())
+    }
+
+    pub fn delete(&self, rid: RecordId) -> Result<(), Error> {
+        let home = self.raw(rid)?.ok_or_else(|| not_found(rid))?;
+        match home[0] {
+            HOME => {}
+            FORWARD => self.remove(RecordId::from_bytes(&home[1..]))?,
+            _ => return Err(not_found(rid)),
+        }
+        self.remove(rid)
+    }
+
+    /// Overwrites a tuple in its page, returning false if it no longer fits.
+    ///
+    /// The page latch is released before the free-space map is locked, since
+    /// `store` takes them in the opposite order.
+    fn replace(&self, rid: RecordId, tuple: &[u8]) -> Result<bool, Error> {
+        let free = {
+            let guard = self.pool.fetch(rid.page)?;
+            let mut page = guard.write();
+            if !slotted::update(&mut page, rid.slot, tuple) {
+                return Ok(false);
+            }
+            slotted::free_space(&page)
+        };
+        self.fsm.lock().update(&self.pool, rid.page, free)?;
+        Ok(true)
+    }
+
+    fn remove(&self, rid: RecordId) -> Result<(), Error> {
+        let free = {
+            let guard = self.pool.fetch(rid.page)?;
+            let mut page = guard.write();
+            slotted::delete(&mut page, rid.slot);
+            slotted::free_space(&page)
+        };
+        self.fsm.lock().update(&self.pool, rid.page, free)
+    }
+
+    /// Frees every page of the heap, rows and free-space map alike. The
+    /// heap must not be used afterwards.
+    pub fn destroy(&self) -> Result<(), Error> {
+        let fsm = self.fsm.lock();
+        for id in fsm.pages().chain(fsm.map_pages().iter().copied()) {
+            self.pool.free_page(id)?;
+        }
+        Ok(())
+    }
+
+    /// Every row in the heap with its record id, in storage order.
+    pub fn scan(&self) -> HeapScan<'_> {
+        let pages: Vec<PageId> = self.fsm.lock().pages().collect();
+        HeapScan { heap: self, pages: pages.into_iter(), buffered: Vec::new().into_iter() }
+    }
+}
+
+/// Iterator over a heap, reading one page at a time.
+pub struct HeapScan<'a> {
+    heap: &'a HeapFile,
+    pages: std::vec::IntoIter<PageId>,
+    buffered: std::vec::IntoIter<(RecordId, Vec<u8>)>,
+}
+
+impl HeapScan<'_> {
+    fn load(&mut self, page_id: PageId) -> Result<(), Error> {
+        let mut rows = Vec::new();
+        let mut forwards = Vec::new();
+        {
+            let guard = self.heap.pool.fetch(page_id)?;
+            let page = guard.read();
+            for (slot, tuple) in slotted::tuples(&page) {
+                let rid = RecordId::new(page_id, slot);
+                match tuple[0] {
+                    HOME => rows.push((rid, tuple[1..].to_vec())),
+                    FORWARD => forwards.push(rid),
+                    _ => {}
+                }
+            }
+        }
+        for rid in forwards {
+            if let Some(row) = self.heap.get(rid)? {
+                rows.push((rid, row));
+            }
+        }
+        rows.sort_by_key(|(rid, _)| rid.slot);
+        self.buffered = rows.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for HeapScan<'_> {
+    type Item = Result<(RecordId, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.buffered.next() {
+                return Some(Ok(row));
+            }
+            let page_id = self.pages.next()?;
+            if let Err(err) = self.load(page_id) {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Pager, PagerOptions};
+
+    fn heap(dir: &tempfile::TempDir) -> HeapFile {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        HeapFile::create(Arc::new(BufferPool::new(pager, 16))).unwrap()
+    }
+
+    fn rows(heap: &HeapFile) -> Vec<(RecordId, Vec<u8>)> {
+        heap.scan().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_insert_get_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let heap = heap(&dir);
+        let rids: Vec<RecordId> = (0..50u8).map(|i| heap.insert(&[i; 30]).unwrap()).collect();
+        assert!(rids.iter().map(|r| r.page).collect::<std::collections::HashSet<_>>().len() > 1);
+        assert_eq!(heap.get(rids[42]).unwrap(), Some(vec![42; 30]));
+
+        heap.delete(rids[42]).unwrap();
+        assert_eq!(heap.get(rids[42]).unwrap(), None);
+        assert!(heap.delete(rids[42]).is_err());
+        assert_eq!(rows(&heap).len(), 49);
+
+        // The freed space is found through the free-space map.
+        assert_eq!(heap.insert(&[1; 30]).unwrap(), rids[42]);
+        assert!(heap.insert(&vec![0; 1000]).unwrap_err().to_string().contains("does not fit"));
+    }
+
+    #[test]
+    fn test_update_forwards_and_keeps_record_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let heap = heap(&dir);
+        let rids: Vec<RecordId> = (0..10u8).map(|i| heap.insert(&[i; 30]).unwrap()).collect();
+        assert!(rids.iter().all(|r| r.page == rids[0].page));
+
+        heap.update(rids[3], &[3; 200]).unwrap();
+        assert_eq!(heap.get(rids[3]).unwrap(), Some(vec![3; 200]));
+        // Grows again, then shrinks: still reachable through the same id.
+        heap.update(rids[3], &[4; 300]).unwrap();
+        heap.update(rids[3], &[5; 5]).unwrap();
+        assert_eq!(heap.get(rids[3]).unwrap(), Some(vec![5; 5]));
+
+        let scanned = rows(&heap);
+        assert_eq!(scanned.len(), 10);
+        assert!(scanned.contains(&(rids[3], vec![5; 5])));
+
+        heap.delete(rids[3]).unwrap();
+        assert_eq!(rows(&heap).len(), 9);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, rid) = {
+            let heap = heap(&dir);
+            let rid = heap.insert(b"persisted").unwrap();
+            (heap.root(), rid)
+        };
+        let pager = Pager::open(dir.path().join("db")).unwrap();
+        let heap = HeapFile::open(Arc::new(BufferPool::new(pager, 4)), root).unwrap();
+        assert_eq!(heap.get(rid).unwrap(), Some(b"persisted".to_vec()));
+    }
+}
@@ -0,0 +1,329 @@
+// src/storage/overflow.rs
+//! Out-of-line storage for values too large to keep in a row.
+//!
+//! A value is split across a chain of overflow pages linked through their
+//! `next` header field; each page holds a `u32` byte count followed by that
+//! many bytes of the value. The row keeps an [`OverflowRef`] to the chain.
+//! Values may be LZ4-compressed (frame format) first, which is only kept if
+//! it actually saves space.
+
+use std::convert::TryInto;
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use crate::buffer::BufferPool;
+use crate::error::Error;
+use crate::row::codec::ExternalStore;
+use crate::storage::page::{PageId, PageType};
+
+const COMPRESSED: u8 = 1;
+
+/// Where an out-of-line value lives and how it is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverflowRef {
+    pub first: PageId,
+    /// Bytes in the chain.
+    pub stored_len: u64,
+    /// Bytes of the value once decompressed.
+    pub len: u64,
+    pub compressed: bool,
+}
+
+impl OverflowRef {
+    pub const SIZE: usize = 25;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend_from_slice(&self.first.to_le_bytes());
+        bytes.extend_from_slice(&self.stored_len.to_le_bytes());
+        bytes.extend_from_slice(&self.len.to_le_bytes());
+        bytes.push(if self.compressed { COMPRESSED } else { 0 });
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(Error::Storage(format!("overflow reference of {} bytes", bytes.len())));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        Ok(OverflowRef {
+            first: u64_at(0),
+            stored_len: u64_at(8),
+            len: u64_at(16),
+            compressed: bytes[24] & COMPRESSED != 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OverflowStore {
+    pool: Arc<BufferPool>,
+    threshold: usize,
+    compress: bool,
+}
+
+impl OverflowStore {
+    /// A store that moves values over a quarter of a page out of line,
+    /// without compression.
+    pub fn new(pool: Arc<BufferPool>) -> Self {
+        let threshold = pool.page_size() / 4;
+        OverflowStore { pool, threshold, compress: false }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn write(&self, value: &[u8]) -> Result<OverflowRef, Error> {
+        let compressed = if self.compress { Some(compress(value)?) } else { None };
+        let (stored, compressed) = match &compressed {
+            Some(c) if c.len() < value.len() => (&c[..], true),
+            _ => (value, false),
+        };
+
+        let mut first = 0;
+        let mut prev: Option<crate::buffer::PageGuard<'_>> = None;
+        for chunk in stored.chunks(self.pool.page_size() - crate::storage::PAGE_HEADER_SIZE - 4) {
+            let guard = self.pool.new_page(PageType::Overflow)?;
+            {
+                let mut page = guard.write();
+                page.data[..4].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+                page.data[4..4 + chunk.len()].copy_from_slice(chunk);
+                page.header.free_space = 0;
+            }
+            match prev {
+                Some(prev) => prev.write().header.next = guard.id(),
+                None => first = guard.id(),
+            }
+            prev = Some(guard);
+        }
+        Ok(OverflowRef {
+            first,
+            stored_len: stored.len() as u64,
+            len: value.len() as u64,
+            compressed,
+        })
+    }
+
+    /// Streams a value, holding at most one page of it in memory at a time
+    /// (plus the decompressor's window, if compressed).
+    pub fn reader(&self, reference: &OverflowRef) -> Box<dyn Read + Send> {
+        let chain = ChainReader {
+            pool: self.pool.clone(),
+            next: reference.first,
+            chunk: Vec::new(),
+            pos: 0,
+            remaining: reference.stored_len,
+        };
+        if reference.compressed {
+            Box::new(lz4_flex::frame::FrameDecoder::new(chain))
+        } else {
+            Box::new(chain)
+        }
+    }
+
+    pub fn read(&self, reference: &OverflowRef) -> Result<Vec<u8>, Error> {
+        let mut value = Vec::with_capacity(reference.len as usize);
+        self.reader(reference).read_to_end(&mut value)?;
+        if value.len() as u64 != reference.len {
+            return Err(Error::Storage(format!(
+                "overflow value at page {} has {} bytes, expected {}",
+                reference.first,
+                value.len(),
+                reference.len
+            )));
+        }
+        Ok(value)
+    }
+
+    /// Returns every page of the chain to the free list.
+    pub fn free(&self, reference: &OverflowRef) -> Result<(), Error> {
+        let mut next = reference.first;
+        while next != 0 {
+            let following = {
+                let guard = self.pool.fetch(next)?;
+                let page = guard.read();
+                if page.header.page_type != PageType::Overflow {
+                    return Err(Error::Storage(format!("page {} is not an overflow page", next)));
+                }
+                page.header.next
+            };
+            self.pool.free_page(next)?;
+            next = following;
+        }
+        Ok(())
+    }
+}
+
+impl ExternalStore for OverflowStore {
+    fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    fn put(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(self.write(value)?.to_bytes())
+    }
+
+    fn get(&self, reference: &[u8]) -> Result<Vec<u8>, Error> {
+        self.read(&OverflowRef::from_bytes(reference)?)
+    }
+}
+
+fn compress(value: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+    io::Write::write_all(&mut encoder, value)?;
+    encoder.finish().map_err(|err| Error::Storage(format!("compression failed: {}", err)))
+}
+
+/// Reads the raw bytes of an overflow chain, one page at a time.
+struct ChainReader {
+    pool: Arc<BufferPool>,
+    next: PageId,
+    chunk: Vec<u8>,
+    pos: usize,
+    remaining: u64,
+}
+
+impl ChainReader {
+    fn load(&mut self) -> Result<(), Error> {
+        let guard = self.pool.fetch(self.next)?;
+        let page = guard.read();
+        if page.header.page_type != PageType::Overflow {
+            return Err(Error::Storage(format!("page {} is not an overflow page", self.next)));
+        }
+        let len = u32::from_le_bytes(page.data[..4].try_into().unwrap()) as usize;
+        let bytes = page
+            .data
+            .get(4..4 + len)
+            .ok_or_else(|| Error::Storage(format!("overflow page {} is corrupt", self.next)))?;
+        self.chunk.clear();
+        self.chunk.extend_from_slice(bytes);
+        self.pos = 0;
+        self.next = page.header.next;
+        Ok(())
+    }
+}
+
+impl Read for ChainReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            if self.next == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "overflow chain ended early"));
+            }
+            self.load().map_err(io::Error::other)?;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        let n = n.min(self.remaining as usize);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{ColumnSchema, TableSchema};
+    use crate::parser::ast::DataType;
+    use crate::row::codec::RowCodec;
+    use crate::storage::{HeapFile, Pager, PagerOptions};
+    use crate::types::Value;
+
+    fn pool(dir: &tempfile::TempDir) -> Arc<BufferPool> {
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        Arc::new(BufferPool::new(pager, 8))
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x: u32 = 12345;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_streaming_and_free() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = OverflowStore::new(pool(&dir));
+        let value = noise(5000);
+        let reference = store.write(&value).unwrap();
+        assert!(!reference.compressed);
+        assert_eq!(OverflowRef::from_bytes(&reference.to_bytes()).unwrap(), reference);
+
+        let mut reader = store.reader(&reference);
+        let mut buf = [0; 100];
+        let mut streamed = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(streamed, value);
+
+        store.free(&reference).unwrap();
+        assert_eq!(store.write(b"again").unwrap().first, reference.first + 11);
+    }
+
+    #[test]
+    fn test_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = OverflowStore::new(pool(&dir)).with_compression(true);
+        let text = "all work and no play makes jack a dull boy. ".repeat(200);
+        let reference = store.write(text.as_bytes()).unwrap();
+        assert!(reference.compressed);
+        assert!(reference.stored_len < reference.len / 10);
+        assert_eq!(store.read(&reference).unwrap(), text.as_bytes());
+
+        // Incompressible data is stored as is.
+        assert!(!store.write(&noise(2000)).unwrap().compressed);
+    }
+
+    #[test]
+    fn test_large_values_in_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = pool(&dir);
+        let store = Arc::new(OverflowStore::new(pool.clone()).with_compression(true));
+        let schema = TableSchema::new(
+            "docs",
+            vec![
+                ColumnSchema::new("id", DataType::Integer(None)),
+                ColumnSchema::new("body", DataType::Text),
+                ColumnSchema::new("blob", DataType::Binary(None)),
+            ],
+        );
+        let codec = RowCodec::new(&schema).with_store(store.clone());
+        let body = "lorem ipsum ".repeat(1000);
+        let values = vec![Value::Int32(1), Value::String(body.clone()), Value::Bytes(vec![1, 2, 3])];
+
+        let row = codec.encode(&values).unwrap();
+        let heap = HeapFile::create(pool).unwrap();
+        let rid = heap.insert(&row).unwrap();
+        let row = heap.get(rid).unwrap().unwrap();
+        assert_eq!(codec.decode(&row).unwrap(), values);
+
+        let refs = codec.external_refs(&row).unwrap();
+        assert_eq!(refs.len(), 1);
+        assert!(codec.external_ref(&row, 2).unwrap().is_none());
+        let reference = OverflowRef::from_bytes(refs[0]).unwrap();
+        let mut streamed = String::new();
+        store.reader(&reference).read_to_string(&mut streamed).unwrap();
+        assert_eq!(streamed, body);
+
+        assert!(RowCodec::new(&schema).decode(&row).unwrap_err().to_string().contains("no store"));
+    }
+}
@@ -0,0 +1,306 @@
+// src/storage/page.rs
+//! In-memory form of a single page and its on-disk header.
+//!
+//! The header occupies the first [`PAGE_HEADER_SIZE`] bytes of every page:
+//!
+//! ```text
+//!  0..8   page_id     u64
+//!  8..12  type_id     u32
+//! 12..14  free_space  u16
+//! 14..16  reserved
+//! 16..20  checksum    u32  (CRC32C of the page with this field zeroed)
+//! 20..24  reserved
+//! 24..32  lsn         u64  (log record of the last change to this page)
+//! 32..40  next        u64  (free-list link, or a chain for multi-page data)
+//! 40..64  reserved
+//! ```
+//!
+//! All fields are little-endian.
+
+use std::convert::TryInto;
+
+use crate::error::Error;
+
+pub type PageId = u64;
+
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+pub const PAGE_HEADER_SIZE: usize = 64;
+
+const CHECKSUM: std::ops::Range<usize> = 16..20;
+const LSN: std::ops::Range<usize> = 24..32;
+
+/// Longest range of a page [`Page::changes_since`] reports as one change.
+const MAX_CHANGE: usize = 1 << 15;
+/// Changed ranges closer than this are reported as one.
+const CHANGE_GAP: usize = 16;
+
+/// What a page is used for, stored as its `type_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageType {
+    /// On the free list, waiting to be reused.
+    Free,
+    /// Rows of a table.
+    Heap,
+    /// Free-space map of a heap file.
+    FreeSpaceMap,
+    /// Part of a value too large to store inline.
+    Overflow,
+    /// Fixed entry point of an index, pointing at its root.
+    IndexMeta,
+    /// Inner node of a B+tree.
+    BTreeInternal,
+    /// Leaf node of a B+tree.
+    BTreeLeaf,
+    /// Fixed entry point of a hash index, leading to its directory.
+    HashMeta,
+    /// Part of the directory of a hash index.
+    HashDirectory,
+    /// Page of a hash index bucket.
+    HashBucket,
+}
+
+impl PageType {
+    pub fn id(self) -> u32 {
+        match self {
+            PageType::Free => 0,
+            PageType::Heap => 1,
+            PageType::FreeSpaceMap => 2,
+            PageType::Overflow => 3,
+            PageType::IndexMeta => 4,
+            PageType::BTreeInternal => 5,
+            PageType::BTreeLeaf => 6,
+            PageType::HashMeta => 7,
+            PageType::HashDirectory => 8,
+            PageType::HashBucket => 9,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Result<Self, Error> {
+        Ok(match id {
+            0 => PageType::Free,
+            1 => PageType::Heap,
+            2 => PageType::FreeSpaceMap,
+            3 => PageType::Overflow,
+            4 => PageType::IndexMeta,
+            5 => PageType::BTreeInternal,
+            6 => PageType::BTreeLeaf,
+            7 => PageType::HashMeta,
+            8 => PageType::HashDirectory,
+            9 => PageType::HashBucket,
+            other => return Err(Error::Storage(format!("unknown page type {}", other))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageHeader {
+    pub page_id: PageId,
+    pub page_type: PageType,
+    /// Bytes of `data` still available; how they are used is up to the page type.
+    pub free_space: u16,
+    /// Checksum read from disk; recomputed on every write.
+    pub checksum: u32,
+    /// LSN of the last logged change applied to the page, 0 if none.
+    pub lsn: u64,
+    pub next: PageId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub header: PageHeader,
+    pub data: Vec<u8>,
+}
+
+impl Page {
+    /// A zeroed page of the given size with all of its data free.
+    pub fn new(page_id: PageId, page_type: PageType, page_size: usize) -> Self {
+        let data_len = page_size - PAGE_HEADER_SIZE;
+        Page {
+            header: PageHeader {
+                page_id,
+                page_type,
+                free_space: data_len as u16,
+                checksum: 0,
+                lsn: 0,
+                next: 0,
+            },
+            data: vec![0; data_len],
+        }
+    }
+
+    pub fn id(&self) -> PageId {
+        self.header.page_id
+    }
+
+    pub fn size(&self) -> usize {
+        PAGE_HEADER_SIZE + self.data.len()
+    }
+
+    /// Serializes the page, filling in its checksum.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.size()];
+        self.write_to(&mut bytes);
+        bytes
+    }
+
+    /// Serializes the page into `out`, which must be exactly one page long.
+    pub fn write_to(&self, out: &mut [u8]) {
+        let h = &self.header;
+        out[..PAGE_HEADER_SIZE].fill(0);
+        out[0..8].copy_from_slice(&h.page_id.to_le_bytes());
+        out[8..12].copy_from_slice(&h.page_type.id().to_le_bytes());
+        out[12..14].copy_from_slice(&h.free_space.to_le_bytes());
+        out[24..32].copy_from_slice(&h.lsn.to_le_bytes());
+        out[32..40].copy_from_slice(&h.next.to_le_bytes());
+        out[PAGE_HEADER_SIZE..].copy_from_slice(&self.data);
+        let checksum = checksum(out);
+        out[CHECKSUM].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// The page as serialized, but with its checksum and LSN left zero: the
+    /// bytes changes to the page are logged as.
+    fn image(&self) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        bytes[CHECKSUM].fill(0);
+        bytes[LSN].fill(0);
+        bytes
+    }
+
+    /// The ranges of the page's image that differ from `before`'s, as
+    /// `(offset, before, after)`. Ranges a few bytes apart are merged.
+    pub fn changes_since(&self, before: &Page) -> Vec<(usize, Vec<u8>, Vec<u8>)> {
+        let (old, new) = (before.image(), self.image());
+        let mut changes = Vec::new();
+        let mut at = 0;
+        while at < new.len() {
+            if old[at] == new[at] {
+                at += 1;
+                continue;
+            }
+            let start = at;
+            let mut end = at + 1;
+            let mut i = end;
+            while i < new.len() && i - start < MAX_CHANGE && i - end < CHANGE_GAP {
+                if old[i] != new[i] {
+                    end = i + 1;
+                }
+                i += 1;
+            }
+            changes.push((start, old[start..end].to_vec(), new[start..end].to_vec()));
+            at = end;
+        }
+        changes
+    }
+
+    /// Overwrites bytes `offset..offset + bytes.len()` of the page's image,
+    /// header fields included. The checksum and LSN stay as they are.
+    pub fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        let end = offset + bytes.len();
+        if end > self.size() {
+            return Err(Error::Storage(format!(
+                "change of {} bytes at {} is outside page {}",
+                bytes.len(),
+                offset,
+                self.id()
+            )));
+        }
+        if offset >= PAGE_HEADER_SIZE {
+            self.data[offset - PAGE_HEADER_SIZE..end - PAGE_HEADER_SIZE].copy_from_slice(bytes);
+            return Ok(());
+        }
+        let mut image = self.to_bytes();
+        image[offset..end].copy_from_slice(bytes);
+        let header = read_header(&image)?;
+        self.header = PageHeader { checksum: self.header.checksum, lsn: self.header.lsn, ..header };
+        self.data.copy_from_slice(&image[PAGE_HEADER_SIZE..]);
+        Ok(())
+    }
+
+    /// Parses a page, failing if its checksum does not match its contents.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() <= PAGE_HEADER_SIZE {
+            return Err(Error::Storage(format!("page of {} bytes is too small", bytes.len())));
+        }
+        let stored = u32::from_le_bytes(bytes[CHECKSUM].try_into().unwrap());
+        let actual = checksum(bytes);
+        let header = read_header(bytes)?;
+        if stored != actual {
+            return Err(Error::Storage(format!(
+                "checksum mismatch on page {}: stored {:08x}, computed {:08x}",
+                header.page_id, stored, actual
+            )));
+        }
+        Ok(Page {
+            header,
+            data: bytes[PAGE_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+/// The header fields of a serialized page.
+fn read_header(bytes: &[u8]) -> Result<PageHeader, Error> {
+    let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    Ok(PageHeader {
+        page_id: u64_at(0),
+        page_type: PageType::from_id(u32_at(8))?,
+        free_space: u16::from_le_bytes(bytes[12..14].try_into().unwrap()),
+        checksum: u32_at(CHECKSUM.start),
+        lsn: u64_at(LSN.start),
+        next: u64_at(32),
+    })
+}
+
+/// CRC32C of a serialized page, treating its checksum field as zero.
+fn checksum(bytes: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(&bytes[..CHECKSUM.start]);
+    let crc = crc32c::crc32c_append(crc, &[0; 4]);
+    crc32c::crc32c_append(crc, &bytes[CHECKSUM.end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_checksum() {
+        let mut page = Page::new(3, PageType::Heap, 512);
+        page.header.next = 9;
+        page.data[..5].copy_from_slice(b"hello");
+        let mut bytes = page.to_bytes();
+        assert_eq!(bytes.len(), 512);
+        assert_eq!(&bytes[0..8], &3u64.to_le_bytes());
+
+        let read = Page::from_bytes(&bytes).unwrap();
+        assert_eq!(read.header.next, 9);
+        assert_eq!(&read.data[..5], b"hello");
+        assert_eq!(read.header.free_space, 512 - PAGE_HEADER_SIZE as u16);
+
+        bytes[100] ^= 1;
+        let err = Page::from_bytes(&bytes).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch on page 3"));
+    }
+
+    #[test]
+    fn test_changes_and_patches() {
+        let before = Page::new(3, PageType::Heap, 512);
+        let mut after = before.clone();
+        after.header.next = 7;
+        after.header.lsn = 99;
+        after.data[10] = 1;
+        after.data[20] = 2;
+        after.data[400] = 3;
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.iter().map(|(offset, ..)| *offset).collect::<Vec<_>>(), vec![32, 74, 464]);
+        assert_eq!(changes[1].2.len(), 11);
+
+        let mut page = before.clone();
+        for (offset, _, bytes) in &changes {
+            page.patch(*offset, bytes).unwrap();
+        }
+        assert_eq!(page.header.next, 7);
+        assert_eq!(page.data, after.data);
+        assert_eq!(page.header.lsn, 0);
+        assert!(page.patch(510, b"xyz").is_err());
+    }
+}
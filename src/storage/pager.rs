@@ -0,0 +1,300 @@
+// src/storage/pager.rs
+//! Allocation and I/O of pages in a single memory-mapped database file.
+//!
+//! Page 0 is the file header:
+//!
+//! ```text
+//!  0..8   magic        b"RUSTDB\0\0"
+//!  8..12  version      u32
+//! 12..16  page_size    u32
+//! 16..24  page_count   u64  (pages in use, including this one)
+//! 24..32  free_head    u64  (first page on the free list, 0 if empty)
+//! 32..36  checksum     u32  (CRC32C of bytes 0..32)
+//! ```
+//!
+//! Freed pages form a singly linked list through their `next` header field
+//! and are reused before the file grows.
+
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use crate::error::Error;
+use crate::storage::page::{Page, PageId, PageType, DEFAULT_PAGE_SIZE};
+
+const MAGIC: &[u8; 8] = b"RUSTDB\0\0";
+const FORMAT_VERSION: u32 = 1;
+const FILE_HEADER_SIZE: usize = 36;
+
+/// The most pages added to the file in one growth step.
+const MAX_GROWTH: u64 = 1024;
+
+#[derive(Debug, Clone)]
+pub struct PagerOptions {
+    /// Page size for new files; a power of two from 512 to 65536 bytes.
+    /// Existing files always keep the page size they were created with.
+    pub page_size: usize,
+}
+
+impl Default for PagerOptions {
+    fn default() -> Self {
+        PagerOptions { page_size: DEFAULT_PAGE_SIZE }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pager {
+    file: File,
+    map: MmapMut,
+    page_size: usize,
+    page_count: u64,
+    free_head: PageId,
+}
+
+impl Pager {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(path, PagerOptions::default())
+    }
+
+    /// Opens the database file at `path`, creating it if it does not exist.
+    pub fn open_with(path: impl AsRef<Path>, options: PagerOptions) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let (page_size, page_count, free_head) = if file.metadata()?.len() == 0 {
+            let page_size = options.page_size;
+            if !page_size.is_power_of_two() || !(512..=65536).contains(&page_size) {
+                return Err(Error::Storage(format!("invalid page size {}", page_size)));
+            }
+            file.set_len(page_size as u64)?;
+            (page_size, 1, 0)
+        } else {
+            let mut header = [0; FILE_HEADER_SIZE];
+            file.read_exact(&mut header)
+                .map_err(|_| Error::Storage("file too short for a database header".to_string()))?;
+            read_file_header(&header)?
+        };
+
+        let capacity = file.metadata()?.len() / page_size as u64;
+        if capacity < page_count {
+            return Err(Error::Storage(format!(
+                "file holds {} pages but its header records {}",
+                capacity, page_count
+            )));
+        }
+
+        // SAFETY: the mapping is private to this pager, which holds the only
+        // handle to the file it writes through; other processes must not
+        // modify the file while it is open.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let mut pager = Pager { file, map, page_size, page_count, free_head };
+        pager.write_file_header();
+        Ok(pager)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Pages in use, including the file header and pages on the free list.
+    pub fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    /// Returns a fresh zeroed page, reusing a freed page if there is one.
+    /// The page is written before it is returned.
+    pub fn allocate(&mut self, page_type: PageType) -> Result<Page, Error> {
+        let id = if self.free_head != 0 {
+            let id = self.free_head;
+            let page = self.read(id)?;
+            if page.header.page_type != PageType::Free {
+                return Err(Error::Storage(format!("free list points at page {} which is in use", id)));
+            }
+            self.free_head = page.header.next;
+            id
+        } else {
+            self.grow_to(self.page_count + 1)?;
+            self.page_count += 1;
+            self.page_count - 1
+        };
+        let page = Page::new(id, page_type, self.page_size);
+        self.write(&page)?;
+        self.write_file_header();
+        Ok(page)
+    }
+
+    /// Returns a page to the free list.
+    pub fn free(&mut self, id: PageId) -> Result<(), Error> {
+        let page = self.read(id)?;
+        if page.header.page_type == PageType::Free {
+            return Err(Error::Storage(format!("page {} is already free", id)));
+        }
+        let mut freed = Page::new(id, PageType::Free, self.page_size);
+        freed.header.next = self.free_head;
+        self.write(&freed)?;
+        self.free_head = id;
+        self.write_file_header();
+        Ok(())
+    }
+
+    /// Reads a page, verifying its checksum.
+    pub fn read(&self, id: PageId) -> Result<Page, Error> {
+        let range = self.range(id)?;
+        let page = Page::from_bytes(&self.map[range])?;
+        if page.id() != id {
+            return Err(Error::Storage(format!("page {} has id {} on disk", id, page.id())));
+        }
+        Ok(page)
+    }
+
+    pub fn write(&mut self, page: &Page) -> Result<(), Error> {
+        if page.size() != self.page_size {
+            return Err(Error::Storage(format!(
+                "page of {} bytes written to a file with {}-byte pages",
+                page.size(),
+                self.page_size
+            )));
+        }
+        let range = self.range(page.id())?;
+        page.write_to(&mut self.map[range]);
+        Ok(())
+    }
+
+    /// Forces all written pages to disk.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.map.flush()?;
+        Ok(())
+    }
+
+    fn range(&self, id: PageId) -> Result<std::ops::Range<usize>, Error> {
+        if id == 0 || id >= self.page_count {
+            return Err(Error::Storage(format!("page {} out of range", id)));
+        }
+        let start = id as usize * self.page_size;
+        Ok(start..start + self.page_size)
+    }
+
+    /// Extends the file, and its mapping, to hold at least `pages` pages.
+    fn grow_to(&mut self, pages: u64) -> Result<(), Error> {
+        let capacity = (self.map.len() / self.page_size) as u64;
+        if pages <= capacity {
+            return Ok(());
+        }
+        let new_capacity = pages.max(capacity + capacity.min(MAX_GROWTH));
+        self.map.flush()?;
+        self.file.set_len(new_capacity * self.page_size as u64)?;
+        // SAFETY: see `open_with`.
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        Ok(())
+    }
+
+    fn write_file_header(&mut self) {
+        let header = &mut self.map[..FILE_HEADER_SIZE];
+        header[0..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&self.page_count.to_le_bytes());
+        header[24..32].copy_from_slice(&self.free_head.to_le_bytes());
+        let checksum = crc32c::crc32c(&header[..32]);
+        header[32..36].copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.map.flush();
+    }
+}
+
+fn read_file_header(header: &[u8; FILE_HEADER_SIZE]) -> Result<(usize, u64, PageId), Error> {
+    if &header[0..8] != MAGIC {
+        return Err(Error::Storage("not a database file (bad magic number)".to_string()));
+    }
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+    if crc32c::crc32c(&header[..32]) != u32_at(32) {
+        return Err(Error::Storage("checksum mismatch in file header".to_string()));
+    }
+    let version = u32_at(8);
+    if version != FORMAT_VERSION {
+        return Err(Error::Storage(format!("unsupported file format version {}", version)));
+    }
+    Ok((u32_at(12) as usize, u64_at(16), u64_at(24)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_allocate_write_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let ids: Vec<PageId> = {
+            let mut pager = Pager::open(&path).unwrap();
+            (0..5)
+                .map(|i| {
+                    let mut page = pager.allocate(PageType::Heap).unwrap();
+                    page.data[0] = i;
+                    pager.write(&page).unwrap();
+                    page.id()
+                })
+                .collect()
+        };
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+
+        let pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 6);
+        assert_eq!(pager.read(4).unwrap().data[0], 3);
+        assert!(pager.read(0).is_err());
+        assert!(pager.read(6).unwrap_err().to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn test_free_list_reuse() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        for _ in 0..4 {
+            pager.allocate(PageType::Heap).unwrap();
+        }
+        pager.free(2).unwrap();
+        pager.free(3).unwrap();
+        assert!(pager.free(3).unwrap_err().to_string().contains("already free"));
+
+        assert_eq!(pager.allocate(PageType::Heap).unwrap().id(), 3);
+        assert_eq!(pager.allocate(PageType::Heap).unwrap().id(), 2);
+        assert_eq!(pager.allocate(PageType::Heap).unwrap().id(), 5);
+        assert_eq!(pager.read(2).unwrap().data, vec![0; 512 - 64]);
+    }
+
+    #[test]
+    fn test_corruption_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        {
+            let mut pager = Pager::open_with(&path, PagerOptions { page_size: 1024 }).unwrap();
+            pager.allocate(PageType::Heap).unwrap();
+        }
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(1024 + 200)).unwrap();
+        file.write_all(&[0xAB]).unwrap();
+        drop(file);
+
+        // The page size comes from the file, not the options.
+        let pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_size(), 1024);
+        assert!(pager.read(1).unwrap_err().to_string().contains("checksum mismatch"));
+
+        std::fs::write(&path, b"definitely not a database file, no...").unwrap();
+        assert!(Pager::open(&path).unwrap_err().to_string().contains("bad magic"));
+        assert!(Pager::open_with(dir.path().join("x"), PagerOptions { page_size: 1000 }).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// src/storage/slotted.rs
+//! Slotted layout for the data area of a page.
+//!
+//! ```text
+//! | slot_count u16 | tuple_start u16 | slots ... -> free <- ... tuples |
+//! ```
+//!
+//! Each slot is an `offset: u16, len: u16` pair; an offset of zero marks an
+//! empty slot. Tuples are packed from the end of the page towards the slot
+//! directory. Slot numbers never change, so callers can hand them out as
+//! stable identifiers; compaction only moves tuple bytes.
+//!
+//! Every tuple reserves at least [`MIN_TUPLE`] bytes so that it can later be
+//! replaced in place by a forwarding pointer.
+
+use std::convert::TryInto;
+
+use crate::storage::page::Page;
+
+const HEADER: usize = 4;
+const SLOT: usize = 4;
+
+/// Smallest space reserved for a tuple.
+pub const MIN_TUPLE: usize = 11;
+
+fn reserved(len: usize) -> usize {
+    len.max(MIN_TUPLE)
+}
+
+fn u16_at(data: &[u8], at: usize) -> usize {
+    u16::from_le_bytes(data[at..at + 2].try_into().unwrap()) as usize
+}
+
+fn set_u16(data: &mut [u8], at: usize, value: usize) {
+    data[at..at + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+/// Formats the page as an empty slotted page.
+pub fn init(page: &mut Page) {
+    let len = page.data.len();
+    page.data.fill(0);
+    set_u16(&mut page.data, 0, 0);
+    set_u16(&mut page.data, 2, len);
+    page.header.free_space = (len - HEADER) as u16;
+}
+
+/// Largest tuple that fits in an empty page of the given data size.
+pub fn max_tuple(data_len: usize) -> usize {
+    data_len - HEADER - SLOT
+}
+
+pub fn slot_count(page: &Page) -> u16 {
+    u16_at(&page.data, 0) as u16
+}
+
+fn slot(page: &Page, slot: u16) -> (usize, usize) {
+    let at = HEADER + SLOT * slot as usize;
+    (u16_at(&page.data, at), u16_at(&page.data, at + 2))
+}
+
+fn set_slot(page: &mut Page, slot: u16, offset: usize, len: usize) {
+    let at = HEADER + SLOT * slot as usize;
+    set_u16(&mut page.data, at, offset);
+    set_u16(&mut page.data, at + 2, len);
+}
+
+/// Total free bytes, including fragments that compaction would reclaim.
+pub fn free_space(page: &Page) -> usize {
+    page.header.free_space as usize
+}
+
+fn contiguous_free(page: &Page) -> usize {
+    u16_at(&page.data, 2) - HEADER - SLOT * slot_count(page) as usize
+}
+
+/// Free space needed to insert a tuple of `len` bytes, assuming no empty
+/// slot can be reused.
+pub fn space_needed(len: usize) -> usize {
+    reserved(len) + SLOT
+}
+
+pub fn get(page: &Page, slot_id: u16) -> Option<&[u8]> {
+    if slot_id >= slot_count(page) {
+        return None;
+    }
+    match slot(page, slot_id) {
+        (0, _) => None,
+        (offset, len) => Some(&page.data[offset..offset + len]),
+    }
+}
+
+/// Stores a tuple and returns its slot, or `None` if the page is too full.
+pub fn insert(page: &mut Page, tuple: &[u8]) -> Option<u16> {
+    let count = slot_count(page);
+    let empty = (0..count).find(|&s| slot(page, s).0 == 0);
+    let need = reserved(tuple.len()) + if empty.is_some() { 0 } else { SLOT };
+    if free_space(page) < need {
+        return None;
+    }
+    let slot_id = match empty {
+        Some(s) => s,
+        None => {
+            if contiguous_free(page) < SLOT {
+                compact(page);
+            }
+            set_u16(&mut page.data, 0, count as usize + 1);
+            set_slot(page, count, 0, 0);
+            count
+        }
+    };
+    page.header.free_space -= (need - reserved(tuple.len())) as u16;
+    place(page, slot_id, tuple);
+    Some(slot_id)
+}
+
+/// Writes a tuple into fresh space for an already-allocated slot, whose
+/// reservation must already be counted as free.
+fn place(page: &mut Page, slot_id: u16, tuple: &[u8]) {
+    let size = reserved(tuple.len());
+    if contiguous_free(page) < size {
+        compact(page);
+    }
+    let start = u16_at(&page.data, 2) - size;
+    page.data[start..start + size].fill(0);
+    page.data[start..start + tuple.len()].copy_from_slice(tuple);
+    set_u16(&mut page.data, 2, start);
+    set_slot(page, slot_id, start, tuple.len());
+    page.header.free_space -= size as u16;
+}
+
+/// Replaces a tuple, moving it within the page if it grew. Returns false,
+/// leaving the page unchanged, if the new tuple does not fit.
+pub fn update(page: &mut Page, slot_id: u16, tuple: &[u8]) -> bool {
+    let Some(old) = get(page, slot_id).map(<[u8]>::len) else {
+        return false;
+    };
+    let (old_size, new_size) = (reserved(old), reserved(tuple.len()));
+    let (offset, _) = slot(page, slot_id);
+    if new_size <= old_size {
+        page.data[offset..offset + old_size].fill(0);
+        page.data[offset..offset + tuple.len()].copy_from_slice(tuple);
+        set_slot(page, slot_id, offset, tuple.len());
+        page.header.free_space += (old_size - new_size) as u16;
+        return true;
+    }
+    if free_space(page) + old_size < new_size {
+        return false;
+    }
+    set_slot(page, slot_id, 0, 0);
+    page.header.free_space += old_size as u16;
+    place(page, slot_id, tuple);
+    true
+}
+
+/// Removes a tuple. Trailing empty slots are dropped from the directory.
+pub fn delete(page: &mut Page, slot_id: u16) -> bool {
+    let Some(len) = get(page, slot_id).map(<[u8]>::len) else {
+        return false;
+    };
+    set_slot(page, slot_id, 0, 0);
+    page.header.free_space += reserved(len) as u16;
+    let mut count = slot_count(page);
+    while count > 0 && slot(page, count - 1).0 == 0 {
+        count -= 1;
+        page.header.free_space += SLOT as u16;
+    }
+    set_u16(&mut page.data, 0, count as usize);
+    true
+}
+
+/// Packs all tuples against the end of the page, leaving the free space in
+/// one piece between the slot directory and the tuples.
+pub fn compact(page: &mut Page) {
+    let mut live: Vec<(u16, usize, usize)> = (0..slot_count(page))
+        .map(|s| (s, slot(page, s)))
+        .filter(|(_, (offset, _))| *offset != 0)
+        .map(|(s, (offset, len))| (s, offset, len))
+        .collect();
+    live.sort_by_key(|&(_, offset, _)| std::cmp::Reverse(offset));
+    let old = page.data.clone();
+    let mut end = page.data.len();
+    for (s, offset, len) in live {
+        let size = reserved(len);
+        end -= size;
+        page.data[end..end + size].copy_from_slice(&old[offset..offset + size]);
+        set_slot(page, s, end, len);
+    }
+    let dir_end = HEADER + SLOT * slot_count(page) as usize;
+    page.data[dir_end..end].fill(0);
+    set_u16(&mut page.data, 2, end);
+}
+
+/// Iterates over the occupied slots and their tuples.
+pub fn tuples(page: &Page) -> impl Iterator<Item = (u16, &[u8])> {
+    (0..slot_count(page)).filter_map(move |s| get(page, s).map(|t| (s, t)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::page::PageType;
+
+    fn page() -> Page {
+        let mut page = Page::new(1, PageType::Heap, 512);
+        init(&mut page);
+        page
+    }
+
+    #[test]
+    fn test_insert_update_delete() {
+        let mut page = page();
+        let a = insert(&mut page, b"alpha").unwrap();
+        let b = insert(&mut page, b"a much longer tuple").unwrap();
+        assert_eq!((a, b), (0, 1));
+        assert_eq!(get(&page, a), Some(&b"alpha"[..]));
+
+        assert!(update(&mut page, a, b"alpha, but grown past its old space"));
+        assert!(update(&mut page, b, b"short"));
+        assert_eq!(get(&page, a), Some(&b"alpha, but grown past its old space"[..]));
+        assert_eq!(get(&page, b), Some(&b"short"[..]));
+
+        assert!(delete(&mut page, a));
+        assert_eq!(get(&page, a), None);
+        assert_eq!(insert(&mut page, b"reuse"), Some(a));
+        assert!(delete(&mut page, a) && delete(&mut page, b));
+        assert_eq!(slot_count(&page), 0);
+        assert_eq!(free_space(&page), 512 - 64 - HEADER);
+    }
+
+    #[test]
+    fn test_fills_and_compacts() {
+        let mut page = page();
+        let tuple = [7u8; 40];
+        let mut slots = Vec::new();
+        while let Some(s) = insert(&mut page, &tuple) {
+            slots.push(s);
+        }
+        assert_eq!(slots.len(), (512 - 64 - HEADER) / (40 + SLOT));
+
+        // Free every other tuple; the space is fragmented but a tuple twice
+        // the size still fits after compaction.
+        for s in slots.iter().step_by(2) {
+            delete(&mut page, *s);
+        }
+        assert!(contiguous_free(&page) < 80);
+        let s = insert(&mut page, &[9u8; 80]).unwrap();
+        assert_eq!(get(&page, s), Some(&[9u8; 80][..]));
+        for s in slots.iter().skip(1).step_by(2) {
+            assert_eq!(get(&page, *s), Some(&tuple[..]));
+        }
+    }
+}
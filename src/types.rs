@@ -1,35 +1,998 @@
 // src/types.rs
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use bigdecimal::BigDecimal;
+use std::hash::Hash;
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, ToPrimitive};
 use std::fmt;
+use uuid::Uuid;
+use crate::error::Error;
+use crate::parser::ast::PathStep;
 
-#[derive(Debug, Clone, PartialEq)]
+pub mod cast;
+pub mod interval;
+pub mod key;
+
+pub use interval::Interval;
+
+/// A single database value.
+///
+/// `Value` has a total order (see the `Ord` impl) so that it can be sorted,
+/// grouped on and used as a map or index key. Values of different kinds
+/// order by kind, NULL first. Integers, floats and decimals of every width
+/// are one kind and compare by numeric value: `-0.0` equals `0.0`, a float
+/// equals the shortest decimal that reads back as it (so `0.1` equals
+/// `DECIMAL 0.1`), and NaN equals itself and sorts above every other number.
+#[derive(Debug, Clone)]
 pub enum Value {
     Null,
     Bool(bool),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
     Int(i64),
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+    Float32(f32),
     Float(f64),
     Decimal(BigDecimal),
     String(String),
     Bytes(Vec<u8>),
+    Uuid(Uuid),
     DateTime(DateTime<Utc>),
-    Date(NaiveDateTime),
-    Time(NaiveDateTime),
-    // Add more types as needed
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Interval(Interval),
+    Json(serde_json::Value),
+    /// `Vec<T>` and `[T; N]`.
+    Array(Vec<Value>),
+    /// A custom struct type; fields are kept in declaration order.
+    Struct(Vec<(String, Value)>),
+    /// A custom enum type. Unit variants have no payload; variants with data
+    /// carry a single value (a `Struct` or `Array` for multiple fields).
+    Enum {
+        variant: String,
+        payload: Option<Box<Value>>,
+    },
+    Map(BTreeMap<Value, Value>),
+}
+
+impl Value {
+    /// Position of the variant in the cross-type ordering.
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Int8(_)
+            | Value::Int16(_)
+            | Value::Int32(_)
+            | Value::Int(_)
+            | Value::UInt8(_)
+            | Value::UInt16(_)
+            | Value::UInt32(_)
+            | Value::UInt64(_)
+            | Value::Float32(_)
+            | Value::Float(_)
+            | Value::Decimal(_) => 2,
+            Value::String(_) => 5,
+            Value::Bytes(_) => 6,
+            Value::Uuid(_) => 7,
+            Value::DateTime(_) => 8,
+            Value::Date(_) => 9,
+            Value::Time(_) => 10,
+            Value::Interval(_) => 11,
+            Value::Json(_) => 12,
+            Value::Array(_) => 13,
+            Value::Struct(_) => 14,
+            Value::Enum { .. } => 15,
+            Value::Map(_) => 16,
+        }
+    }
+
+    /// The value of any integer variant, widened losslessly.
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Int8(i) => Some(*i as i128),
+            Value::Int16(i) => Some(*i as i128),
+            Value::Int32(i) => Some(*i as i128),
+            Value::Int(i) => Some(*i as i128),
+            Value::UInt8(i) => Some(*i as i128),
+            Value::UInt16(i) => Some(*i as i128),
+            Value::UInt32(i) => Some(*i as i128),
+            Value::UInt64(i) => Some(*i as i128),
+            _ => None,
+        }
+    }
+
+    /// The value of either float variant, widened to `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float32(f) => Some(*f as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// A number of any kind as it is ordered, `None` for other kinds.
+    pub(crate) fn number(&self) -> Option<Number> {
+        Some(match self {
+            Value::Decimal(d) => Number::Finite(d.clone()),
+            _ => match (self.as_i128(), self.as_f64()) {
+                (Some(i), _) => Number::Finite(BigDecimal::from(BigInt::from(i))),
+                (_, Some(f)) if f.is_nan() => Number::NaN,
+                (_, Some(f)) if f == f64::INFINITY => Number::PositiveInfinity,
+                (_, Some(f)) if f == f64::NEG_INFINITY => Number::NegativeInfinity,
+                // `{:e}` prints the shortest digits that read back as `f`.
+                (_, Some(f)) => Number::Finite(BigDecimal::from_str(&format!("{:e}", f)).ok()?),
+                _ => return None,
+            },
+        })
+    }
+
+    /// Looks up a field of a `Struct` value by name.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of fields and elements into a composite value. Struct
+    /// fields match case-insensitively if no field matches exactly, and JSON
+    /// objects and arrays (how structs are stored) are followed too, with
+    /// JSON scalars coming back as plain values. A step that finds nothing
+    /// yields NULL.
+    pub fn path(&self, steps: &[PathStep]) -> Value {
+        let Some((step, rest)) = steps.split_first() else { return self.clone() };
+        match (self, step) {
+            (Value::Struct(_), PathStep::Field(name)) => {
+                let found = self.field(name).or_else(|| match self {
+                    Value::Struct(fields) => {
+                        fields.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v)
+                    }
+                    _ => None,
+                });
+                found.map_or(Value::Null, |value| value.path(rest))
+            }
+            (Value::Array(items), PathStep::Element(index)) => {
+                items.get(*index).map_or(Value::Null, |value| value.path(rest))
+            }
+            (Value::Json(json), _) => {
+                let found = match (json, step) {
+                    (serde_json::Value::Object(fields), PathStep::Field(name)) => fields
+                        .get(name)
+                        .or_else(|| fields.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v)),
+                    (serde_json::Value::Array(items), PathStep::Element(index)) => items.get(*index),
+                    _ => None,
+                };
+                match found {
+                    Some(json) => cast::json_scalar(json).unwrap_or_else(|| Value::Json(json.clone())).path(rest),
+                    None => Value::Null,
+                }
+            }
+            _ => Value::Null,
+        }
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Decimal(a), Value::Decimal(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+            (Value::DateTime(a), Value::DateTime(b)) => a.cmp(b),
+            (Value::Date(a), Value::Date(b)) => a.cmp(b),
+            (Value::Time(a), Value::Time(b)) => a.cmp(b),
+            (Value::Interval(a), Value::Interval(b)) => a.cmp(b),
+            // serde_json has no ordering; its canonical text (object keys are
+            // sorted) gives a stable one.
+            (Value::Json(a), Value::Json(b)) => a.to_string().cmp(&b.to_string()),
+            (Value::Array(a), Value::Array(b)) => a.cmp(b),
+            (Value::Struct(a), Value::Struct(b)) => a.cmp(b),
+            (
+                Value::Enum { variant: va, payload: pa },
+                Value::Enum { variant: vb, payload: pb },
+            ) => va.cmp(vb).then_with(|| pa.cmp(pb)),
+            (Value::Map(a), Value::Map(b)) => a.cmp(b),
+            _ => match (self.rank(), other.rank()) {
+                (2, 2) => match (self.as_i128(), other.as_i128(), self.as_f64(), other.as_f64()) {
+                    (Some(a), Some(b), _, _) => a.cmp(&b),
+                    (_, _, Some(a), Some(b)) if !a.is_nan() && !b.is_nan() => a.partial_cmp(&b).unwrap(),
+                    _ => self.number().cmp(&other.number()),
+                },
+                (a, b) => a.cmp(&b),
+            },
+        }
+    }
+}
+
+/// A number of any kind, placed where it sorts among all numbers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Number {
+    NegativeInfinity,
+    Finite(BigDecimal),
+    PositiveInfinity,
+    NaN,
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
+impl Eq for Value {}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Null => write!(f, "NULL"),
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Int8(i) => write!(f, "{}", i),
+            Value::Int16(i) => write!(f, "{}", i),
+            Value::Int32(i) => write!(f, "{}", i),
             Value::Int(i) => write!(f, "{}", i),
+            Value::UInt8(i) => write!(f, "{}", i),
+            Value::UInt16(i) => write!(f, "{}", i),
+            Value::UInt32(i) => write!(f, "{}", i),
+            Value::UInt64(i) => write!(f, "{}", i),
+            Value::Float32(fl) => write!(f, "{}", fl),
             Value::Float(fl) => write!(f, "{}", fl),
+            Value::Decimal(d) => write!(f, "{}", d),
             Value::String(s) => write!(f, "'{}'", s),
+            Value::Bytes(b) => {
+                write!(f, "x'")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
+            Value::Uuid(u) => write!(f, "'{}'", u),
             Value::DateTime(dt) => write!(f, "'{}'", dt),
-            // Implement other variants
-            _ => write!(f, "?"),
+            Value::Date(d) => write!(f, "'{}'", d),
+            Value::Time(t) => write!(f, "'{}'", t),
+            Value::Interval(i) => write!(f, "'{}'", i),
+            Value::Json(j) => write!(f, "'{}'", j),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Enum { variant, payload: None } => write!(f, "{}", variant),
+            Value::Enum { variant, payload: Some(payload) } => write!(f, "{}({})", variant, payload),
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} => {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Conversion of a Rust value into a database [`Value`].
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+
+    /// Converts a sequence of `Self`, e.g. a `Vec<Self>`. Sequences become a
+    /// `Value::Array`, except for `u8` which packs into `Value::Bytes`.
+    #[doc(hidden)]
+    fn slice_to_value(items: &[Self]) -> Value
+    where
+        Self: Sized,
+    {
+        Value::Array(items.iter().map(ToValue::to_value).collect())
+    }
+}
+
+/// Conversion of a database [`Value`] back into a Rust value.
+///
+/// Narrowing conversions are checked: a value that does not fit the target
+/// type yields an `Error::Type` instead of being truncated.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, Error>;
+
+    /// Counterpart of [`ToValue::slice_to_value`].
+    #[doc(hidden)]
+    fn vec_from_value(value: &Value) -> Result<Vec<Self>, Error> {
+        match value {
+            Value::Array(items) => items.iter().map(Self::from_value).collect(),
+            Value::Bytes(bytes) => bytes
+                .iter()
+                .map(|b| Self::from_value(&Value::UInt8(*b)))
+                .collect(),
+            _ => Err(mismatch("array", value)),
+        }
+    }
+}
+
+/// Conversion of a positional row (e.g. a tuple) into a list of values.
+pub trait ToValues {
+    fn to_values(&self) -> Vec<Value>;
+}
+
+/// Conversion of a positional row of values into a Rust value such as a tuple.
+pub trait FromValues: Sized {
+    fn from_values(values: &[Value]) -> Result<Self, Error>;
+}
+
+impl Value {
+    /// Name of the value's type, used in conversion error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "NULL",
+            Value::Bool(_) => "BOOL",
+            Value::Int8(_) => "I8",
+            Value::Int16(_) => "I16",
+            Value::Int32(_) => "I32",
+            Value::Int(_) => "I64",
+            Value::UInt8(_) => "U8",
+            Value::UInt16(_) => "U16",
+            Value::UInt32(_) => "U32",
+            Value::UInt64(_) => "U64",
+            Value::Float32(_) => "F32",
+            Value::Float(_) => "F64",
+            Value::Decimal(_) => "DECIMAL",
+            Value::String(_) => "STRING",
+            Value::Bytes(_) => "BYTES",
+            Value::Uuid(_) => "UUID",
+            Value::DateTime(_) => "DATETIME",
+            Value::Date(_) => "DATE",
+            Value::Time(_) => "TIME",
+            Value::Interval(_) => "INTERVAL",
+            Value::Json(_) => "JSON",
+            Value::Array(_) => "ARRAY",
+            Value::Struct(_) => "STRUCT",
+            Value::Enum { .. } => "ENUM",
+            Value::Map(_) => "MAP",
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+fn mismatch(expected: &str, value: &Value) -> Error {
+    Error::Type(format!("cannot convert {} value {} to {}", value.type_name(), value, expected))
+}
+
+// Used by the code generated by `#[derive(FromValue)]`.
+#[doc(hidden)]
+pub fn conversion_mismatch(expected: &str, value: &Value) -> Error {
+    mismatch(expected, value)
+}
+
+#[doc(hidden)]
+pub fn conversion_context(context: &str, err: Error) -> Error {
+    match err {
+        Error::Type(msg) => Error::Type(format!("{}: {}", context, msg)),
+        other => other,
+    }
+}
+
+fn out_of_range(target: &str, value: &Value) -> Error {
+    Error::Type(format!("value {} is out of range for {}", value, target))
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        Ok(value.clone())
+    }
+}
+
+macro_rules! impl_integer {
+    ($($t:ty => $variant:ident),*) => {$(
+        impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                Value::$variant(*self)
+            }
+        }
+
+        impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<Self, Error> {
+                match value.as_i128() {
+                    Some(i) => <$t>::try_from(i).map_err(|_| out_of_range(stringify!($t), value)),
+                    None => Err(mismatch(stringify!($t), value)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_integer!(
+    i8 => Int8, i16 => Int16, i32 => Int32, i64 => Int,
+    u16 => UInt16, u32 => UInt32, u64 => UInt64
+);
+
+impl ToValue for u8 {
+    fn to_value(&self) -> Value {
+        Value::UInt8(*self)
+    }
+
+    fn slice_to_value(items: &[Self]) -> Value {
+        Value::Bytes(items.to_vec())
+    }
+}
+
+impl FromValue for u8 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value.as_i128() {
+            Some(i) => u8::try_from(i).map_err(|_| out_of_range("u8", value)),
+            None => Err(mismatch("u8", value)),
+        }
+    }
+
+    fn vec_from_value(value: &Value) -> Result<Vec<Self>, Error> {
+        match value {
+            Value::Bytes(bytes) => Ok(bytes.clone()),
+            Value::Array(items) => items.iter().map(Self::from_value).collect(),
+            _ => Err(mismatch("Vec<u8>", value)),
+        }
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Float(f) => Ok(*f),
+            Value::Float32(f) => Ok(*f as f64),
+            Value::Decimal(d) => d.to_f64().ok_or_else(|| out_of_range("f64", value)),
+            v => match v.as_i128() {
+                Some(i) => Ok(i as f64),
+                None => Err(mismatch("f64", value)),
+            },
+        }
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float32(*self)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        if let Value::Float32(f) = value {
+            return Ok(*f);
+        }
+        let f = f64::from_value(value)?;
+        // Precision loss is fine, but finite values must stay finite.
+        if f.is_finite() && f.abs() > f32::MAX as f64 {
+            return Err(out_of_range("f32", value));
+        }
+        Ok(f as f32)
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(mismatch("bool", value)),
+        }
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(mismatch("String", value)),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        T::slice_to_value(self)
+    }
+}
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self) -> Value {
+        T::slice_to_value(self)
+    }
+}
+
+impl<T: ToValue, const N: usize> ToValue for [T; N] {
+    fn to_value(&self) -> Value {
+        T::slice_to_value(self)
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        T::vec_from_value(value)
+    }
+}
+
+impl<T: FromValue, const N: usize> FromValue for [T; N] {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        let items = T::vec_from_value(value)?;
+        let len = items.len();
+        items.try_into().map_err(|_| {
+            Error::Type(format!("expected an array of length {}, got length {}", N, len))
+        })
+    }
+}
+
+impl<K: ToValue, V: ToValue> ToValue for BTreeMap<K, V> {
+    fn to_value(&self) -> Value {
+        Value::Map(self.iter().map(|(k, v)| (k.to_value(), v.to_value())).collect())
+    }
+}
+
+impl<K: FromValue + Ord, V: FromValue> FromValue for BTreeMap<K, V> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Map(entries) => entries
+                .iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            _ => Err(mismatch("map", value)),
+        }
+    }
+}
+
+impl<K: ToValue, V: ToValue, S> ToValue for HashMap<K, V, S> {
+    fn to_value(&self) -> Value {
+        Value::Map(self.iter().map(|(k, v)| (k.to_value(), v.to_value())).collect())
+    }
+}
+
+impl<K, V, S> FromValue for HashMap<K, V, S>
+where
+    K: FromValue + Eq + Hash,
+    V: FromValue,
+    S: std::hash::BuildHasher + Default,
+{
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Map(entries) => entries
+                .iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            _ => Err(mismatch("map", value)),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Box<T> {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: FromValue> FromValue for Box<T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        T::from_value(value).map(Box::new)
+    }
+}
+
+impl ToValue for BigDecimal {
+    fn to_value(&self) -> Value {
+        Value::Decimal(self.clone())
+    }
+}
+
+impl FromValue for BigDecimal {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Decimal(d) => Ok(d.clone()),
+            v => match v.as_i128() {
+                Some(i) => Ok(BigDecimal::from(i)),
+                None => Err(mismatch("BigDecimal", value)),
+            },
+        }
+    }
+}
+
+impl ToValue for DateTime<Utc> {
+    fn to_value(&self) -> Value {
+        Value::DateTime(*self)
+    }
+}
+
+impl FromValue for DateTime<Utc> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::DateTime(dt) => Ok(*dt),
+            _ => Err(mismatch("DateTime<Utc>", value)),
+        }
+    }
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty => $variant:ident, $name:expr);* $(;)?) => {$(
+        impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                Value::$variant(self.clone())
+            }
+        }
+
+        impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<Self, Error> {
+                match value {
+                    Value::$variant(v) => Ok(v.clone()),
+                    _ => Err(mismatch($name, value)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_scalar!(
+    NaiveDate => Date, "NaiveDate";
+    NaiveTime => Time, "NaiveTime";
+    Uuid => Uuid, "Uuid";
+    Interval => Interval, "Interval";
+    serde_json::Value => Json, "serde_json::Value";
+);
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(v) => v.to_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Null => Ok(None),
+            v => T::from_value(v).map(Some),
+        }
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+// `From`/`TryFrom` so that application code can write `Value::from(42)` and
+// `i64::try_from(value)?`.
+macro_rules! impl_std_conversions {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Value {
+            fn from(v: $t) -> Self {
+                v.to_value()
+            }
+        }
+
+        impl TryFrom<Value> for $t {
+            type Error = Error;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                <$t>::from_value(&value)
+            }
+        }
+
+        impl TryFrom<&Value> for $t {
+            type Error = Error;
+
+            fn try_from(value: &Value) -> Result<Self, Self::Error> {
+                <$t>::from_value(value)
+            }
+        }
+    )*};
+}
+
+impl_std_conversions!(
+    i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, bool, String, Vec<u8>, BigDecimal,
+    DateTime<Utc>, NaiveDate, NaiveTime, Uuid, Interval, serde_json::Value
+);
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl<T: ToValue> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.to_value()
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr; $($name:ident $idx:tt),+) => {
+        impl<$($name: ToValue),+> ToValues for ($($name,)+) {
+            fn to_values(&self) -> Vec<Value> {
+                vec![$(self.$idx.to_value()),+]
+            }
+        }
+
+        impl<$($name: FromValue),+> FromValues for ($($name,)+) {
+            fn from_values(values: &[Value]) -> Result<Self, Error> {
+                if values.len() != $len {
+                    return Err(Error::Type(format!(
+                        "expected a row of {} columns, got {}",
+                        $len,
+                        values.len()
+                    )));
+                }
+                Ok(($($name::from_value(&values[$idx])?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(1; A 0);
+impl_tuple!(2; A 0, B 1);
+impl_tuple!(3; A 0, B 1, C 2);
+impl_tuple!(4; A 0, B 1, C 2, D 3);
+impl_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+impl_tuple!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_tuple!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+impl<T: ToValue> ToValues for [T] {
+    fn to_values(&self) -> Vec<Value> {
+        self.iter().map(ToValue::to_value).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_narrowing() {
+        assert_eq!(i64::try_from(Value::Int(42)).unwrap(), 42);
+        assert_eq!(u8::try_from(Value::Int(255)).unwrap(), 255);
+        assert!(matches!(u8::try_from(Value::Int(256)), Err(Error::Type(_))));
+        assert!(matches!(u32::try_from(Value::Int(-1)), Err(Error::Type(_))));
+        assert!(matches!(i8::try_from(Value::Int(-129)), Err(Error::Type(_))));
+        assert!(matches!(i64::try_from(Value::String("1".into())), Err(Error::Type(_))));
+    }
+
+    #[test]
+    fn test_u64_round_trip() {
+        for n in [0u64, 7, i64::MAX as u64, u64::MAX] {
+            assert_eq!(u64::try_from(Value::from(n)).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(f64::try_from(Value::Int(3)).unwrap(), 3.0);
+        assert_eq!(f32::try_from(Value::Float(1.5)).unwrap(), 1.5);
+        assert!(matches!(f32::try_from(Value::Float(1e300)), Err(Error::Type(_))));
+    }
+
+    #[test]
+    fn test_numbers_compare_by_value() {
+        let decimal = |s: &str| Value::Decimal(BigDecimal::from_str(s).unwrap());
+        assert_eq!(Value::Float(-0.0), Value::Float(0.0));
+        assert_eq!(Value::Float(-0.0), Value::Int(0));
+        assert_eq!(Value::Float(0.1), decimal("0.1"));
+        assert_eq!(Value::Int(2), decimal("2.00"));
+        assert!(Value::Int(5) > decimal("1"));
+        assert!(Value::Int(5) > Value::Float(1.0));
+        assert!(Value::UInt64(u64::MAX) > Value::Float(1e18));
+        assert!(Value::Float(f64::INFINITY) > decimal("1e400"));
+        assert!(Value::Float(f64::NAN) > Value::Float(f64::INFINITY));
+
+        let distinct: std::collections::BTreeSet<Value> =
+            [Value::Float(0.0), Value::Float(-0.0), Value::Int(0), decimal("0.0")].into();
+        assert_eq!(distinct.len(), 1);
+    }
+
+    #[test]
+    fn test_options_and_tuples() {
+        assert_eq!(Option::<String>::from_value(&Value::Null).unwrap(), None);
+        assert_eq!(
+            Option::<String>::from_value(&Value::String("a".into())).unwrap(),
+            Some("a".to_string())
+        );
+        assert_eq!(Value::from(None::<i32>), Value::Null);
+
+        let row = (1i64, "x".to_string(), Some(true)).to_values();
+        let back: (i64, String, Option<bool>) = FromValues::from_values(&row).unwrap();
+        assert_eq!(back, (1, "x".to_string(), Some(true)));
+        assert!(<(i64,)>::from_values(&row).is_err());
+    }
+
+    #[test]
+    fn test_composite_ordering_and_display() {
+        let a = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        let b = Value::Array(vec![Value::Int(1), Value::Int(3)]);
+        assert!(a < b);
+        assert!(Value::Null < Value::Bool(false));
+        assert_eq!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+        assert_eq!(a.to_string(), "[1, 2]");
+
+        let s = Value::Struct(vec![
+            ("country_code".to_string(), Value::Int(44)),
+            ("area_code".to_string(), Value::Null),
+        ]);
+        assert_eq!(s.to_string(), "{country_code: 44, area_code: NULL}");
+        assert_eq!(s.field("country_code"), Some(&Value::Int(44)));
+        let steps = |path: &[&str]| -> Vec<PathStep> {
+            path.iter()
+                .map(|p| p.parse().map_or_else(|_| PathStep::Field(p.to_string()), PathStep::Element))
+                .collect()
+        };
+        assert_eq!(s.path(&steps(&["Country_Code"])), Value::Int(44));
+        assert_eq!(s.path(&steps(&["missing"])), Value::Null);
+        assert_eq!(Value::Array(vec![s.clone()]).path(&steps(&["0", "country_code"])), Value::Int(44));
+        let json = Value::Json(serde_json::json!({"phone": {"country_code": 44}, "tags": ["a"]}));
+        assert_eq!(json.path(&steps(&["phone", "country_code"])), Value::Int(44));
+        assert_eq!(json.path(&steps(&["tags", "0"])), Value::String("a".into()));
+        assert_eq!(json.path(&steps(&["tags", "1"])), Value::Null);
+
+        let none = Value::Enum { variant: "None".into(), payload: None };
+        let some = Value::Enum { variant: "Some".into(), payload: Some(Box::new(Value::Int(415))) };
+        assert_eq!(some.to_string(), "Some(415)");
+        assert!(none < some);
+
+        let map: BTreeMap<String, i64> = [("b".to_string(), 2), ("a".to_string(), 1)].into();
+        let value = map.to_value();
+        assert_eq!(value.to_string(), "{'a' => 1, 'b' => 2}");
+        assert_eq!(BTreeMap::<String, i64>::from_value(&value).unwrap(), map);
+    }
+
+    #[test]
+    fn test_sequences() {
+        assert_eq!(vec![1u8, 2].to_value(), Value::Bytes(vec![1, 2]));
+        assert_eq!(
+            vec![1i32, 2].to_value(),
+            Value::Array(vec![Value::Int(1), Value::Int(2)])
+        );
+        let prefs = vec!["dark_mode".to_string()];
+        assert_eq!(Vec::<String>::from_value(&prefs.to_value()).unwrap(), prefs);
+
+        let number = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(<[u8; 8]>::from_value(&number.to_value()).unwrap(), number);
+        assert!(matches!(<[u8; 4]>::from_value(&number.to_value()), Err(Error::Type(_))));
+    }
+
+    #[derive(Debug, PartialEq, crate::ToValue, crate::FromValue)]
+    struct PhoneNumber {
+        country_code: u16,
+        area_code: Option<u16>,
+        number: [u8; 8],
+    }
+
+    #[derive(Debug, PartialEq, crate::ToValue, crate::FromValue)]
+    enum Contact {
+        Unknown,
+        Phone(PhoneNumber),
+        #[rustdb(rename = "mail")]
+        Email { address: String },
+        Pair(i32, i32),
+    }
+
+    #[derive(Debug, PartialEq, crate::ToValue, crate::FromValue)]
+    enum Tagged<T> {
+        Empty,
+        Holding { value: T },
+    }
+
+    #[test]
+    fn test_derived_custom_types() {
+        let phone = PhoneNumber {
+            country_code: 44,
+            area_code: None,
+            number: [0, 2, 0, 7, 9, 4, 6, 0],
+        };
+        let value = phone.to_value();
+        assert_eq!(value.field("country_code"), Some(&Value::Int(44)));
+        assert_eq!(PhoneNumber::from_value(&value).unwrap(), phone);
+
+        for contact in [
+            Contact::Unknown,
+            Contact::Phone(phone),
+            Contact::Email { address: "a@b.c".into() },
+            Contact::Pair(1, 2),
+        ] {
+            assert_eq!(Contact::from_value(&contact.to_value()).unwrap(), contact);
+        }
+
+        let mail = Contact::Email { address: "x".into() }.to_value();
+        assert!(matches!(&mail, Value::Enum { variant, .. } if variant == "mail"));
+
+        let bad = Value::Struct(vec![("country_code".to_string(), Value::String("44".into()))]);
+        match PhoneNumber::from_value(&bad) {
+            Err(Error::Type(msg)) => assert!(msg.contains("PhoneNumber::country_code"), "{}", msg),
+            other => panic!("expected type error, got {:?}", other),
         }
+        let unknown = Value::Enum { variant: "Fax".into(), payload: None };
+        assert!(Contact::from_value(&unknown).is_err());
+
+        let tagged = Tagged::Holding { value: vec![1i32, 2] };
+        assert_eq!(Tagged::from_value(&tagged.to_value()).unwrap(), tagged);
+        assert_eq!(Tagged::<u8>::from_value(&Tagged::<u8>::Empty.to_value()).unwrap(), Tagged::Empty);
+    }
+
+    #[test]
+    fn test_scalar_variants() {
+        assert_eq!(7u16.to_value(), Value::UInt16(7));
+        assert_eq!(1.5f32.to_value(), Value::Float32(1.5));
+        // Integer widths compare by value.
+        assert_eq!(Value::UInt8(1), Value::Int(1));
+        assert!(Value::Int8(-1) < Value::UInt64(0));
+        assert!(i8::try_from(Value::UInt64(200)).is_err());
+
+        let id = Uuid::from_u128(0x1234);
+        assert_eq!(Uuid::try_from(id.to_value()).unwrap(), id);
+
+        let doc = serde_json::json!({"b": 1, "a": [true, null]});
+        let value = Value::from(doc.clone());
+        assert_eq!(value.to_string(), r#"'{"a":[true,null],"b":1}'"#);
+        assert_eq!(serde_json::Value::try_from(value).unwrap(), doc);
+
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(Value::Date(date).to_string(), "'2024-02-29'");
     }
 }
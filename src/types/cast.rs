@@ -0,0 +1,486 @@
+// src/types/cast.rs
+//! Conversions between SQL types, used by `CAST`, `TRY_CAST`, `::` and the
+//! implicit casts the analyzer inserts.
+
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use uuid::Uuid;
+
+use super::{Interval, Value};
+use crate::error::Error;
+use crate::parser::ast::DataType;
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y", "%Y%m%d"];
+const TIME_FORMATS: &[&str] = &["%H:%M:%S%.f", "%H:%M"];
+const TIMESTAMP_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+    "%Y/%m/%d %H:%M:%S%.f",
+];
+const OFFSET_TIMESTAMP_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f%#z", "%Y-%m-%d %H:%M:%S%.f %#z"];
+
+/// Converts `value` to `to`. NULL converts to NULL for every type.
+pub fn cast(value: &Value, to: &DataType) -> Result<Value, Error> {
+    convert(value, to)
+        .map_err(|reason| Error::Type(format!("cannot cast {} to {}: {}", value, to, reason)))
+}
+
+/// Like [`cast`], but a failed conversion yields NULL.
+pub fn try_cast(value: &Value, to: &DataType) -> Value {
+    cast(value, to).unwrap_or(Value::Null)
+}
+
+/// Whether any value of type `from` may be converted to `to`. Individual
+/// values can still fail, e.g. `'abc'` as an integer.
+pub fn can_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    if matches!(from, Custom(_)) || matches!(to, Custom(_)) {
+        return from == to;
+    }
+    if from.same_family(to) || from.is_string() || to.is_string() {
+        return true;
+    }
+    match (from, to) {
+        (f, t) if f.is_numeric() && t.is_numeric() => true,
+        (Boolean, t) if t.is_numeric() => true,
+        (f, Boolean) if f.is_integer() => true,
+        (Date, DateTime | Timestamp) | (DateTime | Timestamp, Date | Time) => true,
+        (f, DateTime | Timestamp) if f.is_integer() => true,
+        (Json, t) | (t, Json) if t.is_numeric() || *t == Boolean => true,
+        (Uuid, Binary(_)) | (Binary(_), Uuid) => true,
+        _ => false,
+    }
+}
+
+fn convert(value: &Value, to: &DataType) -> Result<Value, String> {
+    if value.is_null() {
+        return Ok(Value::Null);
+    }
+    // JSON scalars convert like the SQL value they hold.
+    if let Value::Json(json) = value {
+        if !matches!(to, DataType::Json) && !to.is_string() {
+            let inner = json_scalar(json).ok_or("not a JSON scalar")?;
+            return convert(&inner, to);
+        }
+    }
+
+    match to {
+        t if t.is_integer() => to_integer(value, t),
+        DataType::Real => {
+            let f = to_f64(value)?;
+            if f.is_finite() && (f as f32).is_infinite() {
+                return Err("out of range".to_string());
+            }
+            Ok(Value::Float32(f as f32))
+        }
+        DataType::Float(_) => to_f64(value).map(Value::Float),
+        DataType::Decimal(precision) => to_decimal(value, *precision).map(Value::Decimal),
+        DataType::Char(len) => {
+            let mut s = to_text(value)?;
+            if let Some(len) = len {
+                check_length(s.chars().count(), *len, to)?;
+                s.extend(std::iter::repeat_n(' ', *len as usize - s.chars().count()));
+            }
+            Ok(Value::String(s))
+        }
+        DataType::Varchar(len) => {
+            let s = to_text(value)?;
+            if let Some(len) = len {
+                check_length(s.chars().count(), *len, to)?;
+            }
+            Ok(Value::String(s))
+        }
+        DataType::Text => to_text(value).map(Value::String),
+        DataType::Boolean => match value {
+            Value::Bool(b) => Ok(Value::Bool(*b)),
+            Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "t" | "yes" | "y" | "on" | "1" => Ok(Value::Bool(true)),
+                "false" | "f" | "no" | "n" | "off" | "0" => Ok(Value::Bool(false)),
+                _ => Err("invalid boolean".to_string()),
+            },
+            v => match v.as_i128() {
+                Some(i) => Ok(Value::Bool(i != 0)),
+                None => Err(unsupported()),
+            },
+        },
+        DataType::Date => match value {
+            Value::Date(d) => Ok(Value::Date(*d)),
+            Value::DateTime(dt) => Ok(Value::Date(dt.date_naive())),
+            Value::String(s) => parse_date(s.trim())
+                .or_else(|| parse_timestamp(s.trim()).map(|dt| dt.date_naive()))
+                .map(Value::Date)
+                .ok_or_else(|| "invalid date".to_string()),
+            _ => Err(unsupported()),
+        },
+        DataType::Time => match value {
+            Value::Time(t) => Ok(Value::Time(*t)),
+            Value::DateTime(dt) => Ok(Value::Time(dt.time())),
+            Value::String(s) => TIME_FORMATS
+                .iter()
+                .find_map(|f| NaiveTime::parse_from_str(s.trim(), f).ok())
+                .or_else(|| parse_timestamp(s.trim()).map(|dt| dt.time()))
+                .map(Value::Time)
+                .ok_or_else(|| "invalid time".to_string()),
+            _ => Err(unsupported()),
+        },
+        DataType::DateTime | DataType::Timestamp => match value {
+            Value::DateTime(dt) => Ok(Value::DateTime(*dt)),
+            Value::Date(d) => Ok(Value::DateTime(d.and_time(NaiveTime::MIN).and_utc())),
+            Value::String(s) => parse_timestamp(s.trim())
+                .map(Value::DateTime)
+                .ok_or_else(|| "invalid timestamp".to_string()),
+            v => {
+                let seconds = v.as_i128().ok_or_else(unsupported)?;
+                i64::try_from(seconds)
+                    .ok()
+                    .and_then(|s| Utc.timestamp_opt(s, 0).single())
+                    .map(Value::DateTime)
+                    .ok_or_else(|| "out of range".to_string())
+            }
+        },
+        DataType::Binary(len) => {
+            let bytes = match value {
+                Value::Bytes(b) => b.clone(),
+                Value::String(s) => s.as_bytes().to_vec(),
+                Value::Uuid(u) => u.as_bytes().to_vec(),
+                _ => return Err(unsupported()),
+            };
+            if let Some(len) = len {
+                check_length(bytes.len(), *len, to)?;
+            }
+            Ok(Value::Bytes(bytes))
+        }
+        DataType::Json => to_json(value).map(Value::Json),
+        DataType::Uuid => match value {
+            Value::Uuid(u) => Ok(Value::Uuid(*u)),
+            Value::String(s) => Uuid::parse_str(s.trim())
+                .map(Value::Uuid)
+                .map_err(|_| "invalid UUID".to_string()),
+            Value::Bytes(b) => Uuid::from_slice(b)
+                .map(Value::Uuid)
+                .map_err(|_| format!("expected 16 bytes, got {}", b.len())),
+            _ => Err(unsupported()),
+        },
+        DataType::Interval => match value {
+            Value::Interval(i) => Ok(Value::Interval(*i)),
+            Value::String(s) => Interval::from_str(s)
+                .map(Value::Interval)
+                .map_err(|_| "invalid interval".to_string()),
+            _ => Err(unsupported()),
+        },
+        // Checked against the type's definition when stored.
+        DataType::Custom(_) => match value {
+            Value::Struct(_) | Value::Enum { .. } => Ok(value.clone()),
+            _ => Err(unsupported()),
+        },
+        _ => Err(unsupported()),
+    }
+}
+
+fn unsupported() -> String {
+    "unsupported conversion".to_string()
+}
+
+fn check_length(len: usize, max: u32, to: &DataType) -> Result<(), String> {
+    if len > max as usize {
+        Err(format!("value too long for {} ({} > {})", to, len, max))
+    } else {
+        Ok(())
+    }
+}
+
+fn to_integer(value: &Value, to: &DataType) -> Result<Value, String> {
+    let n: i128 = match value {
+        Value::Bool(b) => *b as i128,
+        Value::Float32(_) | Value::Float(_) => {
+            let rounded = finite(value.as_f64().unwrap())?.round();
+            if rounded.abs() >= 1e38 {
+                return Err("out of range".to_string());
+            }
+            rounded as i128
+        }
+        Value::Decimal(d) => d
+            .with_scale_round(0, RoundingMode::HalfUp)
+            .to_i128()
+            .ok_or("out of range")?,
+        Value::String(s) => s.trim().parse().map_err(|_| "invalid integer")?,
+        v => v.as_i128().ok_or_else(unsupported)?,
+    };
+    let out_of_range = |_| "out of range".to_string();
+    match to {
+        DataType::TinyInt => i8::try_from(n).map(Value::Int8).map_err(out_of_range),
+        DataType::SmallInt => i16::try_from(n).map(Value::Int16).map_err(out_of_range),
+        DataType::Integer(_) => i32::try_from(n).map(Value::Int32).map_err(out_of_range),
+        DataType::BigInt => i64::try_from(n).map(Value::Int).map_err(out_of_range),
+        DataType::UnsignedTinyInt => u8::try_from(n).map(Value::UInt8).map_err(out_of_range),
+        DataType::UnsignedSmallInt => u16::try_from(n).map(Value::UInt16).map_err(out_of_range),
+        DataType::UnsignedInteger => u32::try_from(n).map(Value::UInt32).map_err(out_of_range),
+        _ => u64::try_from(n).map(Value::UInt64).map_err(out_of_range),
+    }
+}
+
+/// Rejects NaN and infinities, which have no integer, decimal or JSON form.
+fn finite(f: f64) -> Result<f64, String> {
+    if f.is_nan() {
+        Err("not a number".to_string())
+    } else if f.is_infinite() {
+        Err("infinite value".to_string())
+    } else {
+        Ok(f)
+    }
+}
+
+fn to_f64(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Bool(b) => Ok(*b as u8 as f64),
+        // Text and decimals too large for a float must not turn into infinity.
+        Value::Decimal(d) => d.to_f64().filter(|f| f.is_finite()).ok_or_else(|| "out of range".to_string()),
+        Value::String(s) => match s.trim().parse::<f64>() {
+            Ok(f) if f.is_nan() => Err("not a number".to_string()),
+            Ok(f) if f.is_finite() => Ok(f),
+            Ok(_) => Err("out of range".to_string()),
+            Err(_) => Err("invalid number".to_string()),
+        },
+        v => v
+            .as_f64()
+            .or_else(|| v.as_i128().map(|i| i as f64))
+            .ok_or_else(unsupported),
+    }
+}
+
+fn to_decimal(value: &Value, precision: Option<(u32, u32)>) -> Result<BigDecimal, String> {
+    let d = match value {
+        Value::Bool(b) => BigDecimal::from(*b as u8),
+        Value::Decimal(d) => d.clone(),
+        Value::String(s) => BigDecimal::from_str(s.trim()).map_err(|_| "invalid number")?,
+        Value::Float32(_) | Value::Float(_) => {
+            let f = finite(value.as_f64().unwrap())?;
+            // Go through the shortest decimal representation rather than the
+            // exact binary expansion, so 0.1 stays 0.1.
+            let repr = match value {
+                Value::Float32(f) => f.to_string(),
+                _ => f.to_string(),
+            };
+            BigDecimal::from_str(&repr).map_err(|_| "invalid number")?
+        }
+        v => {
+            let i = v.as_i128().ok_or_else(unsupported)?;
+            BigDecimal::from_str(&i.to_string()).unwrap()
+        }
+    };
+    let Some((precision, scale)) = precision else {
+        return Ok(d);
+    };
+    let rounded = d.with_scale_round(scale as i64, RoundingMode::HalfUp);
+    if rounded.digits() > precision as u64 {
+        return Err(format!(
+            "numeric field overflow (at most {} digits before the decimal point)",
+            precision.saturating_sub(scale)
+        ));
+    }
+    Ok(rounded)
+}
+
+fn to_text(value: &Value) -> Result<String, String> {
+    Ok(match value {
+        Value::String(s) => s.clone(),
+        Value::Bytes(b) => String::from_utf8(b.clone()).map_err(|_| "invalid UTF-8")?,
+        Value::Uuid(u) => u.to_string(),
+        Value::DateTime(dt) => dt.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+        Value::Date(d) => d.to_string(),
+        Value::Time(t) => t.to_string(),
+        Value::Interval(i) => i.to_string(),
+        Value::Json(j) => j.to_string(),
+        other => other.to_string(),
+    })
+}
+
+fn to_json(value: &Value) -> Result<serde_json::Value, String> {
+    use serde_json::Value as Json;
+    Ok(match value {
+        Value::Null => Json::Null,
+        Value::Json(j) => j.clone(),
+        Value::Bool(b) => Json::Bool(*b),
+        Value::String(s) => serde_json::from_str(s).map_err(|e| format!("invalid JSON: {}", e))?,
+        Value::Float32(_) | Value::Float(_) | Value::Decimal(_) => {
+            let f = to_f64(value)?;
+            serde_json::Number::from_f64(f)
+                .map(Json::Number)
+                .ok_or_else(|| finite(f).unwrap_err())?
+        }
+        Value::Array(items) => Json::Array(items.iter().map(to_json).collect::<Result<_, _>>()?),
+        Value::Struct(fields) => Json::Object(
+            fields
+                .iter()
+                .map(|(name, v)| Ok((name.clone(), to_json(v)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        v => match v.as_i128() {
+            Some(i) => match i64::try_from(i) {
+                Ok(i) => Json::from(i),
+                Err(_) => Json::from(i as u64),
+            },
+            None => return Err(unsupported()),
+        },
+    })
+}
+
+/// A JSON scalar as a plain value; `None` for arrays and objects.
+pub(crate) fn json_scalar(json: &serde_json::Value) -> Option<Value> {
+    use serde_json::Value as Json;
+    Some(match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(*b),
+        Json::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Value::Int(i),
+            (None, Some(u)) => Value::UInt64(u),
+            _ => Value::Float(n.as_f64()?),
+        },
+        Json::String(s) => Value::String(s.clone()),
+        Json::Array(_) | Json::Object(_) => return None,
+    })
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    DATE_FORMATS.iter().find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+}
+
+/// Accepts RFC 3339, the common `YYYY-MM-DD HH:MM[:SS[.f]]` layouts (with or
+/// without a UTC offset) and bare dates, which mean midnight.
+fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    if let Some(dt) = OFFSET_TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|f| DateTime::parse_from_str(s, f).ok())
+    {
+        return Some(dt.with_timezone(&Utc));
+    }
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .or_else(|| parse_date(s).map(|d| d.and_time(NaiveTime::MIN)))
+        .map(|dt| dt.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast_str(s: &str, to: DataType) -> Result<Value, Error> {
+        cast(&Value::String(s.to_string()), &to)
+    }
+
+    #[test]
+    fn test_numeric_conversions() {
+        assert_eq!(cast_str(" 42 ", DataType::TinyInt).unwrap(), Value::Int8(42));
+        assert_eq!(cast(&Value::Float(2.5), &DataType::BigInt).unwrap(), Value::Int(3));
+        assert_eq!(cast(&Value::Bool(true), &DataType::Integer(None)).unwrap(), Value::Int32(1));
+        assert_eq!(
+            cast(&Value::Int(300), &DataType::TinyInt).unwrap_err().to_string(),
+            "Type error: cannot cast 300 to TINYINT: out of range"
+        );
+        assert_eq!(
+            cast_str("abc", DataType::Integer(None)).unwrap_err().to_string(),
+            "Type error: cannot cast 'abc' to INTEGER: invalid integer"
+        );
+        assert!(cast(&Value::Float(1e300), &DataType::Real).is_err());
+        let err = cast_str("1e400", DataType::Float(None)).unwrap_err();
+        assert!(matches!(&err, Error::Type(msg) if msg.ends_with("out of range")), "{}", err);
+        let huge = Value::Decimal(BigDecimal::from_str("1e400").unwrap());
+        assert!(matches!(cast(&huge, &DataType::Float(None)), Err(Error::Type(_))));
+        assert!(cast_str("1e40", DataType::Real).is_err());
+        assert_eq!(try_cast(&Value::Int(-1), &DataType::UnsignedInteger), Value::Null);
+        for to in [DataType::BigInt, DataType::Decimal(None)] {
+            let err = cast(&Value::Float(f64::NAN), &to).unwrap_err();
+            assert!(matches!(&err, Error::Type(msg) if msg.ends_with("not a number")), "{}", err);
+        }
+        let err = cast_str("NaN", DataType::Float(None)).unwrap_err();
+        assert!(matches!(&err, Error::Type(msg) if msg.ends_with("not a number")), "{}", err);
+        let err = cast(&Value::Float(f64::INFINITY), &DataType::BigInt).unwrap_err();
+        assert!(matches!(&err, Error::Type(msg) if msg.ends_with("infinite value")), "{}", err);
+        let err = cast(&Value::Float(1e39), &DataType::BigInt).unwrap_err();
+        assert!(matches!(&err, Error::Type(msg) if msg.ends_with("out of range")), "{}", err);
+    }
+
+    #[test]
+    fn test_decimal_precision() {
+        let to = DataType::Decimal(Some((5, 2)));
+        assert_eq!(cast_str("123.456", to.clone()).unwrap().to_string(), "123.46");
+        assert_eq!(cast(&Value::Float(0.1), &to).unwrap().to_string(), "0.10");
+        assert!(cast_str("1234.5", to.clone())
+            .unwrap_err()
+            .to_string()
+            .contains("numeric field overflow"));
+        assert_eq!(
+            cast(&Value::Decimal("2.5".parse().unwrap()), &DataType::SmallInt).unwrap(),
+            Value::Int16(3)
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(cast(&Value::Int(7), &DataType::Text).unwrap(), Value::String("7".into()));
+        assert_eq!(
+            cast_str("ab", DataType::Char(Some(4))).unwrap(),
+            Value::String("ab  ".into())
+        );
+        assert_eq!(
+            cast_str("hello", DataType::Varchar(Some(3))).unwrap_err().to_string(),
+            "Type error: cannot cast 'hello' to VARCHAR(3): value too long for VARCHAR(3) (5 > 3)"
+        );
+        assert_eq!(cast_str("Yes", DataType::Boolean).unwrap(), Value::Bool(true));
+        assert!(cast_str("maybe", DataType::Boolean).is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        for s in ["2024-03-01", "2024/03/01", "01.03.2024", "20240301", "2024-03-01 10:00:00"] {
+            assert_eq!(cast_str(s, DataType::Date).unwrap(), Value::Date(date), "{}", s);
+        }
+        let midnight = date.and_time(NaiveTime::MIN).and_utc();
+        assert_eq!(cast_str("2024-03-01", DataType::Timestamp).unwrap(), Value::DateTime(midnight));
+        assert_eq!(
+            cast_str("2024-03-01T02:00:00+02:00", DataType::Timestamp).unwrap(),
+            Value::DateTime(midnight)
+        );
+        assert_eq!(
+            cast(&Value::DateTime(midnight), &DataType::Text).unwrap(),
+            Value::String("2024-03-01 00:00:00".into())
+        );
+        assert_eq!(
+            cast_str("10:30", DataType::Time).unwrap(),
+            Value::Time(NaiveTime::from_hms_opt(10, 30, 0).unwrap())
+        );
+        assert!(cast_str("2024-02-30", DataType::Date).is_err());
+        assert!(cast(&Value::Date(date), &DataType::Boolean).is_err());
+    }
+
+    #[test]
+    fn test_other_types() {
+        let json = cast_str(r#"{"a": [1, 2]}"#, DataType::Json).unwrap();
+        assert!(matches!(&json, Value::Json(j) if j["a"][1] == 2));
+        assert_eq!(
+            cast(&Value::Json(serde_json::json!(5)), &DataType::SmallInt).unwrap(),
+            Value::Int16(5)
+        );
+        let uuid = cast_str("67e55044-10b1-426f-9247-bb680e5fe0c8", DataType::Uuid).unwrap();
+        let Value::Bytes(bytes) = cast(&uuid, &DataType::Binary(Some(16))).unwrap() else {
+            panic!("Expected bytes")
+        };
+        assert_eq!(cast(&Value::Bytes(bytes), &DataType::Uuid).unwrap(), uuid);
+        assert!(matches!(cast_str("2 days", DataType::Interval).unwrap(), Value::Interval(i) if i.days == 2));
+        assert_eq!(cast(&Value::Null, &DataType::Date).unwrap(), Value::Null);
+
+        assert!(can_cast(&DataType::Text, &DataType::Date));
+        assert!(can_cast(&DataType::Boolean, &DataType::TinyInt));
+        assert!(!can_cast(&DataType::Boolean, &DataType::Date));
+        assert!(!can_cast(&DataType::Uuid, &DataType::BigInt));
+    }
+}
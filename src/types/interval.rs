@@ -0,0 +1,425 @@
+// src/types/interval.rs
+//! The INTERVAL type and date/time arithmetic.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Days, Months, NaiveDate, NaiveTime, TimeDelta, Utc};
+
+use super::Value;
+use crate::error::Error;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// A span of time. Months and days are kept separate from the sub-day part
+/// because their length depends on the date they are added to.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+impl Interval {
+    pub const ZERO: Interval = Interval {
+        months: 0,
+        days: 0,
+        micros: 0,
+    };
+
+    pub fn new(months: i32, days: i32, micros: i64) -> Self {
+        Interval {
+            months,
+            days,
+            micros,
+        }
+    }
+
+    pub fn from_days(days: i32) -> Self {
+        Interval::new(0, days, 0)
+    }
+
+    pub fn from_micros(micros: i64) -> Self {
+        Interval::new(0, 0, micros)
+    }
+
+    /// Length used for comparisons, counting a month as 30 days (the same
+    /// convention PostgreSQL uses).
+    pub(crate) fn approx_micros(&self) -> i128 {
+        (self.months as i128 * 30 + self.days as i128) * MICROS_PER_DAY as i128
+            + self.micros as i128
+    }
+
+    /// Inverse of `approx_micros`: an interval that compares equal to any
+    /// interval of that length, using whole 30-day months only when the day
+    /// count would not fit.
+    pub(crate) fn from_approx_micros(total: i128) -> Interval {
+        let day = MICROS_PER_DAY as i128;
+        let (mut days, micros) = (total.div_euclid(day), total.rem_euclid(day) as i64);
+        let mut months = 0;
+        if i32::try_from(days).is_err() {
+            months = (days / 30).clamp(i32::MIN as i128, i32::MAX as i128);
+            days -= months * 30;
+        }
+        Interval::new(months as i32, days as i32, micros)
+    }
+
+    pub fn checked_add(&self, other: &Interval) -> Option<Interval> {
+        Some(Interval {
+            months: self.months.checked_add(other.months)?,
+            days: self.days.checked_add(other.days)?,
+            micros: self.micros.checked_add(other.micros)?,
+        })
+    }
+
+    pub fn checked_neg(&self) -> Option<Interval> {
+        Some(Interval {
+            months: self.months.checked_neg()?,
+            days: self.days.checked_neg()?,
+            micros: self.micros.checked_neg()?,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Interval) -> Option<Interval> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    pub fn add_to_datetime(&self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let dt = add_months(dt, self.months)?;
+        let dt = add_days(dt, self.days)?;
+        dt.checked_add_signed(TimeDelta::microseconds(self.micros))
+    }
+
+    pub fn add_to_date(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let date = add_months(date, self.months)?;
+        add_days(date, self.days)
+    }
+
+    /// Adds the sub-day part to a time of day, wrapping around midnight.
+    pub fn add_to_time(&self, time: NaiveTime) -> NaiveTime {
+        let micros = self.micros.rem_euclid(MICROS_PER_DAY);
+        time.overflowing_add_signed(TimeDelta::microseconds(micros)).0
+    }
+}
+
+fn add_months<T: ChronoChecked>(value: T, months: i32) -> Option<T> {
+    if months >= 0 {
+        value.checked_add_months_(Months::new(months as u32))
+    } else {
+        value.checked_sub_months_(Months::new(months.unsigned_abs()))
+    }
+}
+
+fn add_days<T: ChronoChecked>(value: T, days: i32) -> Option<T> {
+    if days >= 0 {
+        value.checked_add_days_(Days::new(days as u64))
+    } else {
+        value.checked_sub_days_(Days::new(days.unsigned_abs() as u64))
+    }
+}
+
+/// The checked calendar operations shared by `NaiveDate` and `DateTime<Utc>`.
+trait ChronoChecked: Sized {
+    fn checked_add_months_(self, m: Months) -> Option<Self>;
+    fn checked_sub_months_(self, m: Months) -> Option<Self>;
+    fn checked_add_days_(self, d: Days) -> Option<Self>;
+    fn checked_sub_days_(self, d: Days) -> Option<Self>;
+}
+
+macro_rules! impl_chrono_checked {
+    ($t:ty) => {
+        impl ChronoChecked for $t {
+            fn checked_add_months_(self, m: Months) -> Option<Self> {
+                self.checked_add_months(m)
+            }
+            fn checked_sub_months_(self, m: Months) -> Option<Self> {
+                self.checked_sub_months(m)
+            }
+            fn checked_add_days_(self, d: Days) -> Option<Self> {
+                self.checked_add_days(d)
+            }
+            fn checked_sub_days_(self, d: Days) -> Option<Self> {
+                self.checked_sub_days(d)
+            }
+        }
+    };
+}
+
+impl_chrono_checked!(NaiveDate);
+impl_chrono_checked!(DateTime<Utc>);
+
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Interval {}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.approx_micros().cmp(&other.approx_micros())
+    }
+}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        for (n, unit) in [(years, "year"), (months, "mon"), (self.days, "day")] {
+            if n != 0 {
+                parts.push(format!("{} {}{}", n, unit, if n.abs() == 1 { "" } else { "s" }));
+            }
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 { "-" } else { "" };
+            let total = self.micros.unsigned_abs();
+            let secs = total / MICROS_PER_SECOND as u64;
+            let frac = total % MICROS_PER_SECOND as u64;
+            let mut time = format!("{}{:02}:{:02}:{:02}", sign, secs / 3600, secs / 60 % 60, secs % 60);
+            if frac != 0 {
+                time.push_str(&format!(".{:06}", frac));
+            }
+            parts.push(time);
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    /// Parses PostgreSQL-style intervals such as `1 year 2 mons 3 days 04:05:06`,
+    /// `90 minutes` or `-2 weeks`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Type(format!("invalid interval: '{}'", s));
+        let mut interval = Interval::ZERO;
+        let mut tokens = s.split_whitespace().peekable();
+        if tokens.peek().is_none() {
+            return Err(invalid());
+        }
+
+        while let Some(token) = tokens.next() {
+            if token.contains(':') {
+                interval.micros = interval
+                    .micros
+                    .checked_add(parse_clock(token).ok_or_else(invalid)?)
+                    .ok_or_else(invalid)?;
+                continue;
+            }
+
+            let amount: f64 = token.parse().map_err(|_| invalid())?;
+            let unit = tokens.next().ok_or_else(invalid)?.to_ascii_lowercase();
+            let (months, days, micros) = match unit.trim_end_matches(',') {
+                "year" | "years" | "yr" | "yrs" | "y" => (amount * 12.0, 0.0, 0.0),
+                "mon" | "mons" | "month" | "months" => (amount, 0.0, 0.0),
+                "week" | "weeks" | "w" => (0.0, amount * 7.0, 0.0),
+                "day" | "days" | "d" => (0.0, amount, 0.0),
+                "hour" | "hours" | "hr" | "hrs" | "h" => (0.0, 0.0, amount * 3600e6),
+                "minute" | "minutes" | "min" | "mins" | "m" => (0.0, 0.0, amount * 60e6),
+                "second" | "seconds" | "sec" | "secs" | "s" => (0.0, 0.0, amount * 1e6),
+                "millisecond" | "milliseconds" | "ms" => (0.0, 0.0, amount * 1e3),
+                "microsecond" | "microseconds" | "us" => (0.0, 0.0, amount),
+                _ => return Err(invalid()),
+            };
+            let part = carry(months, days, micros).ok_or_else(invalid)?;
+            interval = interval.checked_add(&part).ok_or_else(invalid)?;
+        }
+
+        Ok(interval)
+    }
+}
+
+/// Builds an interval from fractional amounts, carrying the fraction of a
+/// month into days and that of a day into microseconds, a month counting as
+/// 30 days as in [`Interval::approx_micros`]. `None` if a part overflows.
+fn carry(months: f64, days: f64, micros: f64) -> Option<Interval> {
+    let whole_months = months.trunc();
+    let days = days + (months - whole_months) * 30.0;
+    let mut whole_days = days.trunc();
+    let mut fraction = ((days - whole_days) * MICROS_PER_DAY as f64).round();
+    // Rounding can make a full day of what was a hair short of one.
+    if fraction.abs() >= MICROS_PER_DAY as f64 {
+        whole_days += fraction.signum();
+        fraction = 0.0;
+    }
+    let micros = micros.round() + fraction;
+    let fits = |v: f64, max: f64| v.is_finite() && v.abs() <= max;
+    if !fits(whole_months, i32::MAX as f64) || !fits(whole_days, i32::MAX as f64) || !fits(micros, i64::MAX as f64) {
+        return None;
+    }
+    Some(Interval::new(whole_months as i32, whole_days as i32, micros as i64))
+}
+
+/// Parses `[-]HH:MM[:SS[.ffffff]]` into microseconds.
+fn parse_clock(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let mut parts = s.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = match parts.next() {
+        Some(sec) => sec.parse().ok()?,
+        None => 0.0,
+    };
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60.0 {
+        return None;
+    }
+    let micros = (hours * 3600 + minutes * 60) * MICROS_PER_SECOND + (seconds * 1e6).round() as i64;
+    Some(if negative { -micros } else { micros })
+}
+
+fn out_of_range() -> Error {
+    Error::Execution("date/time value out of range".to_string())
+}
+
+fn unsupported(op: &str, left: &Value, right: &Value) -> Error {
+    Error::Type(format!(
+        "operator {} is not defined for {} and {}",
+        op,
+        left.type_name(),
+        right.type_name()
+    ))
+}
+
+/// `left + right` where at least one side is a date, time, timestamp or
+/// interval. Dates also accept a whole number of days.
+pub fn temporal_add(left: &Value, right: &Value) -> Result<Value, Error> {
+    match (left, right) {
+        (Value::Interval(a), Value::Interval(b)) => {
+            a.checked_add(b).map(Value::Interval).ok_or_else(out_of_range)
+        }
+        (Value::DateTime(dt), Value::Interval(i)) | (Value::Interval(i), Value::DateTime(dt)) => {
+            i.add_to_datetime(*dt).map(Value::DateTime).ok_or_else(out_of_range)
+        }
+        (Value::Date(d), Value::Interval(i)) | (Value::Interval(i), Value::Date(d)) => {
+            if i.micros == 0 {
+                i.add_to_date(*d).map(Value::Date).ok_or_else(out_of_range)
+            } else {
+                // A sub-day part turns the date into a timestamp at midnight.
+                let midnight = d.and_time(NaiveTime::MIN).and_utc();
+                i.add_to_datetime(midnight).map(Value::DateTime).ok_or_else(out_of_range)
+            }
+        }
+        (Value::Time(t), Value::Interval(i)) | (Value::Interval(i), Value::Time(t)) => {
+            Ok(Value::Time(i.add_to_time(*t)))
+        }
+        (Value::Date(d), n) | (n, Value::Date(d)) if n.as_i128().is_some() => {
+            let days = i32::try_from(n.as_i128().unwrap()).map_err(|_| out_of_range())?;
+            Interval::from_days(days)
+                .add_to_date(*d)
+                .map(Value::Date)
+                .ok_or_else(out_of_range)
+        }
+        _ => Err(unsupported("+", left, right)),
+    }
+}
+
+/// `left - right` for date/time operands. Subtracting two timestamps gives an
+/// interval; subtracting two dates gives the number of days between them.
+pub fn temporal_sub(left: &Value, right: &Value) -> Result<Value, Error> {
+    match (left, right) {
+        (
+            Value::Interval(_) | Value::DateTime(_) | Value::Date(_) | Value::Time(_),
+            Value::Interval(i),
+        ) => {
+            let negated = i.checked_neg().ok_or_else(out_of_range)?;
+            temporal_add(left, &Value::Interval(negated))
+        }
+        (Value::DateTime(a), Value::DateTime(b)) => {
+            let micros = (*a - *b).num_microseconds().ok_or_else(out_of_range)?;
+            Ok(Value::Interval(Interval::new(
+                0,
+                (micros / MICROS_PER_DAY) as i32,
+                micros % MICROS_PER_DAY,
+            )))
+        }
+        (Value::Date(a), Value::Date(b)) => Ok(Value::Int((*a - *b).num_days())),
+        (Value::Time(a), Value::Time(b)) => {
+            let micros = (*a - *b).num_microseconds().ok_or_else(out_of_range)?;
+            Ok(Value::Interval(Interval::from_micros(micros)))
+        }
+        (Value::Date(_), n) if n.as_i128().is_some() => {
+            let days = n.as_i128().unwrap().checked_neg().ok_or_else(out_of_range)?;
+            temporal_add(left, &Value::Int(i64::try_from(days).map_err(|_| out_of_range())?))
+        }
+        _ => Err(unsupported("-", left, right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let i: Interval = "1 year 2 mons 3 days 04:05:06".parse().unwrap();
+        assert_eq!((i.months, i.days, i.micros), (14, 3, (4 * 3600 + 5 * 60 + 6) * 1_000_000));
+        assert_eq!(i.to_string(), "1 year 2 mons 3 days 04:05:06");
+        assert_eq!("90 minutes".parse::<Interval>().unwrap().to_string(), "01:30:00");
+        assert_eq!("-2 weeks".parse::<Interval>().unwrap().days, -14);
+        assert!("3 fortnights".parse::<Interval>().is_err());
+        assert_eq!("1.5 days".parse::<Interval>().unwrap().to_string(), "1 day 12:00:00");
+        let i: Interval = "0.7 months".parse().unwrap();
+        assert_eq!((i.months, i.days, i.micros), (0, 21, 0));
+        assert_eq!("1.5 years".parse::<Interval>().unwrap().to_string(), "1 year 6 mons");
+        let i: Interval = "-0.5 weeks".parse().unwrap();
+        assert_eq!((i.months, i.days, i.micros), (0, -3, -12 * 3600 * 1_000_000));
+        assert!("1e10 days".parse::<Interval>().is_err());
+        assert_eq!(Interval::new(1, 0, 0), Interval::from_days(30));
+    }
+
+    #[test]
+    fn test_temporal_arithmetic() {
+        let month = Value::Interval(Interval::new(1, 0, 0));
+        assert_eq!(
+            temporal_add(&Value::Date(date(2024, 1, 31)), &month).unwrap(),
+            Value::Date(date(2024, 2, 29))
+        );
+
+        let ts = date(2024, 3, 1).and_hms_opt(23, 0, 0).unwrap().and_utc();
+        let two_hours = Value::Interval("2 hours".parse().unwrap());
+        assert_eq!(
+            temporal_add(&Value::DateTime(ts), &two_hours).unwrap(),
+            Value::DateTime(date(2024, 3, 2).and_hms_opt(1, 0, 0).unwrap().and_utc())
+        );
+        assert_eq!(
+            temporal_sub(&Value::DateTime(ts), &Value::DateTime(ts)).unwrap(),
+            Value::Interval(Interval::ZERO)
+        );
+
+        assert_eq!(
+            temporal_sub(&Value::Date(date(2024, 3, 1)), &Value::Date(date(2024, 2, 1))).unwrap(),
+            Value::Int(29)
+        );
+        assert_eq!(
+            temporal_add(&Value::Date(date(2024, 3, 1)), &Value::Int(1)).unwrap(),
+            Value::Date(date(2024, 3, 2))
+        );
+
+        let late = Value::Time(NaiveTime::from_hms_opt(23, 30, 0).unwrap());
+        assert_eq!(
+            temporal_add(&late, &two_hours).unwrap(),
+            Value::Time(NaiveTime::from_hms_opt(1, 30, 0).unwrap())
+        );
+
+        assert!(matches!(
+            temporal_add(&Value::String("x".into()), &month),
+            Err(Error::Type(_))
+        ));
+    }
+}
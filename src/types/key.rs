@@ -0,0 +1,597 @@
+// src/types/key.rs
+//! Order-preserving binary encoding of values, for index keys.
+//!
+//! Encoded keys compare with plain `memcmp` in the same order as the values
+//! they encode (see the `Ord` impl of [`Value`]), so B-tree pages can compare
+//! keys without decoding them. Each value starts with a tag byte holding its
+//! kind's position in the cross-type order, followed by a payload:
+//!
+//! - numbers of every kind: normalized to `±0.DIGITS × 10^EXP`, with the
+//!   exponent and digits inverted for negative numbers, floats taking their
+//!   shortest decimal digits and infinities and NaN a class byte of their
+//!   own;
+//! - strings, bytes and JSON text: `0x00` escaped as `0x00 0xFF` and
+//!   terminated by `0x00 0x01`;
+//! - composite values: each element prefixed by `0x01`, terminated by `0x00`.
+//!
+//! Every encoding is prefix-free, so a descending column is encoded by
+//! inverting all of its bytes. NULL gets a fixed `0x00` or `0xFF` marker so
+//! its position does not depend on the direction.
+//!
+//! Values that compare equal encode identically, so decoding is normalizing:
+//! whole numbers come back as `Int` (or `UInt64` above `i64::MAX`), other
+//! finite numbers as `Decimal`, infinities and NaN as `Float` and intervals
+//! as days and microseconds.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike};
+use uuid::Uuid;
+
+use super::{Interval, Number, Value};
+use crate::error::Error;
+
+const NULL_FIRST: u8 = 0x00;
+const NULL_LAST: u8 = 0xFF;
+
+const TAG_BOOL: u8 = 0x02;
+const TAG_NUMBER: u8 = 0x03;
+const TAG_STRING: u8 = 0x06;
+const TAG_BYTES: u8 = 0x07;
+const TAG_UUID: u8 = 0x08;
+const TAG_DATETIME: u8 = 0x09;
+const TAG_DATE: u8 = 0x0A;
+const TAG_TIME: u8 = 0x0B;
+const TAG_INTERVAL: u8 = 0x0C;
+const TAG_JSON: u8 = 0x0D;
+const TAG_ARRAY: u8 = 0x0E;
+const TAG_STRUCT: u8 = 0x0F;
+const TAG_ENUM: u8 = 0x10;
+const TAG_MAP: u8 = 0x11;
+
+const NUMBER_NEGATIVE_INFINITY: u8 = 0x00;
+const NUMBER_NEGATIVE: u8 = 0x01;
+const NUMBER_ZERO: u8 = 0x02;
+const NUMBER_POSITIVE: u8 = 0x03;
+const NUMBER_POSITIVE_INFINITY: u8 = 0x04;
+const NUMBER_NAN: u8 = 0x05;
+
+/// Sort direction and NULL placement of one key column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyOrder {
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl KeyOrder {
+    /// Ascending with NULLs first, the natural order of [`Value`].
+    pub const ASC: KeyOrder = KeyOrder {
+        descending: false,
+        nulls_first: true,
+    };
+    /// Descending with NULLs last, the exact reverse of [`KeyOrder::ASC`].
+    pub const DESC: KeyOrder = KeyOrder {
+        descending: true,
+        nulls_first: false,
+    };
+}
+
+impl Default for KeyOrder {
+    fn default() -> Self {
+        KeyOrder::ASC
+    }
+}
+
+/// Encodes a single value in ascending order.
+pub fn encode_value(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(&mut out, value);
+    out
+}
+
+/// Decodes a value written by [`encode_value`].
+pub fn decode_value(bytes: &[u8]) -> Result<Value, Error> {
+    let mut reader = Reader::new(bytes);
+    let value = reader.value()?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Encodes a composite key. Columns without an entry in `orders` are
+/// ascending.
+pub fn encode_key(values: &[Value], orders: &[KeyOrder]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        let order = orders.get(i).copied().unwrap_or_default();
+        if value.is_null() {
+            out.push(if order.nulls_first { NULL_FIRST } else { NULL_LAST });
+            continue;
+        }
+        let start = out.len();
+        encode_into(&mut out, value);
+        if order.descending {
+            for byte in &mut out[start..] {
+                *byte = !*byte;
+            }
+        }
+    }
+    out
+}
+
+/// Decodes a key written by [`encode_key`] with the same `orders`.
+pub fn decode_key(bytes: &[u8], orders: &[KeyOrder]) -> Result<Vec<Value>, Error> {
+    let mut reader = Reader::new(bytes);
+    let mut values = Vec::new();
+    while !reader.at_end() {
+        let order = orders.get(values.len()).copied().unwrap_or_default();
+        let marker = reader.peek()?;
+        if marker == NULL_FIRST || marker == NULL_LAST {
+            reader.pos += 1;
+            values.push(Value::Null);
+            continue;
+        }
+        reader.invert = order.descending;
+        values.push(reader.value()?);
+        reader.invert = false;
+    }
+    Ok(values)
+}
+
+fn encode_into(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(NULL_FIRST),
+        Value::Bool(b) => out.extend([TAG_BOOL, *b as u8]),
+        Value::String(s) => {
+            out.push(TAG_STRING);
+            encode_bytes(out, s.as_bytes());
+        }
+        Value::Bytes(b) => {
+            out.push(TAG_BYTES);
+            encode_bytes(out, b);
+        }
+        Value::Uuid(u) => {
+            out.push(TAG_UUID);
+            out.extend(u.as_bytes());
+        }
+        Value::DateTime(dt) => {
+            out.push(TAG_DATETIME);
+            out.extend(flip_i64(dt.timestamp()));
+            out.extend(dt.timestamp_subsec_nanos().to_be_bytes());
+        }
+        Value::Date(d) => {
+            out.push(TAG_DATE);
+            out.extend(((d.num_days_from_ce() as u32) ^ (1 << 31)).to_be_bytes());
+        }
+        Value::Time(t) => {
+            out.push(TAG_TIME);
+            out.extend(t.num_seconds_from_midnight().to_be_bytes());
+            out.extend(t.nanosecond().to_be_bytes());
+        }
+        Value::Interval(i) => {
+            out.push(TAG_INTERVAL);
+            out.extend(((i.approx_micros() as u128) ^ (1 << 127)).to_be_bytes());
+        }
+        // JSON orders by its canonical text.
+        Value::Json(j) => {
+            out.push(TAG_JSON);
+            encode_bytes(out, j.to_string().as_bytes());
+        }
+        Value::Array(items) => {
+            out.push(TAG_ARRAY);
+            for item in items {
+                out.push(0x01);
+                encode_into(out, item);
+            }
+            out.push(0x00);
+        }
+        Value::Struct(fields) => {
+            out.push(TAG_STRUCT);
+            for (name, value) in fields {
+                out.push(0x01);
+                encode_bytes(out, name.as_bytes());
+                encode_into(out, value);
+            }
+            out.push(0x00);
+        }
+        Value::Enum { variant, payload } => {
+            out.push(TAG_ENUM);
+            encode_bytes(out, variant.as_bytes());
+            match payload {
+                None => out.push(0x00),
+                Some(payload) => {
+                    out.push(0x01);
+                    encode_into(out, payload);
+                }
+            }
+        }
+        Value::Map(entries) => {
+            out.push(TAG_MAP);
+            for (key, value) in entries {
+                out.push(0x01);
+                encode_into(out, key);
+                encode_into(out, value);
+            }
+            out.push(0x00);
+        }
+        number => {
+            out.push(TAG_NUMBER);
+            match number.as_i128() {
+                Some(i) => encode_digits(out, i < 0, &i.unsigned_abs().to_string(), 0),
+                None => match number.number().unwrap() {
+                    Number::NegativeInfinity => out.push(NUMBER_NEGATIVE_INFINITY),
+                    Number::Finite(d) => encode_decimal(out, &d),
+                    Number::PositiveInfinity => out.push(NUMBER_POSITIVE_INFINITY),
+                    Number::NaN => out.push(NUMBER_NAN),
+                },
+            }
+        }
+    }
+}
+
+fn flip_i64(i: i64) -> [u8; 8] {
+    ((i as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+fn encode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    for &byte in bytes {
+        out.push(byte);
+        if byte == 0x00 {
+            out.push(0xFF);
+        }
+    }
+    out.extend([0x00, 0x01]);
+}
+
+fn encode_decimal(out: &mut Vec<u8>, d: &BigDecimal) {
+    let (int, scale) = d.as_bigint_and_exponent();
+    let negative = int.sign() == bigdecimal::num_bigint::Sign::Minus;
+    encode_digits(out, negative, &int.magnitude().to_string(), scale);
+}
+
+/// Encodes the number `±DIGITS × 10^-scale`, `digits` having no leading
+/// zeros.
+fn encode_digits(out: &mut Vec<u8>, negative: bool, digits: &str, scale: i64) {
+    let significant = digits.trim_end_matches('0');
+    if significant.is_empty() {
+        out.push(NUMBER_ZERO);
+        return;
+    }
+    // value = 0.DIGITS × 10^exponent
+    let exponent = digits.len() as i64 - scale;
+    let start = out.len() + 1;
+    out.push(if negative { NUMBER_NEGATIVE } else { NUMBER_POSITIVE });
+    out.extend(flip_i64(exponent));
+    out.extend(significant.bytes());
+    out.push(0x00);
+    if negative {
+        for byte in &mut out[start..] {
+            *byte = !*byte;
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Set while reading a descending column.
+    invert: bool,
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::Execution(format!("corrupt index key: {}", what))
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader {
+            bytes,
+            pos: 0,
+            invert: false,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(corrupt("trailing bytes"))
+        }
+    }
+
+    fn peek(&self) -> Result<u8, Error> {
+        self.bytes.get(self.pos).copied().ok_or_else(|| corrupt("unexpected end"))
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(if self.invert { !byte } else { byte })
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0; N];
+        for b in &mut buf {
+            *b = self.byte()?;
+        }
+        Ok(buf)
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        Ok((u64::from_be_bytes(self.array()?) ^ (1 << 63)) as i64)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    /// Reads an escaped byte string up to its terminator.
+    fn escaped(&mut self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.byte()? {
+                0x00 => match self.byte()? {
+                    0xFF => bytes.push(0x00),
+                    0x01 => return Ok(bytes),
+                    _ => return Err(corrupt("bad escape")),
+                },
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        String::from_utf8(self.escaped()?).map_err(|_| corrupt("invalid UTF-8"))
+    }
+
+    /// Reads the `0x01` (another element) / `0x00` (end) marker of a
+    /// composite value.
+    fn more(&mut self) -> Result<bool, Error> {
+        match self.byte()? {
+            0x00 => Ok(false),
+            0x01 => Ok(true),
+            _ => Err(corrupt("bad element marker")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        Ok(match self.byte()? {
+            NULL_FIRST => Value::Null,
+            TAG_BOOL => Value::Bool(self.byte()? != 0),
+            TAG_NUMBER => self.number()?,
+            TAG_STRING => Value::String(self.string()?),
+            TAG_BYTES => Value::Bytes(self.escaped()?),
+            TAG_UUID => Value::Uuid(Uuid::from_bytes(self.array()?)),
+            TAG_DATETIME => {
+                let seconds = self.i64()?;
+                let nanos = self.u32()?;
+                Value::DateTime(
+                    DateTime::from_timestamp(seconds, nanos).ok_or_else(|| corrupt("bad timestamp"))?,
+                )
+            }
+            TAG_DATE => {
+                let days = (self.u32()? ^ (1 << 31)) as i32;
+                Value::Date(NaiveDate::from_num_days_from_ce_opt(days).ok_or_else(|| corrupt("bad date"))?)
+            }
+            TAG_TIME => {
+                let seconds = self.u32()?;
+                let nanos = self.u32()?;
+                Value::Time(
+                    NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos)
+                        .ok_or_else(|| corrupt("bad time"))?,
+                )
+            }
+            TAG_INTERVAL => {
+                let micros = (u128::from_be_bytes(self.array()?) ^ (1 << 127)) as i128;
+                Value::Interval(Interval::from_approx_micros(micros))
+            }
+            TAG_JSON => Value::Json(
+                serde_json::from_str(&self.string()?).map_err(|_| corrupt("bad JSON"))?,
+            ),
+            TAG_ARRAY => {
+                let mut items = Vec::new();
+                while self.more()? {
+                    items.push(self.value()?);
+                }
+                Value::Array(items)
+            }
+            TAG_STRUCT => {
+                let mut fields = Vec::new();
+                while self.more()? {
+                    let name = self.string()?;
+                    fields.push((name, self.value()?));
+                }
+                Value::Struct(fields)
+            }
+            TAG_ENUM => {
+                let variant = self.string()?;
+                let payload = if self.more()? { Some(Box::new(self.value()?)) } else { None };
+                Value::Enum { variant, payload }
+            }
+            TAG_MAP => {
+                let mut entries = BTreeMap::new();
+                while self.more()? {
+                    let key = self.value()?;
+                    entries.insert(key, self.value()?);
+                }
+                Value::Map(entries)
+            }
+            tag => return Err(corrupt(&format!("unknown tag {:#04x}", tag))),
+        })
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let negative = match self.byte()? {
+            NUMBER_NEGATIVE_INFINITY => return Ok(Value::Float(f64::NEG_INFINITY)),
+            NUMBER_ZERO => return Ok(Value::Int(0)),
+            NUMBER_POSITIVE_INFINITY => return Ok(Value::Float(f64::INFINITY)),
+            NUMBER_NAN => return Ok(Value::Float(f64::NAN)),
+            NUMBER_NEGATIVE => true,
+            NUMBER_POSITIVE => false,
+            _ => return Err(corrupt("bad number class")),
+        };
+        let outer = self.invert;
+        self.invert ^= negative;
+        let exponent = self.i64()?;
+        let mut digits = String::new();
+        loop {
+            match self.byte()? {
+                0x00 => break,
+                d @ b'0'..=b'9' => digits.push(d as char),
+                _ => return Err(corrupt("bad decimal digit")),
+            }
+        }
+        self.invert = outer;
+        let sign = if negative { "-" } else { "" };
+        let d = BigDecimal::from_str(&format!("{}0.{}e{}", sign, digits, exponent))
+            .map_err(|_| corrupt("bad number"))?;
+        if !d.is_integer() {
+            return Ok(Value::Decimal(d));
+        }
+        Ok(match (d.to_i64(), d.to_u64()) {
+            (Some(i), _) => Value::Int(i),
+            (None, Some(u)) => Value::UInt64(u),
+            _ => Value::Decimal(d),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use proptest::prelude::*;
+    use std::cmp::Ordering;
+
+    fn scalar() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Bool),
+            any::<i8>().prop_map(Value::Int8),
+            any::<i32>().prop_map(Value::Int32),
+            any::<i64>().prop_map(Value::Int),
+            any::<u8>().prop_map(Value::UInt8),
+            any::<u64>().prop_map(Value::UInt64),
+            any::<f32>().prop_map(Value::Float32),
+            any::<f64>().prop_map(Value::Float),
+            (any::<i64>(), -20i64..20).prop_map(|(i, scale)| {
+                Value::Decimal(BigDecimal::from_str(&format!("{}e{}", i, scale)).unwrap())
+            }),
+            "[a-c\\x00]{0,6}".prop_map(Value::String),
+            prop::collection::vec(0u8..3, 0..6).prop_map(Value::Bytes),
+            any::<[u8; 16]>().prop_map(|b| Value::Uuid(Uuid::from_bytes(b))),
+            (-100_000_000_000i64..100_000_000_000, 0u32..1_000_000_000)
+                .prop_map(|(s, n)| Value::DateTime(Utc.timestamp_opt(s, n).unwrap())),
+            (-700_000i32..3_000_000).prop_map(|d| {
+                Value::Date(NaiveDate::from_num_days_from_ce_opt(d).unwrap())
+            }),
+            (0u32..86_400, 0u32..1_000_000_000).prop_map(|(s, n)| {
+                Value::Time(NaiveTime::from_num_seconds_from_midnight_opt(s, n).unwrap())
+            }),
+            (-30i32..30, -60i32..60, any::<i64>())
+                .prop_map(|(m, d, us)| Value::Interval(Interval::new(m, d, us))),
+            prop_oneof![
+                any::<i64>().prop_map(serde_json::Value::from),
+                "[a-c]{0,3}".prop_map(serde_json::Value::from),
+            ]
+            .prop_map(Value::Json),
+        ]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        scalar().prop_recursive(3, 16, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+                prop::collection::vec(("[ab]{0,2}", inner.clone()), 0..3).prop_map(Value::Struct),
+                ("[ab]{1,2}", prop::option::of(inner.clone())).prop_map(|(variant, payload)| {
+                    Value::Enum {
+                        variant,
+                        payload: payload.map(Box::new),
+                    }
+                }),
+                prop::collection::btree_map(inner.clone(), inner, 0..3).prop_map(Value::Map),
+            ]
+        })
+    }
+
+    fn key_order() -> impl Strategy<Value = KeyOrder> {
+        (any::<bool>(), any::<bool>()).prop_map(|(descending, nulls_first)| KeyOrder {
+            descending,
+            nulls_first,
+        })
+    }
+
+    /// Reference ordering of composite keys under `orders`.
+    fn compare_keys(a: &[Value], b: &[Value], orders: &[KeyOrder]) -> Ordering {
+        for ((x, y), order) in a.iter().zip(b).zip(orders) {
+            let ordering = match (x.is_null(), y.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if order.nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if order.nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                _ if order.descending => y.cmp(x),
+                _ => x.cmp(y),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        a.len().cmp(&b.len())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_value_order_matches_bytes(a in value(), b in value()) {
+            prop_assert_eq!(encode_value(&a).cmp(&encode_value(&b)), a.cmp(&b));
+        }
+
+        #[test]
+        fn prop_value_round_trip(v in value()) {
+            let decoded = decode_value(&encode_value(&v)).unwrap();
+            prop_assert_eq!(decoded.cmp(&v), Ordering::Equal);
+        }
+
+        #[test]
+        fn prop_key_order_matches_bytes(
+            orders in prop::collection::vec(key_order(), 1..4),
+            a in prop::collection::vec(scalar(), 1..4),
+            b in prop::collection::vec(scalar(), 1..4),
+        ) {
+            let (a, b) = (&a[..a.len().min(orders.len())], &b[..b.len().min(orders.len())]);
+            let (ka, kb) = (encode_key(a, &orders), encode_key(b, &orders));
+            if a.len() == b.len() {
+                prop_assert_eq!(ka.cmp(&kb), compare_keys(a, b, &orders));
+            }
+            prop_assert_eq!(decode_key(&ka, &orders).unwrap(), a.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let one = encode_value(&Value::Int(1));
+        assert_eq!(encode_value(&Value::UInt8(1)), one);
+        assert_eq!(
+            encode_value(&Value::Decimal(BigDecimal::from_str("1.50").unwrap())),
+            encode_value(&Value::Decimal(BigDecimal::from_str("1.5").unwrap()))
+        );
+        assert!(encode_value(&Value::String("a".into())) < encode_value(&Value::String("a\0".into())));
+        assert_eq!(encode_value(&Value::Float(-0.0)), encode_value(&Value::Int(0)));
+        assert_eq!(encode_value(&Value::Float(1.0)), one);
+        assert_eq!(encode_value(&Value::Decimal(BigDecimal::from_str("1.000").unwrap())), one);
+        let tenth = Value::Decimal(BigDecimal::from_str("0.1").unwrap());
+        assert_eq!(encode_value(&Value::Float(0.1)), encode_value(&tenth));
+        assert!(encode_value(&Value::Int(5)) > encode_value(&Value::Float(1.5)));
+        assert!(encode_value(&Value::Float(f64::INFINITY)) < encode_value(&Value::Float(f64::NAN)));
+
+        let desc = [KeyOrder::DESC];
+        assert!(encode_key(&[Value::Int(2)], &desc) < encode_key(&[Value::Int(1)], &desc));
+        assert!(encode_key(&[Value::Int(1)], &desc) < encode_key(&[Value::Null], &desc));
+        assert!(decode_value(&[TAG_NUMBER, NUMBER_POSITIVE]).is_err());
+    }
+}
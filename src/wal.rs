@@ -0,0 +1,411 @@
+// src/wal.rs
+//! Write-ahead logging and crash recovery.
+//!
+//! Every change to a page is described by a log record before the page may
+//! reach disk: the page remembers the LSN of its last change, and the buffer
+//! pool flushes the log up to that LSN before writing the page back. A
+//! transaction commits once its commit record is durable. Together these let
+//! the buffer pool write back uncommitted pages ("steal") and skip writing
+//! committed ones ("no force"), with [`recovery`] putting things right after
+//! a crash.
+//!
+//! The log lives in a directory of segment files (see [`segment`]), with a
+//! small `control` file recording the LSN of the last complete checkpoint.
+//! Checkpoints bound how much log recovery has to read, and let segments
+//! that are no longer needed be recycled.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use crate::error::Error;
+
+pub mod checkpoint;
+pub mod record;
+pub mod recovery;
+pub mod segment;
+pub mod txn;
+
+pub use checkpoint::Checkpointer;
+pub use record::{ActiveTxn, LogBody, LogRecord, Lsn, TxnId};
+pub use recovery::{recover, RecoveryReport};
+pub use segment::LOG_START;
+pub use txn::TxnManager;
+
+use segment::Segments;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalOptions {
+    /// Largest size of a segment file, header included. A record bigger
+    /// than this gets a segment of its own.
+    pub segment_size: u64,
+    /// Retired segments kept for reuse instead of being deleted.
+    pub max_spare_segments: usize,
+}
+
+impl Default for WalOptions {
+    fn default() -> Self {
+        WalOptions { segment_size: 16 << 20, max_spare_segments: 2 }
+    }
+}
+
+#[derive(Debug)]
+struct LogState {
+    /// Records appended but not yet handed to a flush.
+    buffer: Vec<u8>,
+    buffer_start: Lsn,
+    /// Segments that buffered records start, not yet created on disk.
+    new_segments: Vec<Lsn>,
+    /// Start of the segment the next record goes into.
+    segment_start: Lsn,
+    next_lsn: Lsn,
+    /// Everything before this LSN is on disk.
+    flushed_lsn: Lsn,
+    flushing: bool,
+}
+
+/// The log manager: appends records and makes them durable.
+///
+/// Flushes are batched (group commit): while one thread is writing and
+/// syncing the log, records appended by others accumulate, and the next
+/// flush makes all of them durable with a single fsync per segment touched.
+#[derive(Debug)]
+pub struct Wal {
+    dir: PathBuf,
+    options: WalOptions,
+    segments: Mutex<Segments>,
+    state: Mutex<LogState>,
+    flushed: Condvar,
+    fsyncs: AtomicU64,
+}
+
+impl Wal {
+    /// Opens the log in directory `dir` with default options, creating it
+    /// if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with(dir, WalOptions::default())
+    }
+
+    /// Opens the log in directory `dir`. A torn or corrupt record at the end
+    /// (from a crash mid-write) is cut off.
+    pub fn open_with(dir: impl AsRef<Path>, options: WalOptions) -> Result<Self, Error> {
+        if options.segment_size <= segment::SEGMENT_HEADER {
+            return Err(Error::Storage(format!("log segment size {} is too small", options.segment_size)));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        let (segments, end) = Segments::open(&dir, options.max_spare_segments)?;
+        let segment_start = segments.last_start();
+        Ok(Wal {
+            dir,
+            options,
+            segments: Mutex::new(segments),
+            state: Mutex::new(LogState {
+                buffer: Vec::new(),
+                buffer_start: end,
+                new_segments: Vec::new(),
+                segment_start,
+                next_lsn: end,
+                flushed_lsn: end,
+                flushing: false,
+            }),
+            flushed: Condvar::new(),
+            fsyncs: AtomicU64::new(0),
+        })
+    }
+
+    /// Appends a record to the log buffer and returns its LSN. The record is
+    /// not durable until a flush covers it.
+    pub fn append(&self, txn: TxnId, prev_lsn: Lsn, body: LogBody) -> Lsn {
+        let mut state = self.state.lock();
+        let lsn = state.next_lsn;
+        let bytes = LogRecord { lsn, txn, prev_lsn, body }.encode();
+        let used = segment::SEGMENT_HEADER + (lsn - state.segment_start);
+        if lsn > state.segment_start && used + bytes.len() as u64 > self.options.segment_size {
+            state.segment_start = lsn;
+            state.new_segments.push(lsn);
+        }
+        state.next_lsn += bytes.len() as Lsn;
+        state.buffer.extend(bytes);
+        lsn
+    }
+
+    /// The LSN the next appended record will get.
+    pub fn next_lsn(&self) -> Lsn {
+        self.state.lock().next_lsn
+    }
+
+    /// Everything before this LSN is durable.
+    pub fn flushed_lsn(&self) -> Lsn {
+        self.state.lock().flushed_lsn
+    }
+
+    /// LSN of the oldest record still in the log.
+    pub fn first_lsn(&self) -> Lsn {
+        self.segments.lock().first_lsn()
+    }
+
+    /// Number of times a log segment has been synced to disk.
+    pub fn fsyncs(&self) -> u64 {
+        self.fsyncs.load(Ordering::Relaxed)
+    }
+
+    /// Number of segment files holding the log.
+    pub fn segment_count(&self) -> usize {
+        self.segments.lock().count()
+    }
+
+    /// Number of retired segment files waiting to be reused.
+    pub fn spare_count(&self) -> usize {
+        self.segments.lock().spare_count()
+    }
+
+    /// Makes the record at `lsn`, and everything before it, durable.
+    pub fn flush_to(&self, lsn: Lsn) -> Result<(), Error> {
+        self.flush_until(lsn + 1)
+    }
+
+    /// Makes every appended record durable.
+    pub fn flush(&self) -> Result<(), Error> {
+        let end = self.next_lsn();
+        self.flush_until(end)
+    }
+
+    fn flush_until(&self, end: Lsn) -> Result<(), Error> {
+        let mut state = self.state.lock();
+        if end > state.next_lsn {
+            return Err(Error::Storage(format!("log ends at {}, before {}", state.next_lsn, end - 1)));
+        }
+        loop {
+            if state.flushed_lsn >= end {
+                return Ok(());
+            }
+            if state.flushing {
+                self.flushed.wait(&mut state);
+                continue;
+            }
+            state.flushing = true;
+            let batch = std::mem::take(&mut state.buffer);
+            let new_segments = std::mem::take(&mut state.new_segments);
+            let start = state.buffer_start;
+            let target = state.next_lsn;
+            state.buffer_start = target;
+
+            let result = MutexGuard::unlocked(&mut state, || self.write_batch(start, &batch, &new_segments));
+            state.flushing = false;
+            if let Err(err) = result {
+                // Put the batch back so a later flush can retry it.
+                let mut buffer = batch;
+                buffer.append(&mut state.buffer);
+                state.buffer = buffer;
+                state.buffer_start = start;
+                let mut segments = new_segments;
+                segments.append(&mut state.new_segments);
+                state.new_segments = segments;
+                self.flushed.notify_all();
+                return Err(err);
+            }
+            state.flushed_lsn = target;
+            self.flushed.notify_all();
+        }
+    }
+
+    /// Writes records starting at `start`, beginning the segments in
+    /// `new_segments` on the way, then syncs every segment written to.
+    fn write_batch(&self, start: Lsn, batch: &[u8], new_segments: &[Lsn]) -> Result<(), Error> {
+        let mut segments = self.segments.lock();
+        let mut touched = BTreeSet::new();
+        let mut lsn = start;
+        let end = start + batch.len() as Lsn;
+        let mut bounds = new_segments.iter().copied().peekable();
+        while lsn < end {
+            if bounds.peek() == Some(&lsn) {
+                segments.create(lsn)?;
+                bounds.next();
+            }
+            let until = bounds.peek().copied().unwrap_or(end);
+            let chunk = &batch[(lsn - start) as usize..(until - start) as usize];
+            touched.insert(segments.write(lsn, chunk)?);
+            lsn = until;
+        }
+        segments.sync(&touched)?;
+        self.fsyncs.fetch_add(touched.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Reads the record at `lsn`, flushing the log first if it has not
+    /// reached disk yet.
+    pub fn read(&self, lsn: Lsn) -> Result<LogRecord, Error> {
+        self.flush_to(lsn)?;
+        let mut segments = self.segments.lock();
+        let mut frame = [0; record::FRAME];
+        segments.read_exact(lsn, &mut frame)?;
+        let len = u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize;
+        let mut bytes = frame.to_vec();
+        bytes.resize(record::FRAME + len, 0);
+        segments.read_exact(lsn + record::FRAME as Lsn, &mut bytes[record::FRAME..])?;
+        LogRecord::decode(lsn, &bytes)
+            .map(|(record, _)| record)
+            .ok_or_else(|| Error::Storage(format!("corrupt log record at {}", lsn)))
+    }
+
+    /// Every record from `lsn` to the end of the log.
+    pub fn records_from(&self, lsn: Lsn) -> Result<Vec<LogRecord>, Error> {
+        self.flush()?;
+        let end = self.flushed_lsn();
+        let (records, reached) = self.segments.lock().records_from(lsn)?;
+        if reached < end {
+            return Err(Error::Storage(format!("corrupt log record at {}", reached)));
+        }
+        Ok(records)
+    }
+
+    /// LSN of the last complete checkpoint record, if there is one.
+    pub fn checkpoint_lsn(&self) -> Result<Option<Lsn>, Error> {
+        let bytes = match fs::read(self.dir.join("control")) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if bytes.len() != 12 || crc32c::crc32c(&bytes[..8]).to_le_bytes() != bytes[8..12] {
+            return Err(Error::Storage("corrupt log control file".to_string()));
+        }
+        Ok(Some(u64::from_le_bytes(bytes[..8].try_into().unwrap())))
+    }
+
+    /// Records `lsn` as the last complete checkpoint. The checkpoint record
+    /// must already be durable.
+    pub fn set_checkpoint(&self, lsn: Lsn) -> Result<(), Error> {
+        let mut bytes = lsn.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&crc32c::crc32c(&bytes).to_le_bytes());
+        let tmp = self.dir.join("control.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join("control"))?;
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+
+    /// Retires the segments holding only records before `lsn`, keeping a few
+    /// for reuse. Returns how many segments were retired.
+    pub fn truncate(&self, lsn: Lsn) -> Result<usize, Error> {
+        let lsn = lsn.min(self.flushed_lsn());
+        self.segments.lock().truncate(lsn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_flush_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let (first, second) = {
+            let wal = Wal::open(&path).unwrap();
+            let first = wal.append(1, 0, LogBody::Begin);
+            let second = wal.append(1, first, LogBody::Commit);
+            assert_eq!(first, LOG_START);
+            assert_eq!(wal.flushed_lsn(), LOG_START);
+            assert_eq!(wal.read(second).unwrap().prev_lsn, first);
+            wal.append(1, second, LogBody::End);
+            (first, second)
+        };
+
+        // The unflushed End record was lost; the rest survived.
+        let wal = Wal::open(&path).unwrap();
+        let records = wal.records_from(LOG_START).unwrap();
+        assert_eq!(records.iter().map(|r| r.lsn).collect::<Vec<_>>(), vec![first, second]);
+        assert_eq!(records[1].body, LogBody::Commit);
+    }
+
+    #[test]
+    fn test_group_commit_batches_fsyncs() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(dir.path().join("log")).unwrap();
+        let commits: Vec<Lsn> = (1..=10).map(|txn| wal.append(txn, 0, LogBody::Commit)).collect();
+        wal.flush_to(commits[9]).unwrap();
+        for lsn in commits {
+            wal.flush_to(lsn).unwrap();
+        }
+        assert_eq!(wal.fsyncs(), 1);
+    }
+
+    #[test]
+    fn test_past_the_end_of_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(dir.path()).unwrap();
+        assert!(matches!(wal.read(LOG_START + 1000), Err(Error::Storage(_))));
+        let begin = wal.append(1, 0, LogBody::Begin);
+        assert!(matches!(wal.flush_to(wal.next_lsn()), Err(Error::Storage(_))));
+        wal.flush_to(begin).unwrap();
+        assert_eq!(wal.flushed_lsn(), wal.next_lsn());
+    }
+
+    #[test]
+    fn test_torn_tail_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(dir.path()).unwrap();
+        wal.append(1, 0, LogBody::Begin);
+        let commit = wal.append(1, LOG_START, LogBody::Commit);
+        wal.flush().unwrap();
+        drop(wal);
+
+        let path = dir.path().join(segment::segment_name(LOG_START));
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 3).unwrap();
+        drop(file);
+
+        let wal = Wal::open(dir.path()).unwrap();
+        assert_eq!(wal.next_lsn(), commit);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), segment::SEGMENT_HEADER + commit - LOG_START);
+        let begin = wal.append(2, 0, LogBody::Begin);
+        assert_eq!(begin, commit);
+        assert_eq!(wal.read(begin).unwrap().txn, 2);
+    }
+
+    #[test]
+    fn test_segments_are_truncated_and_recycled() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = WalOptions { segment_size: 128, max_spare_segments: 2 };
+        let wal = Wal::open_with(dir.path(), options).unwrap();
+        let lsns: Vec<Lsn> = (1..=20).map(|txn| wal.append(txn, 0, LogBody::Commit)).collect();
+        wal.flush().unwrap();
+        let segments = wal.segment_count();
+        assert!(segments > 4);
+
+        // Records never straddle segments, and reads cross them freely.
+        assert_eq!(wal.records_from(LOG_START).unwrap().len(), 20);
+        assert_eq!(wal.read(lsns[19]).unwrap().txn, 20);
+
+        let retired = wal.truncate(lsns[15]).unwrap();
+        assert!(retired >= 3);
+        assert_eq!(wal.segment_count(), segments - retired);
+        assert_eq!(wal.spare_count(), 2);
+        assert!(wal.first_lsn() <= lsns[15] && wal.first_lsn() > LOG_START);
+        assert!(wal.read(LOG_START).is_err());
+
+        // New segments reuse the spares, whose old records stay invisible.
+        let more: Vec<Lsn> = (21..=30).map(|txn| wal.append(txn, 0, LogBody::Commit)).collect();
+        wal.flush().unwrap();
+        assert_eq!(wal.spare_count(), 0);
+        drop(wal);
+        let wal = Wal::open_with(dir.path(), options).unwrap();
+        assert_eq!(wal.next_lsn(), more[9] + (more[9] - more[8]));
+        let txns: Vec<TxnId> = wal.records_from(wal.first_lsn()).unwrap().iter().map(|r| r.txn).collect();
+        assert_eq!(txns.last(), Some(&30));
+        assert!(txns.windows(2).all(|w| w[1] == w[0] + 1));
+
+        assert_eq!(wal.checkpoint_lsn().unwrap(), None);
+        wal.set_checkpoint(more[3]).unwrap();
+        assert_eq!(wal.checkpoint_lsn().unwrap(), Some(more[3]));
+    }
+}
@@ -0,0 +1,100 @@
+// src/wal/checkpoint.rs
+//! Periodic checkpoints in the background.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::error::Error;
+use crate::wal::TxnManager;
+
+/// A tokio task taking a checkpoint every `interval`, skipping it when
+/// nothing has been logged since the last one. Dropping the checkpointer
+/// stops the task without waiting for it.
+#[derive(Debug)]
+pub struct Checkpointer {
+    stop: Arc<Notify>,
+    task: JoinHandle<Result<u64, Error>>,
+}
+
+impl Checkpointer {
+    /// Starts the checkpointer. Must be called within a tokio runtime.
+    pub fn spawn(txns: Arc<TxnManager>, interval: Duration) -> Self {
+        let stop = Arc::new(Notify::new());
+        let stopped = stop.clone();
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes immediately.
+            ticks.tick().await;
+            let mut taken = 0;
+            let mut last = None;
+            loop {
+                tokio::select! {
+                    _ = stopped.notified() => return Ok(taken),
+                    _ = ticks.tick() => {}
+                }
+                if last == Some(txns.wal().next_lsn()) {
+                    continue;
+                }
+                let manager = txns.clone();
+                tokio::task::spawn_blocking(move || manager.checkpoint())
+                    .await
+                    .map_err(|err| Error::Storage(format!("checkpoint task failed: {}", err)))??;
+                taken += 1;
+                last = Some(txns.wal().next_lsn());
+            }
+        });
+        Checkpointer { stop, task }
+    }
+
+    /// Stops the checkpointer, returning how many checkpoints it took, or
+    /// the error that stopped it early.
+    pub async fn stop(mut self) -> Result<u64, Error> {
+        self.stop.notify_one();
+        (&mut self.task)
+            .await
+            .map_err(|err| Error::Storage(format!("checkpoint task failed: {}", err)))?
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        self.stop.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::storage::{PageType, Pager, PagerOptions};
+    use crate::wal::Wal;
+
+    #[tokio::test]
+    async fn test_checkpoints_in_the_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Arc::new(Wal::open(dir.path().join("log")).unwrap());
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = Arc::new(BufferPool::new(pager, 4).with_wal(wal.clone()));
+        let page = pool.new_page(PageType::Heap).unwrap().id();
+        let (txns, _) = TxnManager::open(pool, wal.clone()).unwrap();
+        let txns = Arc::new(txns);
+
+        let checkpointer = Checkpointer::spawn(txns.clone(), Duration::from_millis(10));
+        let txn = txns.begin();
+        txns.write(txn, page, 0, b"data").unwrap();
+        txns.commit(txn).unwrap();
+        while wal.checkpoint_lsn().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // An idle log gets no further checkpoints.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let lsn = wal.checkpoint_lsn().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(wal.checkpoint_lsn().unwrap(), lsn);
+        assert!(checkpointer.stop().await.unwrap() >= 1);
+    }
+}
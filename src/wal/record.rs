@@ -0,0 +1,235 @@
+// src/wal/record.rs
+//! Log records and their on-disk encoding.
+//!
+//! Each record is framed as `len u32, crc u32, body`, where the CRC32C covers
+//! the record's LSN followed by the body, so bytes left over from a recycled
+//! log segment never pass for a record at a different position. The body is
+//!
+//! ```text
+//! txn u64 | prev_lsn u64 | kind u8 | payload
+//! ```
+//!
+//! A record's LSN is not stored: LSNs count the bytes of records appended
+//! since the log was created, so the LSN of a record is where it starts.
+
+use std::convert::TryInto;
+
+use crate::error::Error;
+use crate::storage::PageId;
+
+pub type Lsn = u64;
+pub type TxnId = u64;
+
+pub const FRAME: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogBody {
+    Begin,
+    /// Bytes `offset..offset + after.len()` of a page changed from `before`
+    /// to `after`. Offsets count from the start of the page, header
+    /// included.
+    Update { page: PageId, offset: u16, before: Vec<u8>, after: Vec<u8> },
+    /// Compensation for an undone update: redo-only, and `undo_next` is the
+    /// next record of the transaction still to be undone.
+    Compensation { page: PageId, offset: u16, after: Vec<u8>, undo_next: Lsn },
+    Commit,
+    Abort,
+    /// The transaction is finished and needs no further work in recovery.
+    End,
+    /// A fuzzy checkpoint: the live transactions and dirty pages as of some
+    /// point after `begin`, where recovery starts its analysis.
+    Checkpoint { begin: Lsn, next_txn: TxnId, active: Vec<ActiveTxn>, dirty: Vec<(PageId, Lsn)> },
+}
+
+/// A live transaction as recorded in a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveTxn {
+    pub txn: TxnId,
+    pub first_lsn: Lsn,
+    pub last_lsn: Lsn,
+    pub committed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    pub txn: TxnId,
+    /// Previous record of the same transaction, 0 for its first.
+    pub prev_lsn: Lsn,
+    pub body: LogBody,
+}
+
+impl LogRecord {
+    /// The page this record changes, if any.
+    pub fn page(&self) -> Option<PageId> {
+        match &self.body {
+            LogBody::Update { page, .. } | LogBody::Compensation { page, .. } => Some(*page),
+            _ => None,
+        }
+    }
+
+    /// Frames the record for appending to the log.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.txn.to_le_bytes());
+        body.extend_from_slice(&self.prev_lsn.to_le_bytes());
+        match &self.body {
+            LogBody::Begin => body.push(0),
+            LogBody::Update { page, offset, before, after } => {
+                body.push(1);
+                body.extend_from_slice(&page.to_le_bytes());
+                body.extend_from_slice(&offset.to_le_bytes());
+                body.extend_from_slice(&(after.len() as u16).to_le_bytes());
+                body.extend_from_slice(before);
+                body.extend_from_slice(after);
+            }
+            LogBody::Compensation { page, offset, after, undo_next } => {
+                body.push(2);
+                body.extend_from_slice(&page.to_le_bytes());
+                body.extend_from_slice(&offset.to_le_bytes());
+                body.extend_from_slice(&(after.len() as u16).to_le_bytes());
+                body.extend_from_slice(after);
+                body.extend_from_slice(&undo_next.to_le_bytes());
+            }
+            LogBody::Commit => body.push(3),
+            LogBody::Abort => body.push(4),
+            LogBody::End => body.push(5),
+            LogBody::Checkpoint { begin, next_txn, active, dirty } => {
+                body.push(6);
+                body.extend_from_slice(&begin.to_le_bytes());
+                body.extend_from_slice(&next_txn.to_le_bytes());
+                body.extend_from_slice(&(active.len() as u32).to_le_bytes());
+                for t in active {
+                    body.extend_from_slice(&t.txn.to_le_bytes());
+                    body.extend_from_slice(&t.first_lsn.to_le_bytes());
+                    body.extend_from_slice(&t.last_lsn.to_le_bytes());
+                    body.push(t.committed as u8);
+                }
+                body.extend_from_slice(&(dirty.len() as u32).to_le_bytes());
+                for (page, rec_lsn) in dirty {
+                    body.extend_from_slice(&page.to_le_bytes());
+                    body.extend_from_slice(&rec_lsn.to_le_bytes());
+                }
+            }
+        }
+        let mut framed = Vec::with_capacity(FRAME + body.len());
+        framed.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        framed.extend_from_slice(&checksum(self.lsn, &body).to_le_bytes());
+        framed.extend(body);
+        framed
+    }
+
+    /// Decodes the record at the start of `bytes`, returning it and its
+    /// framed length. Returns `None` for a torn or corrupt record, which
+    /// marks the end of the usable log.
+    pub fn decode(lsn: Lsn, bytes: &[u8]) -> Option<(LogRecord, usize)> {
+        let len = u32::from_le_bytes(bytes.get(0..4)?.try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(bytes.get(4..8)?.try_into().unwrap());
+        let body = bytes.get(FRAME..FRAME + len)?;
+        if checksum(lsn, body) != crc {
+            return None;
+        }
+        let record = Self::decode_body(lsn, body).ok()?;
+        Some((record, FRAME + len))
+    }
+
+    fn decode_body(lsn: Lsn, body: &[u8]) -> Result<LogRecord, Error> {
+        let bad = || Error::Storage(format!("malformed log record at {}", lsn));
+        let u64_at = |at: usize| -> Result<u64, Error> {
+            Ok(u64::from_le_bytes(body.get(at..at + 8).ok_or_else(bad)?.try_into().unwrap()))
+        };
+        let u32_at = |at: usize| -> Result<u32, Error> {
+            Ok(u32::from_le_bytes(body.get(at..at + 4).ok_or_else(bad)?.try_into().unwrap()))
+        };
+        let u16_at = |at: usize| -> Result<u16, Error> {
+            Ok(u16::from_le_bytes(body.get(at..at + 2).ok_or_else(bad)?.try_into().unwrap()))
+        };
+        let bytes = |at: usize, len: usize| -> Result<Vec<u8>, Error> {
+            Ok(body.get(at..at + len).ok_or_else(bad)?.to_vec())
+        };
+        let kind = *body.get(16).ok_or_else(bad)?;
+        let body = match kind {
+            0 => LogBody::Begin,
+            1 => {
+                let len = u16_at(27)? as usize;
+                LogBody::Update {
+                    page: u64_at(17)?,
+                    offset: u16_at(25)?,
+                    before: bytes(29, len)?,
+                    after: bytes(29 + len, len)?,
+                }
+            }
+            2 => {
+                let len = u16_at(27)? as usize;
+                LogBody::Compensation {
+                    page: u64_at(17)?,
+                    offset: u16_at(25)?,
+                    after: bytes(29, len)?,
+                    undo_next: u64_at(29 + len)?,
+                }
+            }
+            3 => LogBody::Commit,
+            4 => LogBody::Abort,
+            5 => LogBody::End,
+            6 => {
+                let mut at = 37;
+                let mut active = Vec::new();
+                for _ in 0..u32_at(33)? {
+                    active.push(ActiveTxn {
+                        txn: u64_at(at)?,
+                        first_lsn: u64_at(at + 8)?,
+                        last_lsn: u64_at(at + 16)?,
+                        committed: *body.get(at + 24).ok_or_else(bad)? != 0,
+                    });
+                    at += 25;
+                }
+                let mut dirty = Vec::new();
+                for _ in 0..u32_at(at)? {
+                    dirty.push((u64_at(at + 4)?, u64_at(at + 12)?));
+                    at += 16;
+                }
+                LogBody::Checkpoint { begin: u64_at(17)?, next_txn: u64_at(25)?, active, dirty }
+            }
+            _ => return Err(bad()),
+        };
+        Ok(LogRecord { lsn, txn: u64_at(0)?, prev_lsn: u64_at(8)?, body })
+    }
+}
+
+fn checksum(lsn: Lsn, body: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(&lsn.to_le_bytes()), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_torn_records() {
+        let records = vec![
+            LogBody::Begin,
+            LogBody::Update { page: 3, offset: 10, before: vec![0; 4], after: b"abcd".to_vec() },
+            LogBody::Compensation { page: 3, offset: 10, after: vec![0; 4], undo_next: 40 },
+            LogBody::Commit,
+            LogBody::Abort,
+            LogBody::End,
+            LogBody::Checkpoint {
+                begin: 16,
+                next_txn: 9,
+                active: vec![ActiveTxn { txn: 3, first_lsn: 16, last_lsn: 80, committed: false }],
+                dirty: vec![(4, 40), (5, 80)],
+            },
+        ];
+        for body in records {
+            let record = LogRecord { lsn: 99, txn: 7, prev_lsn: 42, body };
+            let bytes = record.encode();
+            assert_eq!(LogRecord::decode(99, &bytes), Some((record, bytes.len())));
+            assert_eq!(LogRecord::decode(99, &bytes[..bytes.len() - 1]), None);
+            let mut flipped = bytes.clone();
+            *flipped.last_mut().unwrap() ^= 0x40;
+            assert_eq!(LogRecord::decode(99, &flipped), None);
+            // The same bytes are not a valid record at another LSN.
+            assert_eq!(LogRecord::decode(100, &bytes), None);
+        }
+    }
+}
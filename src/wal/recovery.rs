@@ -0,0 +1,419 @@
+// src/wal/recovery.rs
+//! ARIES-style crash recovery.
+//!
+//! 1. **Analysis** scans the log to find the transactions that were live at
+//!    the crash and the pages that may have been dirty, with the first LSN
+//!    that may not have reached each of them.
+//! 2. **Redo** repeats history: every logged change whose LSN is newer than
+//!    the page's LSN is applied again, including those of transactions that
+//!    will be rolled back.
+//! 3. **Undo** rolls back the transactions that never committed, logging
+//!    compensation records as during a normal abort.
+//!
+//! Analysis starts from the last checkpoint, which supplies the tables as
+//! they were at some point after the checkpoint's `begin` LSN; replaying
+//! the log from `begin` brings them up to date.
+//!
+//! Page allocation is not logged, so pages must exist on disk before they
+//! are written through a transaction. Pages allocated or freed carry an LSN
+//! past the changes logged to them before, so those are not repeated.
+
+use std::collections::HashMap;
+
+use crate::buffer::BufferPool;
+use crate::error::Error;
+use crate::storage::PageId;
+use crate::wal::txn::rollback;
+use crate::wal::{LogBody, LogRecord, Lsn, TxnId, Wal};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Logged changes that had to be applied again.
+    pub redone: usize,
+    /// Transactions that were rolled back.
+    pub rolled_back: Vec<TxnId>,
+    /// First transaction id not used in the log.
+    pub next_txn: TxnId,
+}
+
+#[derive(Debug)]
+struct TxnState {
+    last_lsn: Lsn,
+    committed: bool,
+    aborting: bool,
+}
+
+pub fn recover(pool: &BufferPool, wal: &Wal) -> Result<RecoveryReport, Error> {
+    let mut report = RecoveryReport { next_txn: 1, ..Default::default() };
+    let mut txns: HashMap<TxnId, TxnState> = HashMap::new();
+    let mut dirty: HashMap<PageId, Lsn> = HashMap::new();
+
+    // Start from the last checkpoint's tables, if there is one.
+    let scan_start = match wal.checkpoint_lsn()? {
+        Some(lsn) => match wal.read(lsn)?.body {
+            LogBody::Checkpoint { begin, next_txn, active, dirty: pages } => {
+                report.next_txn = next_txn;
+                for t in active {
+                    txns.insert(t.txn, TxnState { last_lsn: t.last_lsn, committed: t.committed, aborting: false });
+                }
+                dirty.extend(pages);
+                begin
+            }
+            _ => return Err(Error::Storage(format!("log record at {} is not a checkpoint", lsn))),
+        },
+        None => wal.first_lsn(),
+    };
+
+    // Analysis.
+    let records = wal.records_from(scan_start)?;
+    for record in &records {
+        if let LogBody::Checkpoint { .. } = record.body {
+            continue;
+        }
+        report.next_txn = report.next_txn.max(record.txn + 1);
+        if let LogBody::End = record.body {
+            txns.remove(&record.txn);
+            continue;
+        }
+        let state = txns.entry(record.txn).or_insert(TxnState {
+            last_lsn: 0,
+            committed: false,
+            aborting: false,
+        });
+        state.last_lsn = state.last_lsn.max(record.lsn);
+        match record.body {
+            LogBody::Commit => state.committed = true,
+            LogBody::Abort => state.aborting = true,
+            _ => {}
+        }
+        if let Some(page) = record.page() {
+            let rec_lsn = dirty.entry(page).or_insert(record.lsn);
+            *rec_lsn = (*rec_lsn).min(record.lsn);
+        }
+    }
+
+    // Redo.
+    let start = dirty.values().min().copied().unwrap_or(Lsn::MAX);
+    // Pages dirty at the checkpoint may need changes from before it.
+    let records = if start < scan_start { wal.records_from(start)? } else { records };
+    for record in records.iter().filter(|r| r.lsn >= start) {
+        let (page_id, offset, after) = match &record.body {
+            LogBody::Update { page, offset, after, .. } => (*page, *offset, after),
+            LogBody::Compensation { page, offset, after, .. } => (*page, *offset, after),
+            _ => continue,
+        };
+        if dirty.get(&page_id).is_none_or(|&rec_lsn| record.lsn < rec_lsn) {
+            continue;
+        }
+        let guard = pool.fetch(page_id)?;
+        if guard.read().header.lsn >= record.lsn {
+            continue;
+        }
+        let mut page = guard.write();
+        page.patch(offset as usize, after)?;
+        page.header.lsn = record.lsn;
+        guard.mark_logged(record.lsn);
+        report.redone += 1;
+    }
+
+    // Undo, newest transaction first.
+    let mut losers: Vec<(TxnId, TxnState)> = txns.into_iter().collect();
+    losers.sort_by_key(|(_, state)| std::cmp::Reverse(state.last_lsn));
+    for (txn, state) in losers {
+        if state.committed {
+            wal.append(txn, state.last_lsn, LogBody::End);
+            continue;
+        }
+        let mut last = state.last_lsn;
+        if !state.aborting {
+            last = wal.append(txn, last, LogBody::Abort);
+        }
+        let last = rollback(pool, wal, state.last_lsn, last)?;
+        wal.append(txn, last, LogBody::End);
+        report.rolled_back.push(txn);
+    }
+
+    wal.flush()?;
+    pool.flush_all()?;
+    Ok(report)
+}
+
+/// Every record still in the log, for inspection in tests and tools.
+pub fn dump(wal: &Wal) -> Result<Vec<LogRecord>, Error> {
+    wal.records_from(wal.first_lsn())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{PageType, Pager, PagerOptions};
+    use crate::wal::segment::{segment_name, SEGMENT_HEADER};
+    use crate::wal::{TxnManager, WalOptions, LOG_START};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    const PAGES: usize = 4;
+
+    struct Db {
+        pool: Arc<BufferPool>,
+        txns: TxnManager,
+    }
+
+    fn open(dir: &Path, frames: usize) -> (Db, RecoveryReport) {
+        // Small segments, so crashes land in all parts of a multi-segment log.
+        let options = WalOptions { segment_size: 256, max_spare_segments: 2 };
+        let wal = Arc::new(Wal::open_with(dir.join("log"), options).unwrap());
+        let pager = Pager::open_with(dir.join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = Arc::new(BufferPool::new(pager, frames).with_wal(wal.clone()));
+        let (txns, report) = TxnManager::open(pool.clone(), wal).unwrap();
+        (Db { pool, txns }, report)
+    }
+
+    fn contents(db: &Db) -> Vec<Vec<u8>> {
+        (1..=PAGES as PageId).map(|id| db.pool.fetch(id).unwrap().read().data.clone()).collect()
+    }
+
+    /// One scripted transaction: its writes, and whether it commits.
+    struct Script {
+        writes: Vec<(PageId, usize, &'static [u8])>,
+        commit: bool,
+    }
+
+    /// Runs the scripts, interleaving the first write of each transaction
+    /// with the previous one, and returns the LSN at which each committed
+    /// transaction became durable (`None` if it did not commit).
+    fn run(db: &Db, scripts: &[Script]) -> Vec<Option<Lsn>> {
+        let mut durable = Vec::new();
+        let mut pending: Option<(TxnId, &Script)> = None;
+        for script in scripts {
+            let txn = db.txns.begin();
+            let (page, offset, bytes) = script.writes[0];
+            db.txns.write(txn, page, offset, bytes).unwrap();
+            if let Some((prev, prev_script)) = pending.take() {
+                durable.push(finish(db, prev, prev_script));
+            }
+            pending = Some((txn, script));
+        }
+        let (txn, script) = pending.unwrap();
+        durable.push(finish(db, txn, script));
+        durable
+    }
+
+    fn finish(db: &Db, txn: TxnId, script: &Script) -> Option<Lsn> {
+        for &(page, offset, bytes) in &script.writes[1..] {
+            db.txns.write(txn, page, offset, bytes).unwrap();
+        }
+        if script.commit {
+            db.txns.commit(txn).unwrap();
+            Some(db.txns.wal().flushed_lsn())
+        } else {
+            // Some losers abort explicitly, the rest are still running at
+            // the crash.
+            if txn % 4 == 2 {
+                db.txns.abort(txn).unwrap();
+            }
+            None
+        }
+    }
+
+    fn scripts() -> Vec<Script> {
+        vec![
+            Script { writes: vec![(1, 0, b"aaaa"), (2, 10, b"bbbb")], commit: true },
+            Script { writes: vec![(1, 20, b"XXXX"), (3, 0, b"YYYY")], commit: false },
+            Script { writes: vec![(2, 10, b"cccc"), (4, 100, b"dddd")], commit: true },
+            Script { writes: vec![(3, 40, b"ZZZZ"), (4, 200, b"WWWW"), (1, 60, b"VVVV")], commit: false },
+            Script { writes: vec![(1, 0, b"eeee"), (3, 80, b"ffff")], commit: true },
+            Script { writes: vec![(2, 300, b"QQQQ"), (4, 0, b"RRRR")], commit: false },
+        ]
+    }
+
+    /// Page contents if exactly the transactions durable by `end` survive.
+    fn expected(scripts: &[Script], durable: &[Option<Lsn>], end: Lsn) -> Vec<Vec<u8>> {
+        let mut pages = vec![vec![0; 512 - 64]; PAGES];
+        for (script, durable) in scripts.iter().zip(durable) {
+            if durable.is_some_and(|lsn| lsn <= end) {
+                for &(page, offset, bytes) in &script.writes {
+                    pages[page as usize - 1][offset..offset + bytes.len()].copy_from_slice(bytes);
+                }
+            }
+        }
+        pages
+    }
+
+    /// Copies the log in `from` as it was on disk when everything before
+    /// `cut` had been written.
+    fn copy_log(from: &Path, to: &Path, cut: Lsn) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            let Some(start) = name.strip_suffix(".wal").and_then(|hex| Lsn::from_str_radix(hex, 16).ok()) else {
+                continue;
+            };
+            if start <= cut {
+                assert_eq!(name, segment_name(start));
+                let bytes = std::fs::read(from.join(&name)).unwrap();
+                let len = (SEGMENT_HEADER + cut - start).min(bytes.len() as u64) as usize;
+                std::fs::write(to.join(&name), &bytes[..len]).unwrap();
+            }
+        }
+    }
+
+    /// Recovers a copy of the database made from `db_file` and the log in
+    /// `log` cut at LSN `cut`, as if the machine had crashed there.
+    fn crash(db_file: &[u8], log: &Path, cut: Lsn) -> Vec<Vec<u8>> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("db"), db_file).unwrap();
+        copy_log(log, &dir.path().join("log"), cut);
+        let (db, _) = open(dir.path(), 8);
+        let pages = contents(&db);
+        drop(db);
+
+        // Recovery is idempotent: a crash during recovery changes nothing.
+        let (db, report) = open(dir.path(), 8);
+        assert_eq!(contents(&db), pages);
+        assert!(report.rolled_back.is_empty());
+        pages
+    }
+
+    fn setup(dir: &Path, frames: usize) -> (Db, Vec<u8>) {
+        let (db, _) = open(dir, frames);
+        for _ in 0..PAGES {
+            db.pool.new_page(PageType::Heap).unwrap();
+        }
+        db.pool.flush_all().unwrap();
+        let base = std::fs::read(dir.join("db")).unwrap();
+        (db, base)
+    }
+
+    #[test]
+    fn test_crash_at_every_point_of_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, base) = setup(dir.path(), 8);
+        let scripts = scripts();
+        let durable = run(&db, &scripts);
+        db.txns.wal().flush().unwrap();
+        let log = dir.path().join("log");
+        let end = db.txns.wal().next_lsn();
+        assert!(db.txns.wal().segment_count() > 1);
+
+        // With the data file as it was before any transaction ran, cut the
+        // log at every record boundary and at points inside records.
+        let mut cuts: Vec<Lsn> = dump(db.txns.wal()).unwrap().iter().map(|r| r.lsn).collect();
+        cuts.extend((LOG_START..end).step_by(13));
+        cuts.push(end);
+        for cut in cuts {
+            assert_eq!(
+                crash(&base, &log, cut),
+                expected(&scripts, &durable, cut),
+                "crash with {} bytes of log",
+                cut
+            );
+        }
+    }
+
+    #[test]
+    fn test_crash_after_stolen_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        // Two frames for four pages: uncommitted changes get written back.
+        let (db, _) = setup(dir.path(), 2);
+        let scripts = scripts();
+        let durable = run(&db, &scripts);
+        let stats = db.pool.stats();
+        assert!(stats.writebacks > 0);
+
+        // The data file now holds some uncommitted changes; the WAL rule
+        // guarantees the log covering them was flushed first.
+        let db_file = std::fs::read(dir.path().join("db")).unwrap();
+        let flushed = db.txns.wal().flushed_lsn();
+        db.txns.wal().flush().unwrap();
+        let end = db.txns.wal().next_lsn();
+        let log = dir.path().join("log");
+        for cut in (flushed..=end).step_by(7).chain([end]) {
+            assert_eq!(crash(&db_file, &log, cut), expected(&scripts, &durable, cut));
+        }
+    }
+
+    #[test]
+    fn test_recovery_from_a_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, _) = setup(dir.path(), 2);
+        let wal = db.txns.wal().clone();
+
+        // A long transaction holds on to the log it needs for undo.
+        let long = db.txns.begin();
+        db.txns.write(long, 1, 0, b"long").unwrap();
+        let mut last = 0;
+        for i in 0..20u8 {
+            last = db.txns.begin();
+            db.txns.write(last, 2, 4 * i as usize, &[i + 1; 4]).unwrap();
+            db.txns.commit(last).unwrap();
+        }
+        db.txns.checkpoint().unwrap();
+        assert_eq!(wal.first_lsn(), LOG_START);
+
+        // Checkpoints write no pages back, so the log a dirty page needs
+        // stays too.
+        db.txns.commit(long).unwrap();
+        db.txns.checkpoint().unwrap();
+        assert_eq!(wal.first_lsn(), LOG_START);
+
+        // Once the pages are written back, a checkpoint lets the old
+        // segments go.
+        db.pool.flush_all().unwrap();
+        db.txns.checkpoint().unwrap();
+        let first = wal.first_lsn();
+        assert!(first > LOG_START);
+        assert_eq!(wal.spare_count(), 2);
+
+        // Crash with a transaction spanning the last checkpoint.
+        let loser = db.txns.begin();
+        db.txns.write(loser, 3, 0, b"gone").unwrap();
+        db.txns.checkpoint().unwrap();
+        db.txns.write(loser, 4, 0, b"gone").unwrap();
+        let kept = db.txns.begin();
+        db.txns.write(kept, 1, 8, b"kept").unwrap();
+        db.txns.commit(kept).unwrap();
+        wal.flush().unwrap();
+
+        let crashed = dir.path().join("crashed");
+        std::fs::create_dir(&crashed).unwrap();
+        std::fs::write(crashed.join("db"), std::fs::read(dir.path().join("db")).unwrap()).unwrap();
+        copy_log(&dir.path().join("log"), &crashed.join("log"), wal.next_lsn());
+        std::fs::copy(dir.path().join("log/control"), crashed.join("log/control")).unwrap();
+        drop(db);
+
+        let (db, report) = open(&crashed, 8);
+        assert_eq!(report.rolled_back, vec![loser]);
+        assert_eq!(report.next_txn, kept + 1);
+        assert!(last < loser);
+        let pages = contents(&db);
+        assert_eq!(&pages[0][..12], b"long\0\0\0\0kept");
+        assert_eq!(&pages[1][76..80], &[20; 4]);
+        assert_eq!(&pages[2][..4], &[0; 4]);
+        assert_eq!(&pages[3][..4], &[0; 4]);
+    }
+
+    #[test]
+    fn test_transactions_continue_after_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let last_txn = {
+            let (db, _) = setup(dir.path(), 8);
+            let txn = db.txns.begin();
+            db.txns.write(txn, 1, 0, b"lost").unwrap();
+            db.txns.wal().flush().unwrap();
+            txn
+        };
+        let (db, report) = open(dir.path(), 8);
+        assert_eq!(report.rolled_back, vec![last_txn]);
+        assert_eq!(report.next_txn, last_txn + 1);
+        assert_eq!(&contents(&db)[0][..4], &[0; 4]);
+
+        let txn = db.txns.begin();
+        assert!(txn > last_txn);
+        db.txns.write(txn, 1, 0, b"kept").unwrap();
+        db.txns.commit(txn).unwrap();
+        drop(db);
+        let (db, _) = open(dir.path(), 8);
+        assert_eq!(&contents(&db)[0][..4], b"kept");
+    }
+}
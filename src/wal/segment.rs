@@ -0,0 +1,238 @@
+// src/wal/segment.rs
+//! The files holding the log.
+//!
+//! The log is split into segment files named after the LSN of their first
+//! record (`{lsn:016x}.wal`). Each starts with a 24-byte header
+//! (`b"RUSTWAL\0"`, a `u32` format version, 4 reserved bytes, the start LSN)
+//! and holds whole records: one that would overflow the segment size starts
+//! a new segment instead. LSNs run on across segments without gaps.
+//!
+//! Segments no longer needed after a checkpoint are renamed to
+//! `spare-{n}.wal` and reused for later segments, so the log does not keep
+//! allocating new files. Their old contents are harmless, as log record
+//! checksums include the record's LSN.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::wal::record::{LogRecord, Lsn};
+
+const MAGIC: &[u8; 8] = b"RUSTWAL\0";
+const FORMAT_VERSION: u32 = 1;
+
+pub(crate) const SEGMENT_HEADER: u64 = 24;
+
+/// LSN of the first record in a new log.
+pub const LOG_START: Lsn = 16;
+
+pub(crate) fn segment_name(start: Lsn) -> String {
+    format!("{:016x}.wal", start)
+}
+
+#[derive(Debug)]
+pub(crate) struct Segments {
+    dir: PathBuf,
+    files: BTreeMap<Lsn, File>,
+    spares: Vec<PathBuf>,
+    max_spares: usize,
+    next_spare: u64,
+}
+
+impl Segments {
+    /// Opens the log in `dir`, returning it and the LSN just past its last
+    /// valid record. A torn record at the end is cut off, along with any
+    /// segment after it.
+    pub fn open(dir: &Path, max_spares: usize) -> Result<(Self, Lsn), Error> {
+        fs::create_dir_all(dir)?;
+        let mut starts = Vec::new();
+        let mut spares = Vec::new();
+        let mut next_spare = 0;
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(n) = name.strip_prefix("spare-").and_then(|n| n.strip_suffix(".wal")) {
+                next_spare = next_spare.max(n.parse::<u64>().unwrap_or(0) + 1);
+                spares.push(dir.join(&name));
+            } else if let Some(hex) = name.strip_suffix(".wal") {
+                if let Ok(start) = Lsn::from_str_radix(hex, 16) {
+                    starts.push(start);
+                }
+            }
+        }
+        starts.sort_unstable();
+
+        let mut segments = Segments { dir: dir.to_path_buf(), files: BTreeMap::new(), spares, max_spares, next_spare };
+        if starts.is_empty() {
+            segments.create(LOG_START)?;
+            return Ok((segments, LOG_START));
+        }
+
+        let mut end: Option<Lsn> = None;
+        for start in starts {
+            let path = dir.join(segment_name(start));
+            if end.is_some_and(|end| end != start) {
+                // Follows a torn record: nothing here was durable.
+                fs::remove_file(&path)?;
+                continue;
+            }
+            let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            check_header(&bytes, start)?;
+            let body = &bytes[SEGMENT_HEADER as usize..];
+            let mut at = 0;
+            while let Some((_, len)) = LogRecord::decode(start + at as Lsn, &body[at..]) {
+                at += len;
+            }
+            if at < body.len() {
+                // A torn record, or what a recycled file held before.
+                file.set_len(SEGMENT_HEADER + at as u64)?;
+                file.sync_all()?;
+            }
+            segments.files.insert(start, file);
+            end = Some(start + at as Lsn);
+        }
+        Ok((segments, end.unwrap()))
+    }
+
+    pub fn first_lsn(&self) -> Lsn {
+        *self.files.keys().next().unwrap()
+    }
+
+    pub fn last_start(&self) -> Lsn {
+        *self.files.keys().next_back().unwrap()
+    }
+
+    pub fn count(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn spare_count(&self) -> usize {
+        self.spares.len()
+    }
+
+    /// Starts a new segment at `start`, reusing a spare file if there is one.
+    pub fn create(&mut self, start: Lsn) -> Result<(), Error> {
+        if self.files.contains_key(&start) {
+            return Ok(());
+        }
+        let path = self.dir.join(segment_name(start));
+        let mut file = match self.spares.pop() {
+            Some(spare) => {
+                fs::rename(&spare, &path)?;
+                OpenOptions::new().read(true).write(true).open(&path)?
+            }
+            None => OpenOptions::new().read(true).write(true).create_new(true).open(&path)?,
+        };
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&start.to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_all()?;
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        self.files.insert(start, file);
+        Ok(())
+    }
+
+    /// The segment holding `lsn`.
+    fn segment(&mut self, lsn: Lsn) -> Result<(Lsn, &mut File), Error> {
+        self.files
+            .range_mut(..=lsn)
+            .next_back()
+            .map(|(&start, file)| (start, file))
+            .ok_or_else(|| Error::Storage(format!("log position {} has been truncated", lsn)))
+    }
+
+    /// Writes bytes that all belong to the segment holding `lsn`.
+    pub fn write(&mut self, lsn: Lsn, bytes: &[u8]) -> Result<Lsn, Error> {
+        let (start, file) = self.segment(lsn)?;
+        file.seek(SeekFrom::Start(SEGMENT_HEADER + lsn - start))?;
+        file.write_all(bytes)?;
+        Ok(start)
+    }
+
+    pub fn sync(&mut self, starts: &BTreeSet<Lsn>) -> Result<(), Error> {
+        for start in starts {
+            if let Some(file) = self.files.get_mut(start) {
+                file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_exact(&mut self, lsn: Lsn, buf: &mut [u8]) -> Result<(), Error> {
+        let (start, file) = self.segment(lsn)?;
+        file.seek(SeekFrom::Start(SEGMENT_HEADER + lsn - start))?;
+        file.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Every valid record from `lsn` on, and the LSN just past the last.
+    pub fn records_from(&mut self, lsn: Lsn) -> Result<(Vec<LogRecord>, Lsn), Error> {
+        let first = self.segment(lsn)?.0;
+        let mut records = Vec::new();
+        let mut lsn = lsn;
+        for (&start, file) in self.files.range_mut(first..) {
+            lsn = lsn.max(start);
+            file.seek(SeekFrom::Start(SEGMENT_HEADER + lsn - start))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            let mut at = 0;
+            while let Some((record, len)) = LogRecord::decode(lsn, &bytes[at..]) {
+                records.push(record);
+                at += len;
+                lsn += len as Lsn;
+            }
+        }
+        Ok((records, lsn))
+    }
+
+    /// Retires every segment that holds only records before `lsn`. The
+    /// newest segment is always kept. Returns how many were retired.
+    pub fn truncate(&mut self, lsn: Lsn) -> Result<usize, Error> {
+        let starts: Vec<Lsn> = self.files.keys().copied().collect();
+        let mut retired = 0;
+        for pair in starts.windows(2) {
+            if pair[1] > lsn {
+                break;
+            }
+            self.files.remove(&pair[0]);
+            let path = self.dir.join(segment_name(pair[0]));
+            if self.spares.len() < self.max_spares {
+                let spare = self.dir.join(format!("spare-{}.wal", self.next_spare));
+                self.next_spare += 1;
+                fs::rename(&path, &spare)?;
+                self.spares.push(spare);
+            } else {
+                fs::remove_file(&path)?;
+            }
+            retired += 1;
+        }
+        Ok(retired)
+    }
+}
+
+fn check_header(bytes: &[u8], start: Lsn) -> Result<(), Error> {
+    if bytes.len() < SEGMENT_HEADER as usize || &bytes[..8] != MAGIC {
+        return Err(Error::Storage(format!("log segment {} is not a log file", segment_name(start))));
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(Error::Storage(format!("unsupported log format version {}", version)));
+    }
+    let recorded = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+    if recorded != start {
+        return Err(Error::Storage(format!(
+            "log segment {} says it starts at {}",
+            segment_name(start),
+            recorded
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,299 @@
+// src/wal/txn.rs
+//! Transactions over logged page writes.
+//!
+//! Concurrency control is the caller's job: two live transactions must not
+//! write the same bytes, as under strict two-phase locking. Rollback relies
+//! on this, undoing each transaction's changes independently.
+//!
+//! [`TxnManager::atomic`] instead runs one writer at a time, logging every
+//! page it changes through the buffer pool, so that storage structures
+//! updated in place come back whole after a crash.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, ReentrantMutex};
+
+use crate::buffer::{BufferPool, ChangeLog};
+use crate::error::Error;
+use crate::storage::{PageId, PAGE_HEADER_SIZE};
+use crate::wal::recovery::{recover, RecoveryReport};
+use crate::wal::{ActiveTxn, LogBody, LogRecord, Lsn, TxnId, Wal};
+
+#[derive(Debug)]
+pub struct TxnManager {
+    pool: Arc<BufferPool>,
+    wal: Arc<Wal>,
+    next_txn: AtomicU64,
+    /// The live transactions, with the first and last LSN each wrote.
+    active: Arc<Mutex<HashMap<TxnId, ActiveTxn>>>,
+    /// Held by [`TxnManager::atomic`]; set while its transaction runs.
+    writer: ReentrantMutex<Cell<bool>>,
+}
+
+/// Logs the page changes captured by the buffer pool for a transaction.
+#[derive(Debug)]
+struct TxnLog {
+    wal: Arc<Wal>,
+    active: Arc<Mutex<HashMap<TxnId, ActiveTxn>>>,
+    txn: TxnId,
+}
+
+impl ChangeLog for TxnLog {
+    fn log(&self, page: PageId, offset: u16, before: Vec<u8>, after: Vec<u8>) -> Lsn {
+        // Appended under the lock, like every record of a live transaction,
+        // so a checkpoint sees the transaction's last LSN up to date.
+        let mut active = self.active.lock();
+        let t = active.get_mut(&self.txn).expect("capturing transaction is not active");
+        t.last_lsn = self.wal.append(self.txn, t.last_lsn, LogBody::Update { page, offset, before, after });
+        t.last_lsn
+    }
+}
+
+impl TxnManager {
+    /// Recovers the database from its log, then starts accepting
+    /// transactions. The pool must have been built with the same log.
+    pub fn open(pool: Arc<BufferPool>, wal: Arc<Wal>) -> Result<(Self, RecoveryReport), Error> {
+        let report = recover(&pool, &wal)?;
+        let manager = TxnManager {
+            pool,
+            wal,
+            next_txn: AtomicU64::new(report.next_txn),
+            active: Arc::new(Mutex::new(HashMap::new())),
+            writer: ReentrantMutex::new(Cell::new(false)),
+        };
+        Ok((manager, report))
+    }
+
+    pub fn pool(&self) -> &Arc<BufferPool> {
+        &self.pool
+    }
+
+    pub fn wal(&self) -> &Arc<Wal> {
+        &self.wal
+    }
+
+    pub fn begin(&self) -> TxnId {
+        let txn = self.next_txn.fetch_add(1, Ordering::Relaxed);
+        // Appended under the lock so a checkpoint never sees a transaction
+        // missing from the table whose Begin precedes the checkpoint.
+        let mut active = self.active.lock();
+        let lsn = self.wal.append(txn, 0, LogBody::Begin);
+        active.insert(txn, ActiveTxn { txn, first_lsn: lsn, last_lsn: lsn, committed: false });
+        txn
+    }
+
+    fn last_lsn(&self, txn: TxnId) -> Result<Lsn, Error> {
+        self.active
+            .lock()
+            .get(&txn)
+            .map(|t| t.last_lsn)
+            .ok_or_else(|| Error::Transaction(format!("transaction {} is not active", txn)))
+    }
+
+    /// Overwrites bytes of a page's data on behalf of a transaction.
+    pub fn write(&self, txn: TxnId, page_id: PageId, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        let guard = self.pool.fetch(page_id)?;
+        let mut page = guard.write();
+        let end = offset + bytes.len();
+        if end > page.data.len() || bytes.len() > u16::MAX as usize {
+            return Err(Error::Storage(format!(
+                "write of {} bytes at {} is outside page {}",
+                bytes.len(),
+                offset,
+                page_id
+            )));
+        }
+        // Logged while the page is latched, so the page's changes appear in
+        // the log in the order they were made.
+        let lsn = {
+            let mut active = self.active.lock();
+            let t = active
+                .get_mut(&txn)
+                .ok_or_else(|| Error::Transaction(format!("transaction {} is not active", txn)))?;
+            t.last_lsn = self.wal.append(
+                txn,
+                t.last_lsn,
+                LogBody::Update {
+                    page: page_id,
+                    offset: (PAGE_HEADER_SIZE + offset) as u16,
+                    before: page.data[offset..end].to_vec(),
+                    after: bytes.to_vec(),
+                },
+            );
+            t.last_lsn
+        };
+        page.data[offset..end].copy_from_slice(bytes);
+        page.header.lsn = lsn;
+        guard.mark_logged(lsn);
+        Ok(())
+    }
+
+    /// Runs `f` as one transaction over the pages it changes, committed
+    /// even if `f` fails: `f` undoes its own work on failure, and logging it
+    /// is what lets recovery roll back an `f` cut short by a crash. Pages
+    /// `f` frees are freed once the transaction is durable.
+    ///
+    /// Calls run one at a time, and a call made from within `f` is part of
+    /// the outer call's transaction.
+    pub fn atomic<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let running = self.writer.lock();
+        if running.get() {
+            return f();
+        }
+        let txn = self.begin();
+        let log = TxnLog { wal: self.wal.clone(), active: self.active.clone(), txn };
+        if let Err(err) = self.pool.begin_capture(Box::new(log)) {
+            self.commit(txn)?;
+            return Err(err);
+        }
+        running.set(true);
+        let result = f();
+        running.set(false);
+        let captured = self.pool.finish_capture();
+        // The log takes the pages it refers to to exist on disk.
+        if captured.allocated {
+            self.pool.sync()?;
+        }
+        self.commit(txn)?;
+        self.pool.free_pages(captured.freed)?;
+        result
+    }
+
+    /// Commits once the commit record is durable. Commits from concurrent
+    /// transactions share fsyncs.
+    pub fn commit(&self, txn: TxnId) -> Result<(), Error> {
+        let prev = self.last_lsn(txn)?;
+        let lsn = self.wal.append(txn, prev, LogBody::Commit);
+        if let Some(t) = self.active.lock().get_mut(&txn) {
+            t.last_lsn = lsn;
+            t.committed = true;
+        }
+        self.wal.flush_to(lsn)?;
+        self.wal.append(txn, lsn, LogBody::End);
+        self.active.lock().remove(&txn);
+        Ok(())
+    }
+
+    /// Undoes everything the transaction wrote.
+    pub fn abort(&self, txn: TxnId) -> Result<(), Error> {
+        let prev = self.last_lsn(txn)?;
+        let abort = self.wal.append(txn, prev, LogBody::Abort);
+        self.set_last(txn, abort);
+        let last = rollback(&self.pool, &self.wal, prev, abort)?;
+        self.wal.append(txn, last, LogBody::End);
+        self.active.lock().remove(&txn);
+        Ok(())
+    }
+
+    fn set_last(&self, txn: TxnId, lsn: Lsn) {
+        if let Some(t) = self.active.lock().get_mut(&txn) {
+            t.last_lsn = lsn;
+        }
+    }
+
+    /// Takes a fuzzy checkpoint and retires the log segments recovery no
+    /// longer needs. Returns the checkpoint record's LSN.
+    ///
+    /// Transactions keep running meanwhile: the checkpoint records the live
+    /// transactions and the dirty pages as of some point after `begin`, and
+    /// recovery replays the log from there to catch up. No page is written
+    /// back for it, so the log a dirty page needs stays until the buffer
+    /// pool writes the page back of its own accord.
+    pub fn checkpoint(&self) -> Result<Lsn, Error> {
+        let begin = self.wal.next_lsn();
+        let active: Vec<ActiveTxn> = self.active.lock().values().copied().collect();
+        let dirty = self.pool.dirty_pages();
+        // Pages written back before the table was taken are missing from
+        // it, so they must be on disk before the log covering them goes.
+        self.pool.sync()?;
+        let keep = active
+            .iter()
+            .map(|t| t.first_lsn)
+            .chain(dirty.iter().map(|&(_, lsn)| lsn))
+            .fold(begin, Lsn::min);
+        let next_txn = self.next_txn.load(Ordering::Relaxed);
+        let lsn = self.wal.append(0, 0, LogBody::Checkpoint { begin, next_txn, active, dirty });
+        self.wal.flush_to(lsn)?;
+        self.wal.set_checkpoint(lsn)?;
+        self.wal.truncate(keep)?;
+        Ok(lsn)
+    }
+}
+
+/// Undoes a transaction's updates, following its log chain back from
+/// `from`. Each undone update is logged as a compensation record chained
+/// after `last`, so a crash part-way through picks up where it left off.
+/// Returns the transaction's last LSN afterwards.
+pub(crate) fn rollback(pool: &BufferPool, wal: &Wal, from: Lsn, mut last: Lsn) -> Result<Lsn, Error> {
+    let mut next = from;
+    while next != 0 {
+        let record = wal.read(next)?;
+        next = match &record.body {
+            LogBody::Update { page, offset, before, .. } => {
+                last = compensate(pool, wal, &record, *page, *offset, before, last)?;
+                record.prev_lsn
+            }
+            LogBody::Compensation { undo_next, .. } => *undo_next,
+            LogBody::Begin => 0,
+            _ => record.prev_lsn,
+        };
+    }
+    Ok(last)
+}
+
+fn compensate(
+    pool: &BufferPool,
+    wal: &Wal,
+    record: &LogRecord,
+    page_id: PageId,
+    offset: u16,
+    before: &[u8],
+    last: Lsn,
+) -> Result<Lsn, Error> {
+    let guard = pool.fetch(page_id)?;
+    let mut page = guard.write();
+    page.patch(offset as usize, before)?;
+    let lsn = wal.append(
+        record.txn,
+        last,
+        LogBody::Compensation { page: page_id, offset, after: before.to_vec(), undo_next: record.prev_lsn },
+    );
+    page.header.lsn = lsn;
+    guard.mark_logged(lsn);
+    Ok(lsn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{PageType, Pager, PagerOptions};
+
+    #[test]
+    fn test_commit_and_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Arc::new(Wal::open(dir.path().join("log")).unwrap());
+        let pager = Pager::open_with(dir.path().join("db"), PagerOptions { page_size: 512 }).unwrap();
+        let pool = Arc::new(BufferPool::new(pager, 4).with_wal(wal.clone()));
+        let page = pool.new_page(PageType::Heap).unwrap().id();
+        let (txns, _) = TxnManager::open(pool.clone(), wal.clone()).unwrap();
+
+        let t1 = txns.begin();
+        txns.write(t1, page, 0, b"kept").unwrap();
+        txns.commit(t1).unwrap();
+
+        let t2 = txns.begin();
+        txns.write(t2, page, 0, b"gone").unwrap();
+        txns.write(t2, page, 8, b"also gone").unwrap();
+        assert_eq!(&pool.fetch(page).unwrap().read().data[..4], b"gone");
+        txns.abort(t2).unwrap();
+
+        let data = pool.fetch(page).unwrap().read().data.clone();
+        assert_eq!(&data[..4], b"kept");
+        assert_eq!(&data[8..17], &[0; 9]);
+        assert!(txns.commit(t2).unwrap_err().to_string().contains("not active"));
+        assert!(txns.write(txns.begin(), page, 500, b"x").is_err());
+    }
+}